@@ -0,0 +1,121 @@
+//! Benchmarks of the work done on every change of a manifest: parsing it,
+//! converting between offsets and positions, and regenerating its
+//! diagnostics from the cached analysis.
+
+use std::{fmt::Write, hint::black_box};
+
+use crates_language_server::bench::{self, Parsed};
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use tower_lsp::lsp_types::Position;
+
+const SIZES: &[usize] = &[50, 500];
+
+/// A manifest with `n` dependencies, declared in all of the usual forms
+/// across the usual tables.
+fn manifest(n: usize) -> String {
+    const TABLES: &[&str] = &[
+        "dependencies",
+        "dev-dependencies",
+        "build-dependencies",
+        "target.'cfg(unix)'.dependencies",
+    ];
+
+    let mut s = String::from(
+        "[package]\nname = \"large\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    );
+    let mut features = String::from("\n[features]\n");
+    let mut dotted = String::new();
+
+    for (t, table) in TABLES.iter().enumerate() {
+        writeln!(s, "\n[{table}]").unwrap();
+        for i in (t..n).step_by(TABLES.len()) {
+            match i / TABLES.len() % 4 {
+                0 => writeln!(s, "crate-{i} = \"1.{i}\""),
+                1 => writeln!(
+                    s,
+                    "# the comment of crate-{i}\ncrate-{i} = {{ version = \
+                     \"0.{i}\", features = [\"std\", \"derive\"], optional = \
+                     true }}"
+                ),
+                2 => writeln!(
+                    s,
+                    "crate-{i} = {{ git = \"https://github.com/a/crate-{i}\", \
+                     branch = \"main\" }}"
+                ),
+                _ => writeln!(
+                    dotted,
+                    "\n[{table}.crate-{i}]\nversion = \"~2.{i}\"\n\
+                     default-features = false"
+                ),
+            }
+            .unwrap();
+            if i / TABLES.len() % 4 == 1 {
+                writeln!(features, "feature-{i} = [\"dep:crate-{i}\"]")
+                    .unwrap();
+            }
+        }
+    }
+    s + &dotted + &features
+}
+
+fn parse_document(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse_document");
+    for &n in SIZES {
+        let s = manifest(n);
+        group.bench_with_input(BenchmarkId::from_parameter(n), &s, |b, s| {
+            b.iter(|| bench::parse_document(black_box(s)))
+        });
+    }
+    group.finish();
+}
+
+fn positions(c: &mut Criterion) {
+    let s = manifest(*SIZES.last().unwrap());
+    let parsed = bench::parse_document(&s);
+    let end = bench::idx_to_position(&s, s.len());
+
+    let mut group = c.benchmark_group("positions");
+    group.bench_function("idx_to_position", |b| {
+        b.iter(|| bench::idx_to_position(black_box(&s), black_box(s.len())))
+    });
+    group.bench_function("position_to_idx", |b| {
+        b.iter(|| bench::position_to_idx(black_box(&s), black_box(end)))
+    });
+    group.bench_function("range_to_positions", |b| {
+        b.iter(|| {
+            bench::range_to_positions(black_box(&s), s.len() / 2..s.len())
+        })
+    });
+    // a lookup on every line, as the hovers and completions do.
+    group.bench_function("find", |b| {
+        b.iter(|| {
+            (0..end.line)
+                .filter(|&line| parsed.find(Position::new(line, 2)))
+                .count()
+        })
+    });
+    group.finish();
+}
+
+fn cached_diagnostics(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cached_diagnostics");
+    for &n in SIZES {
+        let s = manifest(n);
+        let analysis = bench::parse_document(&s).analyze();
+        // a line inserted at the top moves all of the entries, which is the
+        // worst case of reusing their analyses.
+        let edited: Parsed = bench::parse_document(&format!("# edited\n{s}"));
+
+        group.bench_with_input(
+            BenchmarkId::from_parameter(n),
+            &edited,
+            |b, edited| {
+                b.iter(|| edited.cached_diagnostics(black_box(&analysis)))
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse_document, positions, cached_diagnostics);
+criterion_main!(benches);
@@ -0,0 +1,80 @@
+//! Refreshes the bundled lists of lints (`data/lints`) from the toolchain that
+//! builds the server, so the `[lints]` completions are as new as the
+//! toolchain. The bundled lists are used when the toolchain (or clippy) isn't
+//! available.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// The lists, with the driver that lists the lints and the sections of its
+/// `-W help` output they're in.
+const LISTS: &[(&str, &str, &str)] = &[
+    ("rust.tsv", "rustc", "provided by rustc"),
+    ("clippy.tsv", "clippy-driver", "loaded by this crate"),
+];
+
+fn main() {
+    println!("cargo::rerun-if-changed=data/lints");
+    println!("cargo::rerun-if-env-changed=RUSTC");
+
+    let out = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    let rustc = PathBuf::from(env::var_os("RUSTC").unwrap_or("rustc".into()));
+
+    for &(file, driver, section) in LISTS {
+        let lints = list(&rustc.with_file_name(driver), section)
+            .unwrap_or_else(|| {
+                let bundled = Path::new("data/lints").join(file);
+                fs::read_to_string(bundled).unwrap()
+            });
+        fs::write(out.join(file), lints).unwrap();
+    }
+}
+
+/// List the lints (and lint groups) in the `section` of the `-W help` output
+/// of `driver`, as `name\tlevel\tdescription` lines (with `group` as the
+/// level of groups).
+fn list(driver: &Path, section: &str) -> Option<String> {
+    let output = Command::new(driver).args(["-W", "help"]).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let help = String::from_utf8(output.stdout).ok()?;
+
+    let mut lints = String::new();
+    let mut is_group = None;
+    for line in help.lines() {
+        let line = line.trim();
+        if line.starts_with("Lint checks") {
+            is_group = line.contains(section).then_some(false);
+            continue;
+        }
+        if line.starts_with("Lint groups") {
+            is_group = line.contains(section).then_some(true);
+            continue;
+        }
+        let Some(is_group) = is_group else { continue };
+
+        let Some((name, rest)) = line.split_once(char::is_whitespace) else {
+            continue;
+        };
+        // `warnings` is also listed as a lint.
+        if matches!(name, "name" | "----") || is_group && name == "warnings" {
+            continue;
+        }
+        let name = name.trim_start_matches("clippy::").replace('-', "_");
+        let rest = rest.trim_start();
+        let (level, description) = if is_group {
+            let count = rest.split(", ").count();
+            ("group", format!("a group of {count} lints"))
+        } else {
+            let (level, description) = rest.split_once(char::is_whitespace)?;
+            (level, description.trim().to_owned())
+        };
+        lints.push_str(&format!("{name}\t{level}\t{description}\n"));
+    }
+
+    (!lints.is_empty()).then_some(lints)
+}
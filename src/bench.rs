@@ -0,0 +1,74 @@
+//! The hot paths of the server, exposed for the benchmarks (`benches/`),
+//! which can only use the public API. Not meant to be used otherwise.
+
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, Position};
+
+use crate::{ls, messages::Catalog, parse::Manifest};
+
+pub use crate::parse::{idx_to_position, position_to_idx, range_to_positions};
+
+/// A parsed manifest.
+#[derive(Debug)]
+pub struct Parsed {
+    manifest: Manifest,
+}
+
+/// Parse a manifest, as it's parsed on every change of a document.
+pub fn parse_document(s: &str) -> Parsed {
+    let dom = taplo::parser::parse(s).into_dom();
+    Parsed { manifest: Manifest::parse(s, &dom) }
+}
+
+/// An analysis of the crates.io dependencies of a manifest, as it's cached
+/// between the changes of a document.
+#[derive(Debug)]
+pub struct Analysis {
+    analysis: ls::Analysis,
+}
+
+impl Parsed {
+    pub fn dependencies(&self) -> usize {
+        self.manifest.dependencies.len()
+    }
+
+    /// Whether there's a dependency field under the cursor.
+    pub fn find(&self, pos: Position) -> bool {
+        self.manifest.index.find(pos).is_some()
+    }
+
+    /// Analyze the crates.io dependencies with a version hint each (as
+    /// their crates would be fetched), without the network.
+    pub fn analyze(&self) -> Analysis {
+        let mut analysis = ls::Analysis::default();
+        for dependency in &self.manifest.dependencies {
+            let Some(version) = &dependency.version else { continue };
+            let fingerprint = dependency.fingerprint;
+            let hint = Diagnostic {
+                range: fingerprint.to_relative(version.range),
+                severity: Some(DiagnosticSeverity::INFORMATION),
+                message: version.value.raw.to_owned(),
+                ..Diagnostic::default()
+            };
+            analysis.insert(fingerprint, 0, vec![hint]);
+        }
+        Analysis { analysis }
+    }
+
+    /// The diagnostics of the manifest, with the crates.io dependencies'
+    /// diagnostics from a previous analysis (as when an unrelated part of the
+    /// document changed).
+    pub fn cached_diagnostics(&self, previous: &Analysis) -> Vec<Diagnostic> {
+        let mut diags =
+            ls::manifest_diagnostics(&self.manifest, Catalog::for_locale(None));
+        let mut analysis = ls::Analysis::default();
+        for dependency in &self.manifest.dependencies {
+            let fingerprint = dependency.fingerprint;
+            diags.extend(analysis.reuse(
+                &previous.analysis,
+                fingerprint,
+                Some(0),
+            ));
+        }
+        diags.into_iter().flatten().collect()
+    }
+}
@@ -0,0 +1,134 @@
+//! The budget of the requests to the crates.io API, which all of the API
+//! requests go through, so the server stays within the [data access
+//! guidance] however many features use the API.
+//!
+//! The requests are spaced by at least a second, and limited per minute. A
+//! part of the budget is reserved for the requests the user is waiting on
+//! (e.g. a hover), so background requests can't starve them.
+//!
+//! [data access guidance]: https://crates.io/data-access#api
+
+use std::{collections::VecDeque, time::Duration};
+
+use tokio::sync::Mutex;
+
+use crate::platform::{self, Instant};
+
+/// "A maximum of 1 request per second".
+const INTERVAL: Duration = Duration::from_secs(1);
+const WINDOW: Duration = Duration::from_mins(1);
+/// The maximal number of requests per [`WINDOW`].
+const PER_WINDOW: usize = 30;
+/// The part of [`PER_WINDOW`] that only interactive requests may use.
+const RESERVED: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// A request the user is waiting on (e.g. a hover).
+    Interactive,
+    /// A request that prefetches data (e.g. the descriptions of all of the
+    /// dependencies of a manifest).
+    Background,
+}
+
+impl Priority {
+    fn limit(self) -> usize {
+        match self {
+            Self::Interactive => PER_WINDOW,
+            Self::Background => PER_WINDOW - RESERVED,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ApiBudget {
+    /// The times of the requests in the last [`WINDOW`], from the oldest.
+    requests: Mutex<VecDeque<Instant>>,
+}
+
+impl ApiBudget {
+    /// Take a request from the budget if one is available right away.
+    pub async fn try_acquire(&self, priority: Priority) -> bool {
+        let mut requests = self.requests.lock().await;
+        let now = Instant::now();
+        if delay(&mut requests, priority, now).is_zero() {
+            requests.push_back(now);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Wait until a request is available, and take it from the budget.
+    pub async fn acquire(&self, priority: Priority) {
+        loop {
+            let mut requests = self.requests.lock().await;
+            let now = Instant::now();
+            let delay = delay(&mut requests, priority, now);
+            if delay.is_zero() {
+                requests.push_back(now);
+                return;
+            }
+            // others may take the request meanwhile, so it's checked again.
+            drop(requests);
+            platform::sleep(delay).await;
+        }
+    }
+}
+
+/// How long to wait until a request of `priority` is allowed at `now` (after
+/// dropping the requests that left the window).
+fn delay(
+    requests: &mut VecDeque<Instant>,
+    priority: Priority,
+    now: Instant,
+) -> Duration {
+    while requests
+        .front()
+        .is_some_and(|&oldest| now.duration_since(oldest) >= WINDOW)
+    {
+        requests.pop_front();
+    }
+
+    let spacing = requests.back().map_or(Duration::ZERO, |&last| {
+        INTERVAL.saturating_sub(now.duration_since(last))
+    });
+    let limit = priority.limit();
+    let window = match requests.len().checked_sub(limit) {
+        // the request that has to leave the window for this one to fit.
+        Some(i) => WINDOW.saturating_sub(now.duration_since(requests[i])),
+        None => Duration::ZERO,
+    };
+
+    spacing.max(window)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        let mut requests = VecDeque::from([at(0)]);
+        assert_eq!(delay(&mut requests, Priority::Background, at(0)), INTERVAL);
+        assert!(delay(&mut requests, Priority::Background, at(1)).is_zero());
+
+        // the background requests leave the reserved part of the budget.
+        let mut requests = (0..(PER_WINDOW - RESERVED) as u64)
+            .map(at)
+            .collect::<VecDeque<_>>();
+        let now = at(30);
+        assert_eq!(
+            delay(&mut requests, Priority::Background, now),
+            Duration::from_secs(30)
+        );
+        assert!(delay(&mut requests, Priority::Interactive, now).is_zero());
+
+        // the requests that left the window are dropped.
+        assert!(delay(&mut requests, Priority::Background, at(60)).is_zero());
+        assert_eq!(requests.len(), PER_WINDOW - RESERVED - 1);
+    }
+}
@@ -0,0 +1,428 @@
+//! The registries cargo is configured with, so the dependencies with
+//! `registry = "<name>"` are fetched from their own index (with the token of
+//! `cargo login`, for private registries), and its `[http]` settings, so the
+//! registries are reached wherever cargo reaches them.
+//!
+//! Like cargo, the `.cargo/config.toml` files of the manifest's directory
+//! and its ancestors are read (closer ones take precedence), then the one in
+//! `CARGO_HOME` and its `credentials.toml`, and the environment variables
+//! (e.g. `CARGO_REGISTRIES_<NAME>_INDEX` and `CARGO_HTTP_PROXY`) take
+//! precedence over all of them (see [Cargo's docs]).
+//!
+//! [Cargo's docs]: https://doc.rust-lang.org/cargo/reference/config.html
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use taplo::dom::{Node, node::IntegerValue};
+use url::Url;
+
+use crate::path;
+
+const CONFIG_DIR: &str = ".cargo";
+/// The names of the configuration file, the latter being the legacy one
+/// (which cargo still reads when there's no `config.toml`).
+const CONFIG_FILES: &[&str] = &["config.toml", "config"];
+/// The names of the file `cargo login` writes the tokens to, in
+/// `CARGO_HOME`.
+const CREDENTIALS_FILES: &[&str] = &["credentials.toml", "credentials"];
+
+const REGISTRIES_KEY: &str = "registries";
+const INDEX_KEY: &str = "index";
+const TOKEN_KEY: &str = "token";
+const HTTP_KEY: &str = "http";
+const PROXY_KEY: &str = "proxy";
+const CAINFO_KEY: &str = "cainfo";
+const TIMEOUT_KEY: &str = "timeout";
+
+/// The protocol prefix of the URLs of sparse indexes. Other indexes (i.e.
+/// git ones) aren't supported.
+const SPARSE_PREFIX: &str = "sparse+";
+
+/// The registries of a cargo configuration.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CargoConfig {
+    /// The alternative registries, by name.
+    registries: HashMap<String, Registry>,
+    pub http: HttpConfig,
+}
+
+/// A `[registries.<name>]` table.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Registry {
+    index: Option<String>,
+    token: Option<Token>,
+}
+
+/// The token of a registry, which is never printed (e.g. in logs).
+#[derive(Clone, PartialEq, Eq)]
+pub struct Token(String);
+
+impl Token {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Token(..)")
+    }
+}
+
+/// The `[http]` settings of a cargo configuration.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HttpConfig {
+    /// The proxy the requests go through (e.g. `host:port`). Without it, the
+    /// `HTTPS_PROXY` and `HTTP_PROXY` environment variables are used, as
+    /// cargo does.
+    pub proxy: Option<String>,
+    /// A bundle of CA certificates (in PEM) to trust, besides the system's.
+    pub cainfo: Option<PathBuf>,
+    /// The seconds a request may take.
+    pub timeout: Option<u64>,
+}
+
+impl CargoConfig {
+    /// Load the configuration that applies to the manifest at `manifest`.
+    pub fn load(manifest: &Url) -> Self {
+        let manifest = path::to_path(manifest);
+        Self::load_dir(manifest.as_deref().and_then(Path::parent))
+    }
+
+    /// Load the configuration that applies in `dir`, or only the one of
+    /// `CARGO_HOME` (and the environment) without a directory.
+    pub fn load_dir(dir: Option<&Path>) -> Self {
+        let mut config = Self::default();
+        let credentials = cargo_home().and_then(|home| {
+            let mut files = CREDENTIALS_FILES.iter().map(|f| home.join(f));
+            files.find(|file| file.is_file())
+        });
+        for file in config_files(dir).into_iter().chain(credentials) {
+            // relative paths are relative to the directory of `.cargo`.
+            let base = file.parent().and_then(Path::parent);
+            if let Ok(s) = std::fs::read_to_string(&file) {
+                config.merge(&s, base.unwrap_or(Path::new("")));
+            }
+        }
+        config.merge_env(std::env::vars());
+        config
+    }
+
+    /// Add the settings of a configuration file whose paths are relative to
+    /// `base`, unless they're already set (by a file that takes precedence).
+    fn merge(&mut self, s: &str, base: &Path) {
+        let dom = taplo::parser::parse(s).into_dom();
+
+        let registries = dom.get(REGISTRIES_KEY);
+        let registries = registries.as_table().map(|r| r.entries().read());
+        for (name, table) in registries.iter().flat_map(|r| r.iter()) {
+            let string =
+                |key| table.get(key).as_str().map(|s| s.value().to_owned());
+            let registry =
+                self.registries.entry(name.value().to_owned()).or_default();
+            if registry.index.is_none() {
+                registry.index = string(INDEX_KEY);
+            }
+            if registry.token.is_none() {
+                registry.token = string(TOKEN_KEY).map(Token);
+            }
+        }
+
+        let http = dom.get(HTTP_KEY);
+        let string = |key| http.get(key).as_str().map(|s| s.value().to_owned());
+        if self.http.proxy.is_none() {
+            self.http.proxy = string(PROXY_KEY);
+        }
+        if self.http.cainfo.is_none() {
+            self.http.cainfo = string(CAINFO_KEY).map(|path| base.join(path));
+        }
+        if self.http.timeout.is_none()
+            && let Node::Integer(timeout) = http.get(TIMEOUT_KEY)
+            && let IntegerValue::Positive(timeout) = timeout.value()
+        {
+            self.http.timeout = Some(timeout);
+        }
+    }
+
+    /// Override the settings with the environment variables (e.g.
+    /// `CARGO_REGISTRIES_<NAME>_INDEX`).
+    fn merge_env(&mut self, vars: impl IntoIterator<Item = (String, String)>) {
+        let mut vars = vars.into_iter().collect::<HashMap<_, _>>();
+        if let Some(proxy) = vars.remove("CARGO_HTTP_PROXY") {
+            self.http.proxy = Some(proxy);
+        }
+        if let Some(cainfo) = vars.remove("CARGO_HTTP_CAINFO") {
+            self.http.cainfo = Some(PathBuf::from(cainfo));
+        }
+        if let Some(timeout) = vars.remove("CARGO_HTTP_TIMEOUT")
+            && let Ok(timeout) = timeout.parse()
+        {
+            self.http.timeout = Some(timeout);
+        }
+
+        for (var, value) in vars {
+            let Some(var) = var.strip_prefix("CARGO_REGISTRIES_") else {
+                continue;
+            };
+            let (name, key) = if let Some(name) = var.strip_suffix("_INDEX") {
+                (name, INDEX_KEY)
+            } else if let Some(name) = var.strip_suffix("_TOKEN") {
+                (name, TOKEN_KEY)
+            } else {
+                continue;
+            };
+            // the registries that are only configured by the environment are
+            // only known by their names in uppercase, which cargo matches
+            // too.
+            let name = self
+                .registries
+                .keys()
+                .find(|n| env_name(n) == name)
+                .map_or_else(|| name.to_owned(), String::clone);
+            let registry = self.registries.entry(name).or_default();
+            match key {
+                INDEX_KEY => registry.index = Some(value),
+                _ => registry.token = Some(Token(value)),
+            }
+        }
+    }
+
+    /// The registry `name`, as it's configured or by the name of its
+    /// environment variables.
+    fn registry(&self, name: &str) -> Option<&Registry> {
+        self.registries.get(name).or_else(|| {
+            let env = env_name(name);
+            let mut registries = self.registries.iter();
+            registries.find(|(n, _)| env_name(n) == env).map(|(_, r)| r)
+        })
+    }
+
+    /// The URL of the sparse index of the registry `name` (without the
+    /// `sparse+` prefix, nor a trailing slash), or `None` if it isn't
+    /// configured, or its index isn't sparse.
+    pub fn sparse_index(&self, name: &str) -> Option<&str> {
+        let index = self.registry(name)?.index.as_ref()?;
+        let index = index.strip_prefix(SPARSE_PREFIX)?;
+        Some(index.trim_end_matches('/'))
+    }
+
+    /// The URL of the (legacy) git index of the registry `name` (without a
+    /// trailing slash), or `None` if it isn't configured, or its index is
+    /// sparse.
+    pub fn git_index(&self, name: &str) -> Option<&str> {
+        let index = self.registry(name)?.index.as_ref()?;
+        if index.starts_with(SPARSE_PREFIX) {
+            return None;
+        }
+        Some(index.trim_end_matches('/'))
+    }
+
+    /// The token of the registry `name` (e.g. from `cargo login`), if it has
+    /// one.
+    pub fn token(&self, name: &str) -> Option<&Token> {
+        self.registry(name)?.token.as_ref()
+    }
+}
+
+/// The name of the registry `name` in its environment variables (e.g.
+/// `MY_COMPANY` in `CARGO_REGISTRIES_MY_COMPANY_INDEX`).
+fn env_name(name: &str) -> String {
+    name.to_ascii_uppercase().replace('-', "_")
+}
+
+/// The configuration files that apply in `dir`, from the one that takes
+/// precedence.
+fn config_files(dir: Option<&Path>) -> Vec<PathBuf> {
+    let home = cargo_home();
+    let dirs = dir
+        .into_iter()
+        .flat_map(Path::ancestors)
+        .map(|dir| dir.join(CONFIG_DIR))
+        .filter(|dir| home.as_ref() != Some(dir))
+        .chain(home.clone());
+
+    dirs.filter_map(|dir| {
+        CONFIG_FILES
+            .iter()
+            .map(|file| dir.join(file))
+            .find(|file| file.is_file())
+    })
+    .collect()
+}
+
+/// The directory of cargo's own files: `CARGO_HOME`, or `~/.cargo`.
+fn cargo_home() -> Option<PathBuf> {
+    if let Some(home) = std::env::var_os("CARGO_HOME") {
+        return Some(PathBuf::from(home));
+    }
+    let home = std::env::var_os("HOME").or_else(|| {
+        // Windows
+        std::env::var_os("USERPROFILE")
+    })?;
+    Some(PathBuf::from(home).join(CONFIG_DIR))
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    #[test]
+    fn test_merge() {
+        let mut config = CargoConfig::default();
+        config.merge(
+            indoc! {r#"
+            [registries.my-company]
+            index = "sparse+https://registry.example.com/index/"
+
+            [registries.git-registry]
+            index = "https://example.com/git-index"
+        "#},
+            Path::new("/project"),
+        );
+        config.merge(
+            indoc! {r#"
+            [registries]
+            my-company = { index = "sparse+https://ignored.example.com/" }
+            other = { index = "sparse+https://other.example.com" }
+        "#},
+            Path::new("/"),
+        );
+
+        assert_eq!(
+            config.sparse_index("my-company"),
+            Some("https://registry.example.com/index")
+        );
+        assert_eq!(
+            config.sparse_index("other"),
+            Some("https://other.example.com")
+        );
+        assert_eq!(config.sparse_index("git-registry"), None);
+        assert_eq!(config.sparse_index("missing"), None);
+        assert_eq!(
+            config.git_index("git-registry"),
+            Some("https://example.com/git-index")
+        );
+        assert_eq!(config.git_index("my-company"), None);
+    }
+
+    #[test]
+    fn test_merge_env() {
+        let mut config = CargoConfig::default();
+        config.merge(
+            indoc! {r#"
+            [registries.my-company]
+            index = "sparse+https://registry.example.com/"
+        "#},
+            Path::new("/project"),
+        );
+        config.merge_env([
+            (
+                "CARGO_REGISTRIES_MY_COMPANY_INDEX".to_owned(),
+                "sparse+https://mirror.example.com/".to_owned(),
+            ),
+            (
+                "CARGO_REGISTRIES_INTERNAL_INDEX".to_owned(),
+                "sparse+https://internal.example.com/".to_owned(),
+            ),
+            ("CARGO_HOME".to_owned(), "/cargo".to_owned()),
+        ]);
+
+        assert_eq!(
+            config.sparse_index("my-company"),
+            Some("https://mirror.example.com")
+        );
+        assert_eq!(
+            config.sparse_index("internal"),
+            Some("https://internal.example.com")
+        );
+        assert_eq!(config.registries.len(), 2);
+    }
+
+    #[test]
+    fn test_token() {
+        let mut config = CargoConfig::default();
+        config.merge(
+            indoc! {r#"
+                [registries.my-company]
+                index = "sparse+https://registry.example.com/"
+            "#},
+            Path::new("/project"),
+        );
+        // `credentials.toml`
+        config.merge(
+            indoc! {r#"
+                [registry]
+                token = "crates-io-token"
+
+                [registries.my-company]
+                token = "secret"
+            "#},
+            Path::new("/home/user"),
+        );
+        assert_eq!(
+            config.token("my-company").map(Token::as_str),
+            Some("secret")
+        );
+        assert_eq!(
+            format!("{:?}", config.token("my-company")),
+            "Some(Token(..))"
+        );
+
+        config.merge_env([(
+            "CARGO_REGISTRIES_MY_COMPANY_TOKEN".to_owned(),
+            "other-secret".to_owned(),
+        )]);
+        assert_eq!(
+            config.token("my-company").map(Token::as_str),
+            Some("other-secret")
+        );
+        assert_eq!(
+            config.sparse_index("my-company"),
+            Some("https://registry.example.com")
+        );
+    }
+
+    #[test]
+    fn test_merge_http() {
+        let mut config = CargoConfig::default();
+        config.merge(
+            indoc! {r#"
+                [http]
+                cainfo = "certs/ca.pem"
+                timeout = 60
+            "#},
+            Path::new("/project"),
+        );
+        config.merge(
+            indoc! {r#"
+                [http]
+                proxy = "proxy.example.com:8080"
+                timeout = 5
+            "#},
+            Path::new("/home/user"),
+        );
+        assert_eq!(
+            config.http,
+            HttpConfig {
+                proxy: Some("proxy.example.com:8080".to_owned()),
+                cainfo: Some(PathBuf::from("/project/certs/ca.pem")),
+                timeout: Some(60),
+            }
+        );
+
+        config.merge_env([
+            ("CARGO_HTTP_TIMEOUT".to_owned(), "10".to_owned()),
+            (
+                "CARGO_HTTP_PROXY".to_owned(),
+                "other.example.com".to_owned(),
+            ),
+        ]);
+        assert_eq!(config.http.timeout, Some(10));
+        assert_eq!(config.http.proxy.as_deref(), Some("other.example.com"));
+    }
+}
@@ -0,0 +1,379 @@
+//! The commands the server provides through `workspace/executeCommand`.
+
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use tower_lsp::{jsonrpc, lsp_types};
+use url::Url;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("unknown command `{0}`")]
+    Unknown(String),
+    #[error("invalid arguments for command `{command}`: {source}")]
+    Arguments {
+        command: &'static str,
+        source: serde_json::Error,
+    },
+    #[error("no dependency `{name}` in `{uri}`")]
+    NoDependency { name: String, uri: Url },
+    #[error("no dependency at {line}:{character} in `{uri}`")]
+    NoDependencyAt { uri: Url, line: u32, character: u32 },
+    #[error("no dependencies table `{table}` in `{uri}`")]
+    NoTable { table: String, uri: Url },
+    #[error("failed to fetch crate `{name}`")]
+    Fetch { name: String },
+    #[error("`cargo {args}` failed: {message}")]
+    Cargo { args: String, message: String },
+}
+
+impl From<Error> for jsonrpc::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Unknown(_) => Self {
+                code: jsonrpc::ErrorCode::MethodNotFound,
+                message: err.to_string().into(),
+                data: None,
+            },
+            Error::Arguments { .. } => Self::invalid_params(err.to_string()),
+            Error::NoDependency { .. }
+            | Error::NoDependencyAt { .. }
+            | Error::NoTable { .. }
+            | Error::Fetch { .. }
+            | Error::Cargo { .. } => Self {
+                code: jsonrpc::ErrorCode::InternalError,
+                message: err.to_string().into(),
+                data: None,
+            },
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Update a dependency's version requirement to the latest version.
+    LatestVersion(DependencyArgs),
+    /// Report what the crates cache holds.
+    CacheStats,
+    /// Drop the crates cache, so everything is fetched again.
+    CacheClear,
+    /// List the breaking upgrades of the dependencies of all of the open
+    /// manifests, and apply the selected ones.
+    UpgradeWorkspace(UpgradeWorkspaceArgs),
+    /// Update the outdated dependencies of a dependencies table to their
+    /// latest versions.
+    UpdateTable(TableArgs),
+    /// Sort the entries of a dependencies table by their keys.
+    SortTable(TableArgs),
+    /// Update a package in the lockfile of a manifest's workspace to the
+    /// latest version its dependents allow (`cargo update -p`).
+    UpdateLock(UpdateLockArgs),
+    /// Return the entry of the dependency at a position as a single line
+    /// (see [`crate::edit::dependency_spec`]).
+    CopyDependencySpec(PositionArgs),
+}
+
+impl Command {
+    pub const LATEST_VERSION: &str = "latest_version";
+    pub const CACHE_STATS: &str = "cache_stats";
+    pub const CACHE_CLEAR: &str = "cache_clear";
+    pub const UPGRADE_WORKSPACE: &str = "upgrade_workspace";
+    pub const UPDATE_TABLE: &str = "update_table";
+    pub const SORT_TABLE: &str = "sort_table";
+    pub const UPDATE_LOCK: &str = "update_lock";
+    pub const COPY_DEPENDENCY_SPEC: &str = "copy_dependency_spec";
+
+    /// The names of all of the commands, for the server's capabilities.
+    pub const ALL: &[&str] = &[
+        Self::LATEST_VERSION,
+        Self::CACHE_STATS,
+        Self::CACHE_CLEAR,
+        Self::UPGRADE_WORKSPACE,
+        Self::UPDATE_TABLE,
+        Self::SORT_TABLE,
+        Self::UPDATE_LOCK,
+        Self::COPY_DEPENDENCY_SPEC,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::LatestVersion(_) => Self::LATEST_VERSION,
+            Self::CacheStats => Self::CACHE_STATS,
+            Self::CacheClear => Self::CACHE_CLEAR,
+            Self::UpgradeWorkspace(_) => Self::UPGRADE_WORKSPACE,
+            Self::UpdateTable(_) => Self::UPDATE_TABLE,
+            Self::SortTable(_) => Self::SORT_TABLE,
+            Self::UpdateLock(_) => Self::UPDATE_LOCK,
+            Self::CopyDependencySpec(_) => Self::COPY_DEPENDENCY_SPEC,
+        }
+    }
+
+    /// Parse a command from the `workspace/executeCommand` params.
+    pub fn parse(
+        name: &str,
+        arguments: Vec<serde_json::Value>,
+    ) -> Result<Self> {
+        match name {
+            Self::LATEST_VERSION => {
+                parse_arguments(Self::LATEST_VERSION, arguments)
+                    .map(Self::LatestVersion)
+            }
+            // any arguments are ignored.
+            Self::CACHE_STATS => Ok(Self::CacheStats),
+            Self::CACHE_CLEAR => Ok(Self::CacheClear),
+            // without arguments, the upgrades are only listed.
+            Self::UPGRADE_WORKSPACE if arguments.is_empty() => {
+                Ok(Self::UpgradeWorkspace(UpgradeWorkspaceArgs::default()))
+            }
+            Self::UPGRADE_WORKSPACE => {
+                parse_arguments(Self::UPGRADE_WORKSPACE, arguments)
+                    .map(Self::UpgradeWorkspace)
+            }
+            Self::UPDATE_TABLE => {
+                parse_arguments(Self::UPDATE_TABLE, arguments)
+                    .map(Self::UpdateTable)
+            }
+            Self::SORT_TABLE => parse_arguments(Self::SORT_TABLE, arguments)
+                .map(Self::SortTable),
+            Self::UPDATE_LOCK => parse_arguments(Self::UPDATE_LOCK, arguments)
+                .map(Self::UpdateLock),
+            Self::COPY_DEPENDENCY_SPEC => {
+                parse_arguments(Self::COPY_DEPENDENCY_SPEC, arguments)
+                    .map(Self::CopyDependencySpec)
+            }
+            _ => Err(Error::Unknown(name.to_owned())),
+        }
+    }
+
+    /// Build the LSP command (e.g. for code actions).
+    pub fn into_lsp(self, title: String) -> lsp_types::Command {
+        let name = self.name().to_owned();
+        let arguments = match self {
+            Self::LatestVersion(args) => serde_json::to_value(args).ok(),
+            Self::UpgradeWorkspace(args) => serde_json::to_value(args).ok(),
+            Self::UpdateTable(args) | Self::SortTable(args) => {
+                serde_json::to_value(args).ok()
+            }
+            Self::UpdateLock(args) => serde_json::to_value(args).ok(),
+            Self::CopyDependencySpec(args) => serde_json::to_value(args).ok(),
+            Self::CacheStats | Self::CacheClear => None,
+        };
+        lsp_types::Command::new(title, name, arguments.map(|a| vec![a]))
+    }
+}
+
+/// Arguments of commands that act on a single dependency in a manifest.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DependencyArgs {
+    pub name: String,
+    pub uri: Url,
+}
+
+/// Arguments of commands that act on a dependencies table in a manifest.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TableArgs {
+    pub uri: Url,
+    /// The key segments of the table's header, e.g.
+    /// `["target", "cfg(unix)", "dependencies"]`.
+    pub table: Vec<String>,
+}
+
+/// Arguments of commands that act on the dependency at a position in a
+/// manifest.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PositionArgs {
+    pub uri: Url,
+    pub position: lsp_types::Position,
+}
+
+/// The result of the `copy_dependency_spec` command.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DependencySpec {
+    pub name: String,
+    /// The entry, e.g. `serde = { version = "1", features = ["derive"] }`.
+    pub spec: String,
+}
+
+/// Arguments of the `update_lock` command.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UpdateLockArgs {
+    /// A manifest of the workspace.
+    pub uri: Url,
+    /// The package to update, as cargo's `-p` takes it (e.g. `serde`, or
+    /// `serde@1.0.100` when several versions of it are locked).
+    pub package: String,
+}
+
+/// Arguments of the `upgrade_workspace` command.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UpgradeWorkspaceArgs {
+    /// The dependencies to upgrade (all of their entries in their manifest),
+    /// or `None` to only list the upgrades.
+    pub selected: Option<Vec<DependencyArgs>>,
+}
+
+/// A breaking upgrade of a dependency, as listed by the `upgrade_workspace`
+/// command.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Upgrade {
+    pub uri: Url,
+    pub name: String,
+    /// The version requirement, as written in the manifest.
+    pub current: String,
+    pub latest: String,
+}
+
+impl Upgrade {
+    pub fn is_selected(&self, args: &UpgradeWorkspaceArgs) -> bool {
+        args.selected.as_ref().is_none_or(|selected| {
+            selected
+                .iter()
+                .any(|d| d.uri == self.uri && d.name == self.name)
+        })
+    }
+}
+
+/// Parse the arguments of a command, which are either a single object (as
+/// built by [`Command::into_lsp`]), or positional.
+fn parse_arguments<T: DeserializeOwned>(
+    command: &'static str,
+    arguments: Vec<serde_json::Value>,
+) -> Result<T> {
+    let arguments = match <[_; 1]>::try_from(arguments) {
+        Ok([object @ serde_json::Value::Object(_)]) => object,
+        Ok([positional]) => serde_json::Value::Array(vec![positional]),
+        Err(positional) => serde_json::Value::Array(positional),
+    };
+    serde_json::from_value(arguments)
+        .map_err(|source| Error::Arguments { command, source })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let expected = Command::LatestVersion(DependencyArgs {
+            name: "serde".to_owned(),
+            uri: Url::parse("file:///Cargo.toml").unwrap(),
+        });
+
+        let object = json!({ "name": "serde", "uri": "file:///Cargo.toml" });
+        assert_eq!(
+            Command::parse(Command::LATEST_VERSION, vec![object]).unwrap(),
+            expected
+        );
+
+        let positional = vec![json!("serde"), json!("file:///Cargo.toml")];
+        assert_eq!(
+            Command::parse(Command::LATEST_VERSION, positional).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(matches!(
+            Command::parse("no_such_command", vec![]),
+            Err(Error::Unknown(_))
+        ));
+        assert!(matches!(
+            Command::parse(Command::LATEST_VERSION, vec![json!("serde")]),
+            Err(Error::Arguments { .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_without_arguments() {
+        assert_eq!(
+            Command::parse(Command::CACHE_STATS, vec![]).unwrap(),
+            Command::CacheStats
+        );
+        assert_eq!(
+            Command::parse(Command::CACHE_CLEAR, vec![]).unwrap(),
+            Command::CacheClear
+        );
+
+        let lsp = Command::CacheClear.into_lsp("Clear cache".to_owned());
+        assert_eq!(lsp.arguments, None);
+    }
+
+    #[test]
+    fn test_parse_upgrade_workspace() {
+        assert_eq!(
+            Command::parse(Command::UPGRADE_WORKSPACE, vec![]).unwrap(),
+            Command::UpgradeWorkspace(UpgradeWorkspaceArgs { selected: None })
+        );
+
+        let selected = json!({
+            "selected": [{ "name": "serde", "uri": "file:///Cargo.toml" }]
+        });
+        let Command::UpgradeWorkspace(args) =
+            Command::parse(Command::UPGRADE_WORKSPACE, vec![selected]).unwrap()
+        else {
+            panic!("not an `upgrade_workspace` command");
+        };
+        let upgrade = |name: &str| Upgrade {
+            uri: Url::parse("file:///Cargo.toml").unwrap(),
+            name: name.to_owned(),
+            current: "1".to_owned(),
+            latest: "2.0.0".to_owned(),
+        };
+        assert!(upgrade("serde").is_selected(&args));
+        assert!(!upgrade("tokio").is_selected(&args));
+    }
+
+    #[test]
+    fn test_parse_table() {
+        let expected = Command::SortTable(TableArgs {
+            uri: Url::parse("file:///Cargo.toml").unwrap(),
+            table: vec!["dev-dependencies".to_owned()],
+        });
+        let object = json!({
+            "uri": "file:///Cargo.toml",
+            "table": ["dev-dependencies"],
+        });
+        assert_eq!(
+            Command::parse(Command::SORT_TABLE, vec![object]).unwrap(),
+            expected
+        );
+        assert!(matches!(
+            Command::parse(Command::UPDATE_TABLE, vec![]),
+            Err(Error::Arguments { .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_position() {
+        let object = json!({
+            "uri": "file:///Cargo.toml",
+            "position": { "line": 3, "character": 5 },
+        });
+        assert_eq!(
+            Command::parse(Command::COPY_DEPENDENCY_SPEC, vec![object])
+                .unwrap(),
+            Command::CopyDependencySpec(PositionArgs {
+                uri: Url::parse("file:///Cargo.toml").unwrap(),
+                position: lsp_types::Position::new(3, 5),
+            })
+        );
+    }
+
+    #[test]
+    fn test_into_lsp_roundtrip() {
+        let command = Command::LatestVersion(DependencyArgs {
+            name: "serde".to_owned(),
+            uri: Url::parse("file:///Cargo.toml").unwrap(),
+        });
+
+        let lsp = command.clone().into_lsp("Latest version".to_owned());
+        assert_eq!(lsp.command, Command::LATEST_VERSION);
+        assert_eq!(
+            Command::parse(&lsp.command, lsp.arguments.unwrap()).unwrap(),
+            command
+        );
+    }
+}
@@ -0,0 +1,391 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::Deserialize;
+use tower_lsp::lsp_types::DiagnosticSeverity;
+
+use crate::version::Behind;
+
+/// User settings, received from the client either as the
+/// `initializationOptions` or through `workspace/didChangeConfiguration`.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Settings {
+    /// Treat every document as a Cargo manifest, even if it doesn't look
+    /// like one.
+    pub force_enable: bool,
+    /// The severities of the outdated version hints, by how far behind the
+    /// declared versions are.
+    pub outdated_severity: OutdatedSeverity,
+    /// When to escalate the outdated version hints to warnings.
+    pub outdated_warning: OutdatedWarning,
+    /// What goto definition on a dependency name does with its docs.
+    pub goto_definition: GotoDefinition,
+    /// Lint the `[package]` table for what `cargo publish` requires.
+    pub publish_lints: bool,
+    /// Let pre-releases be the latest versions of crates.
+    pub prereleases: bool,
+    /// Limits on the cache of fetched crates.
+    pub cache: CacheSettings,
+    /// The timeouts of the requests to the registry.
+    pub http: HttpSettings,
+    /// The use of the crates.io web API.
+    pub api: ApiSettings,
+    /// What the hovers show.
+    pub hover: HoverSettings,
+    /// The alternative registries (as declared in `.cargo/config.toml`)
+    /// whose crate names are completed, by their names.
+    pub registries: HashMap<String, RegistrySettings>,
+    /// The registry of the dependencies without a `registry` key, if it's
+    /// not crates.io (as `registry.default` in `.cargo/config.toml`).
+    pub default_registry: Option<String>,
+    /// The locale of the messages (e.g. `en`), instead of the client's.
+    pub locale: Option<String>,
+    /// The milliseconds the diagnostics of a changed document wait for the
+    /// next change, so only the last of rapid edits is analysed.
+    pub debounce: Option<u64>,
+    /// Log a summary of the metrics (see `crates/metrics`) at most once per
+    /// this many seconds.
+    pub metrics_log_interval: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegistrySettings {
+    /// The base URL of the registry's web API (the `api` of its
+    /// `config.json`), e.g. `https://registry.example.com`.
+    pub api: String,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CacheSettings {
+    /// The maximal number of cached crates, after which the least recently
+    /// fetched ones are evicted.
+    pub max_crates: Option<usize>,
+    /// The seconds after which a cached crate is fetched again, to find
+    /// its new releases.
+    pub ttl: Option<u64>,
+    /// Warm the cache with the crates of the workspace's `Cargo.lock` on
+    /// startup.
+    pub prefetch_lockfile: bool,
+    /// Cache the fetched index files on disk, across restarts.
+    pub disk: bool,
+    /// The seconds a file of the disk cache is used without asking the
+    /// registry whether it changed.
+    pub disk_ttl: u64,
+    /// The megabytes the files of the disk cache may take, past which the
+    /// least recently used ones are removed.
+    pub disk_max_size: Option<u64>,
+    /// Fetch the git indexes of the alternative registries that don't have
+    /// a sparse one into the disk cache.
+    pub git_index: bool,
+}
+
+impl Default for CacheSettings {
+    fn default() -> Self {
+        Self {
+            max_crates: None,
+            ttl: None,
+            prefetch_lockfile: true,
+            disk: true,
+            disk_ttl: 60 * 60,
+            disk_max_size: None,
+            git_index: false,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct HttpSettings {
+    /// The seconds a request to the index may take.
+    pub index_timeout: Option<u64>,
+    /// The seconds a request to the web API may take.
+    pub api_timeout: Option<u64>,
+    /// The maximal number of crates fetched at once (e.g. when a manifest
+    /// is opened).
+    pub concurrency: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ApiSettings {
+    /// Use the crates.io web API (for descriptions, downloads and search),
+    /// rather than only the index (e.g. where the API isn't reachable).
+    pub enabled: bool,
+}
+
+impl Default for ApiSettings {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct HoverSettings {
+    /// The sections of the hover of a dependency name, in the order they are
+    /// shown. Sections that are left out are hidden.
+    pub sections: Vec<HoverSection>,
+}
+
+impl Default for HoverSettings {
+    fn default() -> Self {
+        Self { sections: HoverSection::ALL.to_vec() }
+    }
+}
+
+/// A section of the hover of a dependency name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum HoverSection {
+    /// The latest version (and the latest stable one, if it differs).
+    Version,
+    /// The version `Cargo.lock` pins, and how far it's behind the latest
+    /// release that the requirement allows.
+    Lock,
+    /// What the declaration of the dependency means (e.g. that it's an
+    /// optional dev-dependency).
+    Declaration,
+    /// The native library the crate links (its `links` key), if any.
+    Native,
+    /// The registry the info is from.
+    Registry,
+    /// The docs, repository and homepage of the crate.
+    Links,
+    /// The recent downloads.
+    Downloads,
+    /// The number of direct dependencies, and the size of the `.crate` file.
+    Size,
+    /// Who published the latest version.
+    Publisher,
+    Description,
+    /// The features of the latest version.
+    Features,
+    /// The beginning of the README of the latest version.
+    Readme,
+}
+
+impl HoverSection {
+    /// All of the sections, in their default order.
+    pub const ALL: &[Self] = &[
+        Self::Version,
+        Self::Lock,
+        Self::Declaration,
+        Self::Native,
+        Self::Registry,
+        Self::Links,
+        Self::Downloads,
+        Self::Size,
+        Self::Publisher,
+        Self::Description,
+        Self::Features,
+        Self::Readme,
+    ];
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum GotoDefinition {
+    /// Use [`Self::ShowDocument`] for clients that are known to not follow
+    /// HTTP locations, and [`Self::Location`] for all others.
+    #[default]
+    Auto,
+    /// Respond with the docs URL as the location of the definition.
+    Location,
+    /// Ask the client to open the docs using `window/showDocument`.
+    ///
+    /// Falls back to [`Self::Message`] if the client doesn't support it.
+    ShowDocument,
+    /// Show a hover-like popup message with the docs URL, without opening
+    /// anything (e.g. when working over SSH).
+    Message,
+    /// Do nothing.
+    Disabled,
+}
+
+impl Settings {
+    /// Parse the settings from the client, falling back to the defaults for
+    /// missing (or `null`) settings.
+    pub fn from_value(
+        value: Option<serde_json::Value>,
+    ) -> serde_json::Result<Self> {
+        match value {
+            Some(serde_json::Value::Null) | None => Ok(Self::default()),
+            Some(value) => serde_json::from_value(value),
+        }
+    }
+}
+
+/// The severities of the outdated version hints, so the riskier staleness
+/// comes first in the problems list of the editor.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct OutdatedSeverity {
+    /// The severity of the declared versions that are only patch releases
+    /// behind the latest.
+    pub patch: Severity,
+    /// The severity of the declared versions that are compatible minor
+    /// releases behind the latest.
+    pub minor: Severity,
+    /// The severity of the declared versions that are behind the latest by
+    /// a breaking release.
+    pub major: Severity,
+}
+
+impl Default for OutdatedSeverity {
+    fn default() -> Self {
+        Self {
+            patch: Severity::Hint,
+            minor: Severity::Information,
+            major: Severity::Warning,
+        }
+    }
+}
+
+impl OutdatedSeverity {
+    pub fn get(&self, behind: Behind) -> Severity {
+        match behind {
+            Behind::Patch => self.patch,
+            Behind::Minor => self.minor,
+            Behind::Major => self.major,
+        }
+    }
+}
+
+/// The severity of a diagnostic, from the least severe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Severity {
+    Hint,
+    Information,
+    Warning,
+    Error,
+}
+
+impl From<Severity> for DiagnosticSeverity {
+    fn from(severity: Severity) -> Self {
+        match severity {
+            Severity::Hint => Self::HINT,
+            Severity::Information => Self::INFORMATION,
+            Severity::Warning => Self::WARNING,
+            Severity::Error => Self::ERROR,
+        }
+    }
+}
+
+/// An opt-in policy for escalating outdated version hints to warnings, for
+/// teams that want to enforce freshness budgets.
+///
+/// A dependency is escalated if it exceeds any of the (set) thresholds.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct OutdatedWarning {
+    /// The maximal number of major versions the declared version may be
+    /// behind the latest version.
+    pub majors_behind: Option<u64>,
+    /// The maximal number of years between the release of the declared
+    /// version and the release of the latest version.
+    ///
+    /// This requires the release dates from the crates.io API, so it's only
+    /// applied once they are fetched.
+    pub years_behind: Option<f64>,
+}
+
+impl OutdatedWarning {
+    const YEAR: TimeDelta = TimeDelta::days(365);
+
+    /// Checks whether the `current` version (released at `current_date`) is
+    /// too far behind the `latest` version (released at `latest_date`).
+    pub fn is_exceeded(
+        &self,
+        (current, current_date): (&semver::Version, Option<DateTime<Utc>>),
+        (latest, latest_date): (&semver::Version, Option<DateTime<Utc>>),
+    ) -> bool {
+        let majors_exceeded = self.majors_behind.is_some_and(|max| {
+            latest.major.saturating_sub(current.major) > max
+        });
+
+        let years_exceeded = if let Some(max) = self.years_behind
+            && let Some(current_date) = current_date
+            && let Some(latest_date) = latest_date
+        {
+            let behind = latest_date - current_date;
+            behind.as_seconds_f64() / Self::YEAR.as_seconds_f64() > max
+        } else {
+            false
+        };
+
+        majors_exceeded || years_exceeded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hover_sections() {
+        let settings = Settings::from_value(None).unwrap();
+        assert_eq!(settings.hover.sections, HoverSection::ALL);
+
+        let value = serde_json::json!({
+            "hover": { "sections": ["description", "version"] }
+        });
+        let settings = Settings::from_value(Some(value)).unwrap();
+        assert_eq!(
+            settings.hover.sections,
+            [HoverSection::Description, HoverSection::Version]
+        );
+
+        let value =
+            serde_json::json!({ "hover": { "sections": ["changelog"] } });
+        assert!(Settings::from_value(Some(value)).is_err());
+    }
+
+    #[test]
+    fn test_outdated_severity() {
+        let settings = Settings::from_value(None).unwrap();
+        let severity = settings.outdated_severity;
+        assert_eq!(severity.get(Behind::Patch), Severity::Hint);
+        assert_eq!(severity.get(Behind::Minor), Severity::Information);
+        assert_eq!(severity.get(Behind::Major), Severity::Warning);
+
+        let value = serde_json::json!({
+            "outdatedSeverity": { "patch": "information", "major": "error" }
+        });
+        let settings = Settings::from_value(Some(value)).unwrap();
+        let severity = settings.outdated_severity;
+        assert_eq!(severity.get(Behind::Patch), Severity::Information);
+        assert_eq!(severity.get(Behind::Minor), Severity::Information);
+        assert_eq!(severity.get(Behind::Major), Severity::Error);
+
+        let value = serde_json::json!({ "outdatedSeverity": { "patch": 1 } });
+        assert!(Settings::from_value(Some(value)).is_err());
+    }
+
+    #[test]
+    fn test_outdated_warning() {
+        let date = |s: &str| Some(s.parse::<DateTime<Utc>>().unwrap());
+        let (v1, v3) =
+            (semver::Version::new(1, 0, 0), semver::Version::new(3, 0, 0));
+        let (old, new) =
+            (date("2020-01-01T00:00:00Z"), date("2024-01-01T00:00:00Z"));
+
+        let disabled = OutdatedWarning::default();
+        assert!(!disabled.is_exceeded((&v1, old), (&v3, new)));
+
+        let majors =
+            OutdatedWarning { majors_behind: Some(1), years_behind: None };
+        assert!(majors.is_exceeded((&v1, None), (&v3, None)));
+        assert!(!majors.is_exceeded((&v3, None), (&v3, None)));
+
+        let years =
+            OutdatedWarning { majors_behind: None, years_behind: Some(3.5) };
+        assert!(years.is_exceeded((&v3, old), (&v3, new)));
+        assert!(!years.is_exceeded((&v3, new), (&v3, new)));
+        // unknown release dates are never escalated
+        assert!(!years.is_exceeded((&v1, None), (&v3, new)));
+    }
+}
@@ -0,0 +1,959 @@
+//! Resolving what's under the cursor, for all of the position-sensitive
+//! handlers (completion, hover, goto, code actions).
+
+use taplo::{
+    rowan::{Direction, TextSize},
+    syntax::{SyntaxKind, SyntaxNode, SyntaxToken},
+};
+use tower_lsp::lsp_types::{Position, Range};
+
+use crate::{
+    index::Field,
+    parse::{
+        self, DEPENDENCIES_KEYS, Dependency, DependencyKind, Manifest, Section,
+        TARGET_KEY,
+    },
+    target::{Target, TargetKind},
+};
+
+/// The part of a manifest the cursor is at.
+#[derive(Debug, Clone, Copy)]
+pub enum Context<'a> {
+    /// The name (key) of a dependency.
+    DependencyName(&'a Dependency),
+    /// The version requirement of a dependency.
+    VersionValue(&'a Dependency),
+    /// The feature at `index` in the `features` array of a dependency.
+    FeatureString {
+        dependency: &'a Dependency,
+        index: usize,
+    },
+    /// The `git` URL of a dependency.
+    GitUrl(&'a Dependency),
+    /// The `branch`, `tag` or `rev` of a git dependency.
+    GitSpecifier(&'a Dependency),
+    /// The `path` of a local dependency.
+    PathValue(&'a Dependency),
+    /// The `registry` of a dependency.
+    RegistryValue(&'a Dependency),
+    /// The `package` of a renamed dependency, i.e. the name of its crate.
+    PackageValue(&'a Dependency),
+    /// The `optional` flag of a dependency.
+    OptionalValue(&'a Dependency),
+    /// The `default-features` flag of a dependency.
+    DefaultFeaturesValue(&'a Dependency),
+    /// The `workspace` flag of a dependency.
+    WorkspaceValue(&'a Dependency),
+    /// A key of the table of a dependency, e.g. `version` on its own line
+    /// under `[dependencies.foo]`.
+    DependencyKey(&'a Dependency),
+}
+
+impl<'a> Context<'a> {
+    /// Resolve the context at `pos`, or `None` if the cursor isn't at
+    /// anything the server knows about.
+    pub fn resolve(manifest: &'a Manifest, pos: Position) -> Option<Self> {
+        let (dependency, field) = manifest.find(pos)?;
+
+        Some(match field {
+            Field::Name => Self::DependencyName(dependency),
+            Field::Version => Self::VersionValue(dependency),
+            Field::Feature(index) => Self::FeatureString { dependency, index },
+            Field::GitUrl => Self::GitUrl(dependency),
+            Field::GitSpecifier => Self::GitSpecifier(dependency),
+            Field::Path => Self::PathValue(dependency),
+            Field::Registry => Self::RegistryValue(dependency),
+            Field::Package => Self::PackageValue(dependency),
+            Field::Optional => Self::OptionalValue(dependency),
+            Field::DefaultFeatures => Self::DefaultFeaturesValue(dependency),
+            Field::Workspace => Self::WorkspaceValue(dependency),
+            Field::Key => Self::DependencyKey(dependency),
+        })
+    }
+
+    /// The dependency the context is a part of.
+    pub fn dependency(&self) -> &'a Dependency {
+        match *self {
+            Self::DependencyName(dependency)
+            | Self::VersionValue(dependency)
+            | Self::FeatureString { dependency, .. }
+            | Self::GitUrl(dependency)
+            | Self::GitSpecifier(dependency)
+            | Self::PathValue(dependency)
+            | Self::RegistryValue(dependency)
+            | Self::PackageValue(dependency)
+            | Self::OptionalValue(dependency)
+            | Self::DefaultFeaturesValue(dependency)
+            | Self::WorkspaceValue(dependency)
+            | Self::DependencyKey(dependency) => dependency,
+        }
+    }
+}
+
+/// A feature string that is still being typed (e.g. `features = ["`).
+///
+/// It's found from the syntax tree, as the DOM (and thus the [`Manifest`])
+/// doesn't have it until both the string and the array are complete.
+#[derive(Debug, PartialEq, Eq)]
+pub struct IncompleteFeature {
+    /// The name of the dependency.
+    pub dependency: String,
+    /// The features that are already in the array.
+    pub used: Vec<String>,
+    /// If the cursor isn't in a string (e.g. `features = [|]`), the range
+    /// of the (possibly empty) bare word before it, which the completion
+    /// replaces with a quoted feature.
+    pub bare: Option<Range>,
+}
+
+impl IncompleteFeature {
+    pub fn resolve(s: &str, pos: Position) -> Option<Self> {
+        let (token, array, entry, bare) =
+            match array_string_at(s, pos, "features") {
+                Some((token, array, entry)) => {
+                    (Some(token), array, entry, None)
+                }
+                None => {
+                    let (word, array, entry) =
+                        array_gap_at(s, pos, "features")?;
+                    let range = parse::range_to_positions(s, word);
+                    (None, array, entry, Some(range))
+                }
+            };
+
+        let parent = entry.parent()?;
+        let dependency = if parent.kind() == SyntaxKind::INLINE_TABLE {
+            // `foo = { features = ["` under a dependencies table
+            let entry = parent.parent()?.parent()?;
+            let [name] = <[_; 1]>::try_from(key_segments(&entry)).ok()?;
+            is_dependencies_table(&header_segments(&entry)?).then_some(name)
+        } else {
+            // `[dependencies.foo]`
+            let mut header = header_segments(&entry)?;
+            let name = header.pop()?;
+            is_dependencies_table(&header).then_some(name)
+        }?;
+
+        let used = used_strings(&array, token.as_ref());
+        Some(Self { dependency, used, bare })
+    }
+}
+
+/// A (possibly incomplete) string in the `required-features` array of a
+/// target, e.g. `[[bin]]`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct RequiredFeature {
+    /// The features that are already in the array.
+    pub used: Vec<String>,
+}
+
+impl RequiredFeature {
+    pub fn resolve(s: &str, pos: Position) -> Option<Self> {
+        let (token, array, entry) =
+            array_string_at(s, pos, Target::REQUIRED_FEATURES_KEY)?;
+
+        // all of the targets but `[lib]` (which can't have required features)
+        // are arrays of tables.
+        let header = entry.siblings(Direction::Prev).find(|n| {
+            matches!(
+                n.kind(),
+                SyntaxKind::TABLE_HEADER | SyntaxKind::TABLE_ARRAY_HEADER
+            )
+        })?;
+        let is_target = header.kind() == SyntaxKind::TABLE_ARRAY_HEADER
+            && matches!(
+                key_segments(&header).as_slice(),
+                [key] if TargetKind::from_key(key).is_some()
+            );
+
+        is_target.then(|| Self { used: used_strings(&array, Some(&token)) })
+    }
+}
+
+/// A key that is being typed at the start of a line, in a table.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TableKey {
+    /// The key segments of the table's header.
+    pub table: Vec<String>,
+}
+
+impl TableKey {
+    pub fn resolve(s: &str, pos: Position) -> Option<Self> {
+        let idx = parse::position_to_idx(s, pos);
+        let before = s.get(..idx)?;
+        let line_start = before.rfind(['\n', '\r']).map_or(0, |i| i + 1);
+        let is_key = before[line_start..]
+            .trim_start()
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'));
+        if !is_key {
+            return None;
+        }
+
+        Some(Self { table: table_before(s, line_start)? })
+    }
+
+    /// The name of the dependency, if the table is the table of a
+    /// dependency (e.g. `[dependencies.foo]`).
+    pub fn dependency(&self) -> Option<&str> {
+        let (name, header) = self.table.split_last()?;
+        is_dependencies_table(header).then_some(name.as_str())
+    }
+}
+
+/// The name of a dependency that is being typed at the start of a line, in
+/// a dependencies table, e.g. `[dependencies]\nser|`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct NewDependency {
+    /// What's typed of the name so far.
+    pub prefix: String,
+    pub range: Range,
+}
+
+impl NewDependency {
+    pub fn resolve(s: &str, pos: Position) -> Option<Self> {
+        let key = TableKey::resolve(s, pos)?;
+        if !is_dependencies_table(&key.table) {
+            return None;
+        }
+
+        let idx = parse::position_to_idx(s, pos);
+        let before = s.get(..idx)?;
+        let line_start = before.rfind(['\n', '\r']).map_or(0, |i| i + 1);
+        let prefix = before[line_start..].trim_start();
+        let start = parse::idx_to_position(s, idx - prefix.len());
+        Some(Self { prefix: prefix.to_owned(), range: Range::new(start, pos) })
+    }
+}
+
+/// A position inside the inline table of a dependency, e.g.
+/// `foo = { version = "1", |`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct InlineDependency {
+    /// The key of the entry the cursor is at, if any (e.g. not right after
+    /// a `,`).
+    pub key: Option<String>,
+}
+
+impl InlineDependency {
+    pub fn resolve(s: &str, pos: Position) -> Option<Self> {
+        let idx = parse::position_to_idx(s, pos);
+        let root = taplo::parser::parse(s).into_syntax();
+        let token = root
+            .token_at_offset(TextSize::try_from(idx).ok()?)
+            .left_biased()?;
+        // right after the closing brace, the cursor is out of the table.
+        if token.kind() == SyntaxKind::BRACE_END {
+            return None;
+        }
+
+        let table = token
+            .parent_ancestors()
+            .find(|n| n.kind() == SyntaxKind::INLINE_TABLE)?;
+        let entry = table.parent()?.parent()?;
+        let [_] = <[_; 1]>::try_from(key_segments(&entry)).ok()?;
+        if !is_dependencies_table(&header_segments(&entry)?) {
+            return None;
+        }
+
+        let key = token
+            .parent_ancestors()
+            .take_while(|n| n != &table)
+            .find(|n| {
+                n.kind() == SyntaxKind::ENTRY
+                    && n.parent().as_ref() == Some(&table)
+            })
+            .and_then(|entry| key_segments(&entry).into_iter().next());
+        Some(Self { key })
+    }
+}
+
+/// The header of a dependencies table, e.g. `[dev-dependencies]`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct DependenciesHeader {
+    /// The key segments of the header.
+    pub table: Vec<String>,
+    pub range: Range,
+    /// The entries of the table, as their keys along with the byte ranges of
+    /// their lines (including the comments right above them), grouped by the
+    /// blank lines between them.
+    pub groups: Vec<Vec<(String, std::ops::Range<usize>)>>,
+}
+
+impl DependenciesHeader {
+    /// All of the headers of the dependencies tables, in order.
+    pub fn all(s: &str) -> Vec<Self> {
+        let root = taplo::parser::parse(s).into_syntax();
+        let nodes = root.children().collect::<Vec<_>>();
+
+        let mut headers = Vec::new();
+        for (i, header) in nodes.iter().enumerate() {
+            if header.kind() != SyntaxKind::TABLE_HEADER {
+                continue;
+            }
+            let table = key_segments(header);
+            if !is_dependencies_table(&table) {
+                continue;
+            }
+
+            let mut groups: Vec<Vec<(String, std::ops::Range<usize>)>> =
+                Vec::new();
+            let entries = nodes[i + 1..]
+                .iter()
+                .take_while(|n| n.kind() == SyntaxKind::ENTRY);
+            for entry in entries {
+                let key = key_segments(entry).join(".");
+                let block = entry_block(
+                    s,
+                    parse::text_range_to_range(entry.text_range()),
+                );
+                let previous = groups.last_mut().filter(|group| {
+                    group.last().is_some_and(|(_, previous)| {
+                        matches!(&s[previous.end..block.start], "\n" | "\r\n")
+                    })
+                });
+                match previous {
+                    Some(group) => group.push((key, block)),
+                    None => groups.push(vec![(key, block)]),
+                }
+            }
+
+            let range = parse::text_range_to_range(header.text_range());
+            headers.push(Self {
+                table,
+                range: parse::range_to_positions(s, range),
+                groups,
+            });
+        }
+        headers
+    }
+
+    /// The header at `pos`.
+    pub fn resolve(s: &str, pos: Position) -> Option<Self> {
+        Self::all(s)
+            .into_iter()
+            .find(|h| h.range.start <= pos && pos <= h.range.end)
+    }
+
+    /// Whether the entries of the table are sorted by their keys (within
+    /// each of the groups).
+    pub fn is_sorted(&self) -> bool {
+        self.groups
+            .iter()
+            .all(|group| group.is_sorted_by_key(|(k, _)| k))
+    }
+
+    /// The section of the dependencies declared in the table.
+    pub fn section(&self) -> Option<Section> {
+        let (kind, target) = match self.table.as_slice() {
+            [kind] => (kind, None),
+            [_, target, kind] => (kind, Some(target.clone())),
+            _ => return None,
+        };
+        Some(Section { kind: DependencyKind::from_key(kind)?, target })
+    }
+}
+
+/// A value that is being typed after the key of an entry in a table, e.g.
+/// `opt-level = "`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TableValue {
+    /// The key segments of the table's header.
+    pub table: Vec<String>,
+    pub key: String,
+    /// Whether the value is (the start of) a string.
+    pub in_string: bool,
+}
+
+impl TableValue {
+    pub fn resolve(s: &str, pos: Position) -> Option<Self> {
+        let idx = parse::position_to_idx(s, pos);
+        let before = s.get(..idx)?;
+        let line_start = before.rfind(['\n', '\r']).map_or(0, |i| i + 1);
+        let (key, value) = before[line_start..].split_once('=')?;
+        let key = key.trim();
+        let value = value.trim_start();
+        let (in_string, value) = match value.strip_prefix(['"', '\'']) {
+            Some(value) => (true, value),
+            None => (false, value),
+        };
+        if !is_bare_key(key) || !(value.is_empty() || is_bare_key(value)) {
+            return None;
+        }
+
+        Some(Self {
+            table: table_before(s, line_start)?,
+            key: key.to_owned(),
+            in_string,
+        })
+    }
+}
+
+/// Whether nothing but whitespace (or a comment) follows `pos` on its line,
+/// e.g. after a key that has no `=` yet.
+pub fn ends_line(s: &str, pos: Position) -> bool {
+    let idx = parse::position_to_idx(s, pos);
+    let Some(rest) = s.get(idx..) else { return false };
+    let rest = rest.split(['\n', '\r']).next().unwrap_or_default();
+    let rest = rest.trim_start();
+    rest.is_empty() || rest.starts_with('#')
+}
+
+fn is_bare_key(s: &str) -> bool {
+    !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+}
+
+/// The key segments of the header of the table that the line at `line_start`
+/// is in (if it's not an array of tables).
+fn table_before(s: &str, line_start: usize) -> Option<Vec<String>> {
+    let root = taplo::parser::parse(s).into_syntax();
+    let header = root
+        .children()
+        .take_while(|n| usize::from(n.text_range().start()) < line_start)
+        .filter(|n| {
+            matches!(
+                n.kind(),
+                SyntaxKind::TABLE_HEADER | SyntaxKind::TABLE_ARRAY_HEADER
+            )
+        })
+        .last()?;
+
+    (header.kind() == SyntaxKind::TABLE_HEADER).then(|| key_segments(&header))
+}
+
+/// The (possibly unterminated) string at `pos`, if it's an item of an array
+/// that is the value of `key`, along with the array and its entry.
+fn array_string_at(
+    s: &str,
+    pos: Position,
+    key: &str,
+) -> Option<(SyntaxToken, SyntaxNode, SyntaxNode)> {
+    let idx = parse::position_to_idx(s, pos);
+    let root = taplo::parser::parse(s).into_syntax();
+    let token = root
+        .token_at_offset(TextSize::try_from(idx).ok()?)
+        .left_biased()?;
+    let token = string_at(s, token, idx)?;
+
+    let array = match token.parent()? {
+        value if value.kind() == SyntaxKind::VALUE => value.parent()?,
+        parent => parent,
+    };
+    let entry = array.parent()?.parent()?;
+    (array.kind() == SyntaxKind::ARRAY
+        && entry.kind() == SyntaxKind::ENTRY
+        && key_segments(&entry) == [key])
+    .then_some((token, array, entry))
+}
+
+/// The position between the items of the array of `key` that `pos` is at
+/// (e.g. `key = [|]` or `key = ["a", |`), if it's not in a string.
+///
+/// Returns the byte range of the bare word that is typed there (e.g. `se`
+/// in `key = [se|`), which is empty if there is none.
+fn array_gap_at(
+    s: &str,
+    pos: Position,
+    key: &str,
+) -> Option<(std::ops::Range<usize>, SyntaxNode, SyntaxNode)> {
+    let idx = parse::position_to_idx(s, pos);
+    let root = taplo::parser::parse(s).into_syntax();
+    let token = root
+        .token_at_offset(TextSize::try_from(idx).ok()?)
+        .left_biased()?;
+
+    let word = match token.kind() {
+        // a bare word is an error in an array.
+        SyntaxKind::ERROR
+            if token.text().chars().all(|c| {
+                c.is_ascii_alphanumeric() || matches!(c, '-' | '_')
+            }) =>
+        {
+            usize::from(token.text_range().start())..idx
+        }
+        SyntaxKind::WHITESPACE
+        | SyntaxKind::NEWLINE
+        | SyntaxKind::BRACKET_START
+        | SyntaxKind::COMMA => idx..idx,
+        _ => return None,
+    };
+
+    // the cursor must be after the start of the array, or a separator (the
+    // parser inserts empty values around errors).
+    let previous = token
+        .siblings_with_tokens(Direction::Prev)
+        .skip(usize::from(token.kind() == SyntaxKind::ERROR))
+        .find(|e| {
+            !matches!(e.kind(), SyntaxKind::WHITESPACE | SyntaxKind::NEWLINE)
+                && !e.text_range().is_empty()
+        })?;
+    if !matches!(
+        previous.kind(),
+        SyntaxKind::BRACKET_START | SyntaxKind::COMMA
+    ) {
+        return None;
+    }
+
+    let array = token.parent()?;
+    let entry = array.parent()?.parent()?;
+    (array.kind() == SyntaxKind::ARRAY
+        && entry.kind() == SyntaxKind::ENTRY
+        && key_segments(&entry) == [key])
+    .then_some((word, array, entry))
+}
+
+/// The (complete) strings in `array`, other than `token`.
+fn used_strings(
+    array: &SyntaxNode,
+    token: Option<&SyntaxToken>,
+) -> Vec<String> {
+    array
+        .descendants_with_tokens()
+        .filter_map(|e| e.into_token())
+        .filter(|t| Some(t) != token && t.kind() == SyntaxKind::STRING)
+        .map(|t| unquote(t.text()).to_owned())
+        .collect()
+}
+
+/// The (possibly unterminated) string that `idx` is inside of, given the
+/// token at `idx`.
+fn string_at(s: &str, token: SyntaxToken, idx: usize) -> Option<SyntaxToken> {
+    let start = usize::from(token.text_range().start());
+    let end = usize::from(token.text_range().end());
+    if matches!(
+        token.kind(),
+        SyntaxKind::STRING | SyntaxKind::STRING_LITERAL
+    ) {
+        // the cursor must be between the quotes.
+        let is_closed = token.text().len() >= 2;
+        return (idx > start && (!is_closed || idx < end)).then_some(token);
+    }
+
+    // The lexer fails on an unterminated string, so its opening quote is an
+    // error token, and whatever follows it (on the same line) is lexed as
+    // other tokens.
+    // (`prev_token` stops at the empty nodes the parser inserts on errors.)
+    let root = token.parent_ancestors().last()?;
+    let preceding = root
+        .descendants_with_tokens()
+        .filter_map(|e| e.into_token())
+        .take_while(|t| t.text_range().start() <= token.text_range().start())
+        .collect::<Vec<_>>();
+    for token in preceding.into_iter().rev() {
+        if token.kind() == SyntaxKind::NEWLINE {
+            return None;
+        }
+        let text = token.text();
+        if token.kind() == SyntaxKind::ERROR
+            && let Some(quote) = text.chars().next()
+            && matches!(quote, '"' | '\'')
+        {
+            let start = usize::from(token.text_range().start());
+            let is_closed = s.get(start + 1..idx)?.contains(quote);
+            return (!is_closed).then_some(token);
+        }
+    }
+
+    None
+}
+
+fn unquote(s: &str) -> &str {
+    let s = s.strip_prefix(['"', '\'']).unwrap_or(s);
+    s.strip_suffix(['"', '\'']).unwrap_or(s)
+}
+
+/// The segments of the key of an `ENTRY`, `TABLE_HEADER`, etc.
+fn key_segments(node: &SyntaxNode) -> Vec<String> {
+    node.children()
+        .find(|n| n.kind() == SyntaxKind::KEY)
+        .into_iter()
+        .flat_map(|key| key.children_with_tokens())
+        .filter_map(|e| e.into_token())
+        .filter(|t| {
+            matches!(
+                t.kind(),
+                SyntaxKind::IDENT
+                    | SyntaxKind::STRING
+                    | SyntaxKind::STRING_LITERAL
+            )
+        })
+        .map(|t| unquote(t.text()).to_owned())
+        .collect()
+}
+
+/// The key segments of the header of the table a top-level entry is in.
+fn header_segments(entry: &SyntaxNode) -> Option<Vec<String>> {
+    let header = entry.siblings(Direction::Prev).find(|n| {
+        matches!(
+            n.kind(),
+            SyntaxKind::TABLE_HEADER | SyntaxKind::TABLE_ARRAY_HEADER
+        )
+    })?;
+    (header.kind() == SyntaxKind::TABLE_HEADER).then(|| key_segments(&header))
+}
+
+/// The lines of an entry, along with the comment lines right above it
+/// (without the final line break).
+fn entry_block(
+    s: &str,
+    entry: std::ops::Range<usize>,
+) -> std::ops::Range<usize> {
+    let line_start = |idx: usize| s[..idx].rfind('\n').map_or(0, |i| i + 1);
+
+    let mut start = line_start(entry.start);
+    while start > 0 {
+        let previous = line_start(start - 1);
+        if !s[previous..start].trim_start().starts_with('#') {
+            break;
+        }
+        start = previous;
+    }
+
+    let end = s[entry.end..].find('\n').map_or(s.len(), |i| entry.end + i);
+    let end = if s[..end].ends_with('\r') { end - 1 } else { end };
+    start..end
+}
+
+fn is_dependencies_table(segments: &[String]) -> bool {
+    match segments {
+        [key] | [_, _, key] if !DEPENDENCIES_KEYS.contains(&key.as_str()) => {
+            false
+        }
+        [_] => true,
+        [target, _, _] => target == TARGET_KEY,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let s = indoc! {r#"
+            [dependencies]
+            a = { version = "1", features = ["x"], default-features = false }
+            b = { git = "https://github.com/b/b", tag = "v1" }
+            c = { path = "../c", registry = "my-registry" }
+            d = { package = "serde_json", version = "1" }
+        "#};
+        let dom = taplo::parser::parse(s).into_dom();
+        let manifest = Manifest::parse(s, &dom);
+        let resolve = |line, character| {
+            let context =
+                Context::resolve(&manifest, Position { line, character })?;
+            Some((context.dependency().name.value.as_str(), context))
+        };
+
+        assert!(matches!(
+            resolve(1, 0),
+            Some(("a", Context::DependencyName(_)))
+        ));
+        assert!(matches!(
+            resolve(1, 17),
+            Some(("a", Context::VersionValue(_)))
+        ));
+        assert!(matches!(
+            resolve(1, 34),
+            Some(("a", Context::FeatureString { index: 0, .. }))
+        ));
+        assert!(matches!(
+            resolve(1, 60),
+            Some(("a", Context::DefaultFeaturesValue(_)))
+        ));
+        assert!(matches!(resolve(2, 15), Some(("b", Context::GitUrl(_)))));
+        assert!(matches!(
+            resolve(2, 46),
+            Some(("b", Context::GitSpecifier(_)))
+        ));
+        assert!(matches!(resolve(3, 14), Some(("c", Context::PathValue(_)))));
+        assert!(matches!(
+            resolve(3, 35),
+            Some(("c", Context::RegistryValue(_)))
+        ));
+        assert!(matches!(
+            resolve(4, 20),
+            Some(("d", Context::PackageValue(_)))
+        ));
+        assert!(resolve(1, 3).is_none());
+    }
+
+    #[test]
+    fn test_table_key() {
+        let resolve = |s: &str| {
+            let idx = s.find('|').unwrap();
+            let s = s.replace('|', "");
+            let pos = parse::idx_to_position(&s, idx);
+            TableKey::resolve(&s, pos).map(|k| k.table.join("."))
+        };
+
+        assert_eq!(
+            resolve("[package.metadata.docs.rs]\nall-features = true\n  al|"),
+            Some("package.metadata.docs.rs".to_owned())
+        );
+        assert_eq!(
+            resolve("[package]\n|\n[dependencies]\n"),
+            Some("package".to_owned())
+        );
+        assert_eq!(resolve("[package]\nname = |"), None);
+        assert_eq!(resolve("|[package]\n"), None);
+        assert_eq!(resolve("[[bin]]\n|"), None);
+
+        // the positions count UTF-16 code units, rather than bytes.
+        let s = "[package]\ndescription = \"é\"\n";
+        assert_eq!(TableKey::resolve(s, Position::new(1, 16)), None);
+        assert_eq!(TableValue::resolve(s, Position::new(1, 16)), None);
+        assert_eq!(NewDependency::resolve(s, Position::new(1, 16)), None);
+        assert!(!ends_line(s, Position::new(1, 16)));
+    }
+
+    #[test]
+    fn test_table_key_dependency() {
+        let dependency = |table: &[&str]| {
+            let table = table.iter().map(|&s| s.to_owned()).collect();
+            TableKey { table }.dependency().map(str::to_owned)
+        };
+
+        assert_eq!(
+            dependency(&["dependencies", "foo"]).as_deref(),
+            Some("foo")
+        );
+        assert_eq!(
+            dependency(&["target", "cfg(unix)", "dev-dependencies", "foo"])
+                .as_deref(),
+            Some("foo")
+        );
+        assert_eq!(dependency(&["dependencies"]), None);
+        assert_eq!(dependency(&["package", "foo"]), None);
+    }
+
+    #[test]
+    fn test_new_dependency() {
+        let resolve = |s: &str| {
+            let idx = s.find('|').unwrap();
+            let s = s.replace('|', "");
+            NewDependency::resolve(&s, parse::idx_to_position(&s, idx))
+        };
+
+        assert_eq!(
+            resolve("[dependencies]\nserde = \"1\"\n  ser|"),
+            Some(NewDependency {
+                prefix: "ser".to_owned(),
+                range: Range::new(Position::new(2, 2), Position::new(2, 5)),
+            })
+        );
+        assert_eq!(
+            resolve("[target.'cfg(unix)'.build-dependencies]\n|")
+                .map(|d| d.prefix),
+            Some(String::new())
+        );
+        assert_eq!(resolve("[dependencies.serde]\nver|"), None);
+        assert_eq!(resolve("[package]\nna|"), None);
+        assert_eq!(resolve("[dependencies]\nserde = \"1|"), None);
+    }
+
+    #[test]
+    fn test_ends_line() {
+        let ends_line = |s: &str| {
+            let idx = s.find('|').unwrap();
+            let s = s.replace('|', "");
+            ends_line(&s, parse::idx_to_position(&s, idx))
+        };
+
+        assert!(ends_line("[dependencies]\ntok|\nserde = \"1\""));
+        assert!(ends_line("[dependencies]\ntok|  # async"));
+        assert!(ends_line("tok|"));
+        assert!(!ends_line("[dependencies]\ntok| ="));
+        assert!(!ends_line("[dependencies]\ntok|io"));
+    }
+
+    #[test]
+    fn test_dependencies_header() {
+        let s = indoc! {r#"
+            [package]
+            name = "foo"
+
+            [target.'cfg(unix)'.dev-dependencies]
+            serde = "1"
+        "#};
+        let resolve = |line, character| {
+            DependenciesHeader::resolve(s, Position::new(line, character))
+                .map(|h| h.table.join("."))
+        };
+
+        assert_eq!(
+            resolve(3, 10).as_deref(),
+            Some("target.cfg(unix).dev-dependencies")
+        );
+        assert_eq!(
+            DependenciesHeader::resolve(s, Position::new(3, 0))
+                .and_then(|h| h.section()),
+            Some(Section {
+                kind: DependencyKind::Dev,
+                target: Some("cfg(unix)".to_owned()),
+            })
+        );
+        assert_eq!(resolve(0, 3), None);
+        assert_eq!(resolve(4, 3), None);
+    }
+
+    #[test]
+    fn test_table_value() {
+        let resolve = |s: &str| {
+            let idx = s.find('|').unwrap();
+            let s = s.replace('|', "");
+            TableValue::resolve(&s, parse::idx_to_position(&s, idx))
+        };
+
+        assert_eq!(
+            resolve("[profile.release]\nlto = \"th|"),
+            Some(TableValue {
+                table: vec!["profile".to_owned(), "release".to_owned()],
+                key: "lto".to_owned(),
+                in_string: true,
+            })
+        );
+        assert_eq!(
+            resolve("[profile.dev]\nopt-level=|\n").map(|v| v.in_string),
+            Some(false)
+        );
+        assert_eq!(resolve("[profile.dev]\nopt-level = [|\n"), None);
+        assert_eq!(resolve("opt-level = |"), None);
+    }
+
+    #[test]
+    fn test_incomplete_feature() {
+        // the position of the `|` in `s`.
+        let resolve = |s: &str| {
+            let idx = s.find('|').unwrap();
+            let s = s.replace('|', "");
+            IncompleteFeature::resolve(&s, parse::idx_to_position(&s, idx))
+        };
+        let feature = |dependency: &str, used: &[&str]| {
+            Some(IncompleteFeature {
+                dependency: dependency.to_owned(),
+                used: used.iter().map(|&u| u.to_owned()).collect(),
+                bare: None,
+            })
+        };
+
+        assert_eq!(
+            resolve("[dependencies]\nfoo = { features = [\"|\n"),
+            feature("foo", &[])
+        );
+        assert_eq!(
+            resolve("[dependencies]\nfoo = { features = [\"a\", \"x|\nb = 1"),
+            feature("foo", &["a"])
+        );
+        assert_eq!(
+            resolve("[dev-dependencies.foo]\nfeatures = ['|\n"),
+            feature("foo", &[])
+        );
+        assert_eq!(
+            resolve(
+                "[target.'cfg(unix)'.dependencies]\nfoo = { features = [\"|\"] }"
+            ),
+            feature("foo", &[])
+        );
+        // after a string
+        assert_eq!(
+            resolve("[dependencies]\nfoo = { features = [\"\"|\n"),
+            None
+        );
+        assert_eq!(
+            resolve("[dependencies]\nfoo = { features = [\"a\" |] }"),
+            None
+        );
+        // not in a dependencies table
+        assert_eq!(resolve("[package]\nfoo = { features = [\"|\n"), None);
+        assert_eq!(resolve("[dependencies]\nfoo = { default = [\"|\n"), None);
+    }
+
+    #[test]
+    fn test_bare_feature() {
+        let resolve = |s: &str| {
+            let idx = s.find('|').unwrap();
+            let s = s.replace('|', "");
+            let feature = IncompleteFeature::resolve(
+                &s,
+                parse::idx_to_position(&s, idx),
+            )?;
+            let bare = feature.bare?;
+            Some((feature.used, bare.start.character, bare.end.character))
+        };
+
+        assert_eq!(
+            resolve("[dependencies]\nfoo = { features = [|\n"),
+            Some((vec![], 20, 20))
+        );
+        assert_eq!(
+            resolve("[dependencies]\nfoo = { features = [ | ] }"),
+            Some((vec![], 21, 21))
+        );
+        assert_eq!(
+            resolve("[dependencies]\nfoo = { features = [\"a\", se|\n"),
+            Some((vec!["a".to_owned()], 25, 27))
+        );
+        assert_eq!(
+            resolve("[dependencies.foo]\nfeatures = [\n  \"a\",\n  |\n]\n"),
+            Some((vec!["a".to_owned()], 2, 2))
+        );
+    }
+
+    #[test]
+    fn test_inline_dependency() {
+        let resolve = |s: &str| {
+            let idx = s.find('|').unwrap();
+            let s = s.replace('|', "");
+            InlineDependency::resolve(&s, parse::idx_to_position(&s, idx))
+                .map(|d| d.key)
+        };
+        let key = |key: &str| Some(Some(key.to_owned()));
+
+        assert_eq!(resolve("[dependencies]\nfoo = {|\n"), Some(None));
+        assert_eq!(
+            resolve("[dependencies]\nfoo = { version = \"1\", |\n"),
+            Some(None)
+        );
+        assert_eq!(resolve("[dependencies]\nfoo = { ver|\n"), key("ver"));
+        assert_eq!(
+            resolve("[dependencies]\nfoo = { features = [\"a|\"] }"),
+            key("features")
+        );
+        assert_eq!(
+            resolve("[target.'cfg(unix)'.dependencies]\nfoo = { git = |"),
+            key("git")
+        );
+        // out of the table
+        assert_eq!(resolve("[dependencies]\nfoo = { version = \"1\" }|"), None);
+        // not a dependency
+        assert_eq!(resolve("[package]\nfoo = { |"), None);
+        assert_eq!(resolve("[dependencies.foo]\nbar = { |"), None);
+    }
+
+    #[test]
+    fn test_required_feature() {
+        let resolve = |s: &str| {
+            let idx = s.find('|').unwrap();
+            let s = s.replace('|', "");
+            RequiredFeature::resolve(&s, parse::idx_to_position(&s, idx))
+                .map(|f| f.used)
+        };
+
+        assert_eq!(
+            resolve("[[bin]]\nname = \"a\"\nrequired-features = [\"|\n"),
+            Some(vec![])
+        );
+        assert_eq!(
+            resolve("[[example]]\nrequired-features = [\"cli\", \"f|\"]\n"),
+            Some(vec!["cli".to_owned()])
+        );
+        assert_eq!(resolve("[lib]\nrequired-features = [\"|\"]\n"), None);
+        assert_eq!(resolve("[[bin]]\nfeatures = [\"|\"]\n"), None);
+    }
+}
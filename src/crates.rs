@@ -1,15 +1,67 @@
 use std::{
-    collections::HashMap,
-    sync::Arc,
-    time::{Duration, Instant},
+    collections::{HashMap, VecDeque},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    },
+    time::Duration,
 };
 
-use reqwest::Response;
-use serde::Deserialize;
-use tokio::sync::Mutex;
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use reqwest::{
+    Response, StatusCode,
+    header::{AUTHORIZATION, ETAG, IF_NONE_MATCH},
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Mutex, Notify, RwLock};
+use url::Url;
+
+use crate::{
+    budget::{ApiBudget, Priority},
+    cargo_config::{HttpConfig, Token},
+    disk_cache::{DiskCache, Usage},
+    metrics::Metrics,
+    platform::{self, Instant},
+    readme,
+};
 
 const REGISTRY_URL: &str = "https://index.crates.io";
-const API_URL: &str = "https://crates.io/api/v1/crates";
+/// The maximal `per_page` value of the API's list endpoint.
+const API_PAGE_SIZE: usize = 100;
+/// The maximal size of a response. The largest index files are a few MBs,
+/// so only a misconfigured proxy (or a compromised mirror) exceeds it.
+const MAX_CONTENT_LENGTH: usize = 32 * 1024 * 1024;
+/// The maximal number of crates a search of a registry returns.
+const SEARCH_LIMIT: usize = 20;
+/// How long a search waits for the next keystroke, so only the last query
+/// of a burst is sent.
+const SEARCH_DEBOUNCE: Duration = Duration::from_millis(150);
+/// The maximal number of crates whose API info waits for the budget (see
+/// [`RegistryCache::drain_api_queue`]).
+const MAX_API_QUEUE: usize = 256;
+/// How many times the API info of a queued crate is requested before it's
+/// given up on (e.g. while the API is down).
+const MAX_API_ATTEMPTS: u32 = 3;
+/// The maximal number of cached READMEs, after which they're all evicted.
+const MAX_READMES: usize = 128;
+/// The number of recent searches whose results are kept, and how long, so
+/// deleting a character (or retyping it) doesn't search again.
+const RECENT_SEARCHES: usize = 64;
+const RECENT_SEARCH_TTL: Duration = Duration::from_mins(5);
+/// The number of crates that are fetched at once (e.g. when prefetching),
+/// unless configured otherwise.
+const CONCURRENCY: usize = 8;
+/// The idle connections kept per host by the client of the index (whose
+/// files are fetched many at once) and by that of the rate limited API.
+const INDEX_MAX_IDLE: usize = 32;
+const API_MAX_IDLE: usize = 1;
+/// How long a request may take, unless configured otherwise.
+const INDEX_TIMEOUT: Duration = Duration::from_secs(30);
+const API_TIMEOUT: Duration = Duration::from_secs(10);
+const USER_AGENT: &str = "crates-language-server (github.com/rotmh)";
+/// How often to probe the registry while it's unreachable.
+const PROBE_INTERVAL: Duration = Duration::from_mins(1);
 
 pub const DOCS_RS_URL: &str = "https://docs.rs";
 
@@ -17,156 +69,1086 @@ pub const DOCS_RS_URL: &str = "https://docs.rs";
 pub enum Error {
     #[error("failed to fetch `{url}`")]
     Request { url: String },
+    #[error("`{url}` doesn't exist")]
+    NotFound { url: String },
     #[error("failed to parse body of the index of crate `{name}`")]
     Parse { name: String },
+    #[error("`{name}` isn't a valid crate name")]
+    InvalidName { name: String },
+    #[error("the registry is unreachable")]
+    Offline,
+    #[error("the crates.io API is disabled (see the `api.enabled` setting)")]
+    ApiDisabled,
+    #[error("the registry rejected the credentials of `{url}`")]
+    Unauthorized { url: String },
+    #[error("the `{registry}` registry has no sparse index configured")]
+    UnknownRegistry { registry: String },
+    #[error("failed to fetch the git index `{url}`")]
+    GitIndex { url: String },
+    #[error(
+        "the git index `{url}` is fetched into the disk cache, which is \
+         disabled (see the `cache.disk` setting)"
+    )]
+    NoDiskCache { url: String },
+    #[error("failed to read the CA certificates of `{path}`")]
+    CaInfo { path: String },
+    #[error("failed to build the HTTP client: {message}")]
+    Client { message: String },
+    #[error("failed to parse the registry's `config.json`")]
+    Config,
+    #[error("failed to parse the search results of `{url}`")]
+    Search { url: String },
+    #[error(
+        "the response of `{url}` is larger than {MAX_CONTENT_LENGTH} bytes"
+    )]
+    TooLarge { url: String },
+    #[error("the response of `{url}` is `{content_type}`, rather than data")]
+    ContentType { url: String, content_type: String },
 }
 
 /// A cache for a "latest" entry for crates.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct RegistryCache {
-    crates: Arc<Mutex<HashMap<String, Latest>>>,
-    client: reqwest::Client,
-    last_api_request: Arc<Mutex<Instant>>,
+    crates: Arc<Mutex<HashMap<String, CrateInfo>>>,
+    /// The names of the cached crates, from the least recently fetched.
+    order: Arc<Mutex<VecDeque<String>>>,
+    /// The maximal number of cached crates (`usize::MAX` for no limit).
+    max_crates: Arc<AtomicUsize>,
+    /// The seconds after which a cached crate is refreshed (`u64::MAX` to
+    /// never refresh).
+    ttl: Arc<AtomicU64>,
+    /// The maximal number of crates that are fetched at once.
+    concurrency: Arc<AtomicUsize>,
+    /// The new versions that refreshes found, until they're taken.
+    updates: Arc<Mutex<Vec<Update>>>,
+    /// The clients of the index and of the API, which don't share their
+    /// connections (see [`Channel`]).
+    clients: Arc<RwLock<Clients>>,
+    /// The timeouts of the requests of each client, in milliseconds.
+    index_timeout: Arc<AtomicU64>,
+    api_timeout: Arc<AtomicU64>,
+    /// Shared by all of the requests to the crates.io API.
+    api_budget: Arc<ApiBudget>,
+    /// The crates whose API info was requested while the budget was spent,
+    /// from the first, to be fetched once it allows, along with the number
+    /// of failed attempts to fetch it.
+    api_queue: Arc<Mutex<VecDeque<(String, u32)>>>,
+    /// Notified once a crate is queued, for the task that drains the queue.
+    api_queued: Arc<Notify>,
+    /// Set while the task that drains the queue runs, so there's only one.
+    draining: Arc<AtomicBool>,
+    metrics: Arc<Metrics>,
+    /// Set while the registry is unreachable, so only the cached crates are
+    /// served (see [`Self::check_health`]).
+    offline: Arc<AtomicBool>,
+    last_probe: Arc<Mutex<Option<Instant>>>,
+    /// The number of searches that were started, so a search knows when a
+    /// newer one supersedes it.
+    searches: Arc<AtomicU64>,
+    /// The summaries of the READMEs of crate versions (`None` for the ones
+    /// that have no README, or whose READMEs have nothing to show).
+    readmes: Arc<Mutex<HashMap<CrateVersion, Option<String>>>>,
+    /// The results of the recent searches, by their queries.
+    recent_searches: Arc<Mutex<HashMap<String, RecentSearch>>>,
+    /// The registry's `config.json`, once it's fetched.
+    config: Arc<Mutex<Option<RegistryConfig>>>,
+    /// Bumped whenever all of the cached data changes at once (e.g. it's
+    /// cleared), so the analyses that are based on it know they are stale.
+    generation: Arc<AtomicU64>,
+    /// The last [revision](CrateInfo::revision) given to a cached crate.
+    revision: Arc<AtomicU64>,
+    /// Set once the server is shutting down, so the background work (i.e.
+    /// prefetching and refreshing) stops.
+    cancelled: Arc<AtomicBool>,
+    /// Whether pre-releases can be the latest versions (see
+    /// [`CrateInfo::select_latest`]).
+    prereleases: Arc<AtomicBool>,
+    /// The cache of the index files on disk, unless it's disabled.
+    disk: Arc<RwLock<Option<DiskCache>>>,
+    /// Held while a git index is fetched, so the crates of an index don't
+    /// all fetch it at once.
+    git_fetch: Arc<Mutex<()>>,
+    /// Whether the web API of the registry is used, besides its index.
+    api_enabled: Arc<AtomicBool>,
 }
 
 impl RegistryCache {
     pub fn new() -> Self {
         Self {
             crates: Arc::new(Mutex::new(HashMap::new())),
-            client: reqwest::ClientBuilder::new()
-                .user_agent("crates-language-server (github.com/rotmh)")
-                .build()
-                .unwrap_or_default(),
-            last_api_request: Arc::new(Mutex::new(Instant::now())),
+            order: Arc::new(Mutex::new(VecDeque::new())),
+            max_crates: Arc::new(AtomicUsize::new(usize::MAX)),
+            ttl: Arc::new(AtomicU64::new(u64::MAX)),
+            concurrency: Arc::new(AtomicUsize::new(CONCURRENCY)),
+            updates: Arc::default(),
+            generation: Arc::new(AtomicU64::new(0)),
+            revision: Arc::new(AtomicU64::new(0)),
+            clients: Arc::new(RwLock::new(
+                Clients::new(&HttpConfig::default()).unwrap_or_default(),
+            )),
+            index_timeout: Arc::new(AtomicU64::new(millis(INDEX_TIMEOUT))),
+            api_timeout: Arc::new(AtomicU64::new(millis(API_TIMEOUT))),
+            api_budget: Arc::default(),
+            api_queue: Arc::default(),
+            api_queued: Arc::default(),
+            draining: Arc::default(),
+            metrics: Arc::default(),
+            offline: Arc::default(),
+            last_probe: Arc::default(),
+            searches: Arc::default(),
+            recent_searches: Arc::default(),
+            readmes: Arc::default(),
+            config: Arc::default(),
+            cancelled: Arc::default(),
+            prereleases: Arc::default(),
+            disk: Arc::default(),
+            git_fetch: Arc::default(),
+            api_enabled: Arc::new(AtomicBool::new(true)),
         }
     }
 
-    /// Fetch description only if 1 minute passed since last API request.
-    ///
-    /// This rate limiting is required because it's one of [`crates.io`'s
-    /// limits]:
+    /// Fetch API info only if the [API budget](crate::budget) allows a
+    /// request right away, as the user is waiting on it. Otherwise, the
+    /// crate is queued, so its info is fetched in the background once the
+    /// budget allows (see [`Self::drain_api_queue`]).
+    async fn fetch_api_info_rated(&self, name: &str) -> Option<ApiInfo> {
+        if self.is_offline() {
+            return None;
+        }
+        let api = self.api_url().await?;
+        if self.api_budget.try_acquire(Priority::Interactive).await {
+            return self.fetch_api_info(&api, name).await.ok();
+        }
+
+        let mut queue = self.api_queue.lock().await;
+        if !queue.iter().any(|(queued, _)| queued == name) {
+            if queue.len() >= MAX_API_QUEUE {
+                queue.pop_front();
+            }
+            queue.push_back((name.to_owned(), 0));
+        }
+        drop(queue);
+        self.api_queued.notify_one();
+        self.spawn_drain();
+        None
+    }
+
+    /// The summary of the README of a version of a crate (see
+    /// [`crate::readme`]), fetched from the API only if the
+    /// [budget](crate::budget) allows a request right away, as the user is
+    /// waiting on it.
+    pub async fn fetch_readme(
+        &self,
+        name: &str,
+        version: &semver::Version,
+    ) -> Option<String> {
+        let key = (name.to_owned(), version.clone());
+        if let Some(readme) = self.readmes.lock().await.get(&key) {
+            return readme.clone();
+        }
+        if self.is_offline() {
+            return None;
+        }
+        let api = self.api_url().await?;
+        if !self.api_budget.try_acquire(Priority::Interactive).await {
+            return None;
+        }
+
+        // The README is rendered as HTML, so it's read as is rather than
+        // through `read_content`, which only takes data.
+        let url = api_readme_url(&api, name, version);
+        let start = Instant::now();
+        let html = platform::send(async {
+            let res = self.fetch_endpoint(Channel::Api, &url, None, None);
+            let res = match res.await {
+                Ok(res) => res,
+                // the version has no README.
+                Err(Error::NotFound { .. }) => return Some(None),
+                Err(_) => return None,
+            };
+            let body =
+                platform::read_body(res, MAX_CONTENT_LENGTH).await.ok()?;
+            // a README that's too large (or isn't text) isn't summarized.
+            Some(body.and_then(|body| String::from_utf8(body).ok()))
+        })
+        .await;
+        self.metrics.fetch(start.elapsed());
+        // the requests that failed (e.g. timed out) are made again on the
+        // next hover.
+        let readme = html?.as_deref().and_then(readme::summary);
+
+        let mut readmes = self.readmes.lock().await;
+        if readmes.len() >= MAX_READMES {
+            readmes.clear();
+        }
+        readmes.insert(key, readme.clone());
+        readme
+    }
+
+    /// Spawn the task that drains the queue of API requests (see
+    /// [`Self::drain_api_queue`]), unless it already runs.
+    fn spawn_drain(&self) {
+        /// Clears the flag however the task ends (e.g. if it panics), so
+        /// the next queued crate spawns it again.
+        struct Draining(Arc<AtomicBool>);
+        impl Drop for Draining {
+            fn drop(&mut self) {
+                self.0.store(false, Ordering::Release);
+            }
+        }
+
+        if self.draining.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let registry = self.clone();
+        let draining = Draining(Arc::clone(&self.draining));
+        platform::spawn(async move {
+            let _draining = draining;
+            registry.drain_api_queue().await;
+        });
+    }
+
+    /// Fetch the API info of the queued crates (see
+    /// [`Self::fetch_api_info_rated`]) as the budget allows, waiting for the
+    /// next ones once the queue is empty, until the server shuts down.
     ///
-    /// * "A maximum of 1 request per second"
+    /// The crates whose info fails to be fetched are queued again, up to
+    /// [`MAX_API_ATTEMPTS`] times.
+    async fn drain_api_queue(&self) {
+        while !self.is_cancelled() {
+            if self.is_offline() {
+                // the queue is kept until the registry is reachable again.
+                platform::sleep(PROBE_INTERVAL).await;
+                continue;
+            }
+            let Some(api) = self.api_url().await else {
+                // the API is disabled (or unknown), so there's no point in
+                // keeping the queue.
+                self.api_queue.lock().await.clear();
+                self.api_queued.notified().await;
+                continue;
+            };
+            let next = self.api_queue.lock().await.pop_front();
+            let Some((name, attempts)) = next else {
+                self.api_queued.notified().await;
+                continue;
+            };
+            let is_missing = |crates: &HashMap<String, CrateInfo>| {
+                crates.get(&name).is_some_and(|entry| {
+                    entry.api.as_ref().is_none_or(|a| a.release_dates.is_none())
+                })
+            };
+            // it might have been evicted (or fetched) meanwhile.
+            if !is_missing(&*self.crates.lock().await) {
+                continue;
+            }
+
+            self.api_budget.acquire(Priority::Background).await;
+            let Ok(info) = self.fetch_api_info(&api, &name).await else {
+                let mut queue = self.api_queue.lock().await;
+                if attempts + 1 < MAX_API_ATTEMPTS
+                    && queue.len() < MAX_API_QUEUE
+                    && !queue.iter().any(|(queued, _)| *queued == name)
+                {
+                    queue.push_back((name, attempts + 1));
+                }
+                continue;
+            };
+            let mut crates = self.crates.lock().await;
+            if is_missing(&crates)
+                && let Some(entry) = crates.get_mut(&name)
+            {
+                entry.api = Some(info);
+                entry.revision = self.next_revision();
+            }
+        }
+    }
+
+    /// Fetch the API info of all of the given crates (that are already
+    /// cached, and whose API info is missing) using the API's list endpoint,
+    /// which takes many crates per request.
     ///
-    /// [`crates.io`'s limits]: https://crates.io/data-access#api
-    async fn fetch_description_rated(&self, name: &str) -> Option<String> {
-        let last_req = *self.last_api_request.lock().await;
-        let since_last_req = Instant::now().duration_since(last_req);
-
-        if since_last_req > Duration::from_secs(1) {
-            *self.last_api_request.lock().await = Instant::now();
-            self.fetch_description(name).await.ok()
-        } else {
-            None
+    /// This way a whole manifest is hydrated in a couple of requests, instead
+    /// of one (rate limited) request per crate. Note that the list endpoint
+    /// doesn't include the versions, so the release dates come from the
+    /// index (see [`VersionEntry::published`]), or from
+    /// [`Self::fetch_details`].
+    pub async fn fetch_api_infos(&self, names: &[&str]) {
+        if self.is_offline() {
+            return;
+        }
+        let Some(api) = self.api_url().await else { return };
+        let missing = {
+            let crates = self.crates.lock().await;
+            names
+                .iter()
+                .filter(|&&name| {
+                    crates.get(name).is_some_and(|c| c.api.is_none())
+                })
+                .map(|&name| name.to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        for chunk in missing.chunks(API_PAGE_SIZE) {
+            self.api_budget.acquire(Priority::Background).await;
+            let Ok(mut infos) = self.fetch_api_infos_page(&api, chunk).await
+            else {
+                continue;
+            };
+
+            let mut crates = self.crates.lock().await;
+            for name in chunk {
+                if let Some(entry) = crates.get_mut(name)
+                    && entry.api.is_none()
+                {
+                    // crates that are missing from the response are marked
+                    // as fetched too, so we won't keep requesting them.
+                    entry.api = Some(infos.remove(name).unwrap_or_default());
+                    entry.revision = self.next_revision();
+                }
+            }
         }
     }
 
-    async fn fetch_description(&self, name: &str) -> Result<String> {
+    async fn fetch_api_infos_page(
+        &self,
+        api: &Url,
+        names: &[String],
+    ) -> Result<HashMap<String, ApiInfo>> {
         #[derive(Debug, Deserialize)]
         struct ApiResponse {
-            #[serde(rename = "crate")]
-            krate: Krate,
+            crates: Vec<ApiCrate>,
         }
+
+        let url = api_list_url(api, names);
+        let res: ApiResponse = self
+            .fetch_content(Channel::Api, &url, None)
+            .await
+            .and_then(|body| {
+                serde_json::from_str(&body)
+                    .map_err(|_| Error::Parse { name: names.join(", ") })
+            })?;
+
+        Ok(res
+            .crates
+            .into_iter()
+            .map(|krate| (krate.name.clone(), krate.into_info(None)))
+            .collect())
+    }
+
+    async fn fetch_api_info(&self, api: &Url, name: &str) -> Result<ApiInfo> {
         #[derive(Debug, Deserialize)]
-        struct Krate {
-            description: String,
+        struct ApiResponse {
+            #[serde(rename = "crate")]
+            krate: ApiCrate,
+            versions: Vec<ApiVersion>,
         }
 
-        self.fetch_content(&api_url(name))
+        let res: ApiResponse = self
+            .fetch_content(Channel::Api, &api_url(api, name), None)
             .await
             .and_then(|body| {
-                serde_json::from_str(&body).map_err(|_| Error::Parse {
-                    name: name.to_owned(),
-                })
-            })
-            .map(|res: ApiResponse| res.krate.description)
-    }
-
-    async fn fetch_endpoint(&self, url: &str) -> Result<Response> {
-        let res =
-            self.client
-                .get(url)
-                .send()
-                .await
-                .map_err(|_| Error::Request {
-                    url: url.to_owned(),
-                })?;
-
-        res.status()
-            .is_success()
-            .then_some(res)
-            .ok_or(Error::Request {
-                url: url.to_owned(),
-            })
-    }
+                serde_json::from_str(&body)
+                    .map_err(|_| Error::Parse { name: name.to_owned() })
+            })?;
 
-    async fn fetch_content(&self, url: &str) -> Result<String> {
-        let res: Response = self.fetch_endpoint(url).await?;
-        res.text().await.map_err(|_| Error::Request {
-            url: url.to_owned(),
+        let versions = res
+            .versions
+            .into_iter()
+            .filter_map(|v| Some((semver::Version::parse(&v.num).ok()?, v)))
+            .collect::<Vec<_>>();
+        let release_dates = versions
+            .iter()
+            .map(|(version, v)| (version.clone(), v.created_at))
+            .collect();
+        let crate_sizes = versions
+            .iter()
+            .filter_map(|(version, v)| Some((version.clone(), v.crate_size?)))
+            .collect();
+        let publishers = versions
+            .into_iter()
+            .filter_map(|(version, v)| Some((version, v.publisher()?)))
+            .collect();
+
+        Ok(ApiInfo {
+            publishers,
+            crate_sizes,
+            ..res.krate.into_info(Some(release_dates))
         })
     }
 
-    /// Checks if a crate is available.
+    /// Fetch `url`, with the `token` of its registry (if it's private).
     ///
-    /// This function is meant for checking whether a crate name is a valid
-    /// name of an existing crate.
-    pub async fn is_availabe(&self, name: &str) -> bool {
-        // we check the cache first, and then (if entry does not exist) we
-        // check the crates.io endpoint.
-        self.crates.lock().await.contains_key(name)
-            || self.fetch_endpoint(&index_url(name)).await.is_ok()
-    }
-
-    pub async fn fetch(&self, name: &str) -> Result<Latest> {
-        if let Some(entry) = self.crates.lock().await.get_mut(name) {
-            let description = if let Some(description) = &entry.description {
-                Some(description.to_owned())
-            } else {
-                let description = self.fetch_description_rated(name).await;
-                if let Some(description) = &description {
-                    entry.description = Some(description.to_owned());
-                }
-                description
-            };
-            return Ok(Latest {
-                version: entry.version.clone(),
-                features: entry.features.clone(),
-                description,
-            });
+    /// With the `etag` of a cached response, the response may be `304 Not
+    /// Modified` (and empty).
+    async fn fetch_endpoint(
+        &self,
+        channel: Channel,
+        url: &str,
+        token: Option<&Token>,
+        etag: Option<&str>,
+    ) -> Result<Response> {
+        let clients = self.clients.read().await;
+        let (client, timeout) = match channel {
+            Channel::Index => (clients.index.clone(), &self.index_timeout),
+            Channel::Api => (clients.api.clone(), &self.api_timeout),
+        };
+        drop(clients);
+        let timeout = Duration::from_millis(timeout.load(Ordering::Relaxed));
+        let mut request = platform::timeout(client.get(url), timeout);
+        if let Some(token) = token {
+            request = request.header(AUTHORIZATION, token.as_str());
+        }
+        if let Some(etag) = etag {
+            request = request.header(IF_NONE_MATCH, etag);
         }
-        let entries = self
-            .fetch_content(&index_url(name))
+        let res = platform::send(request.send())
             .await
-            .and_then(|body| Index::parse(name, &body))?
-            .entries;
-        let latest = entries.last().ok_or_else(|| Error::Parse {
-            name: name.to_owned(),
-        })?;
-
-        let version =
-            semver::Version::parse(&latest.vers).map_err(|_| Error::Parse {
-                name: name.to_owned(),
-            })?;
-        let features = if latest.v == 2 {
-            latest.features2.clone()
-        } else {
-            latest.features.clone()
+            .map_err(|_| Error::Request { url: url.to_owned() })?;
+
+        match res.status() {
+            status if status.is_success() => Ok(res),
+            StatusCode::NOT_MODIFIED if etag.is_some() => Ok(res),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                Err(Error::Unauthorized { url: url.to_owned() })
+            }
+            StatusCode::NOT_FOUND => {
+                Err(Error::NotFound { url: url.to_owned() })
+            }
+            _ => Err(Error::Request { url: url.to_owned() }),
+        }
+    }
+
+    async fn fetch_content(
+        &self,
+        channel: Channel,
+        url: &str,
+        token: Option<&Token>,
+    ) -> Result<String> {
+        let start = Instant::now();
+        let content = platform::send(async move {
+            let res = self.fetch_endpoint(channel, url, token, None).await?;
+            read_content(url, res).await
+        })
+        .await;
+        self.metrics.fetch(start.elapsed());
+        content
+    }
+
+    /// Fetch an index file through the [disk cache](DiskCache), if it's
+    /// enabled: a fresh cached file is used as is, and a stale one is only
+    /// fetched again if the registry says it changed (by its ETag).
+    async fn fetch_index_file(
+        &self,
+        url: &str,
+        token: Option<&Token>,
+    ) -> Result<String> {
+        let disk = self.disk.read().await.clone();
+        let Some(disk) = disk else {
+            return self.fetch_content(Channel::Index, url, token).await;
         };
+        let cached = disk.read(url);
+        if let Some(cached) = &cached
+            && disk.is_fresh(cached)
+        {
+            return Ok(cached.content.clone());
+        }
+
+        let start = Instant::now();
+        let etag = cached.as_ref().and_then(|c| c.etag.clone());
+        let content = platform::send(async move {
+            let res = self.fetch_endpoint(
+                Channel::Index,
+                url,
+                token,
+                etag.as_deref(),
+            );
+            let res = res.await?;
+            if res.status() == StatusCode::NOT_MODIFIED
+                && let Some(cached) = cached
+            {
+                let _ = disk.write(url, etag.as_deref(), &cached.content);
+                return Ok(cached.content);
+            }
+
+            let etag = res.headers().get(ETAG).and_then(|e| e.to_str().ok());
+            let etag = etag.map(str::to_owned);
+            let content = read_content(url, res).await?;
+            let _ = disk.write(url, etag.as_deref(), &content);
+            Ok(content)
+        })
+        .await;
+        self.metrics.fetch(start.elapsed());
+        content
+    }
+
+    /// Cache the fetched index files on disk (or stop, with `None`), and
+    /// fit the cache to its (new) cap.
+    pub async fn set_disk_cache(&self, disk: Option<DiskCache>) {
+        *self.disk.write().await = disk.clone();
+        if let Some(disk) = disk
+            && let Err(err) = platform::blocking(move || disk.evict()).await
+        {
+            eprintln!("failed to evict from the disk cache: {err}");
+        }
+    }
+
+    /// Search crates.io for the crates matching `query` as it's typed (e.g.
+    /// for completions), only if no newer search starts meanwhile, and the
+    /// [API budget](crate::budget) allows a request right away, as the user
+    /// is waiting on it. The searches don't take the part of the budget
+    /// reserved for the other requests the user waits on (e.g. hovers), as
+    /// every keystroke may search.
+    pub async fn search(&self, query: &str) -> Result<Vec<SearchResult>> {
+        if !self.is_api_enabled() {
+            return Err(Error::ApiDisabled);
+        }
+        if self.is_offline() {
+            return Err(Error::Offline);
+        }
+        if let Some(found) = self.recent_search(query).await {
+            return Ok(found);
+        }
 
-        let latest = Latest {
-            description: None,
-            version,
-            features,
+        let search = self.searches.fetch_add(1, Ordering::Relaxed) + 1;
+        platform::sleep(SEARCH_DEBOUNCE).await;
+        if self.searches.load(Ordering::Relaxed) != search {
+            // the newer search is the one the user waits for.
+            return Ok(Vec::new());
+        }
+
+        let api = self.api_url().await.ok_or(Error::Config)?;
+        if !self.api_budget.try_acquire(Priority::Background).await {
+            // the next keystroke searches again.
+            return Ok(Vec::new());
+        }
+        let url = api_search_url(&api, query, 1, SEARCH_LIMIT);
+        let found = self.search_api(&url).await?.crates;
+
+        let mut recent = self.recent_searches.lock().await;
+        if recent.len() >= RECENT_SEARCHES {
+            recent.retain(|_, (at, _)| at.elapsed() < RECENT_SEARCH_TTL);
+            if recent.len() >= RECENT_SEARCHES {
+                recent.clear();
+            }
+        }
+        recent.insert(query.to_owned(), (Instant::now(), found.clone()));
+        Ok(found)
+    }
+
+    /// The results of a recent search for `query`, if there was one.
+    async fn recent_search(&self, query: &str) -> Option<Vec<SearchResult>> {
+        let recent = self.recent_searches.lock().await;
+        let (at, found) = recent.get(query)?;
+        (at.elapsed() < RECENT_SEARCH_TTL).then(|| found.clone())
+    }
+
+    /// Search crates.io for a page (from 1) of the crates matching `query`,
+    /// waiting for the API's budget rather than skipping the search.
+    pub async fn search_page(
+        &self,
+        query: &str,
+        page: usize,
+        per_page: usize,
+    ) -> Result<SearchPage> {
+        if !self.is_api_enabled() {
+            return Err(Error::ApiDisabled);
+        }
+        if self.is_offline() {
+            return Err(Error::Offline);
+        }
+        let api = self.api_url().await.ok_or(Error::Config)?;
+        self.api_budget.acquire(Priority::Interactive).await;
+        self.search_api(&api_search_url(&api, query, page, per_page))
+            .await
+    }
+
+    /// Search an alternative registry for the crates matching `query`,
+    /// through its web API at `api` (e.g. `https://registry.example.com`).
+    pub async fn search_registry(
+        &self,
+        api: &str,
+        query: &str,
+    ) -> Result<Vec<SearchResult>> {
+        let api = crates_endpoint(api)
+            .ok_or_else(|| Error::Request { url: api.to_owned() })?;
+        let url = api_search_url(&api, query, 1, SEARCH_LIMIT);
+        self.search_api(&url).await.map(|page| page.crates)
+    }
+
+    async fn search_api(&self, url: &str) -> Result<SearchPage> {
+        #[derive(Debug, Deserialize)]
+        struct SearchResponse {
+            crates: Vec<SearchResult>,
+            meta: Option<SearchMeta>,
+        }
+        #[derive(Debug, Deserialize)]
+        struct SearchMeta {
+            total: u64,
+        }
+
+        let body = self.fetch_content(Channel::Api, url, None).await?;
+        serde_json::from_str::<SearchResponse>(&body)
+            .map(|res| SearchPage {
+                total: res.meta.map_or(res.crates.len() as u64, |m| m.total),
+                crates: res.crates,
+            })
+            .map_err(|_| Error::Search { url: url.to_owned() })
+    }
+
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Probe the registry, and switch to the offline mode (where only the
+    /// cached crates are served) if it's unreachable, or back from it.
+    ///
+    /// Returns whether the registry is reachable.
+    pub async fn check_health(&self) -> bool {
+        *self.last_probe.lock().await = Some(Instant::now());
+        let reachable = match self.fetch_config().await {
+            Ok(config) => {
+                *self.config.lock().await = Some(config);
+                true
+            }
+            // it's reachable, but the API won't be used.
+            Err(Error::Config) => true,
+            Err(_) => false,
         };
+        self.offline.store(!reachable, Ordering::Relaxed);
+        reachable
+    }
 
-        self.crates
-            .lock()
+    async fn fetch_config(&self) -> Result<RegistryConfig> {
+        self.fetch_content(Channel::Index, &config_url(), None)
             .await
-            .insert(name.to_owned(), latest.clone());
+            .and_then(|body| {
+                serde_json::from_str(&body).map_err(|_| Error::Config)
+            })
+    }
 
+    /// The URL of the crates endpoint of the registry's API, or `None` if
+    /// the registry doesn't declare an API (or its configuration isn't
+    /// known), or the API is disabled.
+    async fn api_url(&self) -> Option<Url> {
+        if !self.is_api_enabled() {
+            return None;
+        }
+        if let Some(config) = &*self.config.lock().await {
+            return config.api_url();
+        }
+        // fetched without holding the lock, so the requests that need the
+        // configuration meanwhile don't queue behind the request.
+        let config = self.fetch_config().await.ok()?;
+        let url = config.api_url();
+        *self.config.lock().await = Some(config);
+        url
+    }
+
+    pub fn is_api_enabled(&self) -> bool {
+        self.api_enabled.load(Ordering::Relaxed)
+    }
+
+    /// Use the web API of the registry (for descriptions, downloads and
+    /// search), or only its index.
+    pub fn set_api_enabled(&self, enabled: bool) {
+        self.api_enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.offline.load(Ordering::Relaxed)
+    }
+
+    /// Whether requests to the registry should be made, probing it again (at
+    /// most once per [`PROBE_INTERVAL`]) while it's unreachable.
+    async fn is_online(&self) -> bool {
+        if !self.is_offline() {
+            return true;
+        }
+        let last_probe = *self.last_probe.lock().await;
+        if last_probe.is_some_and(|last| last.elapsed() < PROBE_INTERVAL) {
+            return false;
+        }
+        self.check_health().await
+    }
+
+    pub async fn fetch(&self, name: &str) -> Result<CrateInfo> {
+        // stale entries are still served while the registry is unreachable.
+        let refresh = self.is_expired(name).await && self.is_online().await;
+        if !refresh && let Some(entry) = self.crates.lock().await.get_mut(name)
+        {
+            self.metrics.cache_hit();
+            return Ok(entry.clone());
+        }
+        self.metrics.cache_miss();
+        if !self.is_online().await {
+            return Err(Error::Offline);
+        }
+        let latest = match self.fetch_index(name).await {
+            Ok(index) => index.into_info(name, self.prereleases())?,
+            Err(err) if refresh => {
+                return self.keep_stale(name).await.ok_or(err);
+            }
+            Err(err) => return Err(err),
+        };
+        self.insert(name, name, latest.clone()).await;
+
+        Ok(latest)
+    }
+
+    /// Like [`Self::fetch`], along with the details of the versions that only
+    /// the API's endpoint of the crate has (the release dates of the versions
+    /// the index doesn't date, the publishers and the `.crate` sizes).
+    ///
+    /// As that's a request per crate, it's only made for the requests the
+    /// user waits on (e.g. a hover), and only if the budget allows it right
+    /// away. The diagnostics make do with [`Self::fetch_api_infos`].
+    pub async fn fetch_details(&self, name: &str) -> Result<CrateInfo> {
+        let info = self.fetch(name).await?;
+        if info.api.as_ref().is_some_and(|a| a.release_dates.is_some()) {
+            return Ok(info);
+        }
+        let Some(api) = self.fetch_api_info_rated(name).await else {
+            return Ok(info);
+        };
+        let mut crates = self.crates.lock().await;
+        let Some(entry) = crates.get_mut(name) else { return Ok(info) };
+        entry.api = Some(api);
+        entry.revision = self.next_revision();
+        Ok(entry.clone())
+    }
+
+    /// Fetch a crate from the sparse index of an alternative registry at
+    /// `index` (e.g. `https://registry.example.com/index`), with its `token`
+    /// if it's private.
+    ///
+    /// Only the index is fetched, as the API info is crates.io's.
+    pub async fn fetch_from(
+        &self,
+        index: &str,
+        token: Option<&Token>,
+        name: &str,
+    ) -> Result<CrateInfo> {
+        let url = index_url(index, name)
+            .ok_or_else(|| Error::InvalidName { name: name.to_owned() })?;
+        // the crates of alternative registries are cached by their URLs, as
+        // their names may be taken on crates.io too.
+        if !self.is_expired(&url).await
+            && let Some(entry) = self.crates.lock().await.get(&url)
+        {
+            self.metrics.cache_hit();
+            return Ok(entry.clone());
+        }
+        self.metrics.cache_miss();
+        let body = match self.fetch_index_file(&url, token).await {
+            Ok(body) => body,
+            Err(err) => return self.keep_stale(&url).await.ok_or(err),
+        };
+        let latest =
+            Index::parse(name, &body).into_info(name, self.prereleases())?;
+        self.insert(&url, name, latest.clone()).await;
+
+        Ok(latest)
+    }
+
+    /// Fetch a crate from the legacy git index of an alternative registry at
+    /// `index` (e.g. `https://example.com/index.git`), which is fetched into
+    /// the disk cache (see [`crate::git_index`]) whenever it's older than the
+    /// TTL of the disk cache.
+    pub async fn fetch_from_git(
+        &self,
+        index: &str,
+        name: &str,
+    ) -> Result<CrateInfo> {
+        let url = index_url(index, name)
+            .ok_or_else(|| Error::InvalidName { name: name.to_owned() })?;
+        if !self.is_expired(&url).await
+            && let Some(entry) = self.crates.lock().await.get(&url)
+        {
+            self.metrics.cache_hit();
+            return Ok(entry.clone());
+        }
+        self.metrics.cache_miss();
+
+        let body = match self.read_git_index(index, &url).await {
+            Ok(body) => body,
+            Err(err) => return self.keep_stale(&url).await.ok_or(err),
+        };
+
+        let latest =
+            Index::parse(name, &body).into_info(name, self.prereleases())?;
+        self.insert(&url, name, latest.clone()).await;
         Ok(latest)
     }
+
+    /// Read the file at `url` of the git index at `index`, fetching the
+    /// index first if it's stale.
+    async fn read_git_index(&self, index: &str, url: &str) -> Result<String> {
+        let disk = self.disk.read().await.clone();
+        let disk =
+            disk.ok_or_else(|| Error::NoDiskCache { url: index.to_owned() })?;
+        let failed = || Error::GitIndex { url: index.to_owned() };
+        let git = disk.git_index(index).ok_or_else(failed)?;
+        let start = Instant::now();
+        let fetch = self.git_fetch.lock().await;
+        git.update(disk.ttl()).await.map_err(|_| failed())?;
+        drop(fetch);
+        // the path of the crate's file in the index.
+        let path = &url[index.len() + 1..];
+        let body = git.read(path).await.map_err(|_| failed())?;
+        self.metrics.fetch(start.elapsed());
+        body.ok_or(Error::Request { url: url.to_owned() })
+    }
+
+    /// The cached (stale) info of a crate whose refresh failed, which is then
+    /// counted as fetched now, so it's only retried once the TTL passes
+    /// again, rather than by every request meanwhile.
+    async fn keep_stale(&self, key: &str) -> Option<CrateInfo> {
+        let mut crates = self.crates.lock().await;
+        let entry = crates.get_mut(key)?;
+        entry.fetched_at = Instant::now();
+        Some(entry.clone())
+    }
+
+    /// Cache the info of the crate `name` under `key`, and record it as an
+    /// update if it's newer than the cached one.
+    async fn insert(&self, key: &str, name: &str, mut latest: CrateInfo) {
+        latest.revision = self.next_revision();
+        let mut crates = self.crates.lock().await;
+        let mut order = self.order.lock().await;
+        match crates.insert(key.to_owned(), latest.clone()) {
+            None => order.push_back(key.to_owned()),
+            Some(previous) if previous.version < latest.version => {
+                self.updates.lock().await.push(Update {
+                    name: name.to_owned(),
+                    previous: previous.version.to_string(),
+                    latest: latest.version.to_string(),
+                });
+            }
+            Some(_) => {}
+        }
+        evict(
+            &mut crates,
+            &mut order,
+            self.max_crates.load(Ordering::Relaxed),
+        );
+    }
+
+    /// Fetch the index of a crate, falling back to the names that crates.io
+    /// treats as the same (see [`equivalent_names`]) if there's none under
+    /// `name` itself.
+    async fn fetch_index(&self, name: &str) -> Result<Index> {
+        let url = |name: &str| {
+            index_url(REGISTRY_URL, name)
+                .ok_or_else(|| Error::InvalidName { name: name.to_owned() })
+        };
+
+        let mut result = self.fetch_index_file(&url(name)?, None).await;
+        for alternative in equivalent_names(name) {
+            if !matches!(
+                result,
+                Err(Error::Request { .. } | Error::NotFound { .. })
+            ) {
+                break;
+            }
+            result = self.fetch_index_file(&url(&alternative)?, None).await;
+        }
+        result.map(|body| Index::parse(name, &body))
+    }
+
+    /// Warm the cache with the given crates that aren't cached yet (e.g. the
+    /// packages of a lockfile), a few at once. Prefetching never evicts
+    /// cached crates, so only as many crates as the cache has room for are
+    /// fetched.
+    pub async fn prefetch(&self, names: &[String]) {
+        let missing = {
+            let crates = self.crates.lock().await;
+            let max = self.max_crates.load(Ordering::Relaxed);
+            names
+                .iter()
+                .filter(|&name| !crates.contains_key(name))
+                .take(max.saturating_sub(crates.len()))
+                .collect::<Vec<_>>()
+        };
+
+        futures::stream::iter(missing)
+            .for_each_concurrent(self.concurrency(), |name| async move {
+                if !self.is_cancelled() {
+                    let _ = self.fetch(name).await;
+                }
+            })
+            .await;
+    }
+
+    /// Whether `name` is cached, but for longer than the TTL.
+    async fn is_expired(&self, name: &str) -> bool {
+        let ttl = self.ttl.load(Ordering::Relaxed);
+        self.crates.lock().await.get(name).is_some_and(|entry| {
+            entry.fetched_at.elapsed() > Duration::from_secs(ttl)
+        })
+    }
+
+    /// Refresh the given crates that are cached for longer than the TTL, so
+    /// new releases are found while their manifests are open.
+    pub async fn refresh_expired(&self, names: &[&str]) {
+        for name in names {
+            if !self.is_cancelled() && self.is_expired(name).await {
+                let _ = self.fetch(name).await;
+            }
+        }
+    }
+
+    /// Take the new versions that were found by refreshing the cache.
+    pub async fn take_updates(&self) -> Vec<Update> {
+        std::mem::take(&mut *self.updates.lock().await)
+    }
+
+    /// Let pre-releases be the latest versions (or stop), re-selecting the
+    /// latest versions of the cached crates.
+    pub async fn set_prereleases(&self, prereleases: bool) {
+        if self.prereleases.swap(prereleases, Ordering::Relaxed) == prereleases
+        {
+            return;
+        }
+        for info in self.crates.lock().await.values_mut() {
+            info.select_latest(prereleases);
+        }
+        self.bump_generation();
+    }
+
+    fn prereleases(&self) -> bool {
+        self.prereleases.load(Ordering::Relaxed)
+    }
+
+    /// Refresh the cached crates after `ttl` (`None` to never refresh them).
+    pub fn set_ttl(&self, ttl: Option<Duration>) {
+        let ttl = ttl.map_or(u64::MAX, |ttl| ttl.as_secs());
+        self.ttl.store(ttl, Ordering::Relaxed);
+    }
+
+    /// Limit the number of crates that are fetched at once (`None` for the
+    /// default).
+    pub fn set_concurrency(&self, concurrency: Option<usize>) {
+        let concurrency = concurrency.unwrap_or(CONCURRENCY).max(1);
+        self.concurrency.store(concurrency, Ordering::Relaxed);
+    }
+
+    /// The maximal number of crates that are fetched at once.
+    pub fn concurrency(&self) -> usize {
+        self.concurrency.load(Ordering::Relaxed)
+    }
+
+    /// Make the requests the way cargo is configured to (see
+    /// [`HttpConfig`]), keeping the current clients if it's invalid.
+    pub async fn set_http(&self, http: &HttpConfig) -> Result<()> {
+        *self.clients.write().await = Clients::new(http)?;
+        Ok(())
+    }
+
+    /// Set the timeouts of the requests to the index and to the API (`None`
+    /// for the defaults).
+    pub fn set_timeouts(&self, index: Option<Duration>, api: Option<Duration>) {
+        let index = millis(index.unwrap_or(INDEX_TIMEOUT));
+        self.index_timeout.store(index, Ordering::Relaxed);
+        let api = millis(api.unwrap_or(API_TIMEOUT));
+        self.api_timeout.store(api, Ordering::Relaxed);
+    }
+
+    /// Limit the number of cached crates (`None` for no limit).
+    pub async fn set_max_crates(&self, max: Option<usize>) {
+        let max = max.unwrap_or(usize::MAX);
+        self.max_crates.store(max, Ordering::Relaxed);
+
+        let mut crates = self.crates.lock().await;
+        let mut order = self.order.lock().await;
+        evict(&mut crates, &mut order, max);
+    }
+
+    pub async fn stats(&self) -> CacheStats {
+        let disk = self.disk.read().await.clone();
+        let (disk_dir, disk) = match disk {
+            Some(disk) => {
+                let dir = disk.dir().display().to_string();
+                (
+                    Some(dir),
+                    platform::blocking(move || disk.usage().ok()).await,
+                )
+            }
+            None => (None, None),
+        };
+        let crates = self.crates.lock().await;
+        let max = self.max_crates.load(Ordering::Relaxed);
+        CacheStats {
+            crates: crates.len(),
+            api_infos: crates.values().filter(|c| c.api.is_some()).count(),
+            max_crates: (max != usize::MAX).then_some(max),
+            disk_dir,
+            disk,
+        }
+    }
+
+    /// Stop the background work, as the server is shutting down.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+        // wake the task that drains the queue, so it sees it.
+        self.api_queued.notify_one();
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Drop all of the cached data (on disk too), so it's fetched again.
+    pub async fn clear(&self) {
+        if let Some(disk) = &*self.disk.read().await
+            && let Err(err) = disk.clear()
+        {
+            eprintln!("failed to clear the disk cache: {err}");
+        }
+        self.crates.lock().await.clear();
+        self.order.lock().await.clear();
+        self.recent_searches.lock().await.clear();
+        self.readmes.lock().await.clear();
+        self.bump_generation();
+    }
+
+    /// The current generation of the cached data; it changes whenever the
+    /// data does.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    /// The [revision](CrateInfo::revision) of the crate cached under `key`
+    /// (its name, or the URL of its index file for alternative registries,
+    /// see [`Self::key`]), if it's cached.
+    pub async fn revision(&self, key: &str) -> Option<u64> {
+        self.crates.lock().await.get(key).map(|info| info.revision)
+    }
+
+    /// Cache the crate `name` of crates.io from the `entries` of its index,
+    /// as if it was fetched.
+    #[cfg(test)]
+    pub async fn insert_index(&self, name: &str, entries: &str) {
+        let info = Index::parse(name, entries).into_info(name, false).unwrap();
+        self.insert(name, name, info).await;
+    }
+
+    /// The key the crate `name` of the registry at `index` (or crates.io) is
+    /// cached under.
+    pub fn key(index: Option<&str>, name: &str) -> Option<String> {
+        match index {
+            None => Some(name.to_owned()),
+            Some(index) => index_url(index, name),
+        }
+    }
+
+    fn next_revision(&self) -> u64 {
+        self.revision.fetch_add(1, Ordering::AcqRel) + 1
+    }
+
+    fn bump_generation(&self) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+    }
 }
 
 impl Default for RegistryCache {
@@ -175,39 +1157,488 @@ impl Default for RegistryCache {
     }
 }
 
-// TODO: better name
+/// What the cache holds, for the `cache_stats` command.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheStats {
+    pub crates: usize,
+    /// How many of the crates have their crates.io API info cached.
+    pub api_infos: usize,
+    pub max_crates: Option<usize>,
+    /// The directory of the disk cache, if it's enabled.
+    pub disk_dir: Option<String>,
+    /// How much of the disk the disk cache takes, if it's enabled.
+    pub disk: Option<Usage>,
+}
+
+/// The configuration of a sparse registry (its `config.json`).
+#[derive(Debug, Clone, Deserialize)]
+struct RegistryConfig {
+    /// The base URL of the registry's web API, if it has one.
+    api: Option<String>,
+}
+
+impl RegistryConfig {
+    fn api_url(&self) -> Option<Url> {
+        crates_endpoint(self.api.as_deref()?)
+    }
+}
+
+/// The crates endpoint of a registry's web API, from its base URL.
+fn crates_endpoint(api: &str) -> Option<Url> {
+    let api = api.trim_end_matches('/');
+    Url::parse(&format!("{api}/api/v1/crates")).ok()
+}
+
+/// Which of the HTTP clients a request goes through. The index is fetched
+/// with many requests at once, while the API is rate limited (see
+/// [`crate::budget`]), so they don't compete for connections.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Channel {
+    Index,
+    Api,
+}
+
+/// The HTTP clients of each [`Channel`].
+#[derive(Debug, Default)]
+struct Clients {
+    index: reqwest::Client,
+    api: reqwest::Client,
+}
+
+impl Clients {
+    fn new(http: &HttpConfig) -> Result<Self> {
+        let ca = match &http.cainfo {
+            Some(path) => Some(std::fs::read(path).map_err(|_| {
+                Error::CaInfo { path: path.display().to_string() }
+            })?),
+            None => None,
+        };
+        let client = |max_idle| {
+            let proxy = http.proxy.as_deref();
+            platform::http_client(USER_AGENT, max_idle, proxy, ca.as_deref())
+                .map_err(|err| Error::Client { message: err.to_string() })
+        };
+        Ok(Self { index: client(INDEX_MAX_IDLE)?, api: client(API_MAX_IDLE)? })
+    }
+}
+
+/// Read the content of a response, unless it isn't data (e.g. a login page
+/// of a proxy), or it's too large.
+async fn read_content(url: &str, res: Response) -> Result<String> {
+    if let Some(content_type) = res
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|t| t.to_str().ok())
+        && !is_data(content_type)
+    {
+        return Err(Error::ContentType {
+            url: url.to_owned(),
+            content_type: content_type.to_owned(),
+        });
+    }
+
+    let body = platform::read_body(res, MAX_CONTENT_LENGTH)
+        .await
+        .map_err(|_| Error::Request { url: url.to_owned() })?
+        .ok_or(Error::TooLarge { url: url.to_owned() })?;
+    String::from_utf8(body).map_err(|_| Error::Request { url: url.to_owned() })
+}
+
+fn millis(duration: Duration) -> u64 {
+    duration.as_millis().try_into().unwrap_or(u64::MAX)
+}
+
+/// A crate found by searching a registry.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SearchResult {
+    pub name: String,
+    pub max_version: String,
+    pub description: Option<String>,
+    /// All-time downloads (`0` for registries that don't count them).
+    #[serde(default)]
+    pub downloads: u64,
+}
+
+/// The name of a crate, and one of its versions.
+type CrateVersion = (String, semver::Version);
+
+/// The results of a search, and when it was made.
+type RecentSearch = (Instant, Vec<SearchResult>);
+
+/// A page of the crates found by searching a registry.
+#[derive(Debug, Clone)]
+pub struct SearchPage {
+    pub crates: Vec<SearchResult>,
+    /// The number of the matching crates, on all of the pages.
+    pub total: u64,
+}
+
 #[derive(Clone, Debug)]
-pub struct Latest {
+pub struct CrateInfo {
+    /// The canonical name of the crate, which may differ from the name it
+    /// was fetched by in its `-` and `_` (see [`equivalent_names`]).
+    pub name: String,
+    /// The latest version, as selected by [`Self::select_latest`].
+    pub version: semver::Version,
+    /// The last published version, which may be yanked or a pre-release.
+    pub raw_latest: semver::Version,
+    /// The features of [`Self::version`].
+    pub features: Option<HashMap<String, Vec<String>>>,
+    /// All of the published versions, in the order they were published.
+    pub versions: Vec<VersionEntry>,
+    /// Fetched lazily, as the API is rate limited.
+    pub api: Option<ApiInfo>,
+    /// When the index of the crate was fetched.
+    pub fetched_at: Instant,
+    /// Changes whenever the cached info of the crate does (e.g. its API info
+    /// arrives), so the analyses that are based on it know when they are
+    /// stale.
+    pub revision: u64,
+}
+
+/// A new version of a cached crate, that was found by refreshing it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Update {
+    pub name: String,
+    /// The latest version before the refresh.
+    pub previous: String,
+    pub latest: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VersionEntry {
     pub version: semver::Version,
+    pub yanked: bool,
+    /// The native library the version links (its `links` key).
+    pub links: Option<String>,
+    /// The number of its direct (normal and build) dependencies, including
+    /// the optional ones.
+    pub dependencies: usize,
+    /// The features of the version, if the index lists them.
     pub features: Option<HashMap<String, Vec<String>>>,
+    /// The minimal supported Rust version of the version (its
+    /// `rust-version`, e.g. `1.70`), if it declares one.
+    pub rust_version: Option<String>,
+    /// When the version was published, if the index tells (it does for the
+    /// versions published since it has the `pubtime` field).
+    pub published: Option<DateTime<Utc>>,
+}
+
+impl CrateInfo {
+    /// Select the version that's shown as the latest: the highest one that
+    /// isn't yanked, and isn't a pre-release either, unless `prereleases`
+    /// (or the crate only has pre-releases). If all of the versions are
+    /// yanked, it's the last published one.
+    pub fn select_latest(&mut self, prereleases: bool) {
+        let unyanked = self.versions.iter().filter(|v| !v.yanked);
+        let latest = unyanked
+            .clone()
+            .filter(|v| prereleases || v.version.pre.is_empty())
+            .max_by(|a, b| a.version.cmp(&b.version))
+            .or_else(|| unyanked.max_by(|a, b| a.version.cmp(&b.version)))
+            .or_else(|| self.version(&self.raw_latest));
+        if let Some(latest) = latest {
+            let (version, features) =
+                (latest.version.clone(), latest.features.clone());
+            self.version = version;
+            self.features = features;
+        }
+    }
+
+    /// The version that cargo picks for a requirement without a lockfile: the
+    /// latest unyanked version that matches it (any, without a requirement).
+    pub fn resolve(
+        &self,
+        req: Option<&semver::VersionReq>,
+    ) -> Option<&VersionEntry> {
+        self.versions
+            .iter()
+            .filter(|v| !v.yanked)
+            .filter(|v| req.is_none_or(|req| req.matches(&v.version)))
+            .max_by(|a, b| a.version.cmp(&b.version))
+    }
+
+    /// The features (and optional dependencies, as `dep:<name>`) that a
+    /// feature enables, directly or through the features it enables, in
+    /// the order they're reached. The features of dependencies (`foo/bar`)
+    /// are left out.
+    pub fn implied_features(&self, feature: &str) -> Vec<&str> {
+        let Some(features) = &self.features else { return Vec::new() };
+        let mut implied = Vec::new();
+        let mut pending = vec![feature];
+        while let Some(feature) = pending.pop() {
+            for enabled in features.get(feature).into_iter().flatten() {
+                let enabled = enabled.as_str();
+                if enabled.contains('/')
+                    || enabled == feature
+                    || implied.contains(&enabled)
+                {
+                    continue;
+                }
+                implied.push(enabled);
+                pending.push(enabled);
+            }
+        }
+        implied
+    }
+
+    /// All of the published versions (including the yanked ones), in the
+    /// order they were published.
+    pub fn versions(&self) -> &[VersionEntry] {
+        &self.versions
+    }
+
+    /// The published `version`, if it's known.
+    pub fn version(&self, version: &semver::Version) -> Option<&VersionEntry> {
+        self.versions.iter().find(|v| &v.version == version)
+    }
+
+    /// Whether `version` was yanked (unknown versions aren't).
+    pub fn is_yanked(&self, version: &semver::Version) -> bool {
+        self.version(version).is_some_and(|v| v.yanked)
+    }
+
+    /// Who published `version`, if it's known.
+    pub fn publisher(&self, version: &semver::Version) -> Option<&Publisher> {
+        self.api.as_ref()?.publishers.get(version)
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.api.as_ref()?.description.as_deref()
+    }
+
+    /// The crate's documentation, as declared by the crate (if it's known),
+    /// falling back to its docs.rs page.
+    pub fn docs_url(&self, name: &str) -> String {
+        self.api
+            .as_ref()
+            .and_then(|api| api.documentation.clone())
+            .unwrap_or_else(|| format!("{DOCS_RS_URL}/{name}"))
+    }
+
+    /// The crate's repository, at the tag of `version` (for the known
+    /// forges), if the repository is known.
+    pub fn source_url(&self, version: &semver::Version) -> Option<String> {
+        let repository = self.api.as_ref()?.repository.as_deref()?;
+        Some(repository_tag_url(repository, version))
+    }
+
+    /// When `version` was released, according to the API, or else the
+    /// index.
+    pub fn release_date(
+        &self,
+        version: &semver::Version,
+    ) -> Option<DateTime<Utc>> {
+        let api = self
+            .api
+            .as_ref()
+            .and_then(|api| api.release_dates.as_ref()?.get(version).copied());
+        api.or_else(|| {
+            let entry = self.versions.iter().find(|v| &v.version == version);
+            entry?.published
+        })
+    }
+}
+
+/// Crate data that is only available through the crates.io API (and not
+/// through the index).
+#[derive(Clone, Debug, Default)]
+pub struct ApiInfo {
     pub description: Option<String>,
+    pub homepage: Option<String>,
+    pub documentation: Option<String>,
+    pub repository: Option<String>,
+    pub max_stable_version: Option<semver::Version>,
+    /// Downloads in the last 90 days.
+    pub recent_downloads: Option<u64>,
+    /// `None` if only the description was fetched (see
+    /// [`RegistryCache::fetch_api_infos`]).
+    pub release_dates: Option<HashMap<semver::Version, DateTime<Utc>>>,
+    /// Who published each of the versions, when the API tells (empty if only
+    /// the description was fetched).
+    pub publishers: HashMap<semver::Version, Publisher>,
+    /// The sizes of the `.crate` files of the versions, in bytes (empty if
+    /// only the description was fetched).
+    pub crate_sizes: HashMap<semver::Version, u64>,
+}
+
+/// How a version was published.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Publisher {
+    /// By a CI workflow, through [trusted publishing].
+    ///
+    /// [trusted publishing]: https://crates.io/docs/trusted-publishing
+    Trusted {
+        /// e.g. `github`.
+        provider: String,
+        /// e.g. `rust-lang/crates.io`.
+        repository: Option<String>,
+    },
+    /// By an owner, with an API token.
+    User { login: String },
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// The crate object of the API responses.
+#[derive(Debug, Deserialize)]
+struct ApiCrate {
+    name: String,
+    description: Option<String>,
+    homepage: Option<String>,
+    documentation: Option<String>,
+    repository: Option<String>,
+    max_stable_version: Option<String>,
+    recent_downloads: Option<u64>,
+}
+
+/// The version object of the API responses.
+#[derive(Debug, Deserialize)]
+struct ApiVersion {
+    num: String,
+    created_at: DateTime<Utc>,
+    published_by: Option<ApiUser>,
+    trustpub_data: Option<ApiTrustPub>,
+    crate_size: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiUser {
+    login: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiTrustPub {
+    provider: String,
+    repository: Option<String>,
+}
+
+impl ApiVersion {
+    fn publisher(&self) -> Option<Publisher> {
+        if let Some(data) = &self.trustpub_data {
+            return Some(Publisher::Trusted {
+                provider: data.provider.clone(),
+                repository: data.repository.clone(),
+            });
+        }
+        let login = self.published_by.as_ref()?.login.clone();
+        Some(Publisher::User { login })
+    }
+}
+
+impl ApiCrate {
+    fn into_info(
+        self,
+        release_dates: Option<HashMap<semver::Version, DateTime<Utc>>>,
+    ) -> ApiInfo {
+        ApiInfo {
+            description: self.description,
+            homepage: self.homepage,
+            documentation: self.documentation,
+            repository: self.repository,
+            max_stable_version: self
+                .max_stable_version
+                .and_then(|v| semver::Version::parse(&v).ok()),
+            recent_downloads: self.recent_downloads,
+            release_dates,
+            publishers: HashMap::new(),
+            crate_sizes: HashMap::new(),
+        }
+    }
+}
+
 #[derive(Debug)]
 struct Index {
     pub entries: Vec<Entry>,
 }
 
+/// Whether a response of `content_type` might be data (the index is served
+/// as `text/plain`, `application/json`, or even `application/octet-stream`),
+/// rather than a page (e.g. of an error).
+fn is_data(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or_default().trim();
+    !mime.eq_ignore_ascii_case("text/html")
+}
+
 impl Index {
-    pub fn parse(name: &str, json_entries: &str) -> Result<Self> {
+    /// Parse the lines of an index file.
+    ///
+    /// Historic index data occasionally contains oddities, so the entries
+    /// that fail to parse (or whose version isn't valid semver) are skipped,
+    /// rather than failing the whole crate.
+    ///
+    /// Its size is bounded by [`MAX_CONTENT_LENGTH`], and `serde_json` limits
+    /// the nesting of the JSON (to 128 levels), so a pathological line is
+    /// skipped rather than exhausting the stack.
+    pub fn parse(name: &str, json_entries: &str) -> Self {
         // OPTIMIZE: is counting the lines here worth it?
         let mut entries = Vec::with_capacity(json_entries.lines().count());
 
-        for line in json_entries.lines() {
-            let entry =
-                serde_json::from_str(line).map_err(|_| Error::Parse {
-                    name: name.to_owned(),
-                })?;
-            entries.push(entry);
+        for (i, line) in json_entries.lines().enumerate() {
+            match serde_json::from_str::<Entry>(line) {
+                Ok(entry) if semver::Version::parse(&entry.vers).is_ok() => {
+                    entries.push(entry)
+                }
+                Ok(entry) => eprintln!(
+                    "skipping entry {i} of the index of `{name}`: invalid \
+                     version `{}`",
+                    entry.vers
+                ),
+                Err(err) => eprintln!(
+                    "skipping entry {i} of the index of `{name}`: {err}"
+                ),
+            }
         }
 
-        Ok(Self { entries })
+        Self { entries }
+    }
+
+    /// The info of the crate whose index this is, whose latest version is
+    /// selected by [`CrateInfo::select_latest`].
+    fn into_info(self, name: &str, prereleases: bool) -> Result<CrateInfo> {
+        let parse_error = || Error::Parse { name: name.to_owned() };
+        let last = self.entries.last().ok_or_else(parse_error)?;
+        let raw_latest =
+            semver::Version::parse(&last.vers).map_err(|_| parse_error())?;
+        let versions = self
+            .entries
+            .iter()
+            .filter_map(|entry| {
+                Some(VersionEntry {
+                    version: semver::Version::parse(&entry.vers).ok()?,
+                    yanked: entry.yanked,
+                    links: entry.links.clone(),
+                    dependencies: entry
+                        .deps
+                        .iter()
+                        .filter(|d| d.kind != DependencyKind::Dev)
+                        .count(),
+                    features: entry.all_features(),
+                    rust_version: entry.rust_version.clone(),
+                    published: entry.pubtime,
+                })
+            })
+            .collect();
+
+        let mut info = CrateInfo {
+            name: last.name.clone(),
+            version: raw_latest.clone(),
+            raw_latest,
+            features: None,
+            versions,
+            api: None,
+            fetched_at: Instant::now(),
+            revision: 0,
+        };
+        info.select_latest(prereleases);
+        Ok(info)
     }
 }
 
 /// https://doc.rust-lang.org/cargo/reference/registry-index.html#json-schema
+// this mirrors the schema, so not all of the fields are in use.
+#[allow(dead_code)]
 #[derive(Deserialize, Debug)]
 struct Entry {
     /// The name of the package.
@@ -271,8 +1702,31 @@ struct Entry {
     /// This must be a valid version requirement without an operator (e.g. no
     /// `=`)
     pub rust_version: Option<String>,
+    /// The time the version was published (optional), e.g.
+    /// `2025-01-01T00:00:00Z`. Only set for the versions published since the
+    /// field was introduced.
+    pub pubtime: Option<DateTime<Utc>>,
+}
+
+impl Entry {
+    /// The features of the entry, merging `features2` into `features` (as
+    /// cargo does).
+    fn all_features(&self) -> Option<HashMap<String, Vec<String>>> {
+        match (&self.features, &self.features2) {
+            (None, None) => None,
+            (features, features2) => Some(
+                features
+                    .iter()
+                    .chain(features2)
+                    .flatten()
+                    .map(|(name, enables)| (name.clone(), enables.clone()))
+                    .collect(),
+            ),
+        }
+    }
 }
 
+#[allow(dead_code)]
 #[derive(Deserialize, Debug)]
 struct Dependency {
     /// Name of the dependency.
@@ -314,20 +1768,15 @@ struct Dependency {
     pub package: Option<String>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Default, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 enum DependencyKind {
     Dev,
     Build,
+    #[default]
     Normal,
 }
 
-impl Default for DependencyKind {
-    fn default() -> Self {
-        Self::Normal
-    }
-}
-
 fn return_true() -> bool {
     true
 }
@@ -340,65 +1789,453 @@ fn return_1() -> u32 {
     1
 }
 
-/// Get the path to the index file of the crate according to [Cargo's docs].
-///
-/// # Panics
+/// Names that crates.io reserves, as they are device names on Windows.
+const RESERVED_NAMES: &[&str] = &[
+    "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6",
+    "com7", "com8", "com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6",
+    "lpt7", "lpt8", "lpt9",
+];
+
+/// Whether `name` might be the name of a crate on crates.io: non-empty ASCII
+/// alphanumerics, `-` and `_`, and not a reserved name. Others (e.g. names
+/// that are being typed) are never fetched.
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+        && !RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(name))
+}
+
+/// Get the URL of the index file of the crate in the sparse index at
+/// `registry`, according to [Cargo's docs], or `None` if the name isn't
+/// valid (see [`is_valid_name`]).
 ///
-/// The function will panic for empty names.
+/// The paths of the sparse index are lowercase, while names are matched
+/// case-insensitively, so `Serde` is found at the path of `serde`.
 ///
 /// [Cargo's docs]: https://doc.rust-lang.org/cargo/reference/registry-index.html#index-files
-fn index_url(name: &str) -> String {
-    // the lint is about comparing to zero, but here we check if it's larger
-    // than zero, which is more idiomatic in this case than `.is_empty()`.
-    #[allow(clippy::len_zero)]
-    {
-        assert!(name.len() > 0);
+fn index_url(registry: &str, name: &str) -> Option<String> {
+    if !is_valid_name(name) {
+        return None;
     }
+    // the name is ASCII, so it can be sliced by bytes.
+    let name = name.to_ascii_lowercase();
 
     let path = match name.len() {
-        1 => format!("1/{}", name),
-        2 => format!("2/{}", name),
-        3 => {
-            // the lint is about iterators, but when getting a character in a
-            // specific index, using `.nth()` is more readable.
-            #[allow(clippy::iter_nth_zero)]
-            let first_character = name.chars().nth(0).unwrap();
-            format!("3/{}/{}", first_character, name)
-        }
-        _ => {
-            let first_two: &str = &name[0..2];
-            let second_two: &str = &name[2..4];
-            format!("{}/{}/{}", first_two, second_two, name)
-        }
+        1 => format!("1/{name}"),
+        2 => format!("2/{name}"),
+        3 => format!("3/{}/{name}", &name[..1]),
+        _ => format!("{}/{}/{name}", &name[0..2], &name[2..4]),
     };
 
-    format!("{REGISTRY_URL}/{path}")
+    Some(format!("{registry}/{path}"))
+}
+
+/// The names crates.io treats as the same as `name` (besides itself), as `-`
+/// and `_` are interchangeable in crate names: the name with all of its `-`
+/// replaced by `_`, and the other way around.
+fn equivalent_names(name: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    for alternative in [name.replace('-', "_"), name.replace('_', "-")] {
+        if alternative != name && !names.contains(&alternative) {
+            names.push(alternative);
+        }
+    }
+    names
 }
 
 #[inline]
-fn api_url(name: &str) -> String {
-    format!("{API_URL}/{name}")
+fn api_url(api: &Url, name: &str) -> String {
+    format!("{api}/{name}")
+}
+
+/// The URL of the rendered README of a version of a crate.
+fn api_readme_url(api: &Url, name: &str, version: &semver::Version) -> String {
+    format!("{api}/{name}/{version}/readme")
+}
+
+/// Forges whose tree of a tag is at `<repository>/tree/<tag>`.
+const TREE_FORGES: &[&str] = &["github.com", "gitlab.com", "codeberg.org"];
+
+/// Get the URL of the tree of `version`'s tag in a repository, assuming the
+/// (most common) `v<version>` tag convention.
+///
+/// Falls back to the repository itself for forges with unknown layouts.
+fn repository_tag_url(repository: &str, version: &semver::Version) -> String {
+    let repository = repository.trim_end_matches('/');
+    let repository = repository.strip_suffix(".git").unwrap_or(repository);
+
+    let is_tree_forge = Url::parse(repository).is_ok_and(|url| {
+        url.host_str()
+            .is_some_and(|host| TREE_FORGES.contains(&host))
+    });
+
+    if is_tree_forge {
+        format!("{repository}/tree/v{version}")
+    } else {
+        repository.to_owned()
+    }
+}
+
+/// Get the URL of the registry's configuration, which every registry has.
+fn config_url() -> String {
+    format!("{REGISTRY_URL}/config.json")
+}
+
+/// Evict the least recently fetched crates, until at most `max` are left.
+///
+/// Returns whether any crate was evicted.
+fn evict(
+    crates: &mut HashMap<String, CrateInfo>,
+    order: &mut VecDeque<String>,
+    max: usize,
+) -> bool {
+    let len = crates.len();
+    while crates.len() > max
+        && let Some(evicted) = order.pop_front()
+    {
+        crates.remove(&evicted);
+    }
+    crates.len() != len
+}
+
+/// Get the URL of the API's list endpoint, filtered to the given crates.
+fn api_list_url(api: &Url, names: &[String]) -> String {
+    let mut url = api.clone();
+    url.query_pairs_mut()
+        .extend_pairs(names.iter().map(|name| ("ids[]", name)))
+        .append_pair("per_page", &API_PAGE_SIZE.to_string());
+    url.into()
+}
+
+fn api_search_url(
+    api: &Url,
+    query: &str,
+    page: usize,
+    per_page: usize,
+) -> String {
+    let mut url = api.clone();
+    url.query_pairs_mut()
+        .append_pair("q", query)
+        .append_pair("page", &page.to_string())
+        .append_pair("per_page", &per_page.to_string());
+    url.into()
 }
 
 #[cfg(test)]
 mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
     use super::*;
 
+    /// The path of the index file of `name`, relative to the registry.
+    fn index_path(name: &str) -> Option<String> {
+        let url = index_url(REGISTRY_URL, name)?;
+        Some(url.strip_prefix(&format!("{REGISTRY_URL}/"))?.to_owned())
+    }
+
     #[test]
     fn test_index_url() {
-        let prefix = format!("{REGISTRY_URL}/");
+        assert_eq!(index_path("a").as_deref(), Some("1/a"));
+        assert_eq!(index_path("ab").as_deref(), Some("2/ab"));
+        assert_eq!(index_path("abc").as_deref(), Some("3/a/abc"));
+        assert_eq!(index_path("abcd").as_deref(), Some("ab/cd/abcd"));
+        assert_eq!(index_path("cargo").as_deref(), Some("ca/rg/cargo"));
+    }
 
-        assert_eq!(index_url("a").strip_prefix(&prefix).unwrap(), "1/a");
-        assert_eq!(index_url("ab").strip_prefix(&prefix).unwrap(), "2/ab");
-        assert_eq!(index_url("abc").strip_prefix(&prefix).unwrap(), "3/a/abc");
+    #[test]
+    fn test_index_url_mixed_case() {
+        assert_eq!(index_path("A").as_deref(), Some("1/a"));
+        assert_eq!(index_path("Ab").as_deref(), Some("2/ab"));
+        assert_eq!(index_path("aBC").as_deref(), Some("3/a/abc"));
+        assert_eq!(index_path("AbCd").as_deref(), Some("ab/cd/abcd"));
+        assert_eq!(
+            index_path("Serde_JSON").as_deref(),
+            Some("se/rd/serde_json")
+        );
+    }
+
+    #[test]
+    fn test_index_url_invalid() {
+        assert_eq!(index_path(""), None);
+        assert_eq!(index_path("serde json"), None);
+        assert_eq!(index_path("serde."), None);
+        // a multi-byte character within the first 4 bytes.
+        assert_eq!(index_path("sé"), None);
+        assert_eq!(index_path("nul"), None);
+        assert_eq!(index_path("COM1"), None);
+        assert!(index_path("console").is_some());
+    }
+
+    #[test]
+    fn test_index_url_alternative_registry() {
+        assert_eq!(
+            index_url("https://registry.example.com/index", "Serde").as_deref(),
+            Some("https://registry.example.com/index/se/rd/serde")
+        );
+    }
+
+    #[test]
+    fn test_repository_tag_url() {
+        let version = semver::Version::new(1, 2, 3);
+
+        assert_eq!(
+            repository_tag_url("https://github.com/serde-rs/serde", &version),
+            "https://github.com/serde-rs/serde/tree/v1.2.3"
+        );
+        assert_eq!(
+            repository_tag_url("https://gitlab.com/a/b.git/", &version),
+            "https://gitlab.com/a/b/tree/v1.2.3"
+        );
+        assert_eq!(
+            repository_tag_url("https://git.example.com/a/b", &version),
+            "https://git.example.com/a/b"
+        );
+    }
+
+    #[test]
+    fn test_api_list_url() {
+        let api = Url::parse("https://crates.io/api/v1/crates").unwrap();
         assert_eq!(
-            index_url("abcd").strip_prefix(&prefix).unwrap(),
-            "ab/cd/abcd"
+            api_list_url(&api, &["serde".to_owned(), "tokio".to_owned()]),
+            format!(
+                "{api}?ids%5B%5D=serde&ids%5B%5D=tokio&per_page=\
+                 {API_PAGE_SIZE}"
+            )
         );
+    }
+
+    #[test]
+    fn test_api_search_url() {
+        let api = crates_endpoint("https://registry.example.com/").unwrap();
         assert_eq!(
-            index_url("cargo").strip_prefix(&prefix).unwrap(),
-            "ca/rg/cargo"
+            api_search_url(&api, "serde json", 2, 50),
+            "https://registry.example.com/api/v1/crates?q=serde+json&page=2&\
+             per_page=50"
         );
+
+        // the rest of the fields of crates.io's results are ignored.
+        let result = r#"{"name":"serde","max_version":"1.0.219",
+            "description":null,"downloads":1}"#;
+        let result: SearchResult = serde_json::from_str(result).unwrap();
+        assert_eq!(result.name, "serde");
+        assert_eq!(result.max_version, "1.0.219");
+        assert_eq!(result.downloads, 1);
+    }
+
+    #[test]
+    fn test_registry_config() {
+        let config = r#"{"dl":"https://static.crates.io/crates","api":"https://crates.io"}"#;
+        let config: RegistryConfig = serde_json::from_str(config).unwrap();
+        assert_eq!(
+            config.api_url().map(String::from).as_deref(),
+            Some("https://crates.io/api/v1/crates")
+        );
+
+        let config = r#"{"dl":"https://example.com/{crate}/{version}"}"#;
+        let config: RegistryConfig = serde_json::from_str(config).unwrap();
+        assert_eq!(config.api_url(), None);
+    }
+
+    #[test]
+    fn test_implied_features() {
+        let features = [
+            ("full", vec!["rt", "macros", "net"]),
+            ("rt", vec!["dep:mio"]),
+            ("macros", vec!["dep:tokio-macros", "rt"]),
+            ("net", vec!["mio/net", "net"]),
+        ];
+        let info = CrateInfo {
+            name: "tokio".to_owned(),
+            version: semver::Version::new(1, 0, 0),
+            raw_latest: semver::Version::new(1, 0, 0),
+            features: Some(
+                features
+                    .into_iter()
+                    .map(|(name, enables)| {
+                        let enables = enables.into_iter().map(String::from);
+                        (name.to_owned(), enables.collect())
+                    })
+                    .collect(),
+            ),
+            versions: Vec::new(),
+            api: None,
+            fetched_at: Instant::now(),
+            revision: 0,
+        };
+
+        let mut implied = info.implied_features("full");
+        implied.sort_unstable();
+        assert_eq!(
+            implied,
+            ["dep:mio", "dep:tokio-macros", "macros", "net", "rt"]
+        );
+        assert_eq!(info.implied_features("rt"), ["dep:mio"]);
+        assert!(info.implied_features("unknown").is_empty());
+    }
+
+    #[test]
+    fn test_evict() {
+        let info = CrateInfo {
+            name: "foo".to_owned(),
+            version: semver::Version::new(1, 0, 0),
+            raw_latest: semver::Version::new(1, 0, 0),
+            features: None,
+            versions: Vec::new(),
+            api: None,
+            fetched_at: Instant::now(),
+            revision: 0,
+        };
+        let names = ["a", "b", "c"].map(str::to_owned);
+        let mut crates = names
+            .iter()
+            .map(|name| (name.clone(), info.clone()))
+            .collect::<HashMap<_, _>>();
+        let mut order = VecDeque::from(names);
+
+        assert!(!evict(&mut crates, &mut order, usize::MAX));
+        assert!(evict(&mut crates, &mut order, 1));
+        assert_eq!(crates.keys().collect::<Vec<_>>(), ["c"]);
+        assert_eq!(order, ["c"]);
+    }
+
+    #[test]
+    fn test_equivalent_names() {
+        assert_eq!(equivalent_names("serde-json"), ["serde_json"]);
+        assert_eq!(equivalent_names("serde_json"), ["serde-json"]);
+        assert_eq!(equivalent_names("a-b_c"), ["a_b_c", "a-b-c"]);
+        assert!(equivalent_names("serde").is_empty());
+    }
+
+    #[test]
+    fn test_api_version_publisher() {
+        let parse = |json| {
+            serde_json::from_str::<ApiVersion>(json)
+                .unwrap()
+                .publisher()
+        };
+
+        assert_eq!(
+            parse(
+                r#"{"num":"1.0.0","created_at":"2025-07-01T00:00:00Z",
+                "published_by":null,"trustpub_data":{"provider":"github",
+                "repository":"rust-lang/foo","run_id":"1","sha":"abc"}}"#
+            ),
+            Some(Publisher::Trusted {
+                provider: "github".to_owned(),
+                repository: Some("rust-lang/foo".to_owned()),
+            })
+        );
+        assert_eq!(
+            parse(
+                r#"{"num":"1.0.0","created_at":"2025-07-01T00:00:00Z",
+                "published_by":{"id":1,"login":"ferris","name":null}}"#
+            ),
+            Some(Publisher::User { login: "ferris".to_owned() })
+        );
+        // old versions don't have a publisher.
+        assert_eq!(
+            parse(r#"{"num":"0.1.0","created_at":"2015-01-01T00:00:00Z"}"#),
+            None
+        );
+    }
+
+    #[test]
+    fn test_is_data() {
+        assert!(is_data("text/plain; charset=utf-8"));
+        assert!(is_data("application/octet-stream"));
+        assert!(!is_data("text/html; charset=utf-8"));
+        assert!(!is_data("Text/HTML"));
+    }
+
+    #[test]
+    fn test_parse_nested_index() {
+        let nested = format!("{}{}", "[".repeat(10_000), "]".repeat(10_000));
+        assert!(Index::parse("foo", &nested).entries.is_empty());
+    }
+
+    #[test]
+    fn test_parse_index_oddities() {
+        let entry = |vers: &str| {
+            format!(
+                r#"{{"name":"foo","vers":"{vers}","deps":[],"cksum":"","features":{{}},"yanked":false}}"#
+            )
+        };
+        let index =
+            [entry("0.1.0"), entry("0.2"), "{".to_owned(), entry("1.0.0")]
+                .join("\n");
+
+        let versions = Index::parse("foo", &index)
+            .entries
+            .into_iter()
+            .map(|e| e.vers)
+            .collect::<Vec<_>>();
+        assert_eq!(versions, ["0.1.0", "1.0.0"]);
+    }
+
+    #[test]
+    fn test_versions() {
+        let index = indoc::indoc! {r#"
+            {"name":"foo","vers":"0.1.0","deps":[],"cksum":"","features":{"a":[]},"yanked":true}
+            {"name":"foo","vers":"1.0.0","deps":[],"cksum":"","features":{"b":[]},"features2":{"c":["dep:d"]},"yanked":false,"v":2,"rust_version":"1.70","pubtime":"2025-01-01T00:00:00Z"}
+        "#};
+        let info = Index::parse("foo", index).into_info("foo", false).unwrap();
+
+        assert_eq!(info.versions().len(), 2);
+        let old = info.version(&semver::Version::new(0, 1, 0)).unwrap();
+        assert!(old.yanked);
+        assert_eq!(old.rust_version, None);
+        assert_eq!(info.release_date(&old.version), None);
+        assert!(old.features.as_ref().unwrap().contains_key("a"));
+
+        let latest = info.version(&info.version).unwrap();
+        assert_eq!(latest.rust_version.as_deref(), Some("1.70"));
+        assert_eq!(
+            info.release_date(&latest.version),
+            "2025-01-01T00:00:00Z".parse().ok()
+        );
+        let mut features =
+            latest.features.as_ref().unwrap().keys().collect::<Vec<_>>();
+        features.sort();
+        assert_eq!(features, ["b", "c"]);
+        assert_eq!(info.features, latest.features);
+        assert!(info.version(&semver::Version::new(2, 0, 0)).is_none());
+    }
+
+    #[test]
+    fn test_select_latest() {
+        let entry = |vers: &str, yanked: bool| {
+            format!(
+                r#"{{"name":"foo","vers":"{vers}","deps":[],"cksum":"","yanked":{yanked}}}"#
+            )
+        };
+        let info = |entries: &[(&str, bool)], prereleases: bool| {
+            let index = entries
+                .iter()
+                .map(|&(vers, yanked)| entry(vers, yanked))
+                .collect::<Vec<_>>()
+                .join("\n");
+            let info =
+                Index::parse("foo", &index).into_info("foo", prereleases);
+            let info = info.unwrap();
+            (info.version.to_string(), info.raw_latest.to_string())
+        };
+
+        let entries = [
+            ("1.0.0", false),
+            ("1.1.0", true),
+            ("2.0.0-rc.1", false),
+            ("1.0.1", false),
+        ];
+        assert_eq!(info(&entries, false), ("1.0.1".into(), "1.0.1".into()));
+        assert_eq!(info(&entries, true), ("2.0.0-rc.1".into(), "1.0.1".into()));
+        // only pre-releases, or only yanked versions.
+        assert_eq!(
+            info(&[("0.1.0-alpha", false), ("0.1.0-beta", false)], false).0,
+            "0.1.0-beta"
+        );
+        assert_eq!(info(&[("0.1.0", true), ("0.2.0", true)], false).0, "0.2.0");
     }
 
     #[tokio::test]
@@ -406,6 +2243,157 @@ mod tests {
         RegistryCache::new().fetch("base64").await.unwrap();
     }
 
+    #[tokio::test]
+    async fn test_refresh_expired() {
+        let registry = RegistryCache::new();
+        registry.fetch("base64").await.unwrap();
+        registry.refresh_expired(&["base64"]).await;
+        assert!(registry.take_updates().await.is_empty());
+
+        // pretend that the cached entry is old, and that a newer version was
+        // released since.
+        if let Some(entry) = registry.crates.lock().await.get_mut("base64") {
+            entry.version = semver::Version::new(0, 0, 1);
+            entry.fetched_at = Instant::now() - Duration::from_secs(10);
+        }
+        registry.set_ttl(Some(Duration::from_secs(1)));
+        registry.refresh_expired(&["base64"]).await;
+
+        let updates = registry.take_updates().await;
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].previous, "0.0.1");
+        assert!(registry.take_updates().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_refresh() {
+        let registry = RegistryCache::new();
+        let name = "my_name_is_inigo_montoya_and_there_is_no_way_there_is_a_crate_with_this_name";
+        let index = format!(
+            r#"{{"name":"{name}","vers":"1.0.0","deps":[],"cksum":"","yanked":false}}"#
+        );
+        let mut info =
+            Index::parse(name, &index).into_info(name, false).unwrap();
+        info.fetched_at = Instant::now() - Duration::from_secs(10);
+        registry.crates.lock().await.insert(name.to_owned(), info);
+        registry.set_ttl(Some(Duration::from_secs(1)));
+        assert!(registry.is_expired(name).await);
+
+        // the stale info is served, and isn't retried until the TTL passes.
+        let info = registry.fetch(name).await.unwrap();
+        assert_eq!(info.version, semver::Version::new(1, 0, 0));
+        assert!(!registry.is_expired(name).await);
+    }
+
+    /// A registry whose API is served locally, which responds with
+    /// `response` (or drops the connections without one), along with the
+    /// number of connections to it.
+    async fn local_api(
+        response: Option<&'static str>,
+    ) -> (RegistryCache, Arc<AtomicU64>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await;
+        let listener = listener.unwrap();
+        let api = format!("http://{}", listener.local_addr().unwrap());
+        let connections = Arc::new(AtomicU64::new(0));
+        let counted = Arc::clone(&connections);
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                counted.fetch_add(1, Ordering::SeqCst);
+                if let Some(response) = response {
+                    let _ = stream.read(&mut [0; 4096]).await;
+                    let _ = stream.write_all(response.as_bytes()).await;
+                }
+            }
+        });
+
+        let registry = RegistryCache::new();
+        *registry.config.lock().await = Some(RegistryConfig { api: Some(api) });
+        (registry, connections)
+    }
+
+    #[tokio::test]
+    async fn test_api_disabled() {
+        let (registry, connections) = local_api(None).await;
+        registry.set_api_enabled(false);
+
+        let info = registry.fetch_details("base64").await.unwrap();
+        assert!(info.api.is_none());
+        registry.fetch_api_infos(&["base64"]).await;
+        assert_eq!(registry.fetch_readme("base64", &info.version).await, None);
+        assert!(matches!(
+            registry.search("base64").await,
+            Err(Error::ApiDisabled)
+        ));
+        assert!(matches!(
+            registry.search_page("base64", 1, 10).await,
+            Err(Error::ApiDisabled)
+        ));
+        assert!(registry.api_queue.lock().await.is_empty());
+        assert_eq!(connections.load(Ordering::SeqCst), 0);
+
+        // the same requests reach the API once it's enabled.
+        registry.set_api_enabled(true);
+        registry.fetch_api_infos(&["base64"]).await;
+        assert!(connections.load(Ordering::SeqCst) > 0);
+    }
+
+    #[tokio::test]
+    async fn test_drain_api_queue() {
+        let (registry, connections) = local_api(None).await;
+        let index = r#"{"name":"foo","vers":"1.0.0","deps":[],"cksum":"","yanked":false}"#;
+        let info = Index::parse("foo", index).into_info("foo", false);
+        registry
+            .crates
+            .lock()
+            .await
+            .insert("foo".into(), info.unwrap());
+
+        // the failing request is retried, until it's given up on.
+        registry.api_queue.lock().await.push_back(("foo".into(), 0));
+        registry.api_queued.notify_one();
+        registry.spawn_drain();
+        let attempts = u64::from(MAX_API_ATTEMPTS);
+        while connections.load(Ordering::SeqCst) < attempts {
+            platform::sleep(Duration::from_millis(50)).await;
+        }
+        assert!(registry.api_queue.lock().await.is_empty());
+        assert!(registry.draining.load(Ordering::Acquire));
+
+        registry.cancel();
+        while registry.draining.load(Ordering::Acquire) {
+            platform::sleep(Duration::from_millis(50)).await;
+        }
+        assert_eq!(connections.load(Ordering::SeqCst), attempts);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_readme() {
+        let version = semver::Version::new(1, 0, 0);
+        let key = ("foo".to_owned(), version.clone());
+
+        // a failed request is made again on the next hover.
+        let (registry, connections) = local_api(None).await;
+        assert_eq!(registry.fetch_readme("foo", &version).await, None);
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+        assert!(!registry.readmes.lock().await.contains_key(&key));
+
+        // while a missing README isn't requested again.
+        let (registry, connections) = local_api(Some(
+            "HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\n\r\n",
+        ))
+        .await;
+        assert_eq!(registry.fetch_readme("foo", &version).await, None);
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+        assert_eq!(registry.readmes.lock().await.get(&key), Some(&None));
+    }
+
+    #[tokio::test]
+    async fn test_check_health() {
+        let registry = RegistryCache::new();
+        assert!(registry.check_health().await);
+        assert!(!registry.is_offline());
+    }
+
     #[tokio::test]
     async fn test_failing_fetch() {
         RegistryCache::new()
@@ -0,0 +1,369 @@
+//! A cache of the fetched index files on disk, so the crates of a workspace
+//! aren't all fetched again whenever the server restarts.
+//!
+//! Each file is stored under the host and path of its URL (e.g.
+//! `index.crates.io/se/rd/serde`), as a line of its metadata (its ETag, and
+//! when it was fetched), followed by its content. Files are written to a
+//! temporary file first and then renamed, so an interrupted write (e.g. the
+//! server being killed) never leaves a truncated file behind.
+//!
+//! The cache may be capped to a size, past which the least recently used
+//! files are removed: reading a file marks it as used (by its modification
+//! time), so the crates of the workspaces that are still worked on stay.
+//!
+//! The legacy git indexes are fetched into the `git` directory of the cache
+//! (see [`GitIndex`]), under the host and path of their URLs too.
+
+use std::{
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{git_index::GitIndex, platform};
+
+const DIR_NAME: &str = "crates-language-server";
+/// The directory of the git indexes, which no index file's host can be, as
+/// hosts have dots (or are `localhost`).
+const GIT_DIR: &str = "git";
+
+/// A cache of index files in a directory.
+#[derive(Debug, Clone)]
+pub struct DiskCache {
+    dir: PathBuf,
+    /// How long a cached file is used without asking the registry whether
+    /// it changed.
+    ttl: Duration,
+    /// The most bytes the cached files may take, if they're capped.
+    max_size: Option<u64>,
+    /// The bytes written since the size of the cache was last checked, so
+    /// it isn't walked on every write.
+    written: Arc<AtomicU64>,
+}
+
+/// How much of the disk the cached index files take (the git indexes
+/// aside).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Usage {
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// A cached file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cached {
+    pub etag: Option<String>,
+    /// The UNIX timestamp of when the file was fetched (or revalidated).
+    fetched_at: i64,
+    pub content: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Meta {
+    etag: Option<String>,
+    fetched_at: i64,
+}
+
+impl DiskCache {
+    pub fn new(dir: PathBuf, ttl: Duration) -> Self {
+        Self { dir, ttl, max_size: None, written: Arc::default() }
+    }
+
+    /// Cap the cached files to `max_size` bytes (or not, with `None`).
+    pub fn with_max_size(self, max_size: Option<u64>) -> Self {
+        Self { max_size, ..self }
+    }
+
+    /// The directory of the cache.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The cache in the user's cache directory (e.g.
+    /// `~/.cache/crates-language-server`), or `None` if it's unknown (e.g.
+    /// on WASM).
+    pub fn in_user_dir(ttl: Duration) -> Option<Self> {
+        let dir = std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            // Windows
+            .or_else(|| std::env::var_os("LOCALAPPDATA").map(PathBuf::from))
+            .or_else(|| {
+                std::env::var_os("HOME").map(|h| Path::new(&h).join(".cache"))
+            })?;
+        Some(Self::new(dir.join(DIR_NAME), ttl))
+    }
+
+    /// Read the cached file of `url`, if there's one.
+    pub fn read(&self, url: &str) -> Option<Cached> {
+        let path = self.path(url)?;
+        let s = std::fs::read_to_string(&path).ok()?;
+        // mark the file as used, so it's the last to be removed.
+        let _ = File::options()
+            .write(true)
+            .open(&path)
+            .and_then(|file| file.set_modified(SystemTime::now()));
+        let (meta, content) = s.split_once('\n')?;
+        let meta = serde_json::from_str::<Meta>(meta).ok()?;
+        Some(Cached {
+            etag: meta.etag,
+            fetched_at: meta.fetched_at,
+            content: content.to_owned(),
+        })
+    }
+
+    /// Cache the `content` of `url`, as fetched now.
+    pub fn write(
+        &self,
+        url: &str,
+        etag: Option<&str>,
+        content: &str,
+    ) -> io::Result<()> {
+        let path = self.path(url).ok_or(io::ErrorKind::InvalidInput)?;
+        let meta = Meta {
+            etag: etag.map(str::to_owned),
+            fetched_at: platform::now().timestamp(),
+        };
+        let meta = serde_json::to_string(&meta)?;
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let temp = path.with_extension("tmp");
+        let file = format!("{meta}\n{content}");
+        std::fs::write(&temp, &file)?;
+        std::fs::rename(temp, path)?;
+
+        // the cache is only walked once a sixteenth of the cap was written.
+        let written =
+            self.written.fetch_add(file.len() as u64, Ordering::Relaxed);
+        if let Some(max_size) = self.max_size
+            && written + file.len() as u64 > max_size / 16
+        {
+            self.written.store(0, Ordering::Relaxed);
+            self.evict()?;
+        }
+        Ok(())
+    }
+
+    /// Remove the least recently used files, until the cache fits its cap.
+    pub fn evict(&self) -> io::Result<()> {
+        let Some(max_size) = self.max_size else { return Ok(()) };
+        let mut files = self.files()?;
+        let mut bytes = files.iter().map(|file| file.bytes).sum::<u64>();
+        files.sort_by_key(|file| file.used);
+        for file in files {
+            if bytes <= max_size {
+                break;
+            }
+            std::fs::remove_file(&file.path)?;
+            bytes -= file.bytes;
+        }
+        Ok(())
+    }
+
+    /// How much of the disk the cached files take.
+    pub fn usage(&self) -> io::Result<Usage> {
+        let files = self.files()?;
+        Ok(Usage {
+            files: files.len(),
+            bytes: files.iter().map(|file| file.bytes).sum(),
+        })
+    }
+
+    /// The cached files, which are all of the files of the directory but
+    /// the git indexes.
+    fn files(&self) -> io::Result<Vec<CachedFile>> {
+        let mut files = Vec::new();
+        let mut dirs = vec![self.dir.clone()];
+        while let Some(dir) = dirs.pop() {
+            let entries = match std::fs::read_dir(&dir) {
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                entries => entries?,
+            };
+            for entry in entries {
+                let entry = entry?;
+                let meta = entry.metadata()?;
+                if meta.is_dir() {
+                    if entry.path() != self.dir.join(GIT_DIR) {
+                        dirs.push(entry.path());
+                    }
+                } else {
+                    files.push(CachedFile {
+                        path: entry.path(),
+                        bytes: meta.len(),
+                        used: meta.modified()?,
+                    });
+                }
+            }
+        }
+        Ok(files)
+    }
+
+    /// Whether a cached file is recent enough to be used without asking the
+    /// registry whether it changed.
+    pub fn is_fresh(&self, cached: &Cached) -> bool {
+        let age = platform::now().timestamp() - cached.fetched_at;
+        u64::try_from(age).is_ok_and(|age| age < self.ttl.as_secs())
+    }
+
+    /// The repository in the cache that the git index at `url` is fetched
+    /// into, or `None` if the URL can't be used as a path.
+    pub fn git_index(&self, url: &str) -> Option<GitIndex> {
+        let dir = path_of(&self.dir.join(GIT_DIR), url)?;
+        Some(GitIndex::new(url, dir))
+    }
+
+    /// How long a cached file (or a fetched git index) is used without
+    /// asking the registry whether it changed.
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Drop all of the cached files.
+    pub fn clear(&self) -> io::Result<()> {
+        match std::fs::remove_dir_all(&self.dir) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    /// The path of the cached file of `url`.
+    fn path(&self, url: &str) -> Option<PathBuf> {
+        path_of(&self.dir, url)
+    }
+}
+
+/// A file of the cache, with when it was last used.
+struct CachedFile {
+    path: PathBuf,
+    bytes: u64,
+    used: SystemTime,
+}
+
+/// The path of `url` in `dir`, or `None` for URLs whose host or path can't
+/// be used as is (e.g. `..` segments). URLs without a host (e.g. `file:`
+/// ones) are under `localhost`.
+fn path_of(dir: &Path, url: &str) -> Option<PathBuf> {
+    let url = Url::parse(url).ok()?;
+    let host = url.host_str().unwrap_or("localhost");
+    let host = match url.port() {
+        Some(port) => format!("{host}_{port}"),
+        None => host.to_owned(),
+    };
+    let mut path = dir.join(is_plain(&host).then_some(host)?);
+    for segment in url.path_segments()? {
+        path.push(is_plain(segment).then_some(segment)?);
+    }
+    Some(path)
+}
+
+/// Whether `s` is a plain file name: non-empty ASCII alphanumerics, `-`,
+/// `_` and `.`, other than `.` and `..`.
+fn is_plain(s: &str) -> bool {
+    !s.is_empty()
+        && s != "."
+        && s != ".."
+        && s.bytes().all(|b| {
+            b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.')
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path() {
+        let cache = DiskCache::new(PathBuf::from("/cache"), Duration::ZERO);
+        assert_eq!(
+            cache.path("https://index.crates.io/se/rd/serde"),
+            Some(PathBuf::from("/cache/index.crates.io/se/rd/serde"))
+        );
+        assert_eq!(
+            cache.path("https://registry.example.com:8080/index/1/a"),
+            Some(PathBuf::from("/cache/registry.example.com_8080/index/1/a"))
+        );
+        assert_eq!(cache.path("https://example.com/a%2F..%2Fb"), None);
+        assert_eq!(cache.path("https://example.com/a//b"), None);
+        assert_eq!(cache.path("not a url"), None);
+
+        assert_eq!(
+            cache.git_index("https://git.example.com/index.git"),
+            Some(GitIndex::new(
+                "https://git.example.com/index.git",
+                PathBuf::from("/cache/git/git.example.com/index.git")
+            ))
+        );
+        assert_eq!(
+            cache.git_index("file:///srv/index"),
+            Some(GitIndex::new(
+                "file:///srv/index",
+                PathBuf::from("/cache/git/localhost/srv/index")
+            ))
+        );
+    }
+
+    #[test]
+    fn test_read_write() {
+        let dir = std::env::temp_dir()
+            .join(format!("crates-language-server-{}", std::process::id()));
+        let cache = DiskCache::new(dir, Duration::from_secs(60));
+        let url = "https://index.crates.io/se/rd/serde";
+
+        assert_eq!(cache.read(url), None);
+        cache.write(url, Some("\"etag\""), "{}\n{}\n").unwrap();
+        let cached = cache.read(url).unwrap();
+        assert_eq!(cached.etag.as_deref(), Some("\"etag\""));
+        assert_eq!(cached.content, "{}\n{}\n");
+        assert!(cache.is_fresh(&cached));
+        assert!(
+            !DiskCache::new(PathBuf::new(), Duration::ZERO).is_fresh(&cached)
+        );
+
+        cache.clear().unwrap();
+        assert_eq!(cache.read(url), None);
+        cache.clear().unwrap();
+    }
+
+    #[test]
+    fn test_evict() {
+        let dir = std::env::temp_dir().join(format!(
+            "crates-language-server-evict-{}",
+            std::process::id()
+        ));
+        let cache = DiskCache::new(dir.clone(), Duration::from_secs(60));
+        let urls = ["a", "b", "c"].map(|n| format!("https://index.test/{n}"));
+        for (i, url) in urls.iter().enumerate() {
+            cache.write(url, None, &"x".repeat(100)).unwrap();
+            // from the least recently used.
+            let used = SystemTime::UNIX_EPOCH + Duration::from_secs(i as u64);
+            File::options()
+                .write(true)
+                .open(cache.path(url).unwrap())
+                .and_then(|file| file.set_modified(used))
+                .unwrap();
+        }
+        let usage = cache.usage().unwrap();
+        assert_eq!(usage.files, 3);
+
+        // reading `a` makes `b` the least recently used.
+        assert!(cache.read(&urls[0]).is_some());
+        let cache = cache.with_max_size(Some(usage.bytes - 1));
+        cache.evict().unwrap();
+        assert!(cache.read(&urls[0]).is_some());
+        assert!(cache.read(&urls[1]).is_none());
+        assert!(cache.read(&urls[2]).is_some());
+        assert_eq!(cache.usage().unwrap().files, 2);
+
+        cache.clear().unwrap();
+        assert_eq!(cache.usage().unwrap(), Usage::default());
+    }
+}
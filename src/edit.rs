@@ -0,0 +1,310 @@
+//! Building the text edits that the code actions and commands apply.
+//!
+//! The edits only ever replace the spans of the parsed values (which don't
+//! include the surrounding whitespace and comments), so anything the user
+//! wrote around them (e.g. `serde = "1" # pinned for MSRV`) is preserved.
+
+use tower_lsp::lsp_types::{Range, TextEdit};
+
+use crate::{
+    context::DependenciesHeader,
+    parse::{self, Dependency},
+};
+
+/// Replace the version requirement of `dependency` with `version`.
+///
+/// Returns `None` if the dependency doesn't specify a version.
+pub fn replace_version(
+    dependency: &Dependency,
+    version: &str,
+) -> Option<TextEdit> {
+    let range = dependency.version.as_ref()?.range;
+    Some(TextEdit::new(range, format!("\"{version}\"")))
+}
+
+/// Replace the string at `range` (including its quotes) with `value`.
+pub fn replace_string(range: Range, value: &str) -> TextEdit {
+    TextEdit::new(range, format!("\"{value}\""))
+}
+
+/// The entry of `dependency` as a single line of TOML, e.g. to add it to
+/// another manifest: its source, version requirement and features, without
+/// where it's declared (its table, and whether it's optional).
+///
+/// `path`s are kept as written, relative to the dependency's manifest.
+pub fn dependency_spec(dependency: &Dependency) -> String {
+    let quote = |s: &str| serde_json::to_string(s).unwrap_or_default();
+    let mut keys = Vec::new();
+    match &dependency.kind {
+        parse::Kind::Registry => {}
+        parse::Kind::Git(git) => {
+            keys.push(("git", quote(&git.url.value)));
+            let specifier = git.specifier.as_ref().map(|s| match s {
+                parse::GitSpecifier::Branch(b) => ("branch", b),
+                parse::GitSpecifier::Tag(t) => ("tag", t),
+                parse::GitSpecifier::Rev(r) => ("rev", r),
+            });
+            if let Some((key, value)) = specifier {
+                keys.push((key, quote(&value.value)));
+            }
+        }
+        parse::Kind::Local(local) => {
+            keys.push(("path", quote(&local.path.value.to_string_lossy())));
+        }
+    }
+    if let Some(registry) = &dependency.registry {
+        keys.push(("registry", quote(&registry.value)));
+    }
+    if let Some(package) = &dependency.package {
+        keys.push(("package", quote(&package.value)));
+    }
+    if let Some(version) = &dependency.version {
+        keys.insert(0, ("version", quote(&version.value.raw)));
+    }
+    if dependency.workspace.as_ref().is_some_and(|w| w.value) {
+        keys.push(("workspace", "true".to_owned()));
+    }
+    if let Some(features) = &dependency.features {
+        let features = features.iter().map(|f| quote(&f.value));
+        let features = features.collect::<Vec<_>>().join(", ");
+        keys.push(("features", format!("[{features}]")));
+    }
+    if let Some(default_features) = &dependency.default_features {
+        keys.push(("default-features", default_features.value.to_string()));
+    }
+
+    let name = &dependency.name.value;
+    match keys.as_slice() {
+        [("version", version)] => format!("{name} = {version}"),
+        _ => {
+            let keys =
+                keys.iter().map(|(key, value)| format!("{key} = {value}"));
+            let keys = keys.collect::<Vec<_>>().join(", ");
+            format!("{name} = {{ {keys} }}")
+        }
+    }
+}
+
+/// Remove the item at `index` of an array (whose items are at `items`),
+/// along with the separator between it and the next item (or the previous
+/// one, for the last item).
+pub fn remove_item(items: &[Range], index: usize) -> Option<TextEdit> {
+    let item = items.get(index)?;
+    let previous = index.checked_sub(1).and_then(|i| items.get(i));
+    let range = match (previous, items.get(index + 1)) {
+        (_, Some(next)) => Range::new(item.start, next.start),
+        (Some(previous), None) => Range::new(previous.end, item.end),
+        (None, None) => *item,
+    };
+    Some(TextEdit::new(range, String::new()))
+}
+
+/// Sort the entries of a dependencies table by their keys, moving the
+/// comments above an entry along with it. Groups of entries separated by
+/// blank lines are sorted separately.
+pub fn sort_entries(s: &str, header: &DependenciesHeader) -> Vec<TextEdit> {
+    let line_break = if s.contains("\r\n") { "\r\n" } else { "\n" };
+    header
+        .groups
+        .iter()
+        .filter(|group| !group.is_sorted_by_key(|(k, _)| k))
+        .filter_map(|group| {
+            let (first, last) = (&group.first()?.1, &group.last()?.1);
+            let mut sorted = group.iter().collect::<Vec<_>>();
+            sorted.sort_by_key(|(k, _)| k);
+            let text = sorted
+                .iter()
+                .map(|(_, block)| &s[block.clone()])
+                .collect::<Vec<_>>()
+                .join(line_break);
+            let range = parse::range_to_positions(s, first.start..last.end);
+            Some(TextEdit::new(range, text))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+    use crate::parse;
+
+    fn apply(s: &str, edit: TextEdit) -> String {
+        let start = parse::position_to_idx(s, edit.range.start);
+        let end = parse::position_to_idx(s, edit.range.end);
+        format!("{}{}{}", &s[..start], edit.new_text, &s[end..])
+    }
+
+    #[test]
+    fn test_dependency_spec() {
+        let s = indoc! {r#"
+            [dependencies]
+            serde = "1"
+            tokio = { version = "1.40", features = ["rt", "macros"], optional = true }
+            local = { path = "../local", default-features = false }
+            shared = { workspace = true, features = ["std"] }
+            json = { package = "serde_json", version = "1" }
+
+            [dev-dependencies.forked]
+            git = "https://github.com/a/forked"
+            branch = "fix"
+            version = "0.3"
+        "#};
+        let dom = taplo::parser::parse(s).into_dom();
+        let specs = parse::Manifest::parse(s, &dom)
+            .dependencies
+            .iter()
+            .map(dependency_spec)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            specs,
+            [
+                r#"serde = "1""#,
+                r#"tokio = { version = "1.40", features = ["rt", "macros"] }"#,
+                r#"local = { path = "../local", default-features = false }"#,
+                r#"shared = { workspace = true, features = ["std"] }"#,
+                r#"json = { version = "1", package = "serde_json" }"#,
+                r#"forked = { version = "0.3", git = "https://github.com/a/forked", branch = "fix" }"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_remove_item() {
+        let remove = |s: &str, index| {
+            let dom = taplo::parser::parse(s).into_dom();
+            let deps = parse::Manifest::parse(s, &dom).dependencies;
+            let features = deps[0].features.iter().flatten();
+            let items = features.map(|f| f.range).collect::<Vec<_>>();
+            apply(s, remove_item(&items, index).unwrap())
+        };
+        let s = "[dependencies]\nfoo = { features = [\"a\", \"b\", \"c\"] }\n";
+
+        assert_eq!(
+            remove(s, 0),
+            "[dependencies]\nfoo = { features = [\"b\", \"c\"] }\n"
+        );
+        assert_eq!(
+            remove(s, 2),
+            "[dependencies]\nfoo = { features = [\"a\", \"b\"] }\n"
+        );
+        assert_eq!(
+            remove(
+                "[dependencies.foo]\nfeatures = [\n  \"a\",\n  \"b\",\n]\n",
+                0
+            ),
+            "[dependencies.foo]\nfeatures = [\n  \"b\",\n]\n"
+        );
+        assert_eq!(
+            remove("[dependencies]\nfoo = { features = [\"a\"] }\n", 0),
+            "[dependencies]\nfoo = { features = [] }\n"
+        );
+    }
+
+    fn update_all(s: &str, version: &str) -> String {
+        let dom = taplo::parser::parse(s).into_dom();
+        let deps = parse::Manifest::parse(s, &dom).dependencies;
+
+        // apply the edits from the last to the first, so the earlier ranges
+        // stay valid.
+        deps.iter()
+            .rev()
+            .filter_map(|d| replace_version(d, version))
+            .fold(s.to_owned(), |s, edit| apply(&s, edit))
+    }
+
+    #[test]
+    fn test_replace_version_preserves_comments() {
+        let s = indoc! {r#"
+            [dependencies]
+            serde = "1" # pinned for MSRV
+            tokio = { version = "1.0", features = ["full"] } # async runtime
+            # a comment on its own line
+            anyhow = { version = '1', optional = true }#no space
+
+            [dependencies.ropey] # table form
+            version = "1.6" # inline comment
+            features = ["simd"]
+        "#};
+
+        assert_eq!(
+            update_all(s, "2.0.0"),
+            indoc! {r#"
+                [dependencies]
+                serde = "2.0.0" # pinned for MSRV
+                tokio = { version = "2.0.0", features = ["full"] } # async runtime
+                # a comment on its own line
+                anyhow = { version = "2.0.0", optional = true }#no space
+
+                [dependencies.ropey] # table form
+                version = "2.0.0" # inline comment
+                features = ["simd"]
+            "#}
+        );
+    }
+
+    #[test]
+    fn test_replace_version_preserves_comments_crlf() {
+        let s = "[dependencies]\r\nserde = \"1\" # pinned\r\nurl = \"2\"\r\n";
+
+        assert_eq!(
+            update_all(s, "3"),
+            "[dependencies]\r\nserde = \"3\" # pinned\r\nurl = \"3\"\r\n"
+        );
+    }
+
+    #[test]
+    fn test_sort_entries() {
+        let s = indoc! {r#"
+            [dependencies]
+            tokio = "1"
+            # serialization
+            serde = "1" # pinned
+            anyhow = "1"
+
+            ropey = "1"
+            chrono = "0.4"
+
+            [dev-dependencies]
+            indoc = "2"
+        "#};
+        let headers = DependenciesHeader::all(s);
+        assert!(!headers[0].is_sorted());
+        assert!(headers[1].is_sorted());
+
+        // the groups don't overlap, so the edits are applied from the last.
+        let sorted = sort_entries(s, &headers[0])
+            .into_iter()
+            .rev()
+            .fold(s.to_owned(), |s, edit| apply(&s, edit));
+        assert_eq!(
+            sorted,
+            indoc! {r#"
+                [dependencies]
+                anyhow = "1"
+                # serialization
+                serde = "1" # pinned
+                tokio = "1"
+
+                chrono = "0.4"
+                ropey = "1"
+
+                [dev-dependencies]
+                indoc = "2"
+            "#}
+        );
+        assert!(sort_entries(s, &headers[1]).is_empty());
+    }
+
+    #[test]
+    fn test_replace_version_without_version() {
+        let s = indoc! {r#"
+            [dependencies]
+            foo = { path = "../foo" } # local
+        "#};
+
+        assert_eq!(update_all(s, "1"), s);
+    }
+}
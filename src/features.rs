@@ -0,0 +1,218 @@
+//! The features of a dependency that are enabled anyway (by the crate's
+//! default features, or by the other listed features), and the default
+//! features that it opts out of.
+
+use crate::{crates::CrateInfo, parse::Dependency};
+
+const DEFAULT_FEATURE: &str = "default";
+
+/// Why a feature of a dependency doesn't need to be listed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Redundancy {
+    /// The crate's default features enable it (and the dependency doesn't
+    /// opt out of them).
+    Default,
+    /// Another listed feature enables it (e.g. `macros` along with `full`).
+    ImpliedBy(String),
+}
+
+/// The listed features of a dependency that are enabled anyway, by their
+/// indices in the `features` array.
+pub fn redundant(
+    dependency: &Dependency,
+    info: &CrateInfo,
+) -> Vec<(usize, Redundancy)> {
+    let Some(features) = &dependency.features else { return Vec::new() };
+    let defaults = match uses_defaults(dependency) {
+        Some(true) => info.implied_features(DEFAULT_FEATURE),
+        _ => Vec::new(),
+    };
+    let implied = features
+        .iter()
+        .map(|f| info.implied_features(&f.value))
+        .collect::<Vec<_>>();
+
+    let mut redundant = Vec::new();
+    for (index, feature) in features.iter().enumerate() {
+        let feature = feature.value.as_str();
+        if (feature == DEFAULT_FEATURE
+            && uses_defaults(dependency) == Some(true))
+            || defaults.contains(&feature)
+        {
+            redundant.push((index, Redundancy::Default));
+            continue;
+        }
+        // of features that enable each other, the first one is kept.
+        let by = features.iter().enumerate().find(|&(other, by)| {
+            other != index
+                && implied[other].contains(&feature)
+                && (other < index
+                    || !implied[index].contains(&by.value.as_str()))
+        });
+        if let Some((_, by)) = by {
+            redundant.push((index, Redundancy::ImpliedBy(by.value.clone())));
+        }
+    }
+    redundant
+}
+
+/// The default features of the crate, if the dependency opts out of them
+/// (with `default-features = false`), and there are any.
+pub fn dropped_defaults<'a>(
+    dependency: &Dependency,
+    info: &'a CrateInfo,
+) -> Option<Vec<&'a str>> {
+    // the listed features still enable some of them.
+    let enabled = dependency
+        .features
+        .iter()
+        .flatten()
+        .flat_map(|f| {
+            let implied = info.implied_features(&f.value);
+            implied.into_iter().chain([f.value.as_str()])
+        })
+        .collect::<Vec<_>>();
+    let dropped = info
+        .implied_features(DEFAULT_FEATURE)
+        .into_iter()
+        .filter(|feature| !enabled.contains(feature))
+        .collect::<Vec<_>>();
+    (uses_defaults(dependency) == Some(false) && !dropped.is_empty())
+        .then_some(dropped)
+}
+
+/// Whether the dependency enables the default features of its crate, or
+/// `None` if it's inherited from the workspace (whose declaration decides).
+fn uses_defaults(dependency: &Dependency) -> Option<bool> {
+    if dependency.workspace.as_ref().is_some_and(|w| w.value) {
+        return None;
+    }
+    Some(dependency.default_features.as_ref().is_none_or(|d| d.value))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{parse::Manifest, platform::Instant};
+
+    fn info() -> CrateInfo {
+        let features = [
+            ("default", vec!["std", "derive"]),
+            ("std", vec!["alloc"]),
+            ("alloc", vec![]),
+            ("derive", vec!["dep:serde_derive"]),
+            ("rc", vec![]),
+            // features that enable each other.
+            ("a", vec!["b"]),
+            ("b", vec!["a"]),
+        ];
+        CrateInfo {
+            name: "serde".to_owned(),
+            version: semver::Version::new(1, 0, 0),
+            raw_latest: semver::Version::new(1, 0, 0),
+            features: Some(
+                features
+                    .into_iter()
+                    .map(|(name, enables)| {
+                        let enables = enables.into_iter().map(String::from);
+                        (name.to_owned(), enables.collect())
+                    })
+                    .collect::<HashMap<_, _>>(),
+            ),
+            versions: Vec::new(),
+            api: None,
+            fetched_at: Instant::now(),
+            revision: 0,
+        }
+    }
+
+    fn dependency(entry: &str) -> Dependency {
+        let s = format!("[dependencies]\n{entry}\n");
+        let dom = taplo::parser::parse(&s).into_dom();
+        Manifest::parse(&s, &dom).dependencies.remove(0)
+    }
+
+    #[test]
+    fn test_redundant() {
+        let info = info();
+        let redundant = |entry| {
+            redundant(&dependency(entry), &info)
+                .into_iter()
+                .map(|(index, _)| index)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            redundant(
+                r#"serde = { version = "1", features = ["alloc", "rc", "default"] }"#
+            ),
+            [0, 2]
+        );
+        assert_eq!(
+            redundant(
+                r#"serde = { version = "1", features = ["alloc"], default-features = false }"#
+            ),
+            [] as [usize; 0]
+        );
+        assert_eq!(
+            redundant(r#"serde = { version = "1", features = ["b", "a"] }"#),
+            [1]
+        );
+        assert_eq!(
+            redundant(r#"serde = { workspace = true, features = ["std"] }"#),
+            [] as [usize; 0]
+        );
+    }
+
+    #[test]
+    fn test_redundant_implied() {
+        let info = info();
+        let redundant = |entry| redundant(&dependency(entry), &info);
+
+        assert_eq!(
+            redundant(
+                r#"serde = { version = "1", features = ["alloc", "std", "rc"], default-features = false }"#
+            ),
+            [(0, Redundancy::ImpliedBy("std".to_owned()))]
+        );
+        // the defaults take precedence.
+        assert_eq!(
+            redundant(
+                r#"serde = { version = "1", features = ["std", "alloc"] }"#
+            ),
+            [(0, Redundancy::Default), (1, Redundancy::Default)]
+        );
+        // inherited dependencies are still checked against their features.
+        assert_eq!(
+            redundant(
+                r#"serde = { workspace = true, features = ["std", "alloc"] }"#
+            ),
+            [(1, Redundancy::ImpliedBy("std".to_owned()))]
+        );
+    }
+
+    #[test]
+    fn test_dropped_defaults() {
+        let info = info();
+        let dropped = |entry| dropped_defaults(&dependency(entry), &info);
+
+        let mut all =
+            dropped(r#"serde = { version = "1", default-features = false }"#)
+                .unwrap();
+        all.sort_unstable();
+        assert_eq!(all, ["alloc", "dep:serde_derive", "derive", "std"]);
+        assert_eq!(
+            dropped(
+                r#"serde = { version = "1", default-features = false, features = ["std"] }"#
+            )
+            .map(|mut d| {
+                d.sort_unstable();
+                d
+            }),
+            Some(vec!["dep:serde_derive", "derive"])
+        );
+        assert_eq!(dropped(r#"serde = "1""#), None);
+    }
+}
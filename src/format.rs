@@ -1,40 +1,182 @@
 use std::collections::{HashMap, hash_map};
 
-use tower_lsp::lsp_types::CompletionItem;
+use chrono::{DateTime, Utc};
+use tower_lsp::lsp_types::{
+    CompletionItem, CompletionItemLabelDetails, CompletionTextEdit,
+    Documentation, InsertTextFormat, ParameterInformation, ParameterLabel,
+    Range, SignatureHelp, SignatureInformation, TextEdit,
+};
 
-use crate::{crates, parse};
+use crate::{
+    config::HoverSection, crates, lints, lockfile, markup, messages::Catalog,
+    metadata, parse, platform, profile, version,
+};
 
-pub fn version_completions(latest: crates::Latest) -> Vec<CompletionItem> {
-    let version = latest.version;
+pub fn version_completions(latest: crates::CrateInfo) -> Vec<CompletionItem> {
+    let now = platform::now();
+    let version = &latest.version;
+    let label_details = version_label_details(&latest, version, now);
+    let granularity = |label: String, detail: &str| CompletionItem {
+        label,
+        detail: Some(detail.to_owned()),
+        label_details: label_details.clone(),
+        ..Default::default()
+    };
 
     let mut comps = vec![
-        CompletionItem::new_simple(
+        granularity(
             format!("{}.{}.{}", version.major, version.minor, version.patch),
-            "patch".to_owned(),
-        ),
-        CompletionItem::new_simple(
-            format!("{}.{}", version.major, version.minor),
-            "minor".to_owned(),
-        ),
-        CompletionItem::new_simple(
-            format!("{}", version.major),
-            "major".to_owned(),
+            "patch",
         ),
+        granularity(format!("{}.{}", version.major, version.minor), "minor"),
+        granularity(format!("{}", version.major), "major"),
     ];
 
     // this is often not the case, so it's not that bad the we are
     // inserting here (which is O(N)).
     if !(version.pre.is_empty() && version.build.is_empty()) {
-        let full = CompletionItem::new_simple(
-            version.to_string(),
-            "latest".to_owned(),
-        );
-        comps.insert(0, full);
+        comps.insert(0, granularity(version.to_string(), "latest"));
+    }
+
+    let unyanked = latest
+        .versions()
+        .iter()
+        .filter(|v| !v.yanked)
+        .map(|v| &v.version);
+    comps.extend(
+        version::notable_versions(unyanked, version)
+            .into_iter()
+            .map(|(version, group)| CompletionItem {
+                label: version.to_string(),
+                detail: Some(group),
+                label_details: version_label_details(&latest, version, now),
+                ..Default::default()
+            }),
+    );
+
+    // keep the groups in order, as the clients sort by the labels otherwise.
+    for (i, comp) in comps.iter_mut().enumerate() {
+        comp.sort_text = Some(format!("{i:02}"));
     }
 
     comps
 }
 
+/// How a completed crate name is followed by its latest version, when it's
+/// a bare key (without a value yet).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameValue {
+    /// The name is completed by itself.
+    None,
+    /// ` = "<latest>"` is inserted after the name.
+    Plain,
+    /// ` = "<latest>"` is inserted as a snippet, with the version selected.
+    Snippet,
+}
+
+/// The completions of crate names, from the results of searching registries
+/// (in the order of their priority), labeled with the registry each crate is
+/// from. Picking one replaces `range` (what's typed of the name).
+///
+/// The crates found in each source come along with the packages of the
+/// lockfile that are from the same source.
+pub fn name_completions(
+    results: &[(&str, Vec<crates::SearchResult>, &[lockfile::Locked])],
+    range: Range,
+    value: NameValue,
+) -> Vec<CompletionItem> {
+    results
+        .iter()
+        .flat_map(|(source, found, locked)| {
+            found.iter().map(move |c| (source, c, locked))
+        })
+        .enumerate()
+        .map(|(i, (source, krate, locked))| CompletionItem {
+            label: krate.name.clone(),
+            label_details: Some(CompletionItemLabelDetails {
+                detail: Some(format!(" {}", krate.max_version)),
+                description: Some((*source).to_owned()),
+            }),
+            detail: name_completion_detail(krate, locked),
+            documentation: krate
+                .description
+                .as_deref()
+                .map(markup::escape)
+                .map(Documentation::String),
+            // keep the registries, and their relevance order, as they are.
+            sort_text: Some(format!("{i:03}")),
+            filter_text: Some(krate.name.clone()),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
+                range,
+                match value {
+                    NameValue::None => krate.name.clone(),
+                    NameValue::Plain => {
+                        format!("{} = \"{}\"", krate.name, krate.max_version)
+                    }
+                    NameValue::Snippet => {
+                        format!(
+                            "{} = \"${{1:{}}}\"",
+                            krate.name, krate.max_version
+                        )
+                    }
+                },
+            ))),
+            insert_text_format: (value == NameValue::Snippet)
+                .then_some(InsertTextFormat::SNIPPET),
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// The downloads of a found crate, and whether it's already in the tree
+/// (e.g. `already in your tree: 1.0.219 (via tokio)`), so adding it doesn't
+/// pull in a second copy of another major version by accident.
+fn name_completion_detail(
+    krate: &crates::SearchResult,
+    locked: &[lockfile::Locked],
+) -> Option<String> {
+    // registries that don't count the downloads report none.
+    let downloads = (krate.downloads > 0)
+        .then(|| format!("{} downloads", format_count(krate.downloads)));
+    let in_tree = locked
+        .iter()
+        .filter(|l| l.name == krate.name)
+        .map(|l| match l.dependents.first() {
+            Some(dependent) => {
+                format!("{} (via {})", l.version, dependent.name)
+            }
+            None => l.version.to_string(),
+        })
+        .collect::<Vec<_>>();
+    let in_tree = (!in_tree.is_empty())
+        .then(|| format!("already in your tree: {}", in_tree.join(", ")));
+
+    let detail = [downloads, in_tree]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+    (!detail.is_empty()).then(|| detail.join(" · "))
+}
+
+/// The age of a version (if its release date is known), and whether it was
+/// yanked.
+fn version_label_details(
+    info: &crates::CrateInfo,
+    version: &semver::Version,
+    now: DateTime<Utc>,
+) -> Option<CompletionItemLabelDetails> {
+    let age = info
+        .release_date(version)
+        .map(|date| version::format_age(date, now));
+    let yanked = info.is_yanked(version).then(|| "yanked".to_owned());
+    let description = [age, yanked].into_iter().flatten().collect::<Vec<_>>();
+
+    (!description.is_empty()).then(|| CompletionItemLabelDetails {
+        detail: None,
+        description: Some(description.join(", ")),
+    })
+}
+
 pub fn format_vec(vec: &[String]) -> String {
     if vec.is_empty() {
         "[ ]".to_string()
@@ -43,14 +185,16 @@ pub fn format_vec(vec: &[String]) -> String {
     }
 }
 
+/// Complete the available features, except for the `used` ones.
+///
+/// If the cursor isn't in a string, the `bare` word before it is replaced
+/// with the quoted feature (see [`crate::context::IncompleteFeature`]).
 pub fn features_completions(
-    dependency: &parse::Dependency,
-    latest: crates::Latest,
+    used: &[&str],
+    bare: Option<Range>,
+    latest: crates::CrateInfo,
 ) -> Vec<CompletionItem> {
-    let features = dependency.features.as_ref();
-    let already_used = |name: &str| {
-        features.is_some_and(|f| f.iter().any(|f| f.value == name))
-    };
+    let already_used = |name: &str| used.contains(&name);
 
     // TODO: make the completions _replace_ the current content of the feature.
 
@@ -58,44 +202,741 @@ pub fn features_completions(
         available_features
             .into_iter()
             .filter(|(name, _)| !already_used(name))
-            .map(|(name, f)| CompletionItem::new_simple(name, format_vec(&f)))
+            .map(|(name, f)| {
+                let mut comp =
+                    CompletionItem::new_simple(name.clone(), format_vec(&f));
+                if let Some(range) = bare {
+                    let quoted = format!("\"{name}\"");
+                    comp.text_edit = Some(CompletionTextEdit::Edit(
+                        TextEdit::new(range, quoted),
+                    ));
+                    // like the items of rust-analyzer's attribute lists, so
+                    // the next one can be typed right away. (in a string,
+                    // these would be typed into it.)
+                    comp.commit_characters =
+                        Some(vec![",".to_owned(), "]".to_owned()]);
+                }
+                comp
+            })
             .collect()
     } else {
         Vec::new()
     }
 }
 
+/// Complete the crate's own features, except for the `used` ones.
+pub fn own_features_completions(
+    manifest: &parse::Manifest,
+    used: &[&str],
+) -> Vec<CompletionItem> {
+    manifest
+        .feature_names()
+        .filter(|name| !used.contains(name))
+        .map(|name| {
+            let enables =
+                manifest.features.iter().find(|f| f.name.value == name).map(
+                    |f| {
+                        f.enables
+                            .iter()
+                            .map(|e| e.value.clone())
+                            .collect::<Vec<_>>()
+                    },
+                );
+            let detail = match enables {
+                Some(enables) => format_vec(&enables),
+                None => "optional dependency".to_owned(),
+            };
+            CompletionItem::new_simple(name.to_owned(), detail)
+        })
+        .collect()
+}
+
+/// Complete the keys of a well-known metadata table.
+pub fn metadata_key_completions(
+    table: &metadata::Table,
+) -> Vec<CompletionItem> {
+    table
+        .keys
+        .iter()
+        .map(|key| {
+            CompletionItem::new_simple(
+                key.name.to_owned(),
+                key.description.to_owned(),
+            )
+        })
+        .collect()
+}
+
+/// Complete the keys of a profile table.
+pub fn profile_key_completions(keys: &[&profile::Key]) -> Vec<CompletionItem> {
+    keys.iter()
+        .map(|key| {
+            CompletionItem::new_simple(
+                key.name.to_owned(),
+                key.description.to_owned(),
+            )
+        })
+        .collect()
+}
+
+/// Complete the values of a profile key. Only the strings are completed when
+/// the value is already `in_string`.
+pub fn profile_value_completions(
+    key: &profile::Key,
+    in_string: bool,
+) -> Vec<CompletionItem> {
+    let mut comps = Vec::new();
+    for &value in key.values {
+        match value {
+            profile::Value::Bool if !in_string => {
+                comps.extend(["true", "false"].map(|v| {
+                    CompletionItem::new_simple(
+                        v.to_owned(),
+                        "boolean".to_owned(),
+                    )
+                }));
+            }
+            profile::Value::Int { min, max }
+                if !in_string && max - min <= 3 =>
+            {
+                comps.extend((min..=max).map(|i| {
+                    CompletionItem::new_simple(
+                        i.to_string(),
+                        "integer".to_owned(),
+                    )
+                }));
+            }
+            profile::Value::Str(values) => {
+                comps.extend(values.iter().map(|&v| CompletionItem {
+                    insert_text: (!in_string).then(|| format!("\"{v}\"")),
+                    ..CompletionItem::new_simple(
+                        v.to_owned(),
+                        "string".to_owned(),
+                    )
+                }));
+            }
+            _ => {}
+        }
+    }
+    comps
+}
+
+/// Complete the lints of a tool, in a `[lints.<tool>]` table.
+pub fn lint_completions(lints: &[lints::Lint]) -> Vec<CompletionItem> {
+    lints
+        .iter()
+        .map(|lint| CompletionItem {
+            documentation: Some(Documentation::String(
+                lint.description.to_owned(),
+            )),
+            ..CompletionItem::new_simple(
+                lint.name.to_owned(),
+                lint.level.to_owned(),
+            )
+        })
+        .collect()
+}
+
+/// Complete the keys of a `[lints]` table, i.e., the tools, and `workspace`
+/// (when the lints can be inherited).
+pub fn lints_table_completions(can_inherit: bool) -> Vec<CompletionItem> {
+    let tools = lints::Tool::ALL.iter().map(|tool| {
+        CompletionItem::new_simple(
+            tool.key().to_owned(),
+            format!("the lints of {}", tool.key()),
+        )
+    });
+    let workspace = can_inherit.then(|| {
+        CompletionItem::new_simple(
+            "workspace".to_owned(),
+            "inherit the lints of the workspace".to_owned(),
+        )
+    });
+    tools.chain(workspace).collect()
+}
+
+/// Complete the levels of a lint.
+pub fn lint_level_completions(in_string: bool) -> Vec<CompletionItem> {
+    lints::LEVELS
+        .iter()
+        .map(|&level| CompletionItem {
+            insert_text: (!in_string).then(|| format!("\"{level}\"")),
+            ..CompletionItem::new_simple(level.to_owned(), "level".to_owned())
+        })
+        .collect()
+}
+
+pub fn format_profile_key_hover(
+    key: &profile::Key,
+    messages: Catalog,
+) -> String {
+    let accepts =
+        messages.get("profile-accepts", &[("expected", &key.expected())]);
+    format!("# `{}`\n\n{}\n\n{accepts}", key.name, key.description)
+}
+
+/// The keys of a dependency's table, with their docs, in the order of the
+/// "signature" of a dependency.
+const DEPENDENCY_KEYS: &[(&str, &str)] = &[
+    ("version", "The version requirement, e.g. `\"1.2\"`."),
+    (
+        "features",
+        "The features to enable, besides the default ones.",
+    ),
+    (
+        "default-features",
+        "Whether to enable the default features (`true` by default).",
+    ),
+    (
+        "optional",
+        "Only build the dependency if a feature enables it.",
+    ),
+    (
+        "package",
+        "The name of the crate, if the dependency is renamed.",
+    ),
+    (
+        "registry",
+        "The name of the registry to fetch the crate from.",
+    ),
+    (
+        "path",
+        "The path of a local crate, relative to this manifest.",
+    ),
+    (
+        "git",
+        "The URL of a git repository to fetch the crate from.",
+    ),
+    ("branch", "The git branch to use."),
+    ("tag", "The git tag to use."),
+    ("rev", "The git commit (or another ref) to use."),
+    (
+        "workspace",
+        "Inherit the dependency from `[workspace.dependencies]`.",
+    ),
+];
+
+/// Complete the keys of a dependency's table.
+pub fn dependency_key_completions() -> Vec<CompletionItem> {
+    DEPENDENCY_KEYS
+        .iter()
+        .map(|&(key, docs)| CompletionItem {
+            label: key.to_owned(),
+            documentation: Some(Documentation::String(docs.to_owned())),
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// The "signature" of a dependency's inline table, with the `active` key
+/// (or the first key it's a prefix of, while it's typed) highlighted.
+pub fn dependency_signature(active: Option<&str>) -> SignatureHelp {
+    let mut label = "{ ".to_owned();
+    let mut parameters = Vec::new();
+    for (i, (key, docs)) in DEPENDENCY_KEYS.iter().enumerate() {
+        if i > 0 {
+            label.push_str(", ");
+        }
+        let start = label.len() as u32;
+        label.push_str(key);
+        parameters.push(ParameterInformation {
+            label: ParameterLabel::LabelOffsets([start, label.len() as u32]),
+            documentation: Some(Documentation::String((*docs).to_owned())),
+        });
+    }
+    label.push_str(" }");
+
+    // `default_features` is deprecated, but still supported by cargo.
+    let active = active.map(|key| key.replace('_', "-"));
+    let active_parameter = active.and_then(|active| {
+        let position = |f: fn(&str, &str) -> bool| {
+            DEPENDENCY_KEYS.iter().position(|(key, _)| f(key, &active))
+        };
+        position(|key, active| key == active)
+            .or_else(|| position(|key, active| key.starts_with(active)))
+    });
+
+    SignatureHelp {
+        signatures: vec![SignatureInformation {
+            label,
+            documentation: None,
+            parameters: Some(parameters),
+            active_parameter: None,
+        }],
+        active_signature: Some(0),
+        active_parameter: active_parameter.map(|i| i as u32),
+    }
+}
+
 pub fn format_feature_hover(
     feature: &str,
     feature_description: &[String],
+    messages: Catalog,
 ) -> String {
     format!(
-        "{}\n\n---\n\n## Enables\n\n{}",
+        "{}\n\n---\n\n## {}\n\n{}",
         feature,
+        messages.get("hover-enables", &[]),
         format_vec(feature_description)
     )
 }
 
-pub fn format_name_hover(name: &str, latest: crates::Latest) -> String {
-    let header = format!("{}: {}", name, latest.version);
+/// The hover of a comparator of a version requirement (or of the `,` between
+/// comparators, if `comparator` is `None`), with the published `versions`
+/// the whole requirement matches as examples.
+pub fn format_requirement_hover(
+    comparator: Option<&str>,
+    matching: Option<&[&semver::Version]>,
+    messages: Catalog,
+) -> Option<String> {
+    let explanation = match comparator {
+        Some(comparator) => {
+            let (what, bounds) = version::explain_comparator(comparator)?;
+            messages.get(
+                "requirement-explained",
+                &[
+                    ("comparator", &comparator),
+                    ("what", &messages.get(what, &[])),
+                    ("bounds", &bounds),
+                ],
+            )
+        }
+        None => messages.get("requirement-comma", &[]),
+    };
+
+    let examples = matching.map(|matching| match matching {
+        [] => messages.get("requirement-matches-none", &[]),
+        [version] => {
+            messages.get("requirement-matches-one", &[("version", version)])
+        }
+        [first, .., last] => messages.get(
+            "requirement-matches-many",
+            &[("count", &matching.len()), ("first", first), ("last", last)],
+        ),
+    });
+
+    Some(
+        [Some(explanation), examples]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join("\n\n---\n\n"),
+    )
+}
 
-    let features = latest
-        .features
+/// The hover of the header of a dependencies table, with the stats of its
+/// dependencies.
+///
+/// `outdated` counts the dependencies from crates.io whose requirement
+/// doesn't match the latest version, out of the ones that could be fetched.
+pub fn format_section_hover(
+    header: &str,
+    dependencies: &[&parse::Dependency],
+    outdated: (usize, usize),
+    messages: Catalog,
+) -> String {
+    let from_crates_io =
+        dependencies.iter().filter(|d| d.is_crates_io()).count();
+    let mut features = dependencies
+        .iter()
+        .flat_map(|d| d.features.iter().flatten())
+        .map(|f| f.value.as_str())
+        .collect::<Vec<_>>();
+    features.sort_unstable();
+    features.dedup();
+
+    let (outdated, fetched) = outdated;
+    let outdated = if fetched == from_crates_io {
+        messages.get("section-outdated", &[("count", &outdated)])
+    } else {
+        messages.get(
+            "section-outdated-of",
+            &[("count", &outdated), ("fetched", &fetched)],
+        )
+    };
+    let stats = [
+        messages.get(
+            match dependencies.len() {
+                1 => "section-dependency",
+                _ => "section-dependencies",
+            },
+            &[("count", &dependencies.len()), ("crates-io", &from_crates_io)],
+        ),
+        outdated,
+        messages.get(
+            match features.len() {
+                1 => "section-feature",
+                _ => "section-features",
+            },
+            &[("count", &features.len())],
+        ),
+    ];
+
+    format!("`{header}`\n\n- {}", stats.join("\n- "))
+}
+
+/// Describe how a dependency is declared, e.g. "optional build dependency for
+/// `cfg(unix)`".
+///
+/// Returns `None` for plain dependencies in the `[dependencies]` table.
+pub fn format_declaration(
+    dependency: &parse::Dependency,
+    messages: Catalog,
+) -> Option<String> {
+    let section = &dependency.section;
+    let kind = match section.kind {
+        parse::DependencyKind::Normal => "declaration-normal",
+        parse::DependencyKind::Dev => "declaration-dev",
+        parse::DependencyKind::Build => "declaration-build",
+    };
+    let is = |flag: &Option<parse::Span<bool>>| {
+        flag.as_ref().is_some_and(|f| f.value)
+    };
+    let optional = is(&dependency.optional);
+    let workspace = is(&dependency.workspace);
+    if section.kind == parse::DependencyKind::Normal
+        && !optional
+        && !workspace
+        && section.target.is_none()
+    {
+        return None;
+    }
+
+    let mut description = messages.get(kind, &[]);
+    if optional {
+        description = messages
+            .get("declaration-optional", &[("declaration", &description)]);
+    }
+    if workspace {
+        description = messages
+            .get("declaration-workspace", &[("declaration", &description)]);
+    }
+    if let Some(target) = &section.target {
+        description = messages.get(
+            "declaration-target",
+            &[("declaration", &description), ("target", target)],
+        );
+    }
+
+    Some(format!("*{description}*"))
+}
+
+/// Who published `version`, for teams that check how their dependencies
+/// are released.
+fn format_publisher(
+    version: &semver::Version,
+    publisher: &crates::Publisher,
+    messages: Catalog,
+) -> String {
+    match publisher {
+        crates::Publisher::Trusted { provider, repository } => {
+            let provider = match provider.as_str() {
+                "github" => "GitHub",
+                "gitlab" => "GitLab",
+                provider => provider,
+            };
+            match repository {
+                Some(repository) => messages.get(
+                    "published-trusted-from",
+                    &[
+                        ("version", version),
+                        ("provider", &provider),
+                        ("repository", repository),
+                    ],
+                ),
+                None => messages.get(
+                    "published-trusted",
+                    &[("version", version), ("provider", &provider)],
+                ),
+            }
+        }
+        crates::Publisher::User { login } => {
+            let url = format!("https://crates.io/users/{login}");
+            messages.get(
+                "published-by",
+                &[("version", version), ("login", login), ("url", &url)],
+            )
+        }
+    }
+}
+
+pub fn format_name_hover(
+    dependency: &parse::Dependency,
+    latest: crates::CrateInfo,
+    lag: Option<&lockfile::Lag>,
+    docs: Option<&str>,
+    readme: Option<&str>,
+    sections: &[HoverSection],
+    messages: Catalog,
+) -> String {
+    let name = dependency.crate_name();
+    // the version cargo picks for the requirement, rather than the latest.
+    let req = dependency
+        .version
         .as_ref()
-        .map(HashMap::keys)
-        .map(hash_map::Keys::into_iter)
-        .map(|f| f.map(String::as_str))
-        .map(Iterator::collect::<Vec<_>>)
-        .map(|f| f.join(", "));
-    let features = features
-        .filter(|f| !f.is_empty())
-        .map(|f| format!("---\n\n## Available Features\n\n[ {} ]", f));
-
-    let description = latest.description.map(|d| format!("---\n\n{}", d));
-
-    [Some(header), description, features]
+        .and_then(|v| v.value.req.as_ref());
+    let resolved = latest.resolve(req);
+    let section = |section| match section {
+        HoverSection::Version => {
+            let stable = latest
+                .api
+                .as_ref()
+                .and_then(|api| api.max_stable_version.as_ref())
+                .filter(|&v| v != &latest.version)
+                .map(|v| {
+                    let stable =
+                        messages.get("latest-stable", &[("version", v)]);
+                    format!(" ({stable})")
+                })
+                .unwrap_or_default();
+            // a newer pre-release (or yanked version) that isn't the latest.
+            let raw = Some(&latest.raw_latest)
+                .filter(|&v| v > &latest.version)
+                .map(|v| {
+                    let raw = messages.get("last-published", &[("version", v)]);
+                    format!(" ({raw})")
+                })
+                .unwrap_or_default();
+            Some(format!("{}: {}{}{}", name, latest.version, stable, raw))
+        }
+        HoverSection::Lock => lag.map(|lag| format_lag(name, lag, messages)),
+        HoverSection::Declaration => format_declaration(dependency, messages),
+        HoverSection::Native => resolved
+            .and_then(|v| v.links.as_ref())
+            .map(|links| messages.get("links-native", &[("links", links)])),
+        // the name might also be taken in an alternative registry, so the
+        // source of the metadata is stated.
+        HoverSection::Registry => Some(messages.get("from-crates-io", &[])),
+        HoverSection::Links => latest.api.as_ref().map(|api| {
+            // the project's override of the docs, e.g. for an internal fork.
+            let docs =
+                Some(docs.map_or_else(|| latest.docs_url(name), str::to_owned));
+            [
+                ("link-docs", &docs),
+                ("link-repository", &api.repository),
+                ("link-homepage", &api.homepage),
+            ]
+            .into_iter()
+            .filter_map(|(title, url)| {
+                Some(format!(
+                    "[{}]({})",
+                    messages.get(title, &[]),
+                    url.as_ref()?
+                ))
+            })
+            .collect::<Vec<_>>()
+            .join(" · ")
+        }),
+        HoverSection::Downloads => latest
+            .api
+            .as_ref()
+            .and_then(|api| api.recent_downloads)
+            .map(|d| {
+                messages.get("recent-downloads", &[("count", &format_count(d))])
+            }),
+        HoverSection::Size => resolved.map(|v| {
+            let deps = messages.get(
+                match v.dependencies {
+                    1 => "direct-dependency",
+                    _ => "direct-dependencies",
+                },
+                &[("count", &v.dependencies)],
+            );
+            let size = latest
+                .api
+                .as_ref()
+                .and_then(|api| api.crate_sizes.get(&v.version))
+                .map(|&size| {
+                    let size = messages
+                        .get("crate-size", &[("size", &format_size(size))]);
+                    format!(", {size}")
+                });
+            format!("{deps}{}", size.unwrap_or_default())
+        }),
+        HoverSection::Publisher => latest
+            .publisher(&latest.version)
+            .map(|p| format_publisher(&latest.version, p, messages)),
+        HoverSection::Description => latest
+            .description()
+            .map(|d| format!("---\n\n{}", markup::escape(d))),
+        HoverSection::Features => latest
+            .features
+            .as_ref()
+            .map(HashMap::keys)
+            .map(hash_map::Keys::into_iter)
+            .map(|f| f.map(String::as_str))
+            .map(Iterator::collect::<Vec<_>>)
+            .map(|f| f.join(", "))
+            .filter(|f| !f.is_empty())
+            .map(|f| {
+                let title = messages.get("available-features", &[]);
+                format!("---\n\n## {title}\n\n[ {f} ]")
+            }),
+        HoverSection::Readme => readme.map(|r| format!("---\n\n{r}")),
+    };
+
+    sections
+        .iter()
+        .filter_map(|&s| section(s))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// The version `Cargo.lock` pins a dependency to, compared to the latest
+/// release its requirement allows.
+fn format_lag(name: &str, lag: &lockfile::Lag, messages: Catalog) -> String {
+    let lockfile::Lag { locked, latest, behind } = lag;
+    let id = match behind {
+        0 => "locked-latest",
+        1 => "locked-behind-one",
+        _ => "locked-behind",
+    };
+    messages.get(
+        id,
+        &[
+            ("locked", locked),
+            ("latest", latest),
+            ("behind", behind),
+            ("package", &name),
+        ],
+    )
+}
+
+/// The hover of a `[[package]]` entry of a lockfile: the latest version of
+/// the crate, how far the locked version is behind the latest compatible
+/// release (`package` is the spec `cargo update -p` takes), whether it was
+/// yanked, and the workspace members that depend on it.
+pub fn format_locked_hover(
+    entry: &lockfile::Entry,
+    package: &str,
+    latest: &crates::CrateInfo,
+    lag: &lockfile::Lag,
+    members: &[String],
+    messages: Catalog,
+) -> String {
+    let lockfile::Entry { name, version, .. } = entry;
+    let yanked = latest
+        .is_yanked(version)
+        .then(|| messages.get("locked-yanked", &[("version", version)]));
+    let members = match members {
+        [] => None,
+        [member] => Some(messages.get("used-by-member", &[("member", member)])),
+        members => Some(
+            messages.get(
+                "used-by-members",
+                &[(
+                    "members",
+                    &members
+                        .iter()
+                        .map(|m| format!("`{m}`"))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                )],
+            ),
+        ),
+    };
+    [
+        Some(format!("{name}: {}", latest.version)),
+        Some(format_lag(package, lag, messages)),
+        yanked,
+        members,
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>()
+    .join("\n\n")
+}
+
+/// The hover of a dependency from an alternative registry, whose metadata
+/// isn't fetched (so it's never mixed up with a crates.io crate of the same
+/// name).
+pub fn format_registry_hover(
+    dependency: &parse::Dependency,
+    registry: &str,
+    messages: Catalog,
+) -> String {
+    let header = dependency.name.value.clone();
+    let source = messages.get("from-registry", &[("registry", &registry)]);
+
+    [Some(header), format_declaration(dependency, messages), Some(source)]
         .into_iter()
         .flatten()
         .collect::<Vec<_>>()
         .join("\n\n")
 }
+
+/// The hover of a `[package]` key that's inherited from the workspace, with
+/// its value in the root manifest (at the relative path `root`), if it's
+/// found.
+pub fn format_inherited_hover(
+    key: &str,
+    root: Option<&str>,
+    value: Option<&str>,
+    messages: Catalog,
+) -> String {
+    let source = match (root, value) {
+        (Some(root), Some(value)) => {
+            let inherited = messages.get("inherited-from", &[("root", &root)]);
+            format!("{inherited}\n\n```toml\n{key} = {value}\n```")
+        }
+        (Some(root), None) => {
+            messages.get("inherited-unset", &[("root", &root), ("key", &key)])
+        }
+        (None, _) => messages.get("inherited-no-root", &[]),
+    };
+    format!("# `{key}`\n\n{source}")
+}
+
+/// A size in bytes, in the largest unit (of 1000) that keeps it above 1.
+fn format_size(bytes: u64) -> String {
+    match bytes {
+        0..1_000 => format!("{bytes} B"),
+        // rounded up to `1000.0 kB` otherwise.
+        1_000..999_950 => format!("{:.1} kB", bytes as f64 / 1e3),
+        _ => format!("{:.1} MB", bytes as f64 / 1e6),
+    }
+}
+
+/// Format a count for humans, e.g. `1234567` as `1.2M`.
+pub fn format_count(count: u64) -> String {
+    match count {
+        0..1_000 => count.to_string(),
+        // rounded up to `1000.0K` otherwise.
+        1_000..999_950 => format!("{:.1}K", count as f64 / 1e3),
+        _ => format!("{:.1}M", count as f64 / 1e6),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_count() {
+        assert_eq!(format_count(999), "999");
+        assert_eq!(format_count(1_234), "1.2K");
+        assert_eq!(format_count(999_949), "999.9K");
+        assert_eq!(format_count(999_950), "1.0M");
+        assert_eq!(format_count(1_234_567), "1.2M");
+        assert_eq!(format_size(999_950), "1.0 MB");
+    }
+
+    #[test]
+    fn test_format_lag() {
+        let messages = Catalog::for_locale(None);
+        let lag = |behind| lockfile::Lag {
+            locked: semver::Version::new(1, 0, 1),
+            latest: semver::Version::new(1, 0, 3),
+            behind,
+        };
+        assert_eq!(
+            format_lag("serde", &lag(0), messages),
+            "locked: 1.0.1 (the latest compatible release)"
+        );
+        assert_eq!(
+            format_lag("serde", &lag(2), messages),
+            "locked: 1.0.1, 2 compatible releases behind 1.0.3 (`cargo update \
+             -p serde`)"
+        );
+    }
+}
@@ -0,0 +1,113 @@
+//! A backend for the registries that only serve the legacy git index (e.g.
+//! older private registries): the index is fetched (shallowly, with the `git`
+//! CLI) into a bare repository in the disk cache, and the files of its crates
+//! are read from the fetched commit.
+
+use std::{io, path::PathBuf, time::Duration};
+
+use crate::platform;
+
+/// A bare repository that a git index is fetched into.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitIndex {
+    url: String,
+    dir: PathBuf,
+}
+
+impl GitIndex {
+    pub fn new(url: &str, dir: PathBuf) -> Self {
+        Self { url: url.to_owned(), dir }
+    }
+
+    /// Fetch the index, unless it was fetched less than `ttl` ago.
+    pub async fn update(&self, ttl: Duration) -> io::Result<()> {
+        if self.is_fresh(ttl) {
+            return Ok(());
+        }
+        if !self.dir.join("HEAD").exists() {
+            std::fs::create_dir_all(&self.dir)?;
+            self.git(&["init", "--bare", "--quiet"]).await?;
+        }
+        let fetch = ["fetch", "--quiet", "--depth", "1", &self.url, "HEAD"];
+        self.git(&fetch).await.map(drop)
+    }
+
+    /// Read the file at `path` (e.g. `se/rd/serde`) of the fetched index, or
+    /// `None` if it has no such file.
+    pub async fn read(&self, path: &str) -> io::Result<Option<String>> {
+        let object = format!("FETCH_HEAD:{path}");
+        let output = platform::git(&self.dir, &["show", &object]).await?;
+        if !output.status.success() {
+            return Ok(None);
+        }
+        String::from_utf8(output.stdout)
+            .map(Some)
+            .map_err(|_| io::ErrorKind::InvalidData.into())
+    }
+
+    /// Whether the index was fetched less than `ttl` ago.
+    fn is_fresh(&self, ttl: Duration) -> bool {
+        std::fs::metadata(self.dir.join("FETCH_HEAD"))
+            .and_then(|meta| meta.modified())
+            .is_ok_and(|fetched| {
+                fetched.elapsed().is_ok_and(|elapsed| elapsed < ttl)
+            })
+    }
+
+    /// Run `git` in the repository, failing with its error output if it
+    /// fails.
+    async fn git(&self, args: &[&str]) -> io::Result<Vec<u8>> {
+        let output = platform::git(&self.dir, args).await?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(io::Error::other(stderr.trim().to_owned()));
+        }
+        Ok(output.stdout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, process::Command};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_update_read() {
+        let root = std::env::temp_dir()
+            .join(format!("crates-language-server-git-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+
+        // a registry's index, with a single crate.
+        let upstream = root.join("upstream");
+        fs::create_dir_all(upstream.join("se/rd")).unwrap();
+        fs::write(upstream.join("config.json"), "{}").unwrap();
+        fs::write(upstream.join("se/rd/serde"), "{\"vers\":\"1.0.0\"}\n")
+            .unwrap();
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .args(["-c", "user.name=a", "-c", "user.email=a@a"])
+                .args(args)
+                .current_dir(&upstream)
+                .output()
+                .map(|output| output.status.success());
+            assert!(status.is_ok_and(|s| s), "git {args:?}");
+        };
+        git(&["init", "--quiet"]);
+        git(&["add", "."]);
+        git(&["commit", "--quiet", "-m", "index"]);
+
+        let url = format!("file://{}", upstream.display());
+        let index = GitIndex::new(&url, root.join("clone"));
+        index.update(Duration::from_secs(60)).await.unwrap();
+        assert_eq!(
+            index.read("se/rd/serde").await.unwrap().as_deref(),
+            Some("{\"vers\":\"1.0.0\"}\n")
+        );
+        assert_eq!(index.read("to/ki/tokio").await.unwrap(), None);
+        assert!(index.is_fresh(Duration::from_secs(60)));
+        assert!(!index.is_fresh(Duration::ZERO));
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}
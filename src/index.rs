@@ -0,0 +1,154 @@
+//! An index of the spans of a manifest's dependencies, for finding what's
+//! under the cursor without scanning all of the dependencies.
+
+use tower_lsp::lsp_types::{Position, Range};
+
+use crate::parse::{Dependency, Kind, Span};
+
+/// A spanned field of a dependency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Name,
+    Version,
+    GitUrl,
+    /// The `branch`, `tag` or `rev` of a git dependency.
+    GitSpecifier,
+    Path,
+    /// The feature at this index of the `features` array.
+    Feature(usize),
+    Optional,
+    DefaultFeatures,
+    Registry,
+    /// The `package` of a renamed dependency.
+    Package,
+    Workspace,
+    /// A key of the dependency's table (e.g. `version` in `version = "1"`).
+    Key,
+}
+
+#[derive(Debug)]
+struct Entry {
+    range: Range,
+    /// The index of the dependency in the manifest.
+    dependency: usize,
+    field: Field,
+}
+
+/// The spans of all of the dependencies' fields, sorted by their starts.
+///
+/// The spans never overlap (each one is a distinct token), so the only span
+/// that might contain a position is the last one that starts before it.
+#[derive(Debug, Default)]
+pub struct PositionIndex {
+    entries: Vec<Entry>,
+}
+
+impl PositionIndex {
+    pub fn new(dependencies: &[Dependency]) -> Self {
+        let mut entries = Vec::new();
+
+        for (idx, dependency) in dependencies.iter().enumerate() {
+            let mut push = |range, field| {
+                entries.push(Entry { range, dependency: idx, field })
+            };
+
+            push(dependency.name.range, Field::Name);
+            if let Some(version) = &dependency.version {
+                push(version.range, Field::Version);
+            }
+            match &dependency.kind {
+                Kind::Git(git) => {
+                    push(git.url.range, Field::GitUrl);
+                    if let Some(specifier) = &git.specifier {
+                        push(specifier.span().range, Field::GitSpecifier);
+                    }
+                }
+                Kind::Local(local) => push(local.path.range, Field::Path),
+                Kind::Registry => {}
+            }
+            for (i, feature) in dependency.features.iter().flatten().enumerate()
+            {
+                push(feature.range, Field::Feature(i));
+            }
+            let flags = [
+                (&dependency.optional, Field::Optional),
+                (&dependency.default_features, Field::DefaultFeatures),
+                (&dependency.workspace, Field::Workspace),
+            ];
+            for (flag, field) in flags {
+                if let Some(Span { range, .. }) = flag {
+                    push(*range, field);
+                }
+            }
+            if let Some(registry) = &dependency.registry {
+                push(registry.range, Field::Registry);
+            }
+            if let Some(package) = &dependency.package {
+                push(package.range, Field::Package);
+            }
+            for &key in &dependency.keys {
+                push(key, Field::Key);
+            }
+        }
+
+        entries.sort_by_key(|e| e.range.start);
+        Self { entries }
+    }
+
+    /// Find the dependency (by its index) and the field at `pos`.
+    ///
+    /// The ends of the spans are inclusive, so a cursor right after a value
+    /// (e.g. while typing it) is still considered inside of it.
+    pub fn find(&self, pos: Position) -> Option<(usize, Field)> {
+        let after = self.entries.partition_point(|e| e.range.start <= pos);
+        let entry = self.entries.get(after.checked_sub(1)?)?;
+        (pos <= entry.range.end).then_some((entry.dependency, entry.field))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+    use crate::parse::Manifest;
+
+    #[test]
+    fn test_find() {
+        let s = indoc! {r#"
+            [dependencies]
+            a = "1"
+            b = { version = "2", features = ["x", "y"], optional = true }
+
+            [dev-dependencies.c]
+            workspace = true
+
+            [build-dependencies.d]
+            version = "1"
+            features = ["x"]
+        "#};
+        let dom = taplo::parser::parse(s).into_dom();
+        let manifest = Manifest::parse(s, &dom);
+        let find =
+            |line, character| manifest.index.find(Position { line, character });
+
+        assert_eq!(find(1, 0), Some((0, Field::Name)));
+        assert_eq!(find(1, 1), Some((0, Field::Name)));
+        assert_eq!(find(1, 2), None);
+        assert_eq!(find(1, 5), Some((0, Field::Version)));
+        assert_eq!(find(2, 17), Some((1, Field::Version)));
+        assert_eq!(find(2, 34), Some((1, Field::Feature(0))));
+        assert_eq!(find(2, 40), Some((1, Field::Feature(1))));
+        assert_eq!(find(2, 58), Some((1, Field::Optional)));
+        assert_eq!(find(2, 62), None);
+        assert_eq!(find(4, 18), Some((2, Field::Name)));
+        assert_eq!(find(5, 13), Some((2, Field::Workspace)));
+        assert_eq!(find(3, 0), None);
+        assert_eq!(find(2, 6), Some((1, Field::Key)));
+        assert_eq!(find(5, 3), Some((2, Field::Key)));
+        assert_eq!(find(8, 2), Some((3, Field::Key)));
+        assert_eq!(find(8, 11), Some((3, Field::Version)));
+        assert_eq!(find(9, 4), Some((3, Field::Key)));
+        assert_eq!(find(9, 9), None);
+    }
+}
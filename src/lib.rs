@@ -1,6 +1,38 @@
-#![feature(duration_constructors, let_chains, if_let_guard)]
+#![feature(duration_constructors)]
 
+#[doc(hidden)]
+pub mod bench;
+mod budget;
+mod cargo_config;
+mod command;
+mod config;
+mod context;
 mod crates;
+mod disk_cache;
+mod edit;
+mod features;
 mod format;
+mod git_index;
+mod index;
+mod links;
+mod lints;
+mod lockfile;
 pub mod ls;
+mod markup;
+mod messages;
+mod metadata;
+mod metrics;
+pub mod panic;
 mod parse;
+mod path;
+mod platform;
+mod profile;
+mod project;
+mod publish;
+mod readme;
+mod resolver;
+mod schema;
+mod search;
+mod target;
+mod version;
+mod workspace;
@@ -0,0 +1,153 @@
+//! The native libraries that packages link (their `links` key). Cargo allows
+//! only one package of the dependency graph to link each library, and fails
+//! to build otherwise.
+
+use std::collections::HashMap;
+
+use tower_lsp::lsp_types::Range;
+
+use crate::{
+    crates::CrateInfo,
+    parse::{Dependency, Span},
+};
+
+/// A dependency that links the same native library as another package.
+#[derive(Debug)]
+pub struct Conflict<'a> {
+    pub dependency: &'a Dependency,
+    pub links: String,
+    pub with: Linker<'a>,
+}
+
+/// The package that links a library first.
+#[derive(Debug, Clone, Copy)]
+pub enum Linker<'a> {
+    /// The manifest's own package, by the range of its `links` value.
+    Package(Range),
+    Dependency(&'a Dependency),
+}
+
+/// The dependencies that link the same library as the package itself (its
+/// `links`), or as an earlier dependency on another package.
+///
+/// The dependencies are resolved, from the `infos` of their crates (by their
+/// names), to the versions cargo would pick without a lockfile (see
+/// [`CrateInfo::resolve`]).
+pub fn conflicts<'a>(
+    links: Option<&Span<String>>,
+    dependencies: &[&'a Dependency],
+    infos: &HashMap<String, CrateInfo>,
+) -> Vec<Conflict<'a>> {
+    // the packages that link a library so far, by their crate and version.
+    let mut linkers = Vec::new();
+    if let Some(links) = links {
+        linkers.push((&links.value, None, Linker::Package(links.range)));
+    }
+
+    let mut conflicts = Vec::new();
+    for &dependency in dependencies {
+        let Some(info) = infos.get(dependency.crate_name()) else { continue };
+        let req = dependency
+            .version
+            .as_ref()
+            .and_then(|v| v.value.req.as_ref());
+        let Some(version) = info.resolve(req) else { continue };
+        let Some(links) = &version.links else { continue };
+        let package = Some((&info.name, &version.version));
+
+        // the same package (e.g. in both `[dependencies]` and
+        // `[dev-dependencies]`) is linked once, and reported once.
+        if linkers.iter().any(|(l, p, _)| *l == links && *p == package) {
+            continue;
+        }
+        if let Some(&(.., with)) = linkers.iter().find(|(l, ..)| *l == links) {
+            conflicts.push(Conflict { dependency, links: links.clone(), with });
+        }
+        linkers.push((links, package, Linker::Dependency(dependency)));
+    }
+    conflicts
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+    use crate::{crates::VersionEntry, parse::Manifest, platform::Instant};
+
+    fn info(name: &str, versions: &[(&str, Option<&str>)]) -> CrateInfo {
+        let versions = versions
+            .iter()
+            .map(|&(version, links)| VersionEntry {
+                version: semver::Version::parse(version).unwrap(),
+                yanked: false,
+                links: links.map(str::to_owned),
+                dependencies: 0,
+                features: None,
+                rust_version: None,
+                published: None,
+            })
+            .collect::<Vec<_>>();
+        CrateInfo {
+            name: name.to_owned(),
+            version: versions.last().unwrap().version.clone(),
+            raw_latest: versions.last().unwrap().version.clone(),
+            features: None,
+            versions,
+            api: None,
+            fetched_at: Instant::now(),
+            revision: 0,
+        }
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let s = indoc! {r#"
+            [package]
+            name = "my-zlib"
+            links = "z"
+
+            [dependencies]
+            libz-sys = "1"
+            git2-old = { package = "libgit2-sys", version = "0.14" }
+            libgit2-sys = "0.16"
+            openssl-sys = "0.9"
+
+            [dev-dependencies]
+            libgit2-sys = "0.16"
+        "#};
+        let dom = taplo::parser::parse(s).into_dom();
+        let manifest = Manifest::parse(s, &dom);
+
+        let infos = [
+            info("libz-sys", &[("1.1.20", Some("z"))]),
+            info(
+                "libgit2-sys",
+                &[("0.14.2", Some("git2")), ("0.16.2", Some("git2"))],
+            ),
+            info("openssl-sys", &[("0.9.100", Some("openssl"))]),
+        ];
+        let infos = infos
+            .into_iter()
+            .map(|info| (info.name.clone(), info))
+            .collect::<HashMap<_, _>>();
+        let dependencies = manifest.dependencies.iter().collect::<Vec<_>>();
+
+        let conflicts =
+            conflicts(manifest.links.as_ref(), &dependencies, &infos);
+        let conflicts = conflicts
+            .iter()
+            .map(|c| {
+                let with = match &c.with {
+                    Linker::Package(_) => "package",
+                    Linker::Dependency(d) => d.name.value.as_str(),
+                };
+                (c.dependency.name.value.as_str(), c.links.as_str(), with)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            conflicts,
+            [("libz-sys", "z", "package"), ("libgit2-sys", "git2", "git2-old")]
+        );
+    }
+}
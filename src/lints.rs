@@ -0,0 +1,334 @@
+//! The `[lints]` (and `[workspace.lints]`) tables, which set the levels of the
+//! lints of `rustc` and the lint tools (e.g. `[lints.clippy]`).
+//!
+//! The lists of the known lints are bundled (see `build.rs`).
+
+use std::sync::LazyLock;
+
+use taplo::dom::Node;
+use tower_lsp::lsp_types::{DiagnosticSeverity, Range};
+
+use crate::{messages::Catalog, parse};
+
+#[derive(Debug)]
+pub enum Warning {
+    UnknownLint { lint: String, range: Range },
+    UnknownTool { tool: String, range: Range },
+    InvalidLevel { value: String, range: Range },
+    InvalidLint { range: Range },
+    InvalidWorkspace { range: Range },
+    InheritedLints { range: Range },
+}
+
+impl Warning {
+    /// The message of the warning, in the locale of `messages`.
+    pub fn message(&self, messages: Catalog) -> String {
+        match self {
+            Self::UnknownLint { lint, .. } => {
+                messages.get("unknown-lint", &[("lint", lint)])
+            }
+            Self::UnknownTool { tool, .. } => {
+                messages.get("unknown-lint-tool", &[("tool", tool)])
+            }
+            Self::InvalidLevel { value, .. } => messages.get(
+                "not-one-of",
+                &[("value", value), ("expected", &expected_levels())],
+            ),
+            Self::InvalidLint { .. } => messages.get("invalid-lint", &[]),
+            Self::InvalidWorkspace { .. } => {
+                messages.get("invalid-lints-workspace", &[])
+            }
+            Self::InheritedLints { .. } => messages.get("inherited-lints", &[]),
+        }
+    }
+
+    pub fn range(&self) -> Range {
+        match self {
+            Self::UnknownLint { range, .. }
+            | Self::UnknownTool { range, .. }
+            | Self::InvalidLevel { range, .. }
+            | Self::InvalidLint { range }
+            | Self::InvalidWorkspace { range }
+            | Self::InheritedLints { range } => *range,
+        }
+    }
+
+    /// Whether cargo fails on it, rather than passing it on to the tools
+    /// (which warn about unknown lints).
+    pub fn severity(&self) -> DiagnosticSeverity {
+        match self {
+            Self::UnknownLint { .. } | Self::UnknownTool { .. } => {
+                DiagnosticSeverity::WARNING
+            }
+            _ => DiagnosticSeverity::ERROR,
+        }
+    }
+}
+
+/// A lint (or a lint group) of a tool.
+#[derive(Debug)]
+pub struct Lint {
+    pub name: &'static str,
+    /// The default level, or `group` for lint groups.
+    pub level: &'static str,
+    pub description: &'static str,
+}
+
+/// The tools whose lints can be set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tool {
+    Rust,
+    Clippy,
+    Rustdoc,
+}
+
+impl Tool {
+    pub const ALL: &[Self] = &[Self::Rust, Self::Clippy, Self::Rustdoc];
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|tool| tool.key() == key)
+    }
+
+    pub fn key(self) -> &'static str {
+        match self {
+            Self::Rust => "rust",
+            Self::Clippy => "clippy",
+            Self::Rustdoc => "rustdoc",
+        }
+    }
+
+    /// The known lints of the tool, or `None` if they're not bundled.
+    pub fn lints(self) -> Option<&'static [Lint]> {
+        static RUST: LazyLock<Vec<Lint>> = LazyLock::new(|| {
+            parse_list(include_str!(concat!(env!("OUT_DIR"), "/rust.tsv")))
+        });
+        static CLIPPY: LazyLock<Vec<Lint>> = LazyLock::new(|| {
+            parse_list(include_str!(concat!(env!("OUT_DIR"), "/clippy.tsv")))
+        });
+
+        match self {
+            Self::Rust => Some(&RUST),
+            Self::Clippy => Some(&CLIPPY),
+            Self::Rustdoc => None,
+        }
+    }
+}
+
+fn parse_list(list: &'static str) -> Vec<Lint> {
+    list.lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            Some(Lint {
+                name: fields.next()?,
+                level: fields.next()?,
+                description: fields.next()?,
+            })
+        })
+        .collect()
+}
+
+pub const LEVELS: &[&str] = &["forbid", "deny", "warn", "allow"];
+
+fn expected_levels() -> String {
+    LEVELS
+        .iter()
+        .map(|l| format!("`{l}`"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+const LINTS_KEY: &str = "lints";
+const WORKSPACE_KEY: &str = "workspace";
+const LEVEL_KEY: &str = "level";
+
+/// The tool of the table at `path` (which is relative to the root), if it's
+/// a lints table, e.g. `[lints.clippy]` or `[workspace.lints.rust]`.
+pub fn table_tool(path: &[String]) -> Option<Tool> {
+    match path {
+        [lints, tool] if lints == LINTS_KEY => Tool::from_key(tool),
+        [workspace, lints, tool]
+            if workspace == WORKSPACE_KEY && lints == LINTS_KEY =>
+        {
+            Tool::from_key(tool)
+        }
+        _ => None,
+    }
+}
+
+/// If the table at `path` is a lints table itself (rather than the table of
+/// a tool), whether its lints can be inherited from the workspace, i.e.,
+/// `Some(true)` for `[lints]`, and `Some(false)` for `[workspace.lints]`.
+pub fn lints_table(path: &[String]) -> Option<bool> {
+    match path {
+        [lints] if lints == LINTS_KEY => Some(true),
+        [workspace, lints]
+            if workspace == WORKSPACE_KEY && lints == LINTS_KEY =>
+        {
+            Some(false)
+        }
+        _ => None,
+    }
+}
+
+/// Validate the `[lints]` and `[workspace.lints]` tables.
+pub fn validate(root: &Node, s: &str) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+
+    // only the package's lints can be inherited.
+    let package = root.get(LINTS_KEY);
+    let workspace = root.get(WORKSPACE_KEY).get(LINTS_KEY);
+    for (lints, can_inherit) in [(package, true), (workspace, false)] {
+        let Some(lints) = lints.as_table() else { continue };
+        let entries = lints.entries().read();
+
+        let inherits = entries
+            .iter()
+            .find(|(key, _)| can_inherit && key.value() == WORKSPACE_KEY);
+        if let Some((_, value)) = inherits
+            && value.as_bool().is_none_or(|b| !b.value())
+            && let Some(range) = parse::node_range(value, s)
+        {
+            warnings.push(Warning::InvalidWorkspace { range });
+        }
+
+        for (key, value) in entries.iter() {
+            let Some(range) = parse::key_range(key, s) else { continue };
+            if inherits.is_some() {
+                if key.value() != WORKSPACE_KEY {
+                    warnings.push(Warning::InheritedLints { range });
+                }
+                continue;
+            }
+            let Some(tool) = Tool::from_key(key.value()) else {
+                warnings.push(Warning::UnknownTool {
+                    tool: key.value().to_owned(),
+                    range,
+                });
+                continue;
+            };
+            let Some(lints) = value.as_table() else { continue };
+            for (lint, level) in lints.entries().read().iter() {
+                validate_lint(&mut warnings, tool, lint, level, s);
+            }
+        }
+    }
+
+    warnings
+}
+
+fn validate_lint(
+    warnings: &mut Vec<Warning>,
+    tool: Tool,
+    lint: &taplo::dom::node::Key,
+    level: &Node,
+    s: &str,
+) {
+    // the names are passed to the tools as is, which accept both `-` and `_`.
+    let name = lint.value().replace('-', "_");
+    if let Some(known) = tool.lints()
+        && !known.iter().any(|l| l.name == name)
+        && let Some(range) = parse::key_range(lint, s)
+    {
+        let lint = match tool {
+            Tool::Rust => lint.value().to_owned(),
+            _ => format!("{}::{}", tool.key(), lint.value()),
+        };
+        warnings.push(Warning::UnknownLint { lint, range });
+    }
+
+    let value = match level {
+        Node::Table(table) => table.get(LEVEL_KEY),
+        _ => Some(level.clone()),
+    };
+    let Some(value) = value.filter(|v| v.as_str().is_some()) else {
+        warnings.extend(
+            parse::node_range(level, s)
+                .map(|range| Warning::InvalidLint { range }),
+        );
+        return;
+    };
+    if let Some(str) = value.as_str()
+        && !LEVELS.contains(&str.value())
+        && let Some(range) = parse::node_range(&value, s)
+    {
+        warnings.push(Warning::InvalidLevel {
+            value: str.value().to_owned(),
+            range,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    fn validate(s: &str) -> Vec<String> {
+        let dom = taplo::parser::parse(s).into_dom();
+        super::validate(&dom, s)
+            .iter()
+            .map(|warning| warning.message(Catalog::for_locale(None)))
+            .collect()
+    }
+
+    #[test]
+    fn test_validate() {
+        let s = indoc! {r#"
+            [lints.rust]
+            unsafe_code = "forbid"
+            unused-imports = { level = "deny", priority = -1 }
+            no_such_lint = "warn"
+
+            [lints.clippy]
+            pedantic = { level = "warn", priority = -1 }
+            needless_return = "error"
+            unwrap-used = 1
+
+            [lints.rustdoc]
+            broken_intra_doc_links = "deny"
+
+            [lints.foo]
+            bar = "allow"
+
+            [workspace.lints.clippy]
+            all = "deny"
+            no_such_lint = "deny"
+        "#};
+
+        assert_eq!(
+            validate(s),
+            [
+                "unknown lint `no_such_lint`",
+                "`error` is not one of `forbid`, `deny`, `warn`, `allow`",
+                "the level of a lint must be a string, or a table with a \
+                 `level`",
+                "unknown lint tool `foo`",
+                "unknown lint `clippy::no_such_lint`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_workspace() {
+        let s = indoc! {r#"
+            [lints]
+            workspace = true
+            rust.unsafe_code = "forbid"
+        "#};
+        assert_eq!(
+            validate(s),
+            [
+                "the lints can't be set when they're inherited from the workspace"
+            ]
+        );
+
+        let s = "[lints]\nworkspace = false\n";
+        assert_eq!(
+            validate(s),
+            [
+                "`workspace` can only be `true`, to inherit the workspace's lints"
+            ]
+        );
+    }
+}
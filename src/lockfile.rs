@@ -0,0 +1,587 @@
+//! Reading the packages of a workspace's `Cargo.lock`, to warm the crates
+//! cache with them (see [`crate::crates::RegistryCache::prefetch`]), and to
+//! compare the versions it pins with the ones a manifest allows (or with the
+//! latest releases, when the lockfile itself is open).
+
+use std::path::PathBuf;
+
+use tower_lsp::lsp_types::{Position, Range};
+use url::Url;
+
+use crate::{crates::VersionEntry, parse, path};
+
+pub const LOCKFILE_NAME: &str = "Cargo.lock";
+
+const PACKAGE_KEY: &str = "package";
+const NAME_KEY: &str = "name";
+const VERSION_KEY: &str = "version";
+const SOURCE_KEY: &str = "source";
+const DEPENDENCIES_KEY: &str = "dependencies";
+
+/// The sources of the packages from crates.io, through either its git index
+/// or its sparse index.
+const CRATES_IO_SOURCES: &[&str] = &[
+    "registry+https://github.com/rust-lang/crates.io-index",
+    "sparse+https://index.crates.io/",
+];
+
+/// Read the lockfile at the root of a workspace, if there's one.
+pub fn read(root: &Url) -> Option<String> {
+    let lockfile = path::to_path(root)?.join(LOCKFILE_NAME);
+    std::fs::read_to_string(lockfile).ok()
+}
+
+/// The path of the lockfile of the workspace that the manifest at `manifest`
+/// is in: the closest one in its directory or above it.
+pub fn find(manifest: &Url) -> Option<PathBuf> {
+    let manifest = path::to_path(manifest)?;
+    manifest
+        .ancestors()
+        .skip(1)
+        .map(|dir| dir.join(LOCKFILE_NAME))
+        .find(|lockfile| lockfile.is_file())
+}
+
+/// The packages from crates.io that a lockfile pins.
+#[derive(Debug)]
+pub struct Lockfile {
+    pub uri: Url,
+    pub packages: Vec<Locked>,
+}
+
+impl Lockfile {
+    pub fn parse(uri: Url, s: &str) -> Self {
+        Self { uri, packages: locked_packages(s) }
+    }
+
+    /// The versions of the crate `name` that the lockfile pins (several,
+    /// when the workspace depends on incompatible versions of it).
+    pub fn versions(&self, name: &str) -> Vec<semver::Version> {
+        self.packages
+            .iter()
+            .filter(|l| l.name == name)
+            .map(|l| l.version.clone())
+            .collect()
+    }
+}
+
+/// The (deduplicated) names of the packages from crates.io in a lockfile, in
+/// the order they're locked. Local and git packages, and packages from other
+/// registries, are left out.
+pub fn crates_io_packages(s: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    for (name, _) in crates_io_entries(s) {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+/// The versions of the crate `name` from crates.io that a lockfile pins
+/// (several, when the workspace depends on incompatible versions of it).
+pub fn locked_versions(s: &str, name: &str) -> Vec<semver::Version> {
+    crates_io_entries(s)
+        .into_iter()
+        .filter(|(n, _)| n == name)
+        .filter_map(|(_, version)| semver::Version::parse(&version).ok())
+        .collect()
+}
+
+/// The names and versions of the packages from crates.io in a lockfile.
+fn crates_io_entries(s: &str) -> Vec<(String, String)> {
+    let dom = taplo::parser::parse(s).into_dom();
+    let packages = dom.get(PACKAGE_KEY);
+    let Some(packages) = packages.as_array() else { return Vec::new() };
+
+    let mut entries = Vec::new();
+    for package in packages.items().read().iter() {
+        let is_crates_io = package
+            .get(SOURCE_KEY)
+            .as_str()
+            .is_some_and(|source| CRATES_IO_SOURCES.contains(&source.value()));
+        let field =
+            |key| package.get(key).as_str().map(|s| s.value().to_owned());
+        if let Some(name) = field(NAME_KEY)
+            && let Some(version) = field(VERSION_KEY)
+            && is_crates_io
+        {
+            entries.push((name, version));
+        }
+    }
+    entries
+}
+
+/// A crates.io package that a lockfile pins, and the packages that depend on
+/// it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Locked {
+    pub name: String,
+    pub version: semver::Version,
+    pub dependents: Vec<Dependent>,
+}
+
+/// A package of a lockfile that depends on a [`Locked`] package.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dependent {
+    pub name: String,
+    /// The range of the name of its entry in the lockfile.
+    pub range: Range,
+    /// Whether it's a local package (e.g. a member of the workspace), which
+    /// has no source.
+    pub is_local: bool,
+}
+
+/// The packages from crates.io in a lockfile, with their dependents.
+pub fn locked_packages(s: &str) -> Vec<Locked> {
+    let dom = taplo::parser::parse(s).into_dom();
+    let packages = dom.get(PACKAGE_KEY);
+    let Some(packages) = packages.as_array() else { return Vec::new() };
+    let packages = packages.items().read();
+
+    // The entries of `dependencies` are `name`, or `name version` (and the
+    // source) when several versions of the package are locked.
+    let dependents = packages
+        .iter()
+        .filter_map(|package| {
+            let name = package.get(NAME_KEY);
+            let dependent = Dependent {
+                name: name.as_str()?.value().to_owned(),
+                range: parse::node_range(&name, s)?,
+                is_local: package.get(SOURCE_KEY).as_str().is_none(),
+            };
+            let dependencies = package.get(DEPENDENCIES_KEY);
+            let dependencies = dependencies.as_array()?.items().read();
+            let dependencies = dependencies
+                .iter()
+                .filter_map(|d| d.as_str().map(|d| d.value().to_owned()))
+                .collect::<Vec<_>>();
+            Some((dependent, dependencies))
+        })
+        .collect::<Vec<_>>();
+    let dependents_of = |name: &str, version: &str| {
+        dependents
+            .iter()
+            .filter(|(_, dependencies)| {
+                dependencies.iter().any(|dependency| {
+                    let mut parts = dependency.split_whitespace();
+                    parts.next() == Some(name)
+                        && parts.next().is_none_or(|v| v == version)
+                })
+            })
+            .map(|(dependent, _)| dependent.clone())
+            .collect()
+    };
+
+    crates_io_entries(s)
+        .into_iter()
+        .filter_map(|(name, version)| {
+            Some(Locked {
+                dependents: dependents_of(&name, &version),
+                version: semver::Version::parse(&version).ok()?,
+                name,
+            })
+        })
+        .collect()
+}
+
+/// The other versions of the crate `name` that a lockfile pins, besides the
+/// one that `req` resolves to, i.e. the second copies of the crate (of
+/// other major versions) that are built along with the one of `req`.
+///
+/// If no version matches `req` (the lockfile is out of date with the
+/// manifest), there are none. Neither are there if a package from the
+/// registry depends on the version of `req` too, as that copy is built
+/// anyway, whatever the local packages require.
+pub fn duplicates<'a>(
+    locked: &'a [Locked],
+    name: &str,
+    req: &semver::VersionReq,
+) -> Vec<&'a Locked> {
+    let locked = locked.iter().filter(|l| l.name == name).collect::<Vec<_>>();
+    let resolved = locked
+        .iter()
+        .filter(|l| req.matches(&l.version))
+        .max_by(|a, b| a.version.cmp(&b.version));
+    let Some(resolved) = resolved else { return Vec::new() };
+    if resolved.dependents.iter().any(|d| !d.is_local) {
+        return Vec::new();
+    }
+    locked
+        .into_iter()
+        .filter(|l| !req.matches(&l.version))
+        .collect()
+}
+
+/// A `[[package]]` entry of a lockfile, for a package from crates.io.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub name: String,
+    pub version: semver::Version,
+    /// The range of the entry, from its header to its last line.
+    pub range: Range,
+}
+
+/// The entry of a package from crates.io at a position of a lockfile.
+pub fn entry_at(s: &str, pos: Position) -> Option<Entry> {
+    let lines = s.lines().collect::<Vec<_>>();
+    // the lines of the table headers, and whether they start packages.
+    let headers = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| line.trim_start().starts_with('['))
+        .map(|(i, line)| (i, line.trim() == "[[package]]"))
+        .collect::<Vec<_>>();
+    let at = headers.iter().rposition(|&(i, _)| i <= pos.line as usize)?;
+    let (start, true) = headers[at] else { return None };
+    let end = headers.get(at + 1).map_or(lines.len(), |&(i, _)| i);
+    let last = (start..end).rev().find(|&i| !lines[i].trim().is_empty())?;
+
+    let index = headers[..at].iter().filter(|(_, package)| *package).count();
+    let dom = taplo::parser::parse(s).into_dom();
+    let packages = dom.get(PACKAGE_KEY);
+    let package = packages.as_array()?.items().read().get(index)?.clone();
+    let field = |key| package.get(key).as_str().map(|s| s.value().to_owned());
+    if !field(SOURCE_KEY).is_some_and(|s| CRATES_IO_SOURCES.contains(&&*s)) {
+        return None;
+    }
+
+    Some(Entry {
+        name: field(NAME_KEY)?,
+        version: semver::Version::parse(&field(VERSION_KEY)?).ok()?,
+        range: Range::new(
+            Position::new(start as u32, 0),
+            Position::new(
+                last as u32,
+                lines[last].encode_utf16().count() as u32,
+            ),
+        ),
+    })
+}
+
+/// The workspace members (the packages without a source) that depend on the
+/// package `name` at `version`, directly or through other packages.
+pub fn dependent_members(
+    s: &str,
+    name: &str,
+    version: &semver::Version,
+) -> Vec<String> {
+    let dom = taplo::parser::parse(s).into_dom();
+    let packages = dom.get(PACKAGE_KEY);
+    let Some(packages) = packages.as_array() else { return Vec::new() };
+    let packages = packages
+        .items()
+        .read()
+        .iter()
+        .filter_map(|package| {
+            let field =
+                |key| package.get(key).as_str().map(|s| s.value().to_owned());
+            let dependencies = package.get(DEPENDENCIES_KEY);
+            let dependencies = dependencies.as_array().map(|d| {
+                let dependencies = d.items().read();
+                dependencies
+                    .iter()
+                    .filter_map(|d| d.as_str().map(|d| d.value().to_owned()))
+                    .collect::<Vec<_>>()
+            });
+            Some((
+                field(NAME_KEY)?,
+                field(VERSION_KEY)?,
+                field(SOURCE_KEY).is_none(),
+                dependencies.unwrap_or_default(),
+            ))
+        })
+        .collect::<Vec<_>>();
+
+    let version = version.to_string();
+    if !packages.iter().any(|(n, v, ..)| n == name && v == &version) {
+        return Vec::new();
+    }
+
+    // Walk the dependents up from the package, as in `cargo tree -i`.
+    let mut members = Vec::new();
+    let mut visited = vec![(name.to_owned(), version)];
+    let mut queue = visited.clone();
+    while let Some((name, version)) = queue.pop() {
+        for (dependent, dependent_version, member, dependencies) in &packages {
+            let depends = dependencies.iter().any(|dependency| {
+                let mut parts = dependency.split_whitespace();
+                parts.next() == Some(&name)
+                    && parts.next().is_none_or(|v| v == version)
+            });
+            let key = (dependent.clone(), dependent_version.clone());
+            if !depends || visited.contains(&key) {
+                continue;
+            }
+            if *member {
+                members.push(dependent.clone());
+            }
+            visited.push(key.clone());
+            queue.push(key);
+        }
+    }
+    members.sort();
+    members.dedup();
+    members
+}
+
+/// How far the version that the lockfile pins a dependency to is behind the
+/// latest release its requirement allows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lag {
+    pub locked: semver::Version,
+    /// The latest release that matches the requirement.
+    pub latest: semver::Version,
+    /// The number of (unyanked) releases that match the requirement and are
+    /// newer than the locked version.
+    pub behind: usize,
+}
+
+impl Lag {
+    /// Compare the latest of the `locked` versions that match `req` with the
+    /// published `versions` of the crate, or `None` if none of them match
+    /// (the lockfile is out of date with the manifest).
+    pub fn new(
+        locked: &[semver::Version],
+        req: &semver::VersionReq,
+        versions: &[VersionEntry],
+    ) -> Option<Self> {
+        let locked = locked.iter().filter(|v| req.matches(v)).max()?;
+        let newer = versions
+            .iter()
+            .filter(|v| !v.yanked && req.matches(&v.version))
+            .map(|v| &v.version)
+            .filter(|&v| v > locked)
+            .collect::<Vec<_>>();
+        Some(Self {
+            locked: locked.clone(),
+            latest: newer.iter().copied().max().unwrap_or(locked).clone(),
+            behind: newer.len(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    #[test]
+    fn test_crates_io_packages() {
+        let s = indoc! {r#"
+            version = 4
+
+            [[package]]
+            name = "my-app"
+            version = "0.1.0"
+            dependencies = ["serde"]
+
+            [[package]]
+            name = "serde"
+            version = "1.0.219"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+            checksum = "5f0e2c6ed6606019b4e29e69dbaba95b11854410e5347d525002456dbbb786b6"
+
+            [[package]]
+            name = "syn"
+            version = "1.0.109"
+            source = "sparse+https://index.crates.io/"
+
+            [[package]]
+            name = "syn"
+            version = "2.0.100"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+
+            [[package]]
+            name = "private"
+            version = "1.0.0"
+            source = "sparse+https://example.com/index/"
+
+            [[package]]
+            name = "forked"
+            version = "1.0.0"
+            source = "git+https://github.com/a/forked#0123abc"
+        "#};
+
+        assert_eq!(crates_io_packages(s), ["serde", "syn"]);
+        assert!(crates_io_packages("not = [a lockfile").is_empty());
+
+        let versions = |name| {
+            locked_versions(s, name)
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(versions("syn"), ["1.0.109", "2.0.100"]);
+        assert!(versions("forked").is_empty());
+
+        let uri = Url::parse("file:///ws/Cargo.lock").unwrap();
+        let lockfile = Lockfile::parse(uri, s);
+        assert_eq!(lockfile.versions("syn"), locked_versions(s, "syn"));
+        assert!(lockfile.versions("forked").is_empty());
+    }
+
+    #[test]
+    fn test_locked_packages() {
+        let s = indoc! {r#"
+            version = 4
+
+            [[package]]
+            name = "my-app"
+            version = "0.1.0"
+            dependencies = ["syn 1.0.109", "syn 2.0.100", "tokio"]
+
+            [[package]]
+            name = "tokio"
+            version = "1.47.1"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+            dependencies = ["syn 2.0.100"]
+
+            [[package]]
+            name = "syn"
+            version = "1.0.109"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+
+            [[package]]
+            name = "syn"
+            version = "2.0.100"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+        "#};
+
+        let locked = locked_packages(s);
+        let summary = locked
+            .iter()
+            .map(|l| {
+                let dependents = l.dependents.iter().map(|d| d.name.as_str());
+                let dependents = dependents.collect::<Vec<_>>().join(", ");
+                format!("{} {} <- {dependents}", l.name, l.version)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                "tokio 1.47.1 <- my-app",
+                "syn 1.0.109 <- my-app",
+                "syn 2.0.100 <- my-app, tokio",
+            ]
+        );
+        // the name of the entry of `tokio`
+        assert_eq!(locked[2].dependents[1].range.start.line, 8);
+
+        let req = |r| semver::VersionReq::parse(r).unwrap();
+        let duplicates = |r| {
+            duplicates(&locked, "syn", &req(r))
+                .iter()
+                .map(|l| l.version.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(duplicates("1.0.50"), ["2.0.100"]);
+        // `tokio` needs `syn` 2 anyway, so it's `syn` 1 that's the copy.
+        assert!(duplicates("2").is_empty());
+        // out of date
+        assert!(duplicates("3").is_empty());
+        assert!(super::duplicates(&locked, "tokio", &req("1")).is_empty());
+    }
+
+    #[test]
+    fn test_entry_at() {
+        let s = indoc! {r#"
+            version = 4
+
+            [[package]]
+            name = "app"
+            version = "0.1.0"
+            dependencies = [
+             "lib",
+             "serde",
+            ]
+
+            [[package]]
+            name = "lib"
+            version = "0.1.0"
+            dependencies = ["syn"]
+
+            [[package]]
+            name = "serde"
+            version = "1.0.219"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+            dependencies = ["syn"]
+
+            [[package]]
+            name = "syn"
+            version = "2.0.100"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+
+            [[package]]
+            name = "tool"
+            version = "0.1.0"
+        "#};
+
+        let entry = |line, character| {
+            entry_at(s, Position::new(line, character))
+                .map(|e| format!("{} {} {:?}", e.name, e.version, e.range))
+        };
+        assert_eq!(entry(0, 0), None);
+        // local packages
+        assert_eq!(entry(4, 0), None);
+        assert_eq!(entry(7, 2), None);
+        assert_eq!(entry(27, 0), None);
+        let serde = entry(17, 0).unwrap();
+        assert!(serde.starts_with("serde 1.0.219"));
+        assert_eq!(entry(15, 3).unwrap(), serde);
+        assert!(entry(23, 0).unwrap().starts_with("syn 2.0.100"));
+        assert_eq!(
+            entry_at(s, Position::new(20, 0)).unwrap().range,
+            Range::new(Position::new(15, 0), Position::new(19, 22))
+        );
+
+        let members = |name, version| {
+            dependent_members(
+                s,
+                name,
+                &semver::Version::parse(version).unwrap(),
+            )
+        };
+        assert_eq!(members("syn", "2.0.100"), ["app", "lib"]);
+        assert_eq!(members("serde", "1.0.219"), ["app"]);
+        assert!(members("syn", "1.0.109").is_empty());
+    }
+
+    #[test]
+    fn test_lag() {
+        let version = |v| semver::Version::parse(v).unwrap();
+        let versions = [
+            ("1.0.98", false),
+            ("1.0.99", false),
+            ("1.0.100", true),
+            ("1.0.101", false),
+            ("2.0.0", false),
+        ]
+        .map(|(v, yanked)| VersionEntry {
+            version: version(v),
+            yanked,
+            links: None,
+            dependencies: 0,
+            features: None,
+            rust_version: None,
+            published: None,
+        });
+        let locked = [version("0.9.3"), version("1.0.98")];
+        let req = |r| semver::VersionReq::parse(r).unwrap();
+
+        assert_eq!(
+            Lag::new(&locked, &req("1"), &versions),
+            Some(Lag {
+                locked: version("1.0.98"),
+                latest: version("1.0.101"),
+                behind: 2,
+            })
+        );
+        assert_eq!(
+            Lag::new(&locked, &req("0.9"), &versions).map(|l| l.behind),
+            Some(0)
+        );
+        assert_eq!(Lag::new(&locked, &req("2"), &versions), None);
+    }
+}
@@ -1,41 +1,338 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+    pin::pin,
+    slice,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    task::Poll,
+    time::{Duration, SystemTime},
+};
 
 use crate::{
-    crates::{self, DOCS_RS_URL},
+    cargo_config::{CargoConfig, HttpConfig, Token},
+    command::{
+        self, Command, DependencyArgs, DependencySpec, PositionArgs, TableArgs,
+        UpdateLockArgs, Upgrade, UpgradeWorkspaceArgs,
+    },
+    config::{GotoDefinition, HoverSection, Settings, Severity},
+    context::{
+        self, Context, DependenciesHeader, IncompleteFeature, InlineDependency,
+        NewDependency, RequiredFeature, TableKey, TableValue,
+    },
+    crates,
+    disk_cache::DiskCache,
+    edit,
+    features::{self, Redundancy},
     format,
-    parse::{self, DEPENDENCIES_KEYS, Dependency},
+    links::{self, Linker},
+    lints, lockfile, markup,
+    messages::Catalog,
+    metadata, metrics,
+    parse::{
+        self, CRATES_IO_REGISTRY, Dependency, Fingerprint, MANIFEST_FILE_NAME,
+        Manifest,
+    },
+    path, platform, profile,
+    project::ProjectConfig,
+    publish, resolver, schema, search, target,
+    version::{self, Behind, UpdateKind},
+    workspace,
 };
+use futures::{StreamExt, stream::BoxStream};
 use ropey::Rope;
-use taplo::dom;
-use tokio::sync::RwLock;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Notify, RwLock};
 use tower_lsp::{
-    Client, LanguageServer, jsonrpc,
+    Client, ClientSocket, LanguageServer, Loopback, LspService, jsonrpc,
     lsp_types::{
+        ClientCapabilities, ClientInfo, CodeAction, CodeActionKind,
         CodeActionOrCommand, CodeActionParams, CodeActionProviderCapability,
-        CodeActionResponse, Command, CompletionItem, CompletionOptions,
-        CompletionParams, CompletionResponse, Diagnostic, DiagnosticSeverity,
-        DidChangeTextDocumentParams, DidOpenTextDocumentParams,
-        ExecuteCommandOptions, ExecuteCommandParams, GotoDefinitionParams,
-        GotoDefinitionResponse, Hover, HoverContents, HoverParams,
-        HoverProviderCapability, InitializeParams, InitializeResult,
-        MarkupContent, MarkupKind, MessageType, OneOf, Range,
-        ServerCapabilities, ShowDocumentParams, TextDocumentContentChangeEvent,
-        TextDocumentSyncCapability, TextDocumentSyncKind, TextEdit,
-        WorkDoneProgressOptions, WorkspaceEdit,
+        CodeActionResponse, CodeDescription, CodeLens, CodeLensOptions,
+        CodeLensParams, CompletionItem, CompletionList, CompletionOptions,
+        CompletionParams, CompletionResponse, Diagnostic,
+        DiagnosticRelatedInformation, DiagnosticSeverity, DiagnosticTag,
+        DidChangeConfigurationParams, DidChangeTextDocumentParams,
+        DidCloseTextDocumentParams, DidOpenTextDocumentParams,
+        DidSaveTextDocumentParams, ExecuteCommandOptions, ExecuteCommandParams,
+        GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents,
+        HoverParams, HoverProviderCapability, ImplementationProviderCapability,
+        InitializeParams, InitializeResult, InitializedParams, Location,
+        MarkupContent, MarkupKind, MessageType, OneOf, Position, Range,
+        ServerCapabilities, ShowDocumentParams, SignatureHelp,
+        SignatureHelpOptions, SignatureHelpParams,
+        TextDocumentContentChangeEvent, TextDocumentSyncCapability,
+        TextDocumentSyncKind, TextDocumentSyncOptions,
+        TextDocumentSyncSaveOptions, TextEdit, WorkDoneProgressOptions,
+        WorkspaceEdit,
+        notification::Notification,
+        request::{GotoImplementationParams, GotoImplementationResponse},
     },
 };
 use url::Url;
 
-pub mod code_action {
-    pub const LATEST_VERSION: &str = "latest_version";
+/// Build the service of the server, along with its custom requests.
+pub fn service() -> (LspService<Backend>, ClientSocket) {
+    LspService::build(Backend::new)
+        .custom_method(metrics::METHOD, Backend::metrics)
+        .custom_method(search::METHOD, Backend::search)
+        .custom_method(schema::METHOD, Backend::settings_schema)
+        .finish()
+}
+
+/// How the completions of crate names label the crates from crates.io.
+const CRATES_IO_SOURCE: &str = "crates.io";
+/// How long the diagnostics of a changed document wait for the next change,
+/// unless configured otherwise.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// The `data` of the outdated version diagnostics, e.g.:
+///
+/// ```json
+/// { "name": "serde", "current": "1.0.100", "latest": "1.0.219", "kind": "compatible" }
+/// ```
+///
+/// Clients can use it to group the updates (e.g. "update all compatible").
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OutdatedData {
+    pub name: String,
+    /// The version requirement, as written in the manifest.
+    pub current: String,
+    pub latest: String,
+    pub kind: UpdateKind,
+}
+
+/// The `crates/updatesAvailable` notification, which is sent when refreshing
+/// the cache (see the `cache.ttl` setting) finds new versions of the crates
+/// the open manifests depend on, e.g.:
+///
+/// ```json
+/// { "updates": [{ "name": "serde", "previous": "1.0.218", "latest": "1.0.219" }] }
+/// ```
+enum UpdatesAvailable {}
+
+impl Notification for UpdatesAvailable {
+    type Params = UpdatesAvailableParams;
+    const METHOD: &'static str = "crates/updatesAvailable";
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct UpdatesAvailableParams {
+    updates: Vec<crates::Update>,
+}
+
+/// A `[[package]]` entry of an open lockfile, and how far it's behind the
+/// latest compatible release of its crate.
+struct LockfileEntry {
+    entry: lockfile::Entry,
+    latest: crates::CrateInfo,
+    lag: lockfile::Lag,
+    /// The spec `cargo update -p` takes for the package.
+    package: String,
+}
+
+/// The cached diagnostics of the dependency entries of a document, by the
+/// entries' fingerprints, so only the entries that changed are re-analyzed.
+///
+/// The ranges of the diagnostics are relative to the start of their entries
+/// (see [`parse::Fingerprint`]), as the entries might move.
+#[derive(Debug, Default)]
+pub(crate) struct Analysis {
+    /// The registry generation the diagnostics were generated with.
+    generation: u64,
+    /// The diagnostics by the hashes of the entries' fingerprints, along
+    /// with the [revisions](crates::CrateInfo::revision) of the crates they
+    /// were generated with.
+    diagnostics: HashMap<u64, (u64, Vec<Diagnostic>)>,
+}
+
+impl Analysis {
+    /// The (relative) diagnostics of an entry, if neither it nor its crate
+    /// (now at `revision`) changed since it was analyzed.
+    pub(crate) fn get(
+        &self,
+        fingerprint: Fingerprint,
+        revision: u64,
+    ) -> Option<&[Diagnostic]> {
+        self.diagnostics
+            .get(&fingerprint.hash)
+            .filter(|(analyzed, _)| *analyzed == revision)
+            .map(|(_, relative)| relative.as_slice())
+    }
+
+    pub(crate) fn insert(
+        &mut self,
+        fingerprint: Fingerprint,
+        revision: u64,
+        relative: Vec<Diagnostic>,
+    ) {
+        self.diagnostics
+            .insert(fingerprint.hash, (revision, relative));
+    }
+
+    /// Move the (relative) diagnostics of an entry to where the entry is now.
+    pub(crate) fn anchor(
+        fingerprint: Fingerprint,
+        relative: &[Diagnostic],
+    ) -> Vec<Diagnostic> {
+        relative
+            .iter()
+            .cloned()
+            .map(|d| Diagnostic {
+                range: fingerprint.to_absolute(d.range),
+                ..d
+            })
+            .collect()
+    }
+
+    /// Carry the diagnostics of an entry over from a `previous` analysis, if
+    /// neither it nor its crate (now at `revision`, if it's cached) changed
+    /// since, returning them where the entry is now.
+    pub(crate) fn reuse(
+        &mut self,
+        previous: &Self,
+        fingerprint: Fingerprint,
+        revision: Option<u64>,
+    ) -> Option<Vec<Diagnostic>> {
+        let revision = revision?;
+        let relative = previous.get(fingerprint, revision)?;
+        self.insert(fingerprint, revision, relative.to_vec());
+        Some(Self::anchor(fingerprint, relative))
+    }
+}
+
+/// The index that the crates of an alternative registry are fetched from.
+#[derive(Debug)]
+enum RegistryIndex {
+    Sparse {
+        url: String,
+        token: Option<Token>,
+    },
+    /// A legacy git index, which is fetched into the disk cache.
+    Git {
+        url: String,
+    },
+}
+
+impl RegistryIndex {
+    fn url(&self) -> &str {
+        match self {
+            Self::Sparse { url, .. } | Self::Git { url } => url,
+        }
+    }
 }
 
+/// A parsed file, along with its modification time, so it's only parsed
+/// again once it changes.
+type Parsed<T> = (SystemTime, Arc<T>);
+
 #[derive(Debug)]
 pub struct Backend {
     client: Client,
     documents: Arc<RwLock<HashMap<Url, Rope>>>,
-    manifests: Arc<RwLock<HashMap<Url, Vec<Dependency>>>>,
+    /// The parsed open manifests, shared with the diagnostics runs so the
+    /// runs don't hold the map while they fetch the crates.
+    manifests: Arc<RwLock<HashMap<Url, Arc<Manifest>>>>,
+    /// The cargo configurations of the open manifests, loaded when they're
+    /// opened.
+    cargo_configs: Arc<RwLock<HashMap<Url, CargoConfig>>>,
+    /// The `.crates-ls.toml` configurations of the open manifests, loaded
+    /// when they're opened.
+    project_configs: Arc<RwLock<HashMap<Url, ProjectConfig>>>,
+    /// The files of the packages of the open manifests, which the publish
+    /// lints match the `include`/`exclude` globs against, walked when the
+    /// manifests are opened or saved.
+    package_files: Arc<RwLock<HashMap<Url, publish::Files>>>,
+    /// The roots of the workspaces of the open manifests (see
+    /// [`workspace::Root::find`]), found when the manifests are opened or
+    /// saved rather than on every change.
+    roots: Arc<RwLock<HashMap<Url, Option<workspace::Root>>>>,
+    /// The manifests of the workspace members that aren't open, along with
+    /// the modification times of their files, so they're only parsed again
+    /// once they change on disk.
+    member_manifests: Arc<RwLock<HashMap<Url, Parsed<Manifest>>>>,
+    /// The lockfiles of the workspaces of the open manifests, by their
+    /// paths.
+    lockfiles: Arc<RwLock<HashMap<PathBuf, Parsed<lockfile::Lockfile>>>>,
+    /// The `[http]` settings of the cargo configuration of the workspace,
+    /// which all of the requests share.
+    cargo_http: Arc<RwLock<HttpConfig>>,
+    analyses: Arc<RwLock<HashMap<Url, Analysis>>>,
+    /// The hashes of the last published diagnostics of the documents.
+    published: Arc<RwLock<HashMap<Url, u64>>>,
+    /// The tokens of the pending diagnostics runs of the open documents,
+    /// which are notified once a newer run supersedes them.
+    runs: Arc<RwLock<HashMap<Url, Arc<Notify>>>>,
     registry: crates::RegistryCache,
+    settings: Arc<RwLock<Settings>>,
+    client_info: Arc<RwLock<Option<ClientInfo>>>,
+    client_capabilities: Arc<RwLock<ClientCapabilities>>,
+    /// The locale of the client's UI (e.g. `en-US`), if it sent one.
+    client_locale: Arc<RwLock<Option<String>>>,
+    /// The roots of the workspace folders the client opened.
+    workspace_roots: Arc<RwLock<Vec<Url>>>,
+    /// The unknown settings the client was warned about, so it's only
+    /// warned once about each.
+    unknown_settings: Arc<RwLock<HashSet<String>>>,
+    lifecycle: Lifecycle,
+}
+
+/// Whether the client asked the server to shut down (which decides the exit
+/// code of the server), and whether it's gone.
+#[derive(Debug, Clone, Default)]
+pub struct Lifecycle {
+    shut_down: Arc<AtomicBool>,
+    exited: Arc<Notify>,
+}
+
+impl Lifecycle {
+    /// Wrap the socket of [`service`], so [`Self::exited`] tells when the
+    /// client is gone.
+    pub fn watch(&self, socket: ClientSocket) -> WatchedSocket {
+        WatchedSocket { socket, exited: self.exited.clone() }
+    }
+
+    /// Wait until the client sent the `exit` notification, or closed the
+    /// connection. The server keeps waiting for the next message after
+    /// `exit` otherwise, which never comes if the client keeps the
+    /// connection open.
+    pub async fn exited(&self) {
+        self.exited.notified().await;
+    }
+
+    /// The exit code [the LSP spec] mandates: `0` if the client asked the
+    /// server to shut down before it exited, and `1` otherwise (e.g. the
+    /// client crashed, and closed the connection).
+    ///
+    /// [the LSP spec]: https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#exit
+    pub fn exit_code(&self) -> i32 {
+        if self.shut_down.load(Ordering::Relaxed) { 0 } else { 1 }
+    }
+}
+
+/// The socket of [`service`], whose stream of requests to the client ends
+/// once the client is gone (see [`Lifecycle::exited`]).
+pub struct WatchedSocket {
+    socket: ClientSocket,
+    exited: Arc<Notify>,
+}
+
+impl Loopback for WatchedSocket {
+    type RequestStream = BoxStream<'static, jsonrpc::Request>;
+    type ResponseSink = <ClientSocket as Loopback>::ResponseSink;
+
+    fn split(self) -> (Self::RequestStream, Self::ResponseSink) {
+        let (requests, responses) = Loopback::split(self.socket);
+        let exited = self.exited;
+        let requests = requests.chain(futures::stream::poll_fn(move |_| {
+            exited.notify_one();
+            Poll::Ready(None)
+        }));
+        (requests.boxed(), responses)
+    }
 }
 
 impl Backend {
@@ -44,10 +341,32 @@ impl Backend {
             client,
             documents: Default::default(),
             manifests: Default::default(),
+            cargo_configs: Default::default(),
+            project_configs: Default::default(),
+            package_files: Default::default(),
+            roots: Default::default(),
+            member_manifests: Default::default(),
+            lockfiles: Default::default(),
+            cargo_http: Default::default(),
+            analyses: Default::default(),
+            published: Default::default(),
+            runs: Default::default(),
             registry: Default::default(),
+            settings: Default::default(),
+            client_info: Default::default(),
+            client_capabilities: Default::default(),
+            client_locale: Default::default(),
+            workspace_roots: Default::default(),
+            unknown_settings: Default::default(),
+            lifecycle: Default::default(),
         }
     }
 
+    /// A handle to the lifecycle of the server, for after it stops.
+    pub fn lifecycle(&self) -> Lifecycle {
+        self.lifecycle.clone()
+    }
+
     async fn apply_changes(
         &self,
         uri: &Url,
@@ -63,10 +382,8 @@ impl Backend {
             // [LSP spec]: https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#textDocument_didChange
             for change in changes {
                 if let Some(range) = change.range {
-                    let start = doc.line_to_byte(range.start.line as usize)
-                        + range.start.character as usize;
-                    let end = doc.line_to_byte(range.end.line as usize)
-                        + range.end.character as usize;
+                    let start = rope_idx(doc, range.start);
+                    let end = rope_idx(doc, range.end);
                     doc.remove(start..end);
                     doc.insert(start, &change.text);
                 } else {
@@ -81,178 +398,2171 @@ impl Backend {
         if let Some(doc) =
             self.documents.read().await.get(&uri).map(Rope::to_string)
         {
+            let settings = self.settings.read().await.clone();
+            // the publish lints resolve the keys inherited from the workspace.
+            let root = if settings.publish_lints && doc.contains("workspace") {
+                self.workspace_root(&uri).await
+            } else {
+                None
+            };
+
             // NOTE: we must parse the document in a separate function as the
             // `Node` type does not implement the `Send` trait.
-            let deps = self.parse_document(&doc);
+            let files = self.package_files.read().await;
+            let manifest = parse_manifest(
+                &uri,
+                &doc,
+                &settings,
+                root.as_ref(),
+                files.get(&uri),
+            );
+            drop(files);
+            if let Some(manifest) = manifest {
+                self.manifests.write().await.insert(uri, Arc::new(manifest));
+            } else {
+                // Not a Cargo manifest, so all of the handlers will quietly
+                // return empty results for this document.
+                self.analyses.write().await.remove(&uri);
+                if self.manifests.write().await.remove(&uri).is_some() {
+                    // e.g. `forceEnable` was turned off.
+                    self.clear_diagnostics(uri).await;
+                }
+            }
+        }
+    }
 
-            self.manifests.write().await.insert(uri, deps);
+    /// Walk the package of the manifest at `uri` for the publish lints (if
+    /// they're enabled), which is too slow to do on every change.
+    async fn walk_package(&self, uri: &Url) {
+        if !self.settings.read().await.publish_lints {
+            self.package_files.write().await.remove(uri);
+            return;
+        }
+        let owned = uri.clone();
+        match platform::blocking(move || publish::Files::walk(&owned)).await {
+            Some(files) => {
+                self.package_files.write().await.insert(uri.clone(), files);
+            }
+            None => {
+                self.package_files.write().await.remove(uri);
+            }
         }
     }
 
-    fn parse_document(&self, doc: &str) -> Vec<Dependency> {
-        let parse_dependencies = |table: &dom::node::Table| {
-            table
-                .entries()
-                .read()
-                .iter()
-                .flat_map(|(key, node)| Dependency::parse(doc, key, node))
-                .collect::<Vec<_>>()
+    /// Abort the pending diagnostics run of the document (if any), and
+    /// register the token of its next one.
+    async fn cancel_run(&self, uri: &Url, next: Option<Arc<Notify>>) {
+        let mut runs = self.runs.write().await;
+        let previous = match next {
+            Some(next) => runs.insert(uri.clone(), next),
+            None => runs.remove(uri),
         };
+        // The permit is stored, so a run that isn't waiting on its token yet
+        // is aborted once it does.
+        if let Some(previous) = previous {
+            previous.notify_one();
+        }
+    }
 
-        let dom = taplo::parser::parse(doc).into_dom();
+    /// Publish the diagnostics of the manifest at `uri` after `delay`,
+    /// unless the run of a later change (or of opening or saving the
+    /// document) notifies `token` first (see [`Self::cancel_run`]).
+    async fn run_diagnostics(
+        &self,
+        uri: Url,
+        token: Arc<Notify>,
+        delay: Duration,
+    ) {
+        let run = async {
+            platform::sleep(delay).await;
+            self.publish_diagnostics(uri.clone()).await;
+        };
+        futures::future::select(pin!(run), pin!(token.notified())).await;
 
-        let deps = DEPENDENCIES_KEYS
-            .iter()
-            .filter_map(|&key| dom.as_table().and_then(|t| t.get(key)))
-            .collect::<Vec<_>>();
+        let mut runs = self.runs.write().await;
+        if runs.get(&uri).is_some_and(|t| Arc::ptr_eq(t, &token)) {
+            runs.remove(&uri);
+        }
+    }
 
-        deps.iter()
-            .filter_map(|deps| deps.as_table())
-            .flat_map(parse_dependencies)
-            .collect()
+    /// Clear the published diagnostics of the document, so they don't
+    /// linger in the client after it's closed (or no longer a manifest).
+    async fn clear_diagnostics(&self, uri: Url) {
+        if self.published.write().await.remove(&uri).is_some() {
+            self.client.publish_diagnostics(uri, Vec::new(), None).await;
+        }
+    }
+
+    /// Fetch the crate of a dependency from its registry: crates.io, or an
+    /// alternative registry whose index is configured for the manifest at
+    /// `uri` (see [`Self::registry_index`]).
+    async fn fetch_dependency(
+        &self,
+        uri: &Url,
+        dependency: &Dependency,
+    ) -> crates::Result<crates::CrateInfo> {
+        let name = dependency.crate_name();
+        let Some(registry) = dependency.alternative_registry() else {
+            return self.registry.fetch(name).await;
+        };
+        match self.registry_index(uri, registry).await {
+            Some(RegistryIndex::Sparse { url, token }) => {
+                self.registry.fetch_from(&url, token.as_ref(), name).await
+            }
+            Some(RegistryIndex::Git { url }) => {
+                self.registry.fetch_from_git(&url, name).await
+            }
+            None => Err(crates::Error::UnknownRegistry {
+                registry: registry.to_owned(),
+            }),
+        }
+    }
+
+    /// The index that the crates of the alternative registry `registry` are
+    /// fetched from, as configured for the manifest at `uri`: its sparse
+    /// index, or its git index if the `cache.gitIndex` setting allows it.
+    async fn registry_index(
+        &self,
+        uri: &Url,
+        registry: &str,
+    ) -> Option<RegistryIndex> {
+        let git_index = self.settings.read().await.cache.git_index;
+        let configs = self.cargo_configs.read().await;
+        let config = configs.get(uri)?;
+        if let Some(url) = config.sparse_index(registry) {
+            let token = config.token(registry).cloned();
+            return Some(RegistryIndex::Sparse { url: url.to_owned(), token });
+        }
+        let url = config.git_index(registry).filter(|_| git_index)?;
+        Some(RegistryIndex::Git { url: url.to_owned() })
     }
 
+    /// The [revision](crates::CrateInfo::revision) of the cached crate of a
+    /// dependency, if it's cached.
+    async fn revision(
+        &self,
+        uri: &Url,
+        dependency: &Dependency,
+    ) -> Option<u64> {
+        let index = match dependency.alternative_registry() {
+            None => None,
+            Some(registry) => Some(self.registry_index(uri, registry).await?),
+        };
+        let key = crates::RegistryCache::key(
+            index.as_ref().map(RegistryIndex::url),
+            dependency.crate_name(),
+        )?;
+        self.registry.revision(&key).await
+    }
+
+    /// Whether the crate of a dependency can be fetched (see
+    /// [`Self::fetch_dependency`]).
+    async fn is_fetched(&self, uri: &Url, dependency: &Dependency) -> bool {
+        match dependency.alternative_registry() {
+            None => dependency.is_crates_io(),
+            Some(registry) => {
+                self.registry_index(uri, registry).await.is_some()
+            }
+        }
+    }
+
+    /// Generate the diagnostics of a dependency, along with the revision of
+    /// the crate they're based on, or the error of fetching its crate.
     async fn generate_diagnostics(
         &self,
+        uri: &Url,
         dependency: &Dependency,
-    ) -> Vec<Diagnostic> {
-        if let Ok(latest) = self.registry.fetch(&dependency.name.value).await {
-            let mut diags = Vec::new();
-
-            // Latest version hint
-            if let Some(current_version) = &dependency.version
-                // We don't want to hint latest version, if the user already
-                // uses the latest version in their manifest.
-                && current_version.value.as_ref().is_none_or(|v| !v.matches(&latest.version))
-            {
-                diags.push(Diagnostic {
-                    range: current_version.range,
-                    severity: Some(DiagnosticSeverity::INFORMATION),
-                    code: None,
-                    code_description: None,
-                    source: None,
-                    message: latest.version.to_string(),
-                    related_information: None,
-                    tags: None,
-                    data: None,
+    ) -> crates::Result<(u64, Vec<Diagnostic>)> {
+        let latest = self.fetch_dependency(uri, dependency).await?;
+        let messages = self.messages().await;
+        let mut diags = Vec::new();
+
+        // Latest version hint
+        if let Some(current_version) = &dependency.version
+            // We don't want to hint latest version, if the user already
+            // uses the latest version in their manifest.
+            && current_version.value.req.as_ref().is_none_or(|v| !v.matches(&latest.version))
+            // Nor if it's ahead of it (e.g. a pre-release, when they aren't
+            // the latest versions).
+            && !current_version.value.req.as_ref().is_some_and(|v| version::is_ahead(v, &latest.version))
+        {
+            let data = OutdatedData {
+                name: dependency.name.value.to_owned(),
+                current: current_version.value.raw.to_owned(),
+                latest: latest.version.to_string(),
+                kind: current_version
+                    .value
+                    .req
+                    .as_ref()
+                    .map(|req| version::update_kind(req, &latest.version))
+                    .unwrap_or(UpdateKind::Breaking),
+            };
+
+            let current = current_version
+                .value
+                .req
+                .as_ref()
+                .and_then(version::lower_bound);
+            let current_date =
+                current.as_ref().and_then(|v| latest.release_date(v));
+            let latest_date = latest.release_date(&latest.version);
+
+            let settings = self.settings.read().await;
+            let too_old = current.as_ref().is_some_and(|current| {
+                settings.outdated_warning.is_exceeded(
+                    (current, current_date),
+                    (&latest.version, latest_date),
+                )
+            });
+            // A requirement without a lower bound is as far behind as it gets.
+            let behind = current_version
+                .value
+                .req
+                .as_ref()
+                .filter(|_| current.is_some())
+                .map_or(Behind::Major, |req| {
+                    version::behind(req, &latest.version)
                 });
+            let mut severity = settings.outdated_severity.get(behind);
+            if too_old {
+                severity = severity.max(Severity::Warning);
             }
+            drop(settings);
 
-            // Non-existant features
-            if let Some(available_features) = latest
-                .features
-                .as_ref()
-                .map(HashMap::keys)
-                .map(|f| f.collect::<Vec<_>>())
-                && let Some(features) = &dependency.features
-            {
-                for feature in features.iter() {
-                    if !available_features.contains(&&feature.value) {
-                        diags.push(Diagnostic {
-                            range: feature.range,
-                            severity: Some(DiagnosticSeverity::WARNING),
-                            code: None,
-                            code_description: None,
-                            source: None,
-                            message: format!(
-                                "No such feature available for crate `{}`",
-                                &dependency.name.value
+            // The ages make the cost of staleness tangible, but are only
+            // known once the crates.io API info is cached.
+            let message = match (current_date, latest_date) {
+                (Some(current_date), Some(latest_date)) => {
+                    let now = platform::now();
+                    messages.get(
+                        "outdated-age",
+                        &[
+                            ("current", &current_version.value.raw),
+                            (
+                                "current-age",
+                                &version::format_ago(current_date, now),
                             ),
-                            related_information: None,
-                            tags: None,
-                            data: None,
-                        });
-                    }
+                            ("latest", &latest.version),
+                            (
+                                "latest-age",
+                                &version::format_ago(latest_date, now),
+                            ),
+                        ],
+                    )
+                }
+                _ => latest.version.to_string(),
+            };
+
+            diags.push(Diagnostic {
+                range: current_version.range,
+                severity: Some(severity.into()),
+                code: None,
+                code_description: None,
+                source: None,
+                message,
+                related_information: None,
+                tags: None,
+                data: serde_json::to_value(data).ok(),
+            });
+        }
+
+        // Non-canonical name, which crates.io (and Cargo) accept, but
+        // the lockfile and the docs use the canonical one. The key of a
+        // renamed dependency is a local alias, so its package is compared.
+        let crate_name =
+            dependency.package.as_ref().unwrap_or(&dependency.name);
+        if latest.name != crate_name.value {
+            diags.push(Diagnostic {
+                range: crate_name.range,
+                severity: Some(DiagnosticSeverity::INFORMATION),
+                code: None,
+                code_description: None,
+                source: None,
+                message: messages
+                    .get("canonical-name", &[("name", &latest.name)]),
+                related_information: None,
+                tags: None,
+                data: None,
+            });
+        }
+
+        // Non-existant features
+        if let Some(available_features) = latest
+            .features
+            .as_ref()
+            .map(HashMap::keys)
+            .map(|f| f.collect::<Vec<_>>())
+            && let Some(features) = &dependency.features
+        {
+            for feature in features.iter() {
+                if !available_features.contains(&&feature.value) {
+                    diags.push(Diagnostic {
+                        range: feature.range,
+                        severity: Some(DiagnosticSeverity::WARNING),
+                        code: None,
+                        code_description: None,
+                        source: None,
+                        message: messages.get(
+                            "no-such-feature",
+                            &[("name", &dependency.crate_name())],
+                        ),
+                        related_information: None,
+                        tags: None,
+                        data: None,
+                    });
                 }
             }
+        }
 
-            diags
-        } else {
-            vec![Diagnostic {
-                range: dependency.name.range,
-                severity: Some(DiagnosticSeverity::ERROR),
+        // Features the defaults (or the other listed features) enable
+        // anyway, and the defaults that `default-features = false` drops
+        for (index, redundancy) in features::redundant(dependency, &latest) {
+            let Some(feature) =
+                dependency.features.as_ref().and_then(|f| f.get(index))
+            else {
+                continue;
+            };
+            let message = match redundancy {
+                Redundancy::Default => messages.get(
+                    "redundant-default",
+                    &[
+                        ("feature", &feature.value),
+                        ("name", &dependency.crate_name()),
+                    ],
+                ),
+                Redundancy::ImpliedBy(by) => messages.get(
+                    "redundant-implied",
+                    &[("feature", &feature.value), ("by", &by)],
+                ),
+            };
+            diags.push(Diagnostic {
+                range: feature.range,
+                severity: Some(DiagnosticSeverity::HINT),
+                code: None,
+                code_description: None,
+                source: None,
+                message,
+                related_information: None,
+                tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+                data: None,
+            });
+        }
+        if let Some(default_features) = &dependency.default_features
+            && let Some(dropped) =
+                features::dropped_defaults(dependency, &latest)
+        {
+            let dropped = dropped
+                .iter()
+                .map(|f| format!("`{f}`"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            diags.push(Diagnostic {
+                range: default_features.range,
+                severity: Some(DiagnosticSeverity::HINT),
                 code: None,
                 code_description: None,
                 source: None,
-                message: "No such crate in crates.io".to_owned(),
+                message: messages.get(
+                    "dropped-defaults",
+                    &[
+                        ("name", &dependency.crate_name()),
+                        ("features", &dropped),
+                    ],
+                ),
                 related_information: None,
                 tags: None,
                 data: None,
-            }]
+            });
         }
+
+        Ok((latest.revision, diags))
     }
 
     async fn publish_diagnostics(&self, uri: Url) {
-        let manifests = self.manifests.read().await;
-        let Some(dependencies) = manifests.get(&uri) else { return };
+        // A snapshot, so the manifest can be updated (e.g. on a change) while
+        // the crates are fetched.
+        let Some(snapshot) = self.manifests.read().await.get(&uri).cloned()
+        else {
+            return;
+        };
+        let manifest = snapshot.as_ref();
+        let dependencies = &manifest.dependencies;
+        let messages = self.messages().await;
 
-        let mut diags = Vec::new();
+        let mut diags = manifest_diagnostics(manifest, messages);
+
+        // Refresh the crates that were cached for too long first, so their
+        // new releases make the analysis stale.
+        let names = dependencies
+            .iter()
+            .filter(|d| d.is_crates_io())
+            .map(Dependency::crate_name)
+            .collect::<Vec<_>>();
+        self.registry.refresh_expired(&names).await;
+
+        // Stale analyses (from before the registry data changed) are dropped
+        // entirely, and the rest is replaced by the analysis of the current
+        // entries, so removed entries don't linger.
+        let previous = self
+            .analyses
+            .write()
+            .await
+            .remove(&uri)
+            .filter(|a| a.generation == self.registry.generation())
+            .unwrap_or_default();
+        let mut analysis = Analysis {
+            generation: self.registry.generation(),
+            ..Default::default()
+        };
+
+        // The diagnostics of each dependency, in the order of the
+        // dependencies (rather than of the fetches), so the published
+        // diagnostics don't change with the timing of the responses.
+        let mut slots = vec![Vec::new(); dependencies.len()];
+        let mut pending = Vec::new();
+        for (index, dependency) in dependencies.iter().enumerate() {
+            if !self.is_fetched(&uri, dependency).await {
+                continue;
+            }
+            let revision = self.revision(&uri, dependency).await;
+            match analysis.reuse(&previous, dependency.fingerprint, revision) {
+                Some(cached) => slots[index] = cached,
+                None => pending.push((index, dependency)),
+            }
+        }
+
+        // On open, publish the diagnostics as their crates arrive, rather
+        // than once all of them are fetched. On changes, only the edited
+        // dependencies are pending, and publishing without them would make
+        // their diagnostics flicker.
+        let progressive = !self.published.read().await.contains_key(&uri);
+        let uri_ref = &uri;
+        // The futures are built in a loop rather than by a closure, whose
+        // borrows make the `Send` bound of the handlers' futures unprovable.
+        let mut fetches = Vec::with_capacity(pending.len());
+        for (index, dependency) in pending {
+            fetches.push(async move {
+                let result =
+                    self.generate_diagnostics(uri_ref, dependency).await;
+                (index, dependency, result)
+            });
+        }
+        let mut results = futures::stream::iter(fetches)
+            .buffer_unordered(self.registry.concurrency());
+        while let Some((index, dependency, result)) = results.next().await {
+            let fingerprint = dependency.fingerprint;
+            match result {
+                Ok((revision, diags)) => {
+                    let relative = diags
+                        .into_iter()
+                        .map(|d| Diagnostic {
+                            range: fingerprint.to_relative(d.range),
+                            ..d
+                        })
+                        .collect::<Vec<_>>();
+                    slots[index] = Analysis::anchor(fingerprint, &relative);
+                    analysis.insert(fingerprint, revision, relative);
+                }
+                // Not cached, so the crate is re-fetched on the next change.
+                // While crates.io is unreachable, every crate that isn't
+                // cached would be reported, so none is.
+                Err(_)
+                    if self.registry.is_offline()
+                        && dependency.is_crates_io() =>
+                {
+                    continue;
+                }
+                Err(err) => {
+                    slots[index] =
+                        vec![fetch_error_diagnostic(dependency, err, messages)];
+                }
+            }
+
+            if progressive {
+                let partial = diags
+                    .iter()
+                    .chain(&slots)
+                    .flatten()
+                    .cloned()
+                    .collect::<Vec<_>>();
+                self.publish(uri.clone(), partial).await;
+            }
+        }
+        drop(results);
+        diags.extend(slots);
+
+        diags.push(self.unified_features(&uri, dependencies, messages).await);
+        diags.push(self.links_conflicts(&uri, manifest, messages).await);
+        diags.push(self.duplicate_versions(&uri, dependencies, messages).await);
+
+        diags.push(
+            manifest
+                .incomplete
+                .iter()
+                .map(|name| Diagnostic {
+                    range: name.range,
+                    severity: Some(DiagnosticSeverity::HINT),
+                    code: None,
+                    code_description: None,
+                    source: None,
+                    message: messages
+                        .get("incomplete-entry", &[("name", &name.value)]),
+                    related_information: None,
+                    tags: None,
+                    data: None,
+                })
+                .collect(),
+        );
 
         for dependency in dependencies.iter() {
-            if matches!(dependency.kind, parse::Kind::Registry) {
-                diags.push(self.generate_diagnostics(dependency).await);
+            if let parse::Kind::Local(local) = &dependency.kind
+                && let Some(path) = local.path.value.to_str()
+                && path::resolve_manifest(&uri, path)
+                    .is_some_and(|manifest| path::is_missing(&manifest))
+            {
+                diags.push(vec![Diagnostic {
+                    range: local.path.range,
+                    severity: Some(DiagnosticSeverity::ERROR),
+                    code: None,
+                    code_description: None,
+                    source: None,
+                    message: messages.get(
+                        "no-manifest",
+                        &[("file", &MANIFEST_FILE_NAME), ("path", &path)],
+                    ),
+                    related_information: None,
+                    tags: None,
+                    data: None,
+                }]);
+            }
+        }
+
+        for target in &manifest.targets {
+            if let Some(path) = &target.path
+                && path::resolve_file(&uri, &path.value)
+                    .is_some_and(|file| path::is_missing(&file))
+            {
+                diags.push(vec![Diagnostic {
+                    range: path.range,
+                    severity: Some(DiagnosticSeverity::ERROR),
+                    code: None,
+                    code_description: None,
+                    source: None,
+                    message: messages
+                        .get("no-such-file", &[("path", &path.value)]),
+                    related_information: None,
+                    tags: None,
+                    data: None,
+                }]);
             }
         }
 
-        let diags = diags.into_iter().flatten().collect();
+        self.analyses.write().await.insert(uri.clone(), analysis);
 
-        self.client.publish_diagnostics(uri, diags, None).await;
+        let diags = diags.into_iter().flatten().collect::<Vec<_>>();
+        self.publish(uri, diags).await;
+
+        // Now that all of the crates are cached, hydrate their API info (e.g.
+        // descriptions) for the hovers.
+        self.registry.fetch_api_infos(&names).await;
+
+        let updates = self.registry.take_updates().await;
+        if !updates.is_empty() {
+            self.client
+                .send_notification::<UpdatesAvailable>(UpdatesAvailableParams {
+                    updates,
+                })
+                .await;
+        }
     }
 
-    async fn generate_completion<F>(
-        &self,
-        name: &str,
-        f: F,
-    ) -> Option<CompletionResponse>
-    where
-        F: Fn(crates::Latest) -> Vec<CompletionItem>,
-    {
-        self.registry
-            .fetch(name)
-            .await
-            .ok()
-            .map(f)
-            .map(CompletionResponse::Array)
+    /// Publish the diagnostics of a document, unless they are the ones that
+    /// were last published.
+    async fn publish(&self, uri: Url, diags: Vec<Diagnostic>) {
+        // Most of the changes (e.g. typing in a comment) don't change the
+        // diagnostics, and re-publishing them makes some clients flicker.
+        let hash = diagnostics_hash(&diags);
+        let previous = self.published.write().await.insert(uri.clone(), hash);
+        if previous != Some(hash) {
+            self.client.publish_diagnostics(uri, diags, None).await;
+        }
     }
-}
 
-#[tower_lsp::async_trait]
-impl LanguageServer for Backend {
-    async fn initialize(
+    async fn latest_version(
         &self,
-        _: InitializeParams,
-    ) -> jsonrpc::Result<InitializeResult> {
-        Ok(InitializeResult {
-            server_info: None,
-            capabilities: ServerCapabilities {
-                // We want to keep a synced version of the documents
-                text_document_sync: Some(TextDocumentSyncCapability::Kind(
-                    // sync the document by sending changes using the
-                    // `didChagne` notification.
-                    TextDocumentSyncKind::INCREMENTAL,
-                )),
+        DependencyArgs { name, uri }: DependencyArgs,
+    ) -> command::Result<Option<serde_json::Value>> {
+        let latest = self
+            .registry
+            .fetch(&name)
+            .await
+            .map_err(|_| command::Error::Fetch { name: name.to_owned() })?;
 
-                // We provide completions events
-                completion_provider: Some(CompletionOptions {
-                    // trigger completion event when the user hits `"`
-                    trigger_characters: Some(vec!['\"'.to_string()]),
-                    resolve_provider: Some(false),
-                    ..Default::default()
-                }),
+        let change =
+            self.manifests.read().await.get(&uri).and_then(|manifest| {
+                let deps = &manifest.dependencies;
+                deps.iter().find(|d| d.name.value == name).and_then(|d| {
+                    edit::replace_version(d, &latest.version.to_string())
+                })
+            });
+        let Some(change) = change else {
+            return Err(command::Error::NoDependency { name, uri });
+        };
 
-                // We provide hover events
-                hover_provider: Some(HoverProviderCapability::Simple(true)),
+        let changes =
+            WorkspaceEdit::new(std::iter::once((uri, vec![change])).collect());
+        let _ = self.client.apply_edit(changes).await;
+
+        Ok(None)
+    }
+
+    async fn upgrade_workspace(
+        &self,
+        args: UpgradeWorkspaceArgs,
+    ) -> command::Result<Option<serde_json::Value>> {
+        let manifests = self.manifests.read().await;
+        let mut uris = manifests.keys().collect::<Vec<_>>();
+        uris.sort_unstable();
+
+        let mut upgrades = Vec::new();
+        let mut changes = HashMap::<_, Vec<_>>::new();
+        for uri in uris {
+            for (upgrade, edit) in
+                self.breaking_upgrades(uri, &manifests[uri]).await
+            {
+                if upgrade.is_selected(&args) {
+                    changes.entry(uri.clone()).or_default().push(edit);
+                    upgrades.push(upgrade);
+                }
+            }
+        }
+        drop(manifests);
+
+        // all of the selected upgrades are applied at once, so they can be
+        // undone at once too.
+        if args.selected.is_some() && !changes.is_empty() {
+            let _ = self.client.apply_edit(WorkspaceEdit::new(changes)).await;
+        }
+
+        Ok(serde_json::to_value(upgrades).ok())
+    }
+
+    /// The breaking upgrades of the dependencies of a manifest, along with
+    /// their edits.
+    async fn breaking_upgrades(
+        &self,
+        uri: &Url,
+        manifest: &Manifest,
+    ) -> Vec<(Upgrade, TextEdit)> {
+        let mut upgrades = Vec::new();
+        for dependency in
+            manifest.dependencies.iter().filter(|d| d.is_crates_io())
+        {
+            let Some(version) = &dependency.version else { continue };
+            let Some(req) = &version.value.req else { continue };
+            let Ok(latest) = self.registry.fetch(dependency.crate_name()).await
+            else {
+                continue;
+            };
+            if req.matches(&latest.version)
+                || version::update_kind(req, &latest.version)
+                    != UpdateKind::Breaking
+            {
+                continue;
+            }
+
+            let latest = latest.version.to_string();
+            let Some(edit) = edit::replace_version(dependency, &latest) else {
+                continue;
+            };
+            let upgrade = Upgrade {
+                uri: uri.clone(),
+                name: dependency.name.value.clone(),
+                current: version.value.raw.clone(),
+                latest,
+            };
+            upgrades.push((upgrade, edit));
+        }
+        upgrades
+    }
+
+    /// The dependencies (from crates.io) whose version requirements don't
+    /// match their latest versions, along with those versions, and the number
+    /// of dependencies that were fetched.
+    async fn outdated<'a>(
+        &self,
+        dependencies: &[&'a Dependency],
+    ) -> (Vec<(&'a Dependency, String)>, usize) {
+        let (mut outdated, mut fetched) = (Vec::new(), 0);
+        for &dependency in dependencies.iter().filter(|d| d.is_crates_io()) {
+            let Ok(latest) = self.registry.fetch(dependency.crate_name()).await
+            else {
+                continue;
+            };
+            fetched += 1;
+            let req = dependency
+                .version
+                .as_ref()
+                .and_then(|v| v.value.req.as_ref());
+            if req.is_some_and(|req| !req.matches(&latest.version)) {
+                outdated.push((dependency, latest.version.to_string()));
+            }
+        }
+        (outdated, fetched)
+    }
+
+    /// The document of a manifest, along with the header of the table that
+    /// the arguments of a command refer to.
+    async fn table_header(
+        &self,
+        TableArgs { uri, table }: &TableArgs,
+    ) -> command::Result<(String, DependenciesHeader)> {
+        let doc = self.documents.read().await.get(uri).map(Rope::to_string);
+        doc.and_then(|doc| {
+            let header = DependenciesHeader::all(&doc)
+                .into_iter()
+                .find(|h| &h.table == table)?;
+            Some((doc, header))
+        })
+        .ok_or_else(|| command::Error::NoTable {
+            table: table.join("."),
+            uri: uri.clone(),
+        })
+    }
+
+    async fn update_table(
+        &self,
+        args: TableArgs,
+    ) -> command::Result<Option<serde_json::Value>> {
+        let (_, header) = self.table_header(&args).await?;
+        let Some(section) = header.section() else { return Ok(None) };
+
+        let manifests = self.manifests.read().await;
+        let Some(manifest) = manifests.get(&args.uri) else { return Ok(None) };
+        let dependencies = manifest
+            .dependencies
+            .iter()
+            .filter(|d| d.section == section)
+            .collect::<Vec<_>>();
+        let edits = self
+            .outdated(&dependencies)
+            .await
+            .0
+            .into_iter()
+            .filter_map(|(d, latest)| edit::replace_version(d, &latest))
+            .collect::<Vec<_>>();
+        drop(manifests);
+
+        if !edits.is_empty() {
+            let changes = HashMap::from([(args.uri, edits)]);
+            let _ = self.client.apply_edit(WorkspaceEdit::new(changes)).await;
+        }
+        Ok(None)
+    }
+
+    async fn update_lock(
+        &self,
+        UpdateLockArgs { uri, package }: UpdateLockArgs,
+    ) -> command::Result<Option<serde_json::Value>> {
+        let args = ["update", "-p", &package];
+        let failed = |message: String| command::Error::Cargo {
+            args: args.join(" "),
+            message,
+        };
+        let dir = path::to_path(&uri)
+            .and_then(|manifest| manifest.parent().map(Path::to_path_buf))
+            .ok_or_else(|| failed(format!("`{uri}` isn't a local file")))?;
+
+        let output = platform::cargo(&dir, &args)
+            .await
+            .map_err(|err| failed(err.to_string()))?;
+        // cargo reports what it did (or why it failed) on stderr.
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+        if !output.status.success() {
+            return Err(failed(stderr));
+        }
+        self.client.show_message(MessageType::INFO, stderr).await;
+        Ok(None)
+    }
+
+    async fn copy_dependency_spec(
+        &self,
+        PositionArgs { uri, position }: PositionArgs,
+    ) -> command::Result<Option<serde_json::Value>> {
+        let manifests = self.manifests.read().await;
+        let spec = manifests
+            .get(&uri)
+            .and_then(|manifest| Context::resolve(manifest, position))
+            .map(|context| {
+                let dependency = context.dependency();
+                DependencySpec {
+                    name: dependency.name.value.clone(),
+                    spec: edit::dependency_spec(dependency),
+                }
+            });
+        let Some(spec) = spec else {
+            return Err(command::Error::NoDependencyAt {
+                line: position.line,
+                character: position.character,
+                uri,
+            });
+        };
+        Ok(serde_json::to_value(spec).ok())
+    }
+
+    async fn sort_table(
+        &self,
+        args: TableArgs,
+    ) -> command::Result<Option<serde_json::Value>> {
+        let (doc, header) = self.table_header(&args).await?;
+        let edits = edit::sort_entries(&doc, &header);
+        if !edits.is_empty() {
+            let changes = HashMap::from([(args.uri, edits)]);
+            let _ = self.client.apply_edit(WorkspaceEdit::new(changes)).await;
+        }
+        Ok(None)
+    }
+
+    /// The stats of the dependencies of a dependencies table, for the hover
+    /// of its header.
+    async fn section_hover(
+        &self,
+        manifest: &Manifest,
+        section: &parse::Section,
+    ) -> Hover {
+        let dependencies = manifest
+            .dependencies
+            .iter()
+            .filter(|d| &d.section == section)
+            .collect::<Vec<_>>();
+
+        let (outdated, fetched) = self.outdated(&dependencies).await;
+
+        Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: format::format_section_hover(
+                    &section.header(),
+                    &dependencies,
+                    (outdated.len(), fetched),
+                    self.messages().await,
+                ),
+            }),
+            range: None,
+        }
+    }
+
+    /// Explain the comparator of a version requirement at `pos`, with the
+    /// published versions the requirement matches as examples (for crates
+    /// whose registry is known).
+    async fn requirement_hover(
+        &self,
+        uri: &Url,
+        dependency: &Dependency,
+        version: &parse::Span<parse::Requirement>,
+        pos: Position,
+    ) -> Option<Hover> {
+        let Range { start, end } = version.range;
+        // the requirements are single line strings.
+        if start.line != end.line || start.line != pos.line {
+            return None;
+        }
+        // skip the opening quote.
+        let offset = pos.character.checked_sub(start.character + 1)? as usize;
+        let raw = &version.value.raw;
+        let comparator = version::comparator_at(raw, offset);
+        if comparator.is_none()
+            && !raw.get(offset..).is_some_and(|r| r.starts_with(','))
+        {
+            return None;
+        }
+
+        let latest = match &version.value.req {
+            Some(_) => self.fetch_dependency(uri, dependency).await.ok(),
+            None => None,
+        };
+        let matching = latest.as_ref().zip(version.value.req.as_ref()).map(
+            |(latest, req)| {
+                let mut matching = latest
+                    .versions()
+                    .iter()
+                    .filter(|v| !v.yanked && req.matches(&v.version))
+                    .map(|v| &v.version)
+                    .collect::<Vec<_>>();
+                matching.sort_unstable();
+                matching
+            },
+        );
+
+        let value = format::format_requirement_hover(
+            comparator.clone().map(|r| &raw[r]),
+            matching.as_deref(),
+            self.messages().await,
+        )?;
+        let range = comparator.map_or(
+            Range::new(pos, Position::new(pos.line, pos.character + 1)),
+            |r| {
+                let at = |i: usize| {
+                    Position::new(start.line, start.character + 1 + i as u32)
+                };
+                Range::new(at(r.start), at(r.end))
+            },
+        );
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            }),
+            range: Some(range),
+        })
+    }
+
+    /// The code action that updates the version the lockfile pins a dependency
+    /// from crates.io to, when a newer release matches its requirement.
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn lock_actions(
+        &self,
+        uri: &Url,
+        dependency: &Dependency,
+    ) -> Vec<CodeActionOrCommand> {
+        let Ok(latest) = self.registry.fetch(dependency.crate_name()).await
+        else {
+            return Vec::new();
+        };
+        let Some(lockfile) = self.lockfile(uri).await else {
+            return Vec::new();
+        };
+        let locked = lockfile.versions(&latest.name);
+        let Some(lag) = lockfile_lag(dependency, &locked, &latest) else {
+            return Vec::new();
+        };
+        if lag.behind == 0 {
+            return Vec::new();
+        }
+
+        let package = package_spec(&latest.name, &lag.locked, &locked);
+        vec![update_lock_action(uri, package, &lag, self.messages().await)]
+    }
+
+    /// The entry of a package from crates.io at a position of an open
+    /// lockfile, compared with the releases of its crate.
+    async fn lockfile_entry(
+        &self,
+        doc: &str,
+        pos: Position,
+    ) -> Option<LockfileEntry> {
+        let entry = lockfile::entry_at(doc, pos)?;
+        let latest = self.registry.fetch(&entry.name).await.ok()?;
+        // `cargo update -p` stays within the releases that are compatible
+        // with the locked version.
+        let req = semver::VersionReq::parse(&format!("^{}", entry.version));
+        let version = slice::from_ref(&entry.version);
+        let lag = lockfile::Lag::new(version, &req.ok()?, latest.versions())?;
+        let locked = lockfile::locked_versions(doc, &entry.name);
+        let package = package_spec(&entry.name, &entry.version, &locked);
+        Some(LockfileEntry { entry, latest, lag, package })
+    }
+
+    /// The hover of a `[[package]]` entry of a lockfile.
+    async fn lockfile_hover(&self, uri: &Url, pos: Position) -> Option<Hover> {
+        let doc = self.documents.read().await.get(uri).map(Rope::to_string)?;
+        let LockfileEntry { entry, latest, lag, package } =
+            self.lockfile_entry(&doc, pos).await?;
+        let members =
+            lockfile::dependent_members(&doc, &entry.name, &entry.version);
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: format::format_locked_hover(
+                    &entry,
+                    &package,
+                    &latest,
+                    &lag,
+                    &members,
+                    self.messages().await,
+                ),
+            }),
+            range: Some(entry.range),
+        })
+    }
+
+    /// The code action that updates a `[[package]]` entry of a lockfile, when
+    /// a newer compatible release is out.
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn lockfile_actions(
+        &self,
+        uri: &Url,
+        pos: Position,
+    ) -> Option<CodeActionResponse> {
+        let doc = self.documents.read().await.get(uri).map(Rope::to_string)?;
+        let LockfileEntry { lag, package, .. } =
+            self.lockfile_entry(&doc, pos).await?;
+        if lag.behind == 0 {
+            return None;
+        }
+        let messages = self.messages().await;
+        Some(vec![update_lock_action(uri, package, &lag, messages)])
+    }
+
+    /// The code actions that update the version requirement of a dependency
+    /// from crates.io.
+    async fn update_actions(
+        &self,
+        uri: Url,
+        dependency: &Dependency,
+    ) -> Vec<CodeActionOrCommand> {
+        let Ok(latest) = self.registry.fetch(dependency.crate_name()).await
+        else {
+            return Vec::new();
+        };
+        let req = dependency
+            .version
+            .as_ref()
+            .and_then(|v| v.value.req.as_ref());
+        // We don't want to suggest updates if the user already uses the
+        // latest version in their manifest, or a newer one.
+        if req.is_some_and(|req| {
+            req.matches(&latest.version)
+                || version::is_ahead(req, &latest.version)
+        }) {
+            return Vec::new();
+        }
+
+        let versions = latest
+            .versions()
+            .iter()
+            .filter(|v| !v.yanked)
+            .map(|v| &v.version);
+        let updates = match req {
+            Some(req) => version::updates(req, versions, &latest.version),
+            None => version::Updates {
+                compatible: None,
+                breaking: Some(&latest.version),
+            },
+        };
+
+        let messages = self.messages().await;
+        let mut actions = Vec::new();
+        if let Some(version) = updates.compatible
+            && let Some(edit) =
+                edit::replace_version(dependency, &version.to_string())
+        {
+            actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                title: messages
+                    .get("update-compatible", &[("version", version)]),
+                kind: Some(CodeActionKind::QUICKFIX),
+                edit: Some(WorkspaceEdit::new(HashMap::from([(
+                    uri.clone(),
+                    vec![edit],
+                )]))),
+                is_preferred: Some(true),
+                ..CodeAction::default()
+            }));
+        }
+        if let Some(version) = updates.breaking {
+            // the breaking update is always to the latest version.
+            let title =
+                messages.get("upgrade-breaking", &[("version", version)]);
+            let command = Command::LatestVersion(DependencyArgs {
+                name: dependency.name.value.to_owned(),
+                uri,
+            });
+            actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                title: title.clone(),
+                kind: Some(CodeActionKind::QUICKFIX),
+                command: Some(command.into_lsp(title)),
+                ..CodeAction::default()
+            }));
+        }
+
+        actions
+    }
+
+    async fn cache_stats(&self) -> command::Result<Option<serde_json::Value>> {
+        let stats = self.registry.stats().await;
+        let mut msg = format!(
+            "{} crates cached ({} with crates.io info)",
+            stats.crates, stats.api_infos
+        );
+        if let (Some(dir), Some(disk)) = (&stats.disk_dir, &stats.disk) {
+            let megabytes = disk.bytes as f64 / 1_000_000.0;
+            msg += &format!(
+                ", and {} files ({megabytes:.1} MB) in `{dir}`",
+                disk.files
+            );
+        }
+        self.client.show_message(MessageType::INFO, msg).await;
+
+        Ok(serde_json::to_value(stats).ok())
+    }
+
+    async fn cache_clear(&self) -> command::Result<Option<serde_json::Value>> {
+        // the analyses are dropped along with the cache, as they're stale.
+        self.registry.clear().await;
+
+        // refetch what the open documents depend on.
+        let uris: Vec<_> =
+            self.manifests.read().await.keys().cloned().collect();
+        for uri in uris {
+            self.publish_diagnostics(uri).await;
+        }
+
+        let msg = "cleared the crates cache".to_owned();
+        self.client.show_message(MessageType::INFO, msg).await;
+        Ok(None)
+    }
+
+    /// Count a request in the metrics, and log them if it's time to.
+    async fn record(&self, method: &'static str) {
+        let metrics = self.registry.metrics();
+        metrics.request(method);
+
+        let interval = self.settings.read().await.metrics_log_interval;
+        if let Some(interval) = interval
+            && let Some(line) = metrics.log_line(Duration::from_secs(interval))
+        {
+            self.client.log_message(MessageType::INFO, line).await;
+        }
+    }
+
+    /// Handle the `crates/metrics` request.
+    pub async fn metrics(&self) -> jsonrpc::Result<metrics::Snapshot> {
+        Ok(self.registry.metrics().snapshot())
+    }
+
+    /// Handle the `crates/search` request.
+    pub async fn search(
+        &self,
+        params: search::Params,
+    ) -> jsonrpc::Result<search::Response> {
+        self.record(search::METHOD).await;
+        let page = params.page();
+        self.registry
+            .search_page(&params.query, page, params.per_page())
+            .await
+            .map(|found| search::Response::new(found, page))
+            .map_err(|err| jsonrpc::Error {
+                code: jsonrpc::ErrorCode::InternalError,
+                message: err.to_string().into(),
+                data: None,
+            })
+    }
+
+    /// Handle the `crates/settingsSchema` request.
+    pub async fn settings_schema(&self) -> jsonrpc::Result<serde_json::Value> {
+        self.record(schema::METHOD).await;
+        Ok(schema::schema())
+    }
+
+    /// Warn the client about the settings in `value` the server doesn't
+    /// know (e.g. typos), which are ignored, once per setting.
+    async fn warn_unknown_settings(&self, value: &serde_json::Value) {
+        let mut warned = self.unknown_settings.write().await;
+        let keys: Vec<_> = schema::unknown_keys(value)
+            .into_iter()
+            .filter(|key| warned.insert(key.clone()))
+            .map(|key| format!("`{key}`"))
+            .collect();
+        drop(warned);
+        if keys.is_empty() {
+            return;
+        }
+        let msg = self
+            .messages()
+            .await
+            .get("unknown-settings", &[("keys", &keys.join(", "))]);
+        self.client.show_message(MessageType::WARNING, msg).await;
+    }
+
+    /// The action that centralizes the duplicated dependencies of the
+    /// workspace whose root is at `uri` (see [`workspace::Root::centralize`]).
+    async fn centralize_action(&self, uri: &Url) -> Option<CodeAction> {
+        let root = self
+            .workspace_root(uri)
+            .await
+            .filter(|root| &root.uri == uri)?;
+        let members = self.member_manifests(root.members()).await;
+        let centralization = root.centralize(&members);
+        if centralization.names.is_empty() {
+            return None;
+        }
+
+        let count = centralization.names.len();
+        Some(CodeAction {
+            title: self
+                .messages()
+                .await
+                .get("centralize-dependencies", &[("count", &count)]),
+            kind: Some(CodeActionKind::REFACTOR),
+            edit: Some(WorkspaceEdit::new(centralization.changes)),
+            ..CodeAction::default()
+        })
+    }
+
+    /// The parsed manifests of the workspace `members`: the open ones as
+    /// they're edited, and the others as they're saved, read again only once
+    /// their files change.
+    async fn member_manifests(
+        &self,
+        members: Vec<Url>,
+    ) -> Vec<(Url, Arc<Manifest>)> {
+        let documents = self.documents.read().await;
+        let mut cached = self.member_manifests.write().await;
+        let parse = |doc: &str| {
+            let dom = taplo::parser::parse(doc).into_dom();
+            Arc::new(Manifest::parse(doc, &dom))
+        };
+        members
+            .into_iter()
+            .filter_map(|member| {
+                if let Some(doc) = documents.get(&member) {
+                    let manifest = parse(&doc.to_string());
+                    return Some((member, manifest));
+                }
+                let path = path::to_path(&member)?;
+                let modified = std::fs::metadata(&path)
+                    .and_then(|meta| meta.modified())
+                    .ok()?;
+                if let Some((at, manifest)) = cached.get(&member)
+                    && *at == modified
+                {
+                    return Some((member, Arc::clone(manifest)));
+                }
+                let manifest = parse(&std::fs::read_to_string(&path).ok()?);
+                cached
+                    .insert(member.clone(), (modified, Arc::clone(&manifest)));
+                Some((member, manifest))
+            })
+            .collect()
+    }
+
+    /// The lockfile of the workspace of the manifest at `uri` (see
+    /// [`lockfile::find`]), read again only once its file changes.
+    async fn lockfile(&self, uri: &Url) -> Option<Arc<lockfile::Lockfile>> {
+        let path = lockfile::find(uri)?;
+        let modified = std::fs::metadata(&path)
+            .and_then(|meta| meta.modified())
+            .ok()?;
+        let mut lockfiles = self.lockfiles.write().await;
+        if let Some((at, lockfile)) = lockfiles.get(&path)
+            && *at == modified
+        {
+            return Some(Arc::clone(lockfile));
+        }
+        let s = std::fs::read_to_string(&path).ok()?;
+        let uri = Url::from_file_path(&path).ok()?;
+        let lockfile = Arc::new(lockfile::Lockfile::parse(uri, &s));
+        lockfiles.insert(path, (modified, Arc::clone(&lockfile)));
+        Some(lockfile)
+    }
+
+    /// The root of the workspace of the manifest at `uri` (see
+    /// [`workspace::Root::find`]), as it was found when the manifest was
+    /// opened or saved.
+    async fn workspace_root(&self, uri: &Url) -> Option<workspace::Root> {
+        if let Some(root) = self.roots.read().await.get(uri) {
+            return root.clone();
+        }
+        self.find_workspace_root(uri).await
+    }
+
+    /// Find the root of the workspace of the manifest at `uri` (see
+    /// [`workspace::Root::find`]).
+    async fn find_workspace_root(&self, uri: &Url) -> Option<workspace::Root> {
+        let documents = self.documents.read().await;
+        workspace::Root::find(uri, |uri| read_manifest(&documents, uri))
+    }
+
+    /// Find the roots of the workspaces of the open manifests at `uris`
+    /// again, e.g. once they're opened or saved.
+    async fn update_workspace_roots(&self, uris: &[Url]) {
+        for uri in uris {
+            let root = self.find_workspace_root(uri).await;
+            self.roots.write().await.insert(uri.clone(), root);
+        }
+    }
+
+    /// The docs of the crate `name` that the project configuration of the
+    /// manifest `uri` overrides (see [`ProjectConfig`]), if any.
+    async fn docs_override(&self, uri: &Url, name: &str) -> Option<String> {
+        let configs = self.project_configs.read().await;
+        configs.get(uri)?.docs_url(name).map(str::to_owned)
+    }
+
+    /// The messages in the locale of the settings, or else of the client.
+    async fn messages(&self) -> Catalog {
+        let locale = match self.settings.read().await.locale.clone() {
+            Some(locale) => Some(locale),
+            None => self.client_locale.read().await.clone(),
+        };
+        Catalog::for_locale(locale.as_deref())
+    }
+
+    async fn set_settings(&self, settings: Settings) {
+        self.registry
+            .set_max_crates(settings.cache.max_crates)
+            .await;
+        self.registry
+            .set_ttl(settings.cache.ttl.map(Duration::from_secs));
+        self.registry.set_concurrency(settings.http.concurrency);
+        self.registry.set_prereleases(settings.prereleases).await;
+        self.registry.set_api_enabled(settings.api.enabled);
+        let disk_ttl = Duration::from_secs(settings.cache.disk_ttl);
+        let disk = settings
+            .cache
+            .disk
+            .then(|| DiskCache::in_user_dir(disk_ttl))
+            .flatten()
+            .map(|disk| {
+                let max_size = settings.cache.disk_max_size;
+                disk.with_max_size(max_size.map(|mb| mb * 1_000_000))
+            });
+        self.registry.set_disk_cache(disk).await;
+        // cargo's timeout applies to the requests without their own.
+        let timeout = self.cargo_http.read().await.timeout;
+        self.registry.set_timeouts(
+            settings
+                .http
+                .index_timeout
+                .or(timeout)
+                .map(Duration::from_secs),
+            settings
+                .http
+                .api_timeout
+                .or(timeout)
+                .map(Duration::from_secs),
+        );
+        *self.settings.write().await = settings;
+        // The diagnostics depend on the settings (e.g. the outdated warning
+        // policy).
+        self.analyses.write().await.clear();
+    }
+
+    /// Resolve the goto definition mode, according to the client's info and
+    /// capabilities.
+    ///
+    /// The returned mode is never [`GotoDefinition::Auto`], nor
+    /// [`GotoDefinition::ShowDocument`] if the client doesn't support it.
+    async fn goto_definition_mode(&self) -> GotoDefinition {
+        // The prefered method to tell the client to open a page in the
+        // browser is returning a `GotoDefinitionResponse::Scalar` with a HTTP
+        // link. But helix does not support this at time of writing, so we'll
+        // use `window/showDocument` for it.
+        const SHOW_DOCUMENT_CLIENTS: &[&str] = &["helix"];
+
+        let mode = match self.settings.read().await.goto_definition {
+            GotoDefinition::Auto => {
+                let client_info = self.client_info.read().await;
+                let name = client_info.as_ref().map(|info| info.name.as_str());
+                if name.is_some_and(|n| SHOW_DOCUMENT_CLIENTS.contains(&n)) {
+                    GotoDefinition::ShowDocument
+                } else {
+                    GotoDefinition::Location
+                }
+            }
+            mode => mode,
+        };
+
+        let supports_show_document = self
+            .client_capabilities
+            .read()
+            .await
+            .window
+            .as_ref()
+            .and_then(|w| w.show_document.as_ref())
+            .is_some_and(|sd| sd.support);
+
+        if mode == GotoDefinition::ShowDocument && !supports_show_document {
+            GotoDefinition::Message
+        } else {
+            mode
+        }
+    }
+
+    /// Point the client at a URL (describing `what` it is) according to the
+    /// (resolved) goto definition mode.
+    async fn goto_url(
+        &self,
+        mode: GotoDefinition,
+        uri: Url,
+        what: &str,
+    ) -> Option<GotoDefinitionResponse> {
+        match mode {
+            GotoDefinition::Location => {
+                let location = Location::new(uri, Range::default());
+                return Some(GotoDefinitionResponse::Scalar(location));
+            }
+            GotoDefinition::ShowDocument => {
+                let params = ShowDocumentParams {
+                    uri,
+                    external: Some(true),
+                    take_focus: None,
+                    selection: None,
+                };
+                let was_shown = self.client.show_document(params).await;
+
+                if matches!(was_shown, Ok(true)) {
+                    let msg = self
+                        .messages()
+                        .await
+                        .get("opened-in-browser", &[("what", &what)]);
+                    self.client.show_message(MessageType::INFO, msg).await;
+                }
+            }
+            GotoDefinition::Message => {
+                let msg = self
+                    .messages()
+                    .await
+                    .get("url-of", &[("what", &what), ("url", &uri)]);
+                self.client.show_message(MessageType::INFO, msg).await;
+            }
+            GotoDefinition::Auto | GotoDefinition::Disabled => {}
+        }
+
+        None
+    }
+
+    /// The format the client prefers for the contents of hovers.
+    async fn hover_format(&self) -> MarkupKind {
+        let capabilities = self.client_capabilities.read().await;
+        let formats = capabilities
+            .text_document
+            .as_ref()
+            .and_then(|td| td.hover.as_ref())
+            .and_then(|hover| hover.content_format.as_deref());
+        markup::preferred(formats)
+    }
+
+    /// The format the client prefers for the documentation of completions.
+    async fn completion_format(&self) -> MarkupKind {
+        let capabilities = self.client_capabilities.read().await;
+        let formats = capabilities
+            .text_document
+            .as_ref()
+            .and_then(|td| td.completion.as_ref())
+            .and_then(|c| c.completion_item.as_ref())
+            .and_then(|item| item.documentation_format.as_deref());
+        markup::preferred(formats)
+    }
+
+    /// Whether the client supports snippets in completions.
+    async fn completion_snippets(&self) -> bool {
+        let capabilities = self.client_capabilities.read().await;
+        capabilities
+            .text_document
+            .as_ref()
+            .and_then(|td| td.completion.as_ref())
+            .and_then(|c| c.completion_item.as_ref())
+            .and_then(|item| item.snippet_support)
+            .unwrap_or(false)
+    }
+
+    async fn resolve_completion(
+        &self,
+        params: CompletionParams,
+    ) -> jsonrpc::Result<Option<CompletionResponse>> {
+        let uri = params.text_document_position.text_document.uri;
+        let pos = params.text_document_position.position;
+        let manifests = self.manifests.read().await;
+        let Some(manifest) = manifests.get(&uri) else { return Ok(None) };
+
+        match Context::resolve(manifest, pos) {
+            Some(Context::DependencyName(dependency))
+                if matches!(dependency.kind, parse::Kind::Registry) =>
+            {
+                let name = &dependency.name;
+                let typed =
+                    pos.character.saturating_sub(name.range.start.character);
+                let prefix =
+                    name.value.get(..typed as usize).unwrap_or(&name.value);
+                let registry =
+                    dependency.registry.as_ref().map(|r| r.value.as_str());
+                let comps = self
+                    .name_completions(&uri, prefix, registry, name.range, false)
+                    .await;
+                return Ok(comps);
+            }
+            Some(Context::VersionValue(dependency)) => {
+                // the versions are labeled with their ages.
+                let latest =
+                    self.registry.fetch_details(dependency.crate_name()).await;
+                let comps = latest
+                    .ok()
+                    .map(format::version_completions)
+                    .map(CompletionResponse::Array);
+                return Ok(comps);
+            }
+            Some(Context::FeatureString { dependency, .. }) => {
+                let used = dependency
+                    .features
+                    .iter()
+                    .flatten()
+                    .map(|f| f.value.as_str())
+                    .collect::<Vec<_>>();
+                let comps = self
+                    .generate_completion(dependency.crate_name(), |latest| {
+                        format::features_completions(&used, None, latest)
+                    })
+                    .await;
+                return Ok(comps);
+            }
+            _ => {}
+        }
+
+        // The features array (or the string in it) might still be typed, so
+        // it's not in the parsed manifest yet.
+        let doc = self.documents.read().await.get(&uri).map(Rope::to_string);
+        if let Some(doc) = &doc
+            && let Some(feature) = IncompleteFeature::resolve(doc, pos)
+        {
+            let used =
+                feature.used.iter().map(String::as_str).collect::<Vec<_>>();
+            // the key of a renamed dependency is a local alias of its crate.
+            let name = manifest
+                .dependencies
+                .iter()
+                .find(|d| d.name.value == feature.dependency)
+                .map_or(feature.dependency.as_str(), Dependency::crate_name);
+            let comps = self
+                .generate_completion(name, |latest| {
+                    format::features_completions(&used, feature.bare, latest)
+                })
+                .await;
+            return Ok(comps);
+        }
+
+        // A name without a value (yet) isn't a dependency, so it's completed
+        // before it's validated.
+        if let Some(doc) = &doc
+            && let Some(name) = manifest
+                .incomplete
+                .iter()
+                .find(|name| name.range.start <= pos && pos <= name.range.end)
+        {
+            let typed = (pos.character - name.range.start.character) as usize;
+            let prefix = name.value.get(..typed).unwrap_or(&name.value);
+            let bare = context::ends_line(doc, name.range.end);
+            let comps = self
+                .name_completions(&uri, prefix, None, name.range, bare)
+                .await;
+            return Ok(comps);
+        }
+
+        if let Some(doc) = &doc
+            && let Some(new) = NewDependency::resolve(doc, pos)
+        {
+            let bare = context::ends_line(doc, new.range.end);
+            let comps = self
+                .name_completions(&uri, &new.prefix, None, new.range, bare)
+                .await;
+            return Ok(comps);
+        }
+
+        if let Some(doc) = &doc
+            && let Some(feature) = RequiredFeature::resolve(doc, pos)
+        {
+            let used =
+                feature.used.iter().map(String::as_str).collect::<Vec<_>>();
+            let comps = format::own_features_completions(manifest, &used);
+            return Ok(Some(CompletionResponse::Array(comps)));
+        }
+
+        if let Some(doc) = &doc
+            && let Some(key) = TableKey::resolve(doc, pos)
+        {
+            if key.dependency().is_some() {
+                let comps = format::dependency_key_completions();
+                return Ok(Some(CompletionResponse::Array(comps)));
+            }
+            if let Some(table) = metadata::table(&key.table) {
+                let comps = format::metadata_key_completions(table);
+                return Ok(Some(CompletionResponse::Array(comps)));
+            }
+            if let Some(keys) = profile::table_keys(&key.table) {
+                let comps = format::profile_key_completions(&keys);
+                return Ok(Some(CompletionResponse::Array(comps)));
+            }
+            if let Some(lints) =
+                lints::table_tool(&key.table).and_then(lints::Tool::lints)
+            {
+                let comps = format::lint_completions(lints);
+                return Ok(Some(CompletionResponse::Array(comps)));
+            }
+            if let Some(can_inherit) = lints::lints_table(&key.table) {
+                let comps = format::lints_table_completions(can_inherit);
+                return Ok(Some(CompletionResponse::Array(comps)));
+            }
+        }
+
+        if let Some(doc) = doc
+            && let Some(value) = TableValue::resolve(&doc, pos)
+        {
+            if profile::table_keys(&value.table).is_some()
+                && let Some(key) = profile::find(&value.key)
+            {
+                let comps =
+                    format::profile_value_completions(key, value.in_string);
+                return Ok(Some(CompletionResponse::Array(comps)));
+            }
+            if lints::table_tool(&value.table).is_some() {
+                let comps = format::lint_level_completions(value.in_string);
+                return Ok(Some(CompletionResponse::Array(comps)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    async fn resolve_hover(
+        &self,
+        params: HoverParams,
+    ) -> jsonrpc::Result<Option<Hover>> {
+        let uri = params.text_document_position_params.text_document.uri;
+        let pos = params.text_document_position_params.position;
+        if is_lockfile(&uri) {
+            return Ok(self.lockfile_hover(&uri, pos).await);
+        }
+        let manifests = self.manifests.read().await;
+        let Some(manifest) = manifests.get(&uri) else { return Ok(None) };
+
+        if let Some(key) = manifest.profiles.find(pos) {
+            return Ok(Some(Hover {
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: format::format_profile_key_hover(
+                        key.value,
+                        self.messages().await,
+                    ),
+                }),
+                range: Some(key.range),
+            }));
+        }
+
+        if let Some(key) = workspace::find_inherited(&manifest.inherited, pos) {
+            let root = self.workspace_root(&uri).await;
+            let entry = root.as_ref().and_then(|r| r.package_entry(&key.value));
+            let relative = root
+                .as_ref()
+                .and_then(|r| uri.make_relative(&r.uri))
+                .filter(|r| !r.is_empty())
+                .unwrap_or_else(|| parse::MANIFEST_FILE_NAME.to_owned());
+            let value = format::format_inherited_hover(
+                &key.value,
+                root.is_some().then_some(relative.as_str()),
+                entry.as_ref().map(|e| e.value.as_str()),
+                self.messages().await,
+            );
+            return Ok(Some(Hover {
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value,
+                }),
+                range: Some(key.range),
+            }));
+        }
+
+        let doc = self.documents.read().await.get(&uri).map(Rope::to_string);
+        if let Some(header) =
+            doc.and_then(|doc| DependenciesHeader::resolve(&doc, pos))
+            && let Some(section) = header.section()
+        {
+            return Ok(Some(self.section_hover(manifest, &section).await));
+        }
+
+        let context = Context::resolve(manifest, pos);
+        if let Some(Context::DependencyName(dependency)) = context
+            && let Some(registry) = dependency.alternative_registry()
+        {
+            return Ok(Some(Hover {
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: format::format_registry_hover(
+                        dependency,
+                        registry,
+                        self.messages().await,
+                    ),
+                }),
+                range: Some(dependency.name.range),
+            }));
+        }
+        if let Some(Context::VersionValue(dependency)) = context
+            && let Some(version) = &dependency.version
+        {
+            return Ok(self
+                .requirement_hover(&uri, dependency, version, pos)
+                .await);
+        }
+        let context = context.filter(|c| c.dependency().is_crates_io());
+
+        let hover = if let Some(
+            Context::DependencyName(dependency)
+            | Context::DependencyKey(dependency),
+        ) = context
+            && let Ok(latest) =
+                self.registry.fetch_details(dependency.crate_name()).await
+        {
+            // Hovering over a dependency name (or a key of its table, which
+            // might be far from the name, e.g. under `[dependencies.foo]`)
+            let range = matches!(context, Some(Context::DependencyName(_)))
+                .then_some(dependency.name.range);
+            let sections = self.settings.read().await.hover.sections.clone();
+            let docs = self.docs_override(&uri, dependency.crate_name()).await;
+            let lockfile = if sections.contains(&HoverSection::Lock) {
+                self.lockfile(&uri).await
+            } else {
+                None
+            };
+            let lag = lockfile.and_then(|lockfile| {
+                let locked = lockfile.versions(&latest.name);
+                lockfile_lag(dependency, &locked, &latest)
+            });
+            let readme = if sections.contains(&HoverSection::Readme) {
+                let name = &latest.name;
+                self.registry.fetch_readme(name, &latest.version).await
+            } else {
+                None
+            };
+
+            Some(Hover {
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: format::format_name_hover(
+                        dependency,
+                        latest,
+                        lag.as_ref(),
+                        docs.as_deref(),
+                        readme.as_deref(),
+                        &sections,
+                        self.messages().await,
+                    ),
+                }),
+                range,
+            })
+        } else if let Some(Context::FeatureString { dependency, index }) =
+            context
+            && let Some(feature) =
+                dependency.features.as_ref().and_then(|f| f.get(index))
+            && let Ok(latest) =
+                self.registry.fetch(dependency.crate_name()).await
+            && let Some(features) = latest.features
+            && let Some(feature_description) = features.get(&feature.value)
+        {
+            Some(Hover {
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: format::format_feature_hover(
+                        &feature.value,
+                        feature_description,
+                        self.messages().await,
+                    ),
+                }),
+                range: Some(feature.range),
+            })
+        } else {
+            None
+        };
+
+        Ok(hover)
+    }
+
+    /// Errors on the dependencies that link the same native library as the
+    /// package, or as another dependency, which cargo fails on.
+    async fn links_conflicts(
+        &self,
+        uri: &Url,
+        manifest: &Manifest,
+        messages: Catalog,
+    ) -> Vec<Diagnostic> {
+        let dependencies = manifest
+            .dependencies
+            .iter()
+            .filter(|d| d.is_crates_io())
+            .collect::<Vec<_>>();
+        // the crates by their names, as the renamed dependencies are keyed
+        // by other names.
+        let mut infos = HashMap::new();
+        for dependency in &dependencies {
+            let name = dependency.crate_name();
+            if infos.contains_key(name) {
+                continue;
+            }
+            if let Ok(info) = self.registry.fetch(name).await {
+                infos.insert(name.to_owned(), info);
+            }
+        }
+
+        links::conflicts(manifest.links.as_ref(), &dependencies, &infos)
+            .into_iter()
+            .map(|conflict| {
+                let name = &conflict.dependency.name.value;
+                let links = &conflict.links;
+                let (message, range) = match conflict.with {
+                    Linker::Package(range) => (
+                        messages.get(
+                            "links-package",
+                            &[("name", name), ("links", links)],
+                        ),
+                        range,
+                    ),
+                    Linker::Dependency(other) => (
+                        messages.get(
+                            "links-dependency",
+                            &[
+                                ("name", name),
+                                ("links", links),
+                                ("other", &other.name.value),
+                            ],
+                        ),
+                        other.name.range,
+                    ),
+                };
+                Diagnostic {
+                    range: conflict.dependency.name.range,
+                    severity: Some(DiagnosticSeverity::ERROR),
+                    code: None,
+                    code_description: None,
+                    source: None,
+                    message,
+                    related_information: Some(vec![
+                        DiagnosticRelatedInformation {
+                            location: Location::new(uri.clone(), range),
+                            message: messages
+                                .get("links-first", &[("links", links)]),
+                        },
+                    ]),
+                    tags: None,
+                    data: None,
+                }
+            })
+            .collect()
+    }
+
+    /// Warn about the dependencies whose requirements make the workspace
+    /// build a second copy (of another major version) of a crate that its
+    /// lockfile already has, like `cargo tree -d`.
+    async fn duplicate_versions(
+        &self,
+        uri: &Url,
+        dependencies: &[Dependency],
+        messages: Catalog,
+    ) -> Vec<Diagnostic> {
+        let reqs = dependencies
+            .iter()
+            .filter(|d| d.is_crates_io())
+            .filter_map(|d| Some((d, d.version.as_ref()?)))
+            .filter_map(|(d, v)| Some((d, v.range, v.value.req.as_ref()?)))
+            .collect::<Vec<_>>();
+        // most manifests have none, so the lockfile isn't even read.
+        if reqs.is_empty() {
+            return Vec::new();
+        }
+        let Some(lockfile) = self.lockfile(uri).await else {
+            return Vec::new();
+        };
+
+        reqs.into_iter()
+            .filter_map(|(dependency, range, req)| {
+                let name = dependency.crate_name();
+                let duplicates =
+                    lockfile::duplicates(&lockfile.packages, name, req);
+                if duplicates.is_empty() {
+                    return None;
+                }
+                let versions = duplicates
+                    .iter()
+                    .map(|l| l.version.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let related = duplicates
+                    .iter()
+                    .flat_map(|l| l.dependents.iter().map(move |d| (l, d)))
+                    .map(|(l, dependent)| DiagnosticRelatedInformation {
+                        location: Location::new(
+                            lockfile.uri.clone(),
+                            dependent.range,
+                        ),
+                        message: messages.get(
+                            "duplicate-dependent",
+                            &[
+                                ("dependent", &dependent.name),
+                                ("name", &name),
+                                ("version", &l.version),
+                            ],
+                        ),
+                    })
+                    .collect();
+                Some(Diagnostic {
+                    range,
+                    severity: Some(DiagnosticSeverity::WARNING),
+                    code: None,
+                    code_description: None,
+                    source: None,
+                    message: messages.get(
+                        "duplicate-version",
+                        &[("name", &name), ("versions", &versions)],
+                    ),
+                    related_information: Some(related),
+                    tags: None,
+                    data: None,
+                })
+            })
+            .collect()
+    }
+
+    /// Warn about the heavy features (e.g. `tokio/full`) that the manifest
+    /// enables, which cargo unifies into the builds of the other members of
+    /// its workspace.
+    async fn unified_features(
+        &self,
+        uri: &Url,
+        dependencies: &[Dependency],
+        messages: Catalog,
+    ) -> Vec<Diagnostic> {
+        let mut heavy = Vec::new();
+        for dependency in dependencies.iter().filter(|d| d.is_crates_io()) {
+            let Some(features) = &dependency.features else { continue };
+            let Ok(info) = self.registry.fetch(dependency.crate_name()).await
+            else {
+                continue;
+            };
+            heavy.extend(
+                features
+                    .iter()
+                    .filter(|f| {
+                        info.implied_features(&f.value).len()
+                            >= workspace::HEAVY_FEATURE_SIZE
+                    })
+                    .map(|f| (dependency.crate_name(), f.value.as_str())),
+            );
+        }
+        // most manifests have none, so the workspace isn't even read.
+        if heavy.is_empty() {
+            return Vec::new();
+        }
+
+        let Some(root) = self.workspace_root(uri).await else {
+            return Vec::new();
+        };
+        let members = self.member_manifests(root.other_members(uri)).await;
+
+        let is_heavy = |dependency: &Dependency, feature: &str| {
+            heavy.contains(&(dependency.crate_name(), feature))
+        };
+        workspace::unified_features(dependencies, &members, is_heavy)
+            .into_iter()
+            .map(|unified| {
+                let args: [(&str, &dyn std::fmt::Display); 2] = [
+                    ("name", &unified.dependency.name.value),
+                    ("feature", &unified.feature.value),
+                ];
+                let mut affected_members = unified
+                    .affected
+                    .iter()
+                    .map(|(uri, _)| uri)
+                    .collect::<Vec<_>>();
+                affected_members.dedup();
+                let count = affected_members.len();
+
+                Diagnostic {
+                    range: unified.feature.range,
+                    severity: Some(DiagnosticSeverity::WARNING),
+                    code: None,
+                    code_description: None,
+                    source: None,
+                    message: messages.get(
+                        "unified-feature",
+                        &[args[0], args[1], ("count", &count)],
+                    ),
+                    related_information: Some(
+                        unified
+                            .affected
+                            .into_iter()
+                            .map(|(uri, range)| DiagnosticRelatedInformation {
+                                location: Location::new(uri, range),
+                                message: messages.get("unified-into", &args),
+                            })
+                            .collect(),
+                    ),
+                    tags: None,
+                    data: None,
+                }
+            })
+            .collect()
+    }
+
+    /// Complete the names of the crates matching `prefix`, from the
+    /// registry of the dependency (if it's a configured alternative
+    /// registry) followed by crates.io.
+    ///
+    /// The list is incomplete, so the client searches again as the name is
+    /// typed. A `bare` name (without a value yet) is completed along with the
+    /// latest version of the crate.
+    async fn name_completions(
+        &self,
+        uri: &Url,
+        prefix: &str,
+        registry: Option<&str>,
+        range: Range,
+        bare: bool,
+    ) -> Option<CompletionResponse> {
+        if prefix.is_empty() {
+            return None;
+        }
+        let value = match (bare, self.completion_snippets().await) {
+            (false, _) => format::NameValue::None,
+            (true, false) => format::NameValue::Plain,
+            (true, true) => format::NameValue::Snippet,
+        };
+        // the registry and its API, so the settings aren't locked while
+        // searching.
+        let alternative = {
+            let settings = self.settings.read().await;
+            registry
+                .or(settings.default_registry.as_deref())
+                .filter(|&registry| registry != CRATES_IO_REGISTRY)
+                .and_then(|registry| {
+                    let config = settings.registries.get(registry)?;
+                    Some((registry.to_owned(), config.api.clone()))
+                })
+        };
+
+        let mut results = Vec::new();
+        if let Some((registry, api)) = &alternative {
+            let found = self.registry.search_registry(api, prefix);
+            results.push((
+                registry.as_str(),
+                found.await.unwrap_or_default(),
+                &[][..],
+            ));
+        }
+        let found = self.registry.search(prefix).await.unwrap_or_default();
+        // the crates.io packages of the lockfile, so the found crates that
+        // are already in the tree say so.
+        let lockfile =
+            if found.is_empty() { None } else { self.lockfile(uri).await };
+        let locked = lockfile.as_ref().map_or(&[][..], |l| &l.packages);
+        results.push((CRATES_IO_SOURCE, found, locked));
+
+        Some(CompletionResponse::List(CompletionList {
+            is_incomplete: true,
+            items: format::name_completions(&results, range, value),
+        }))
+    }
+
+    async fn generate_completion<F>(
+        &self,
+        name: &str,
+        f: F,
+    ) -> Option<CompletionResponse>
+    where
+        F: Fn(crates::CrateInfo) -> Vec<CompletionItem>,
+    {
+        self.registry
+            .fetch(name)
+            .await
+            .ok()
+            .map(f)
+            .map(CompletionResponse::Array)
+    }
+}
+
+#[tower_lsp::async_trait]
+impl LanguageServer for Backend {
+    async fn initialize(
+        &self,
+        params: InitializeParams,
+    ) -> jsonrpc::Result<InitializeResult> {
+        // before the settings are parsed, so their errors are localized too.
+        *self.client_locale.write().await = params.locale;
+        if let Some(value) = &params.initialization_options {
+            self.warn_unknown_settings(value).await;
+        }
+        match Settings::from_value(params.initialization_options) {
+            Ok(settings) => self.set_settings(settings).await,
+            Err(err) => {
+                let msg = self
+                    .messages()
+                    .await
+                    .get("invalid-settings", &[("error", &err)]);
+                self.client.log_message(MessageType::WARNING, msg).await;
+            }
+        }
+        *self.client_info.write().await = params.client_info;
+        #[allow(deprecated)]
+        let roots = match params.workspace_folders {
+            Some(folders) => folders.into_iter().map(|f| f.uri).collect(),
+            // clients that don't support multiple folders.
+            None => params.root_uri.into_iter().collect(),
+        };
+        *self.workspace_roots.write().await = roots;
+        *self.client_capabilities.write().await = params.capabilities;
+
+        Ok(InitializeResult {
+            server_info: None,
+            capabilities: ServerCapabilities {
+                // We want to keep a synced version of the documents
+                text_document_sync: Some(TextDocumentSyncCapability::Options(
+                    TextDocumentSyncOptions {
+                        open_close: Some(true),
+                        // sync the document by sending changes using the
+                        // `didChagne` notification.
+                        change: Some(TextDocumentSyncKind::INCREMENTAL),
+                        // the saves refresh what's read from the disk.
+                        save: Some(TextDocumentSyncSaveOptions::Supported(
+                            true,
+                        )),
+                        ..Default::default()
+                    },
+                )),
+
+                // We provide completions events
+                completion_provider: Some(CompletionOptions {
+                    // trigger completion event when the user hits `"`
+                    trigger_characters: Some(vec!['\"'.to_string()]),
+                    resolve_provider: Some(false),
+                    ..Default::default()
+                }),
+
+                // We provide hover events
+                hover_provider: Some(HoverProviderCapability::Simple(true)),
 
                 // We provide code action events
                 code_action_provider: Some(
                     CodeActionProviderCapability::Simple(true),
                 ),
 
+                // We provide code lenses on the headers of the dependencies
+                // tables, with actions on all of their dependencies
+                code_lens_provider: Some(CodeLensOptions {
+                    resolve_provider: Some(false),
+                }),
+
+                // We provide signature help for the inline tables of
+                // dependencies, as a quick reference of their keys
+                signature_help_provider: Some(SignatureHelpOptions {
+                    trigger_characters: Some(vec![
+                        "{".to_owned(),
+                        ",".to_owned(),
+                    ]),
+                    retrigger_characters: None,
+                    work_done_progress_options: WorkDoneProgressOptions {
+                        work_done_progress: None,
+                    },
+                }),
+
                 // We provide goto definition events
                 definition_provider: Some(OneOf::Left(true)),
 
+                // We provide goto implementation events (for the sources)
+                implementation_provider: Some(
+                    ImplementationProviderCapability::Simple(true),
+                ),
+
                 execute_command_provider: Some(ExecuteCommandOptions {
-                    commands: vec![code_action::LATEST_VERSION.to_owned()],
+                    commands: Command::ALL
+                        .iter()
+                        .map(|&c| c.to_owned())
+                        .collect(),
                     work_done_progress_options: WorkDoneProgressOptions {
                         work_done_progress: None,
                     },
@@ -263,152 +2573,318 @@ impl LanguageServer for Backend {
         })
     }
 
+    async fn initialized(&self, _: InitializedParams) {
+        // Reach the registry the way cargo does in the (first) workspace
+        // folder, e.g. through its proxy.
+        let root = self.workspace_roots.read().await.first().cloned();
+        let root = root.as_ref().and_then(path::to_path);
+        let http = CargoConfig::load_dir(root.as_deref()).http;
+        if let Err(err) = self.registry.set_http(&http).await {
+            let msg = format!(
+                "the `[http]` settings of the cargo configuration aren't \
+                 applied: {err}"
+            );
+            self.client.show_message(MessageType::WARNING, msg).await;
+        }
+        *self.cargo_http.write().await = http;
+        let settings = self.settings.read().await.clone();
+        self.set_settings(settings).await;
+
+        // Probe the registry once, so if it's unreachable the user is told
+        // once, instead of getting an error on every dependency.
+        if !self.registry.check_health().await {
+            let msg = "the crates.io index is unreachable, so only cached \
+                       crates are shown until it's back"
+                .to_owned();
+            self.client.show_message(MessageType::WARNING, msg).await;
+            return;
+        }
+
+        // Warm the cache with the crates of the workspaces' lockfiles, so
+        // opening their manifests is instant.
+        if self.settings.read().await.cache.prefetch_lockfile {
+            let roots = self.workspace_roots.read().await.clone();
+            let mut names = Vec::new();
+            for lockfile in roots.iter().filter_map(lockfile::read) {
+                for name in lockfile::crates_io_packages(&lockfile) {
+                    if !names.contains(&name) {
+                        names.push(name);
+                    }
+                }
+            }
+            self.registry.prefetch(&names).await;
+        }
+    }
+
     async fn did_open(&self, params: DidOpenTextDocumentParams) {
+        self.record("textDocument/didOpen").await;
         let uri = params.text_document.uri;
+        let token = Arc::new(Notify::new());
+        self.cancel_run(&uri, Some(token.clone())).await;
         let text = Rope::from_str(&params.text_document.text);
         self.documents.write().await.insert(uri.clone(), text);
+        let config = CargoConfig::load(&uri);
+        self.cargo_configs.write().await.insert(uri.clone(), config);
+        let config = ProjectConfig::load(&uri);
+        self.project_configs
+            .write()
+            .await
+            .insert(uri.clone(), config);
+        self.walk_package(&uri).await;
+        self.update_workspace_roots(slice::from_ref(&uri)).await;
+        // the client may have dropped the diagnostics when the document was
+        // closed, so they're always published on open.
+        self.published.write().await.remove(&uri);
+        self.update_manifest(uri.clone()).await;
+        self.run_diagnostics(uri, token, Duration::ZERO).await;
+    }
+
+    async fn did_save(&self, params: DidSaveTextDocumentParams) {
+        self.record("textDocument/didSave").await;
+        let uri = params.text_document.uri;
+        if !self.documents.read().await.contains_key(&uri) {
+            return;
+        }
+        let token = Arc::new(Notify::new());
+        self.cancel_run(&uri, Some(token.clone())).await;
+        // e.g. the files that an `include` pattern is meant for were added
+        // since the manifest was opened.
+        self.walk_package(&uri).await;
+        // the manifest itself, and the open members of its workspace if it's
+        // their root.
+        let uris = self
+            .roots
+            .read()
+            .await
+            .iter()
+            .filter(|(member, root)| {
+                *member == &uri || root.as_ref().is_some_and(|r| r.uri == uri)
+            })
+            .map(|(member, _)| member.clone())
+            .collect::<Vec<_>>();
+        self.update_workspace_roots(&uris).await;
         self.update_manifest(uri.clone()).await;
-        self.publish_diagnostics(uri).await;
+        self.run_diagnostics(uri, token, Duration::ZERO).await;
     }
 
-    async fn did_change(&self, params: DidChangeTextDocumentParams) {
-        let uri = params.text_document.uri;
-        self.apply_changes(&uri, params.content_changes).await;
-        self.update_manifest(uri.clone()).await;
-        self.publish_diagnostics(uri).await;
+    async fn did_change(&self, params: DidChangeTextDocumentParams) {
+        self.record("textDocument/didChange").await;
+        let uri = params.text_document.uri;
+        // Abort the run of the previous change first, so its diagnostics
+        // aren't published after the ones of this change.
+        let token = Arc::new(Notify::new());
+        self.cancel_run(&uri, Some(token.clone())).await;
+
+        // The manifest is parsed right away, so the requests that follow the
+        // change (e.g. completions) see it.
+        self.apply_changes(&uri, params.content_changes).await;
+        self.update_manifest(uri.clone()).await;
+
+        // Only the last of rapid edits is analysed (and its crates fetched).
+        let debounce = self.settings.read().await.debounce;
+        let delay = debounce.map_or(DEBOUNCE, Duration::from_millis);
+        self.run_diagnostics(uri, token, delay).await;
+    }
+
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        self.record("textDocument/didClose").await;
+        let uri = params.text_document.uri;
+        self.cancel_run(&uri, None).await;
+        self.documents.write().await.remove(&uri);
+        self.manifests.write().await.remove(&uri);
+        self.cargo_configs.write().await.remove(&uri);
+        self.project_configs.write().await.remove(&uri);
+        self.package_files.write().await.remove(&uri);
+        self.roots.write().await.remove(&uri);
+        self.analyses.write().await.remove(&uri);
+        self.clear_diagnostics(uri).await;
+    }
+
+    async fn did_change_configuration(
+        &self,
+        params: DidChangeConfigurationParams,
+    ) {
+        self.warn_unknown_settings(&params.settings).await;
+        match Settings::from_value(Some(params.settings)) {
+            Ok(settings) => self.set_settings(settings).await,
+            Err(err) => {
+                let msg = self
+                    .messages()
+                    .await
+                    .get("invalid-settings", &[("error", &err)]);
+                self.client.log_message(MessageType::WARNING, msg).await;
+                return;
+            }
+        }
+
+        // The settings might affect which documents are treated as manifests.
+        let uris: Vec<_> =
+            self.documents.read().await.keys().cloned().collect();
+        for uri in uris {
+            // e.g. the publish lints were turned on.
+            self.walk_package(&uri).await;
+            self.update_manifest(uri.clone()).await;
+            self.publish_diagnostics(uri).await;
+        }
     }
 
     async fn completion(
         &self,
         params: CompletionParams,
     ) -> jsonrpc::Result<Option<CompletionResponse>> {
-        let uri = params.text_document_position.text_document.uri;
-        let pos = params.text_document_position.position;
-        let manifests = self.manifests.read().await;
-        let Some(dependencies) = manifests.get(&uri) else { return Ok(None) };
-
-        for dependency in dependencies.iter() {
-            if dependency
-                .version
-                .as_ref()
-                .is_some_and(|v| v.contains_pos(pos))
-            {
-                let name = &dependency.name.value;
-                let comps = self
-                    .generate_completion(name, format::version_completions)
-                    .await;
-                return Ok(comps);
-            } else if dependency
-                .features
-                .as_ref()
-                .is_some_and(|f| f.iter().any(|f| f.contains_pos(pos)))
-            {
-                let comps = self
-                    .generate_completion(&dependency.name.value, |latest| {
-                        format::features_completions(dependency, latest)
-                    })
-                    .await;
-                return Ok(comps);
-            }
-        }
-
-        Ok(None)
+        self.record("textDocument/completion").await;
+        let kind = self.completion_format().await;
+        let response = self.resolve_completion(params).await?;
+        Ok(response.map(|r| markup::completions(r, kind)))
     }
 
     async fn hover(
         &self,
         params: HoverParams,
     ) -> jsonrpc::Result<Option<Hover>> {
+        self.record("textDocument/hover").await;
+        let kind = self.hover_format().await;
+        let hover = self.resolve_hover(params).await?;
+        Ok(hover.map(|h| markup::hover(h, kind)))
+    }
+
+    async fn signature_help(
+        &self,
+        params: SignatureHelpParams,
+    ) -> jsonrpc::Result<Option<SignatureHelp>> {
+        self.record("textDocument/signatureHelp").await;
+        let uri = params.text_document_position_params.text_document.uri;
+        let pos = params.text_document_position_params.position;
+        if !self.manifests.read().await.contains_key(&uri) {
+            return Ok(None);
+        }
+
+        let doc = self.documents.read().await.get(&uri).map(Rope::to_string);
+        Ok(doc
+            .and_then(|doc| InlineDependency::resolve(&doc, pos))
+            .map(|dependency| {
+                format::dependency_signature(dependency.key.as_deref())
+            }))
+    }
+
+    async fn goto_definition(
+        &self,
+        params: GotoDefinitionParams,
+    ) -> jsonrpc::Result<Option<GotoDefinitionResponse>> {
+        self.record("textDocument/definition").await;
         let uri = params.text_document_position_params.text_document.uri;
         let pos = params.text_document_position_params.position;
         let manifests = self.manifests.read().await;
-        let Some(dependencies) = manifests.get(&uri) else { return Ok(None) };
+        let Some(manifest) = manifests.get(&uri) else { return Ok(None) };
+        let context = Context::resolve(manifest, pos);
 
-        let hover = if let Some(name) = dependencies
-            .iter()
-            .find(|d| d.name.contains_pos(pos))
-            .and_then(|d| {
-                matches!(d.kind, parse::Kind::Registry).then_some(&d.name)
-            })
-            && let Ok(latest) = self.registry.fetch(&name.value).await
+        // The manifest of a local dependency (rather than its docs).
+        if let Some(Context::PathValue(dependency)) = context
+            && let parse::Kind::Local(local) = &dependency.kind
+            && let Some(path) = local.path.value.to_str()
+            && let Some(target) = path::resolve_manifest(&uri, path)
         {
-            // Hovering over a dependency name
+            let location = Location::new(target, Range::default());
+            return Ok(Some(GotoDefinitionResponse::Scalar(location)));
+        }
 
-            Some(Hover {
-                contents: HoverContents::Markup(MarkupContent {
-                    kind: MarkupKind::Markdown,
-                    value: format::format_name_hover(&name.value, latest),
-                }),
-                range: Some(name.range),
-            })
-        } else if let Some((name, feature)) =
-            dependencies.iter().find_map(|d| {
-                if !matches!(d.kind, parse::Kind::Registry) {
-                    return None;
+        // The entry of the workspace's root that a key (or a dependency)
+        // inherits.
+        let inherited = workspace::find_inherited(&manifest.inherited, pos)
+            .map(|key| (&key.value, false))
+            .or(match context {
+                Some(Context::WorkspaceValue(dependency)) => {
+                    Some((&dependency.name.value, true))
                 }
+                _ => None,
+            });
+        if let Some((key, is_dependency)) = inherited {
+            let root = self.workspace_root(&uri).await;
+            return Ok(root.and_then(|root| {
+                let entry = if is_dependency {
+                    root.dependency_entry(key)
+                } else {
+                    root.package_entry(key)
+                }?;
+                let location = Location::new(root.uri, entry.range);
+                Some(GotoDefinitionResponse::Scalar(location))
+            }));
+        }
 
-                // Hovering over some feature
+        let mode = self.goto_definition_mode().await;
+        if mode == GotoDefinition::Disabled {
+            return Ok(None);
+        }
 
-                d.features
-                    .as_ref()?
-                    .iter()
-                    .find(|f| f.contains_pos(pos))
-                    .map(|f| (&d.name.value, f))
-            })
-            && let Ok(latest) = self.registry.fetch(name).await
-            && let Some(features) = latest.features
-            && let Some(feature_description) = features.get(&feature.value)
+        // The docs the project points the crate to, whatever its source (e.g.
+        // an internal fork that shares its name with a crates.io crate).
+        if let Some(Context::DependencyName(dependency)) = context
+            && let name = dependency.crate_name()
+            && let Some(docs) = self.docs_override(&uri, name).await
+            && let Ok(uri) = Url::parse(&docs)
         {
-            Some(Hover {
-                contents: HoverContents::Markup(MarkupContent {
-                    kind: MarkupKind::Markdown,
-                    value: format::format_feature_hover(
-                        &feature.value,
-                        feature_description,
-                    ),
-                }),
-                range: Some(feature.range),
-            })
-        } else {
-            None
-        };
+            let what = self.messages().await.get("docs-of", &[("name", &name)]);
+            return Ok(self.goto_url(mode, uri, &what).await);
+        }
 
-        Ok(hover)
+        if let Some(Context::DependencyName(dependency)) = context
+            && dependency.is_crates_io()
+            && let name = dependency.crate_name()
+            // a crate that doesn't exist has no docs, and fetching it caches
+            // it for the other handlers.
+            && let Ok(info) = self.registry.fetch(name).await
+            && let Ok(uri) = Url::parse(&info.docs_url(name))
+        {
+            let what = self.messages().await.get("docs-of", &[("name", &name)]);
+            return Ok(self.goto_url(mode, uri, &what).await);
+        }
+
+        Ok(None)
     }
 
-    async fn goto_definition(
+    async fn goto_implementation(
         &self,
-        params: GotoDefinitionParams,
-    ) -> jsonrpc::Result<Option<GotoDefinitionResponse>> {
+        params: GotoImplementationParams,
+    ) -> jsonrpc::Result<Option<GotoImplementationResponse>> {
+        self.record("textDocument/implementation").await;
         let uri = params.text_document_position_params.text_document.uri;
         let pos = params.text_document_position_params.position;
         let manifests = self.manifests.read().await;
-        let Some(dependencies) = manifests.get(&uri) else { return Ok(None) };
+        let Some(manifest) = manifests.get(&uri) else { return Ok(None) };
 
-        if let Some(name) = dependencies
-            .iter()
-            .find(|d| d.name.contains_pos(pos))
-            .and_then(|d| {
-                matches!(d.kind, parse::Kind::Registry).then_some(&d.name.value)
-            })
-            && self.registry.is_availabe(name).await
-            && let Ok(uri) = Url::parse(&format!("{DOCS_RS_URL}/{name}"))
+        let mode = self.goto_definition_mode().await;
+        if mode == GotoDefinition::Disabled {
+            return Ok(None);
+        }
+
+        if let Some(Context::DependencyName(dependency)) =
+            Context::resolve(manifest, pos)
+            && dependency.is_crates_io()
+            && let Ok(info) = self.registry.fetch(dependency.crate_name()).await
         {
-            // The prefered method to tell the client to open a page in the
-            // browser is returning here a `GotoDefinitionResponse::Scalar`
-            // with a HTTP link. But because helix does not support this at
-            // time of writing, we'll use this for now.
-            let params = ShowDocumentParams {
-                uri,
-                external: Some(true),
-                take_focus: None,
-                selection: None,
+            // The version that cargo would resolve to; if the latest version
+            // doesn't match the requirement we can only guess it's the
+            // lowest matching one.
+            let req = dependency
+                .version
+                .as_ref()
+                .and_then(|v| v.value.req.as_ref());
+            let version = match req {
+                Some(req) if !req.matches(&info.version) => {
+                    version::lower_bound(req).unwrap_or(info.version.clone())
+                }
+                _ => info.version.clone(),
             };
-            let was_shown = self.client.show_document(params).await;
 
-            if matches!(was_shown, Ok(true)) {
-                let msg = format!("opened docs for `{name}` in your browser");
-                self.client.show_message(MessageType::INFO, msg).await;
+            if let Some(url) = info.source_url(&version)
+                && let Ok(uri) = Url::parse(&url)
+            {
+                let name = dependency.crate_name();
+                let what = self.messages().await.get(
+                    "source-of",
+                    &[("name", &name), ("version", &version)],
+                );
+                return Ok(self.goto_url(mode, uri, &what).await);
             }
         }
 
@@ -419,79 +2895,663 @@ impl LanguageServer for Backend {
         &self,
         params: CodeActionParams,
     ) -> jsonrpc::Result<Option<CodeActionResponse>> {
+        self.record("textDocument/codeAction").await;
         let uri = params.text_document.uri;
         let Range { start, end } = params.range;
+        if is_lockfile(&uri) {
+            #[cfg(not(target_arch = "wasm32"))]
+            return Ok(self.lockfile_actions(&uri, start).await);
+            // `cargo update` can't run in the browser.
+            #[cfg(target_arch = "wasm32")]
+            return Ok(None);
+        }
         let manifests = self.manifests.read().await;
-        let Some(dependencies) = manifests.get(&uri) else { return Ok(None) };
+        let Some(manifest) = manifests.get(&uri) else { return Ok(None) };
+        // The actions of everything at the range (e.g. the header of the
+        // workspace's dependencies and the key of one of them).
+        let mut actions = Vec::new();
 
-        let dependency = dependencies.iter().find(|d| {
-            d.version
-                .as_ref()
-                .is_some_and(|v| v.contains_pos(start) || v.contains_pos(end))
+        // Replace an unknown required feature with a similarly named one.
+        let unknown_feature = manifest
+            .targets
+            .iter()
+            .flat_map(|t| &t.required_features)
+            .find(|f| f.range.start <= start && end <= f.range.end)
+            .filter(|f| !manifest.has_feature(&f.value));
+        if let Some(feature) = unknown_feature
+            && let Some(suggestion) =
+                target::suggest(&feature.value, manifest.feature_names())
+        {
+            let edit = edit::replace_string(feature.range, suggestion);
+            let action = CodeAction {
+                title: self
+                    .messages()
+                    .await
+                    .get("replace-with", &[("suggestion", &suggestion)]),
+                kind: Some(CodeActionKind::QUICKFIX),
+                edit: Some(WorkspaceEdit::new(HashMap::from([(
+                    uri.clone(),
+                    vec![edit],
+                )]))),
+                is_preferred: Some(true),
+                ..CodeAction::default()
+            };
+            actions.push(CodeActionOrCommand::CodeAction(action));
+        }
+
+        let doc = self.documents.read().await.get(&uri).map(Rope::to_string);
+
+        // Move the dependencies that the members declare identically into
+        // the workspace, from the `[workspace]` (or `[workspace.dependencies]`)
+        // header of the root.
+        let header = doc.as_deref().and_then(|doc| {
+            let line = doc.lines().nth(start.line as usize)?;
+            parse::table_header(line).map(str::to_owned)
         });
+        if matches!(
+            header.as_deref(),
+            Some("workspace" | "workspace.dependencies")
+        ) && let Some(action) = self.centralize_action(&uri).await
+        {
+            actions.push(CodeActionOrCommand::CodeAction(action));
+        }
+
+        // Upgrade all of the dependencies of the manifest at once, from the
+        // header of a dependencies table.
+        let edits = match doc {
+            Some(doc) if DependenciesHeader::resolve(&doc, start).is_some() => {
+                self.breaking_upgrades(&uri, manifest)
+                    .await
+                    .into_iter()
+                    .map(|(_, edit)| edit)
+                    .collect::<Vec<_>>()
+            }
+            _ => Vec::new(),
+        };
+        if !edits.is_empty() {
+            let action = CodeAction {
+                title: self
+                    .messages()
+                    .await
+                    .get("upgrade-all", &[("count", &edits.len())]),
+                kind: Some(CodeActionKind::REFACTOR),
+                edit: Some(WorkspaceEdit::new(HashMap::from([(
+                    uri.clone(),
+                    edits,
+                )]))),
+                ..CodeAction::default()
+            };
+            actions.push(CodeActionOrCommand::CodeAction(action));
+        }
 
-        if dependency.is_some_and(|d| !matches!(d.kind, parse::Kind::Registry))
+        // Rename a dependency to the canonical name of its crate, i.e. its
+        // package if it's renamed, as the code uses its key.
+        let named = [start, end].into_iter().find_map(|pos| {
+            match Context::resolve(manifest, pos)? {
+                Context::DependencyName(dependency)
+                | Context::PackageValue(dependency) => Some(dependency),
+                _ => None,
+            }
+        });
+        if let Some(dependency) = named.filter(|d| d.is_crates_io())
+            && let Ok(info) = self.registry.fetch(dependency.crate_name()).await
+            && info.name != dependency.crate_name()
         {
-            return Ok(None);
+            let edit = match &dependency.package {
+                Some(package) => {
+                    edit::replace_string(package.range, &info.name)
+                }
+                None => TextEdit::new(dependency.name.range, info.name.clone()),
+            };
+            let action = CodeAction {
+                title: self
+                    .messages()
+                    .await
+                    .get("rename-to", &[("name", &info.name)]),
+                kind: Some(CodeActionKind::QUICKFIX),
+                edit: Some(WorkspaceEdit::new(HashMap::from([(
+                    uri.clone(),
+                    vec![edit],
+                )]))),
+                is_preferred: Some(true),
+                ..CodeAction::default()
+            };
+            actions.push(CodeActionOrCommand::CodeAction(action));
         }
 
-        if let Some(dependency) = dependency
-            && let Ok(latest) =
-                self.registry.fetch(&dependency.name.value).await
+        // Remove a feature that is enabled anyway.
+        let feature = [start, end].into_iter().find_map(|pos| {
+            match Context::resolve(manifest, pos)? {
+                Context::FeatureString { dependency, index } => {
+                    Some((dependency, index))
+                }
+                _ => None,
+            }
+        });
+        if let Some((dependency, index)) =
+            feature.filter(|(d, _)| d.is_crates_io())
+            && let Ok(info) = self.registry.fetch(dependency.crate_name()).await
+            && let Some((_, redundancy)) =
+                features::redundant(dependency, &info)
+                    .into_iter()
+                    .find(|(i, _)| *i == index)
+            && let Some(features) = &dependency.features
+            && let Some(edit) = edit::remove_item(
+                &features.iter().map(|f| f.range).collect::<Vec<_>>(),
+                index,
+            )
         {
-            let current_version =
-                dependency.version.as_ref().and_then(|v| v.value.as_ref());
-            // We don't want to suggest the LATEST_VERSION code action if
-            // the user already uses the latest version in their manifest.
-            if current_version.is_none_or(|v| !v.matches(&latest.version)) {
-                let command = CodeActionOrCommand::Command(Command::new(
-                    "Latest version".to_owned(),
-                    code_action::LATEST_VERSION.to_owned(),
-                    Some(vec![
-                        serde_json::Value::String(
-                            dependency.name.value.to_owned(),
-                        ),
-                        serde_json::Value::String(uri.into()),
-                    ]),
-                ));
-                return Ok(Some(vec![command]));
+            let messages = self.messages().await;
+            let feature = &features[index].value;
+            let title = match redundancy {
+                Redundancy::Default => {
+                    messages.get("remove-feature", &[("feature", feature)])
+                }
+                Redundancy::ImpliedBy(by) => messages.get(
+                    "remove-implied-feature",
+                    &[("feature", feature), ("by", &by)],
+                ),
+            };
+            let action = CodeAction {
+                title,
+                kind: Some(CodeActionKind::QUICKFIX),
+                edit: Some(WorkspaceEdit::new(HashMap::from([(
+                    uri.clone(),
+                    vec![edit],
+                )]))),
+                is_preferred: Some(true),
+                ..CodeAction::default()
+            };
+            actions.push(CodeActionOrCommand::CodeAction(action));
+        }
+
+        let dependency = [start, end].into_iter().find_map(|pos| {
+            match Context::resolve(manifest, pos)? {
+                Context::VersionValue(dependency)
+                | Context::DependencyKey(dependency) => Some(dependency),
+                _ => None,
+            }
+        });
+
+        if let Some(dependency) = dependency {
+            let messages = self.messages().await;
+            actions.extend(rewrite_actions(&uri, dependency, messages));
+            if dependency.is_crates_io() {
+                #[cfg(not(target_arch = "wasm32"))]
+                actions.extend(self.lock_actions(&uri, dependency).await);
+                actions.extend(self.update_actions(uri, dependency).await);
             }
         }
 
-        Ok(None)
+        Ok((!actions.is_empty()).then_some(actions))
+    }
+
+    async fn code_lens(
+        &self,
+        params: CodeLensParams,
+    ) -> jsonrpc::Result<Option<Vec<CodeLens>>> {
+        self.record("textDocument/codeLens").await;
+        let uri = params.text_document.uri;
+        let doc = self.documents.read().await.get(&uri).map(Rope::to_string);
+        let Some(doc) = doc else { return Ok(None) };
+        let manifests = self.manifests.read().await;
+        let Some(manifest) = manifests.get(&uri) else { return Ok(None) };
+
+        let messages = self.messages().await;
+        let mut lenses = Vec::new();
+        for header in DependenciesHeader::all(&doc) {
+            let Some(section) = header.section() else { continue };
+            let args =
+                TableArgs { uri: uri.clone(), table: header.table.clone() };
+
+            let dependencies = manifest
+                .dependencies
+                .iter()
+                .filter(|d| d.section == section)
+                .collect::<Vec<_>>();
+            let outdated = self.outdated(&dependencies).await.0.len();
+            if outdated > 0 {
+                let command = Command::UpdateTable(args.clone());
+                let title =
+                    messages.get("update-table", &[("count", &outdated)]);
+                lenses.push(CodeLens {
+                    range: header.range,
+                    command: Some(command.into_lsp(title)),
+                    data: None,
+                });
+            }
+            if !header.is_sorted() {
+                let command = Command::SortTable(args);
+                let title = messages.get("sort-table", &[]);
+                lenses.push(CodeLens {
+                    range: header.range,
+                    command: Some(command.into_lsp(title)),
+                    data: None,
+                });
+            }
+        }
+
+        Ok(Some(lenses))
     }
 
     async fn execute_command(
         &self,
         params: ExecuteCommandParams,
     ) -> jsonrpc::Result<Option<serde_json::Value>> {
-        if params.command == code_action::LATEST_VERSION
-            && let Some(serde_json::Value::String(name)) =
-                params.arguments.first()
-            && let Some(serde_json::Value::String(uri)) =
-                params.arguments.get(1)
-            && let Ok(uri) = Url::parse(uri)
-            && let Some(range) =
-                self.manifests.read().await.get(&uri).and_then(|deps| {
-                    deps.iter()
-                        .find(|d| &d.name.value == name)
-                        .and_then(|d| d.version.as_ref().map(|v| v.range))
-                })
-            && let Ok(latest) = self.registry.fetch(name).await
-        {
-            let change =
-                TextEdit::new(range, format!("\"{}\"", latest.version));
-            let changes = WorkspaceEdit::new(
-                std::iter::once((uri, vec![change])).collect(),
-            );
-            let _ = self.client.apply_edit(changes).await;
-            Ok(None)
-        } else {
-            Err(jsonrpc::Error::invalid_request())
+        self.record("workspace/executeCommand").await;
+        let result = match Command::parse(&params.command, params.arguments) {
+            Ok(Command::LatestVersion(args)) => self.latest_version(args).await,
+            Ok(Command::CacheStats) => self.cache_stats().await,
+            Ok(Command::CacheClear) => self.cache_clear().await,
+            Ok(Command::UpgradeWorkspace(args)) => {
+                self.upgrade_workspace(args).await
+            }
+            Ok(Command::UpdateTable(args)) => self.update_table(args).await,
+            Ok(Command::SortTable(args)) => self.sort_table(args).await,
+            Ok(Command::UpdateLock(args)) => self.update_lock(args).await,
+            Ok(Command::CopyDependencySpec(args)) => {
+                self.copy_dependency_spec(args).await
+            }
+            Err(err) => Err(err),
+        };
+
+        if let Err(err) = &result {
+            // Commands are usually invoked by the user, so they should know
+            // why nothing happened.
+            let msg = self
+                .messages()
+                .await
+                .get("command-failed", &[("error", err)]);
+            self.client.show_message(MessageType::ERROR, msg).await;
         }
+
+        result.map_err(jsonrpc::Error::from)
     }
 
     async fn shutdown(&self) -> jsonrpc::Result<()> {
+        self.lifecycle.shut_down.store(true, Ordering::Relaxed);
+        self.registry.cancel();
         Ok(())
     }
 }
+
+/// The char index of a position (whose character counts UTF-16 code units)
+/// in a document, clamped to the end of its line.
+fn rope_idx(doc: &Rope, pos: Position) -> usize {
+    let line = (pos.line as usize).min(doc.len_lines() - 1);
+    let start = doc.line_to_char(line);
+    // the end of the line, before its line break.
+    let mut end = start + doc.line(line).len_chars();
+    while end > start && matches!(doc.char(end - 1), '\n' | '\r') {
+        end -= 1;
+    }
+    let units = doc.char_to_utf16_cu(start) + pos.character as usize;
+    doc.utf16_cu_to_char(units.min(doc.char_to_utf16_cu(end)))
+}
+
+/// The content of the manifest at `uri`: the open document (with its unsaved
+/// changes), or else the file.
+fn read_manifest(documents: &HashMap<Url, Rope>, uri: &Url) -> Option<String> {
+    match documents.get(uri) {
+        Some(doc) => Some(doc.to_string()),
+        None => std::fs::read_to_string(path::to_path(uri)?).ok(),
+    }
+}
+
+/// A hash of the diagnostics, to tell whether they changed since they were
+/// last published (`Diagnostic` doesn't implement `Hash`).
+fn diagnostics_hash(diags: &[Diagnostic]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_string(diags)
+        .unwrap_or_default()
+        .hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The error of a dependency whose crate couldn't be fetched.
+fn fetch_error_diagnostic(
+    dependency: &Dependency,
+    err: crates::Error,
+    messages: Catalog,
+) -> Diagnostic {
+    let registry = dependency.alternative_registry();
+    let message = match (err, registry) {
+        (crates::Error::Unauthorized { .. }, _) => messages.get(
+            "registry-unauthorized",
+            &[("registry", &registry.unwrap_or("crates.io"))],
+        ),
+        (crates::Error::GitIndex { .. }, Some(registry)) => {
+            messages.get("git-index-failed", &[("registry", &registry)])
+        }
+        (crates::Error::NoDiskCache { .. }, Some(registry)) => {
+            messages.get("git-index-no-disk-cache", &[("registry", &registry)])
+        }
+        (_, Some(registry)) => {
+            messages.get("no-such-crate-in", &[("registry", &registry)])
+        }
+        (_, None) => messages.get("no-such-crate", &[]),
+    };
+    Diagnostic {
+        range: dependency.name.range,
+        severity: Some(DiagnosticSeverity::ERROR),
+        code: None,
+        code_description: None,
+        source: None,
+        message,
+        related_information: None,
+        tags: None,
+        data: None,
+    }
+}
+
+/// Parse the document at `uri`, if it's a manifest (or `forceEnable` is set),
+/// with the publish lints if they're enabled.
+pub(crate) fn parse_manifest(
+    uri: &Url,
+    doc: &str,
+    settings: &Settings,
+    root: Option<&workspace::Root>,
+    files: Option<&publish::Files>,
+) -> Option<Manifest> {
+    let dom = taplo::parser::parse(doc).into_dom();
+
+    if !settings.force_enable && !parse::is_manifest(uri, &dom) {
+        return None;
+    }
+
+    let mut manifest = Manifest::parse(doc, &dom);
+    if settings.publish_lints {
+        let workspace = root.map(workspace::Root::package);
+        manifest.publish_lints =
+            publish::lint(&dom, doc, uri, workspace.as_ref(), files);
+    }
+    Some(manifest)
+}
+
+/// The diagnostics of a manifest that don't depend on the registry (nor on
+/// the file system), grouped by their kind.
+pub(crate) fn manifest_diagnostics(
+    manifest: &Manifest,
+    messages: Catalog,
+) -> Vec<Vec<Diagnostic>> {
+    vec![
+        manifest
+            .errors
+            .iter()
+            .map(|err| Diagnostic {
+                range: err.range(),
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: None,
+                code_description: None,
+                source: None,
+                message: err.message(messages),
+                related_information: None,
+                tags: None,
+                data: None,
+            })
+            .collect(),
+        manifest
+            .metadata_warnings
+            .iter()
+            .map(|warning| Diagnostic {
+                range: warning.range(),
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: None,
+                code_description: None,
+                source: None,
+                message: warning.message(messages),
+                related_information: None,
+                tags: None,
+                data: None,
+            })
+            .collect(),
+        manifest
+            .publish_lints
+            .iter()
+            .map(|lint| Diagnostic {
+                range: lint.range(),
+                severity: Some(lint.severity()),
+                code: None,
+                code_description: None,
+                source: None,
+                message: lint.message(messages),
+                related_information: None,
+                tags: None,
+                data: None,
+            })
+            .collect(),
+        manifest
+            .profiles
+            .warnings
+            .iter()
+            .map(|warning| Diagnostic {
+                range: warning.range(),
+                severity: Some(warning.severity()),
+                code: None,
+                code_description: None,
+                source: None,
+                message: warning.message(messages),
+                related_information: None,
+                tags: None,
+                data: None,
+            })
+            .collect(),
+        manifest
+            .lint_warnings
+            .iter()
+            .map(|warning| Diagnostic {
+                range: warning.range(),
+                severity: Some(warning.severity()),
+                code: None,
+                code_description: None,
+                source: None,
+                message: warning.message(messages),
+                related_information: None,
+                tags: None,
+                data: None,
+            })
+            .collect(),
+        manifest
+            .resolver_warnings
+            .iter()
+            .map(|warning| Diagnostic {
+                range: warning.range(),
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: None,
+                code_description: Url::parse(resolver::DOCS_URL)
+                    .ok()
+                    .map(|href| CodeDescription { href }),
+                source: None,
+                message: warning.message(messages),
+                related_information: None,
+                tags: None,
+                data: None,
+            })
+            .collect(),
+        manifest
+            .target_errors
+            .iter()
+            .map(|err| Diagnostic {
+                range: err.range(),
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: None,
+                code_description: None,
+                source: None,
+                message: err.message(messages),
+                related_information: None,
+                tags: None,
+                data: None,
+            })
+            .collect(),
+    ]
+}
+
+/// Whether the document at `uri` is a lockfile (whose `[[package]]` entries
+/// get hovers and code actions).
+fn is_lockfile(uri: &Url) -> bool {
+    path::file_name(uri).is_some_and(|name| name == lockfile::LOCKFILE_NAME)
+}
+
+/// How far the version that the lockfile pins a dependency to (out of the
+/// `locked` versions of its crate) is behind its latest compatible release.
+fn lockfile_lag(
+    dependency: &Dependency,
+    locked: &[semver::Version],
+    latest: &crates::CrateInfo,
+) -> Option<lockfile::Lag> {
+    let req = dependency.version.as_ref()?.value.req.as_ref()?;
+    lockfile::Lag::new(locked, req, latest.versions())
+}
+
+/// The package spec of a locked version of a crate for `cargo update -p`,
+/// which needs the version when several versions of the crate are `locked`.
+fn package_spec(
+    name: &str,
+    version: &semver::Version,
+    locked: &[semver::Version],
+) -> String {
+    match locked {
+        [_] => name.to_owned(),
+        _ => format!("{name}@{version}"),
+    }
+}
+
+/// The code action that runs `cargo update -p` for a locked `package` (in the
+/// workspace of the manifest or lockfile at `uri`).
+#[cfg(not(target_arch = "wasm32"))]
+fn update_lock_action(
+    uri: &Url,
+    package: String,
+    lag: &lockfile::Lag,
+    messages: Catalog,
+) -> CodeActionOrCommand {
+    let title = messages.get(
+        "update-lock",
+        &[("version", &lag.latest), ("package", &package)],
+    );
+    let command =
+        Command::UpdateLock(UpdateLockArgs { uri: uri.clone(), package });
+    CodeActionOrCommand::CodeAction(CodeAction {
+        title: title.clone(),
+        kind: Some(CodeActionKind::QUICKFIX),
+        command: Some(command.into_lsp(title)),
+        ..CodeAction::default()
+    })
+}
+
+/// The code actions that rewrite the version requirement of a dependency in
+/// a simpler form, or in plain comparisons (that show its upper bound).
+fn rewrite_actions(
+    uri: &Url,
+    dependency: &Dependency,
+    messages: Catalog,
+) -> Vec<CodeActionOrCommand> {
+    let Some(version) = &dependency.version else { return Vec::new() };
+    let raw = &version.value.raw;
+
+    [
+        (
+            version::normalize(raw),
+            CodeActionKind::QUICKFIX,
+            "normalize-to",
+        ),
+        (
+            version::expand(raw),
+            CodeActionKind::REFACTOR_REWRITE,
+            "rewrite-as",
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(req, kind, id)| {
+        let req = req?;
+        let edit = edit::replace_version(dependency, &req)?;
+        Some(CodeActionOrCommand::CodeAction(CodeAction {
+            title: messages.get(id, &[("requirement", &req)]),
+            kind: Some(kind),
+            edit: Some(WorkspaceEdit::new(HashMap::from([(
+                uri.clone(),
+                vec![edit],
+            )]))),
+            ..CodeAction::default()
+        }))
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::{
+        TextDocumentIdentifier, TextDocumentPositionParams,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_renamed_dependencies() {
+        let (service, _socket) = service();
+        let backend = service.inner();
+        backend.registry.set_api_enabled(false);
+        backend
+            .registry
+            .insert_index(
+                "serde_json",
+                r#"{"name":"serde_json","vers":"1.0.0","deps":[],"cksum":"","features":{"std":[]},"yanked":false}"#,
+            )
+            .await;
+        backend
+            .registry
+            .insert_index(
+                "rand",
+                r#"{"name":"rand","vers":"0.7.3","deps":[],"cksum":"","features":{},"yanked":false}"#,
+            )
+            .await;
+
+        let uri = Url::parse("file:///crates-ls/renamed/Cargo.toml").unwrap();
+        let doc = indoc::indoc! {r#"
+            [dependencies]
+            json = { package = "serde_json", version = "0.9", features = ["std"] }
+            rand07 = { package = "rand", version = "0.7" }
+        "#};
+        backend
+            .documents
+            .write()
+            .await
+            .insert(uri.clone(), Rope::from_str(doc));
+        backend.update_manifest(uri.clone()).await;
+
+        // the crates are looked up by their packages, rather than by their
+        // keys.
+        let manifests = backend.manifests.read().await;
+        let dependencies = &manifests[&uri].dependencies;
+        let diagnostics =
+            |i| backend.generate_diagnostics(&uri, &dependencies[i]);
+        let (_, json) = diagnostics(0).await.unwrap();
+        let messages =
+            json.iter().map(|d| d.message.as_str()).collect::<Vec<_>>();
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].contains("1.0.0"), "{messages:?}");
+        let (_, rand) = diagnostics(1).await.unwrap();
+        assert!(rand.is_empty(), "{rand:?}");
+        drop(manifests);
+
+        let hover = backend
+            .resolve_hover(HoverParams {
+                text_document_position_params: TextDocumentPositionParams {
+                    text_document: TextDocumentIdentifier::new(uri),
+                    position: Position::new(1, 1),
+                },
+                work_done_progress_params: Default::default(),
+            })
+            .await
+            .unwrap();
+        let Some(HoverContents::Markup(hover)) = hover.map(|h| h.contents)
+        else {
+            panic!("no hover of `json`");
+        };
+        assert!(
+            hover.value.starts_with("serde_json: 1.0.0"),
+            "{}",
+            hover.value
+        );
+    }
+}
@@ -1,10 +1,26 @@
-use crates_language_server::ls;
-use tower_lsp::{LspService, Server};
-
+#[cfg(not(target_arch = "wasm32"))]
 #[tokio::main]
 async fn main() {
+    use crates_language_server::{ls, panic::CatchPanic};
+    use tower_lsp::Server;
+
     let (stdin, stdout) = (tokio::io::stdin(), tokio::io::stdout());
 
-    let (service, socket) = LspService::new(ls::Backend::new);
-    Server::new(stdin, stdout, socket).serve(service).await;
+    let (service, socket) = ls::service();
+    let lifecycle = service.inner().lifecycle();
+    let socket = lifecycle.watch(socket);
+    tokio::select! {
+        () = Server::new(stdin, stdout, socket)
+            .serve(CatchPanic::new(service)) => {}
+        () = lifecycle.exited() => {}
+    }
+
+    // Exit right away, rather than waiting for the runtime's blocking read
+    // of stdin, which never returns if the client keeps it open.
+    std::process::exit(lifecycle.exit_code());
 }
+
+// On WASM, the embedder (e.g. a web worker) serves `ls::service()` over its own
+// transport.
+#[cfg(target_arch = "wasm32")]
+fn main() {}
@@ -0,0 +1,230 @@
+//! Rendering the (Markdown) documentation of the hovers and completions in
+//! the format the client prefers.
+//!
+//! The documentation is only ever written in Markdown, and the plain text
+//! variant is derived from it, so the two never drift apart. The plain text
+//! that's part of it (e.g. the descriptions of crates) is [escaped](escape).
+
+use tower_lsp::lsp_types::{
+    CompletionItem, CompletionResponse, Documentation, Hover, HoverContents,
+    MarkupContent, MarkupKind,
+};
+
+/// The format to render in, out of the formats the client declared (in the
+/// order of its preference). Clients that didn't declare any get Markdown.
+pub fn preferred(formats: Option<&[MarkupKind]>) -> MarkupKind {
+    formats
+        .and_then(|formats| formats.first())
+        .cloned()
+        .unwrap_or(MarkupKind::Markdown)
+}
+
+pub fn content(kind: MarkupKind, markdown: String) -> MarkupContent {
+    let value = match kind {
+        MarkupKind::Markdown => markdown,
+        MarkupKind::PlainText => to_plain_text(&markdown),
+    };
+    MarkupContent { kind, value }
+}
+
+/// Render the Markdown contents of a hover in `kind`.
+pub fn hover(hover: Hover, kind: MarkupKind) -> Hover {
+    let contents = match hover.contents {
+        HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }) => HoverContents::Markup(content(kind, value)),
+        contents => contents,
+    };
+    Hover { contents, ..hover }
+}
+
+/// Render the (Markdown) documentation of completion items in `kind`.
+pub fn completions(
+    response: CompletionResponse,
+    kind: MarkupKind,
+) -> CompletionResponse {
+    let render = |mut items: Vec<CompletionItem>| {
+        for item in &mut items {
+            if let Some(Documentation::String(markdown)) =
+                item.documentation.take()
+            {
+                let content = content(kind.clone(), markdown);
+                item.documentation = Some(match content.kind {
+                    MarkupKind::Markdown => {
+                        Documentation::MarkupContent(content)
+                    }
+                    MarkupKind::PlainText => {
+                        Documentation::String(content.value)
+                    }
+                });
+            }
+        }
+        items
+    };
+
+    match response {
+        CompletionResponse::Array(items) => {
+            CompletionResponse::Array(render(items))
+        }
+        CompletionResponse::List(mut list) => {
+            list.items = render(list.items);
+            CompletionResponse::List(list)
+        }
+    }
+}
+
+/// The ASCII punctuation that Markdown might treat as syntax (e.g. `-` and
+/// `1.` as list items, at the start of a line).
+const SYNTAX: &[char] =
+    &['\\', '`', '*', '_', '[', ']', '#', '+', '-', '.', '<', '>', '|', '~'];
+
+/// Escape plain text (e.g. the description of a crate) for Markdown, so it's
+/// shown as it is, e.g. `*nix` isn't emphasized.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if SYNTAX.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Strip the Markdown of the documentation: the rules (`---`) are dropped,
+/// and the headings, emphasized lines, inline code and links are left as
+/// their text (links followed by their URL), and the escaped characters as
+/// they are.
+pub fn to_plain_text(markdown: &str) -> String {
+    let lines = markdown
+        .lines()
+        .filter(|line| line.trim() != "---")
+        .map(|line| {
+            let line = line.trim_start_matches('#').trim_start();
+            let line = match line
+                .strip_prefix('*')
+                .and_then(|l| l.strip_suffix('*'))
+            {
+                Some(emphasized) if !emphasized.is_empty() => emphasized,
+                _ => line,
+            };
+            unescape(&strip_links(line))
+        })
+        .collect::<Vec<_>>();
+
+    // dropping the rules leaves the blank lines around them.
+    let mut text = lines.join("\n");
+    while text.contains("\n\n\n") {
+        text = text.replace("\n\n\n", "\n\n");
+    }
+    text.trim().to_owned()
+}
+
+/// Drop the backticks of inline code, and the backslashes of escaped
+/// characters.
+fn unescape(line: &str) -> String {
+    let mut text = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                // any ASCII punctuation can be escaped.
+                Some(next) if next.is_ascii_punctuation() => text.push(next),
+                Some(next) => text.extend([c, next]),
+                None => text.push(c),
+            },
+            '`' => {}
+            c => text.push(c),
+        }
+    }
+    text
+}
+
+/// Replace the `[text](url)` links of a line with `text (url)`, leaving the
+/// escaped brackets as they are.
+fn strip_links(line: &str) -> String {
+    let mut text = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(open) = rest.find('[') {
+        if rest[..open].ends_with('\\') {
+            text.push_str(&rest[..=open]);
+            rest = &rest[open + 1..];
+            continue;
+        }
+        let link =
+            rest[open + 1..].split_once("](").and_then(|(title, url)| {
+                let (url, after) = url.split_once(')')?;
+                Some((title, url, after))
+            });
+        let Some((title, url, after)) =
+            link.filter(|(title, ..)| !title.contains('['))
+        else {
+            text.push_str(&rest[..=open]);
+            rest = &rest[open + 1..];
+            continue;
+        };
+
+        text.push_str(&rest[..open]);
+        if title == url {
+            text.push_str(url);
+        } else {
+            text.push_str(&format!("{title} ({url})"));
+        }
+        rest = after;
+    }
+    text.push_str(rest);
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preferred() {
+        assert_eq!(preferred(None), MarkupKind::Markdown);
+        assert_eq!(preferred(Some(&[])), MarkupKind::Markdown);
+        assert_eq!(
+            preferred(Some(&[MarkupKind::PlainText, MarkupKind::Markdown])),
+            MarkupKind::PlainText
+        );
+    }
+
+    #[test]
+    fn test_to_plain_text() {
+        let markdown = "serde: 1.0.219\n\n*an optional dependency*\n\n\
+                        [docs](https://docs.rs/serde) · \
+                        [repository](https://github.com/serde-rs/serde)\n\n\
+                        ---\n\nA `serde` framework\n\n---\n\n\
+                        ## Available Features\n\n[ derive, std ]";
+        assert_eq!(
+            to_plain_text(markdown),
+            "serde: 1.0.219\n\nan optional dependency\n\n\
+             docs (https://docs.rs/serde) · \
+             repository (https://github.com/serde-rs/serde)\n\n\
+             A serde framework\n\nAvailable Features\n\n[ derive, std ]"
+        );
+    }
+
+    #[test]
+    fn test_to_plain_text_keeps_code() {
+        assert_eq!(
+            to_plain_text("`[profile.dev.package.\"*\"]` or `1.*`"),
+            "[profile.dev.package.\"*\"] or 1.*"
+        );
+        assert_eq!(to_plain_text("[https://a.b](https://a.b)"), "https://a.b");
+    }
+
+    #[test]
+    fn test_escape() {
+        let description = "*nix [tools] for `cargo` #1 - 100% <safe>_\\";
+        assert_eq!(
+            escape(description),
+            "\\*nix \\[tools\\] for \\`cargo\\` \\#1 \\- 100% \
+             \\<safe\\>\\_\\\\"
+        );
+        assert_eq!(to_plain_text(&escape(description)), description);
+        assert_eq!(to_plain_text(&format!("---\n\n{}", escape("*a*"))), "*a*");
+    }
+}
@@ -0,0 +1,125 @@
+//! The catalogs of the messages the server shows the user (diagnostics,
+//! hovers, the titles of code actions and lenses, and `window/showMessage`s),
+//! so they can be translated. The docs of the manifest keys and lints (from
+//! `data`), and the details of completions, are only in English.
+//!
+//! A catalog is a `data/messages/<locale>.tsv` file of `id\ttext` lines,
+//! where the text refers to the arguments of the message as `{name}`. To add
+//! a translation, add its file to [`CATALOGS`]; the messages it doesn't
+//! translate fall back to English.
+
+use std::{collections::HashMap, fmt::Display, sync::LazyLock};
+
+/// The bundled catalogs, by their (lowercase) locale tags.
+const CATALOGS: &[(&str, &str)] =
+    &[("en", include_str!("../data/messages/en.tsv"))];
+
+const FALLBACK: &str = "en";
+
+type Messages = HashMap<&'static str, &'static str>;
+
+static PARSED: LazyLock<HashMap<&str, Messages>> = LazyLock::new(|| {
+    CATALOGS
+        .iter()
+        .map(|&(locale, catalog)| (locale, parse_catalog(catalog)))
+        .collect()
+});
+
+fn parse_catalog(catalog: &'static str) -> Messages {
+    catalog
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .collect()
+}
+
+/// The messages of a locale.
+#[derive(Debug, Clone, Copy)]
+pub struct Catalog {
+    messages: &'static Messages,
+}
+
+impl Catalog {
+    /// The catalog of a locale tag (e.g. `en-US`, as the client sends it),
+    /// falling back to the catalog of its language (`en`), and then to
+    /// English.
+    pub fn for_locale(tag: Option<&str>) -> Self {
+        let tag = tag
+            .unwrap_or(FALLBACK)
+            .to_ascii_lowercase()
+            .replace('_', "-");
+        let language = tag.split('-').next().unwrap_or_default();
+        let messages = PARSED
+            .get(tag.as_str())
+            .or_else(|| PARSED.get(language))
+            .unwrap_or(&PARSED[FALLBACK]);
+        Self { messages }
+    }
+
+    /// The text of the message `id`, with its `{name}` arguments filled in.
+    pub fn get(self, id: &str, args: &[(&str, &dyn Display)]) -> String {
+        let text = self
+            .messages
+            .get(id)
+            .or_else(|| PARSED[FALLBACK].get(id))
+            .copied()
+            .unwrap_or(id);
+
+        args.iter().fold(text.to_owned(), |text, (name, value)| {
+            text.replace(&format!("{{{name}}}"), &value.to_string())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn placeholders(text: &str) -> Vec<&str> {
+        let mut names = text
+            .split('{')
+            .skip(1)
+            .filter_map(|s| s.split_once('}').map(|(name, _)| name))
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        names
+    }
+
+    #[test]
+    fn test_get() {
+        let catalog = Catalog::for_locale(Some("en-US"));
+        assert_eq!(
+            catalog.get("rename-to", &[("name", &"serde_json")]),
+            "Rename to `serde_json`"
+        );
+        assert_eq!(
+            catalog.get("upgrade-all", &[("count", &3)]),
+            "Upgrade all breaking dependencies (3)"
+        );
+        // unknown ids are shown as they are, rather than dropped.
+        assert_eq!(catalog.get("no-such-message", &[]), "no-such-message");
+    }
+
+    #[test]
+    fn test_for_locale_falls_back_to_english() {
+        for tag in [None, Some("fr"), Some("EN_gb"), Some("")] {
+            assert_eq!(Catalog::for_locale(tag).get("sort-table", &[]), "Sort");
+        }
+    }
+
+    #[test]
+    fn test_translations_match_english() {
+        let english = &PARSED[FALLBACK];
+        for (locale, messages) in PARSED.iter() {
+            for (id, text) in messages {
+                let original = english.get(id).unwrap_or_else(|| {
+                    panic!("`{id}` of `{locale}` isn't an English message")
+                });
+                assert_eq!(
+                    placeholders(text),
+                    placeholders(original),
+                    "the arguments of `{id}` of `{locale}`"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,447 @@
+//! The well-known `[package.metadata.*]` tables of tools that read them at
+//! (or after) publish time, e.g. docs.rs and cargo-binstall.
+//!
+//! Cargo ignores these tables entirely, and so do the tools when the keys are
+//! misspelled or have the wrong type, so mistakes only show up after
+//! publishing (e.g. as docs that were built without the right features).
+
+use taplo::dom::Node;
+use tower_lsp::lsp_types::Range;
+
+use crate::{
+    messages::Catalog,
+    parse::{self, ValueType},
+};
+
+#[derive(Debug)]
+pub enum Warning {
+    UnknownKey {
+        key: String,
+        tool: &'static str,
+        range: Range,
+    },
+    InvalidType {
+        key: String,
+        expected: &'static str,
+        tool: &'static str,
+        range: Range,
+    },
+    InvalidValue {
+        value: String,
+        expected: String,
+        range: Range,
+    },
+    UnknownFeature {
+        feature: String,
+        tool: &'static str,
+        range: Range,
+    },
+    MisspelledTable {
+        tool: &'static str,
+        table: String,
+        range: Range,
+    },
+}
+
+impl Warning {
+    /// The message of the warning, in the locale of `messages`.
+    pub fn message(&self, messages: Catalog) -> String {
+        match self {
+            Self::UnknownKey { key, tool, .. } => messages
+                .get("unknown-metadata-key", &[("key", key), ("tool", tool)]),
+            Self::InvalidType { key, expected, tool, .. } => messages.get(
+                "invalid-metadata-type",
+                &[("key", key), ("expected", expected), ("tool", tool)],
+            ),
+            Self::InvalidValue { value, expected, .. } => messages
+                .get("not-one-of", &[("value", value), ("expected", expected)]),
+            Self::UnknownFeature { feature, tool, .. } => messages.get(
+                "unknown-metadata-feature",
+                &[("feature", feature), ("tool", tool)],
+            ),
+            Self::MisspelledTable { tool, table, .. } => messages.get(
+                "misspelled-metadata-table",
+                &[("tool", tool), ("table", table)],
+            ),
+        }
+    }
+
+    pub fn range(&self) -> Range {
+        match self {
+            Self::UnknownKey { range, .. }
+            | Self::InvalidType { range, .. }
+            | Self::InvalidValue { range, .. }
+            | Self::UnknownFeature { range, .. }
+            | Self::MisspelledTable { range, .. } => *range,
+        }
+    }
+}
+
+/// A key of a metadata table.
+#[derive(Debug)]
+pub struct Key {
+    pub name: &'static str,
+    /// `None` for keys that aren't validated (e.g. nested tables).
+    pub ty: Option<ValueType>,
+    /// The valid values, if the value is one of a fixed set of strings.
+    pub values: &'static [&'static str],
+    /// Whether the value is a list of features of the crate itself.
+    pub is_features: bool,
+    pub description: &'static str,
+}
+
+/// A well-known metadata table.
+#[derive(Debug)]
+pub struct Table {
+    pub tool: &'static str,
+    /// The path of the table under `[package.metadata]`.
+    pub path: &'static [&'static str],
+    pub keys: &'static [Key],
+    /// Whether the tool ignores keys that are not in [`Self::keys`] (rather
+    /// than there being keys we don't know of).
+    pub is_closed: bool,
+    /// Common misspellings of the table's name.
+    pub misspellings: &'static [&'static str],
+}
+
+const fn key(
+    name: &'static str,
+    ty: ValueType,
+    description: &'static str,
+) -> Key {
+    Key { name, ty: Some(ty), values: &[], is_features: false, description }
+}
+
+pub const TABLES: &[Table] = &[
+    Table {
+        tool: "docs.rs",
+        path: &["docs", "rs"],
+        keys: &[
+            Key {
+                name: "features",
+                ty: Some(ValueType::StringArray),
+                values: &[],
+                is_features: true,
+                description: "Features to pass to Cargo.",
+            },
+            key(
+                "all-features",
+                ValueType::Bool,
+                "Whether to pass `--all-features` to Cargo.",
+            ),
+            key(
+                "no-default-features",
+                ValueType::Bool,
+                "Whether to pass `--no-default-features` to Cargo.",
+            ),
+            key(
+                "default-target",
+                ValueType::String,
+                "The target to use for the landing page of the docs.",
+            ),
+            key(
+                "targets",
+                ValueType::StringArray,
+                "The targets to build docs for (overrides the default ones).",
+            ),
+            key(
+                "additional-targets",
+                ValueType::StringArray,
+                "Targets to build docs for, in addition to the default ones.",
+            ),
+            key(
+                "rustc-args",
+                ValueType::StringArray,
+                "Additional flags to pass to `rustc`.",
+            ),
+            key(
+                "rustdoc-args",
+                ValueType::StringArray,
+                "Additional flags to pass to `rustdoc` (e.g. `--cfg docsrs`).",
+            ),
+            key(
+                "cargo-args",
+                ValueType::StringArray,
+                "Additional flags to pass to Cargo.",
+            ),
+        ],
+        is_closed: true,
+        misspellings: &["docs-rs", "docsrs", "docs_rs"],
+    },
+    Table {
+        tool: "cargo-binstall",
+        path: &["binstall"],
+        keys: &[
+            key(
+                "pkg-url",
+                ValueType::String,
+                "A template of the URL of the package to download.",
+            ),
+            key(
+                "bin-dir",
+                ValueType::String,
+                "A template of the path of the binary inside of the package.",
+            ),
+            Key {
+                name: "pkg-fmt",
+                ty: Some(ValueType::String),
+                values: &["tar", "tbz2", "tgz", "txz", "tzstd", "zip", "bin"],
+                is_features: false,
+                description: "The format of the package.",
+            },
+            key(
+                "disabled-strategies",
+                ValueType::StringArray,
+                "Installation strategies to never use for this crate.",
+            ),
+            Key {
+                name: "overrides",
+                ty: None,
+                values: &[],
+                is_features: false,
+                description: "Per target overrides of these keys.",
+            },
+            Key {
+                name: "signing",
+                ty: None,
+                values: &[],
+                is_features: false,
+                description: "How to verify the signatures of the packages.",
+            },
+        ],
+        is_closed: false,
+        misspellings: &["cargo-binstall"],
+    },
+];
+
+const PACKAGE_KEY: &str = "package";
+const METADATA_KEY: &str = "metadata";
+
+/// The well-known table at `path` (which is relative to the root).
+pub fn table(path: &[String]) -> Option<&'static Table> {
+    let [package, metadata, path @ ..] = path else { return None };
+    (package == PACKAGE_KEY && metadata == METADATA_KEY)
+        .then(|| TABLES.iter().find(|t| t.path == path))
+        .flatten()
+}
+
+/// Validate the well-known metadata tables of the manifest.
+///
+/// `has_feature` checks whether a feature can be enabled on the crate (see
+/// [`parse::Manifest::has_feature`]).
+pub fn validate(
+    root: &Node,
+    s: &str,
+    has_feature: impl Fn(&str) -> bool,
+) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    let metadata = root.get(PACKAGE_KEY).get(METADATA_KEY);
+    let Some(entries) = metadata.as_table().map(|t| t.entries().read()) else {
+        return warnings;
+    };
+
+    for (key, _) in entries.iter() {
+        if let Some(table) = TABLES
+            .iter()
+            .find(|t| t.misspellings.contains(&key.value()))
+            && let Some(range) = parse::key_range(key, s)
+        {
+            warnings.push(Warning::MisspelledTable {
+                tool: table.tool,
+                table: table.path.join("."),
+                range,
+            });
+        }
+    }
+
+    for table in TABLES {
+        let node = table.path.iter().fold(metadata.clone(), |n, &k| n.get(k));
+        let Some(node) = node.as_table() else { continue };
+
+        for (key, value) in node.entries().read().iter() {
+            let Some(known) = table.keys.iter().find(|k| k.name == key.value())
+            else {
+                if table.is_closed
+                    && let Some(range) = parse::key_range(key, s)
+                {
+                    warnings.push(Warning::UnknownKey {
+                        key: key.value().to_owned(),
+                        tool: table.tool,
+                        range,
+                    });
+                }
+                continue;
+            };
+            let Some(ty) = known.ty else { continue };
+
+            let invalid_items = match value.as_array() {
+                Some(items) if matches!(ty, ValueType::StringArray) => items
+                    .items()
+                    .read()
+                    .iter()
+                    .filter(|i| i.as_str().is_none())
+                    .cloned()
+                    .collect(),
+                _ if !ty.matches(value) => vec![value.clone()],
+                _ => Vec::new(),
+            };
+            warnings.extend(invalid_items.iter().filter_map(|item| {
+                Some(Warning::InvalidType {
+                    key: key.value().to_owned(),
+                    expected: ty.expected(),
+                    tool: table.tool,
+                    range: parse::node_range(item, s)?,
+                })
+            }));
+
+            if !known.values.is_empty()
+                && let Some(value_str) = value.as_str()
+                && !known.values.contains(&value_str.value())
+                && let Some(range) = parse::node_range(value, s)
+            {
+                let expected = known
+                    .values
+                    .iter()
+                    .map(|v| format!("`{v}`"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                warnings.push(Warning::InvalidValue {
+                    value: value_str.value().to_owned(),
+                    expected,
+                    range,
+                });
+            }
+
+            if known.is_features
+                && let Some(items) = value.as_array()
+            {
+                let items = items.items().read();
+                let unknown = items.iter().filter_map(|item| {
+                    let feature = item.as_str()?.value();
+                    (!has_feature(feature)).then(|| (feature.to_owned(), item))
+                });
+                warnings.extend(unknown.filter_map(|(feature, item)| {
+                    Some(Warning::UnknownFeature {
+                        feature,
+                        tool: table.tool,
+                        range: parse::node_range(item, s)?,
+                    })
+                }));
+            }
+        }
+    }
+
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    fn validate(s: &str) -> Vec<String> {
+        let dom = taplo::parser::parse(s).into_dom();
+        super::validate(&dom, s, |_| true)
+            .iter()
+            .map(|warning| warning.message(Catalog::for_locale(None)))
+            .collect()
+    }
+
+    #[test]
+    fn test_validate() {
+        let s = indoc! {r#"
+            [package]
+            name = "foo"
+
+            [package.metadata.docs.rs]
+            all-features = "yes"
+            rustdoc-args = ["--cfg", 1]
+            feature = ["full"]
+
+            [package.metadata.binstall]
+            pkg-fmt = "tar.gz"
+            pkg-url = "{ repo }/releases"
+            overrides.x86_64-pc-windows-msvc = { pkg-fmt = "zip" }
+
+            [package.metadata.docs-rs]
+            all-features = true
+        "#};
+
+        assert_eq!(
+            validate(s),
+            [
+                "the docs.rs metadata table is `[package.metadata.docs.rs]`",
+                "`all-features` must be a boolean, or it will be ignored by \
+                 docs.rs",
+                "`rustdoc-args` must be an array of strings, or it will be \
+                 ignored by docs.rs",
+                "unknown key `feature`, it will be ignored by docs.rs",
+                "`tar.gz` is not one of `tar`, `tbz2`, `tgz`, `txz`, `tzstd`, \
+                 `zip`, `bin`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_valid() {
+        let s = indoc! {r#"
+            [package.metadata.docs.rs]
+            all-features = true
+            rustdoc-args = ["--cfg", "docsrs"]
+            targets = []
+
+            [package.metadata.other-tool]
+            anything = 1
+        "#};
+
+        assert!(validate(s).is_empty());
+    }
+
+    #[test]
+    fn test_validate_features() {
+        let s = indoc! {r#"
+            [features]
+            cli = ["dep:clap"]
+            full = ["cli"]
+
+            [dependencies]
+            clap = { version = "4", optional = true }
+            serde = { version = "1", optional = true }
+            tokio = "1"
+
+            [package.metadata.docs.rs]
+            features = ["full", "serde", "tokio/rt", "tokio?/net", "ful", "tokio", "clap"]
+        "#};
+        let dom = taplo::parser::parse(s).into_dom();
+        let manifest = parse::Manifest::parse(s, &dom);
+        let warnings = manifest
+            .metadata_warnings
+            .iter()
+            .map(|warning| warning.message(Catalog::for_locale(None)))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            warnings,
+            [
+                "unknown feature `ful`, the docs.rs build will fail",
+                "unknown feature `tokio`, the docs.rs build will fail",
+                "unknown feature `clap`, the docs.rs build will fail",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_table() {
+        let path =
+            |p: &[&str]| p.iter().map(|&s| s.to_owned()).collect::<Vec<_>>();
+
+        assert_eq!(
+            table(&path(&["package", "metadata", "docs", "rs"]))
+                .map(|t| t.tool),
+            Some("docs.rs")
+        );
+        assert!(table(&path(&["package", "metadata"])).is_none());
+        assert!(table(&path(&["docs", "rs"])).is_none());
+    }
+}
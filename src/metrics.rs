@@ -0,0 +1,193 @@
+//! Local usage metrics (request counts, the cache hit rate and the fetch
+//! latencies), for diagnosing performance regressions on users' machines.
+//!
+//! Nothing is ever sent anywhere: the metrics are only returned by the
+//! `crates/metrics` request, and optionally logged to the client.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::Mutex,
+    time::Duration,
+};
+
+use serde::Serialize;
+
+use crate::platform::Instant;
+
+/// The name of the custom request that returns a [`Snapshot`].
+pub const METHOD: &str = "crates/metrics";
+
+/// The number of the most recent fetches the latency percentiles are
+/// computed from.
+const LATENCY_SAMPLES: usize = 1000;
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    requests: BTreeMap<&'static str, u64>,
+    cache_hits: u64,
+    cache_misses: u64,
+    fetches: u64,
+    /// The latencies of the most recent fetches, from the oldest.
+    latencies: VecDeque<Duration>,
+    last_log: Option<Instant>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    /// The number of the handled LSP requests and notifications, by method.
+    pub requests: BTreeMap<&'static str, u64>,
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+    /// `None` before the cache is first used.
+    pub cache_hit_rate: Option<f64>,
+    /// The number of the requests to the registry (and the API).
+    pub fetches: u64,
+    pub fetch_latency_ms: Option<Percentiles>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Percentiles {
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+}
+
+impl Metrics {
+    pub fn request(&self, method: &'static str) {
+        *self.lock().requests.entry(method).or_default() += 1;
+    }
+
+    pub fn cache_hit(&self) {
+        self.lock().cache_hits += 1;
+    }
+
+    pub fn cache_miss(&self) {
+        self.lock().cache_misses += 1;
+    }
+
+    pub fn fetch(&self, latency: Duration) {
+        let mut inner = self.lock();
+        inner.fetches += 1;
+        if inner.latencies.len() == LATENCY_SAMPLES {
+            inner.latencies.pop_front();
+        }
+        inner.latencies.push_back(latency);
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        let inner = self.lock();
+        let lookups = inner.cache_hits + inner.cache_misses;
+        let mut latencies = inner.latencies.iter().copied().collect::<Vec<_>>();
+        latencies.sort_unstable();
+
+        Snapshot {
+            requests: inner.requests.clone(),
+            cache_hits: inner.cache_hits,
+            cache_misses: inner.cache_misses,
+            cache_hit_rate: (lookups > 0)
+                .then(|| inner.cache_hits as f64 / lookups as f64),
+            fetches: inner.fetches,
+            fetch_latency_ms: Percentiles::of(&latencies),
+        }
+    }
+
+    /// A summary line of the metrics, if `interval` passed since the last
+    /// one (or there was none).
+    pub fn log_line(&self, interval: Duration) -> Option<String> {
+        let now = Instant::now();
+        {
+            let mut inner = self.lock();
+            if inner
+                .last_log
+                .is_some_and(|last| now.duration_since(last) < interval)
+            {
+                return None;
+            }
+            inner.last_log = Some(now);
+        }
+
+        let snapshot = self.snapshot();
+        let requests = snapshot.requests.values().sum::<u64>();
+        let hit_rate = snapshot
+            .cache_hit_rate
+            .map_or("-".to_owned(), |r| format!("{:.0}%", r * 100.0));
+        let latency = snapshot.fetch_latency_ms.map_or("-".to_owned(), |l| {
+            format!("p50 {:.0}ms, p99 {:.0}ms", l.p50, l.p99)
+        });
+        Some(format!(
+            "metrics: {requests} requests, cache hit rate {hit_rate}, {} \
+             fetches ({latency})",
+            snapshot.fetches
+        ))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        // the metrics are only counters, so they're fine to use even if a
+        // thread panicked while updating them.
+        self.inner.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl Percentiles {
+    /// The percentiles of the `sorted` latencies, in milliseconds.
+    fn of(sorted: &[Duration]) -> Option<Self> {
+        let at = |p: f64| {
+            let i = ((sorted.len() - 1) as f64 * p).round() as usize;
+            sorted[i].as_micros() as f64 / 1000.0
+        };
+        (!sorted.is_empty()).then(|| Self {
+            p50: at(0.5),
+            p90: at(0.9),
+            p99: at(0.99),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot() {
+        let metrics = Metrics::default();
+        assert_eq!(metrics.snapshot().cache_hit_rate, None);
+        assert_eq!(metrics.snapshot().fetch_latency_ms, None);
+
+        metrics.request("textDocument/hover");
+        metrics.request("textDocument/hover");
+        metrics.cache_hit();
+        metrics.cache_hit();
+        metrics.cache_hit();
+        metrics.cache_miss();
+        for ms in 1..=100 {
+            metrics.fetch(Duration::from_millis(ms));
+        }
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.requests["textDocument/hover"], 2);
+        assert_eq!(snapshot.cache_hit_rate, Some(0.75));
+        assert_eq!(snapshot.fetches, 100);
+        assert_eq!(
+            snapshot.fetch_latency_ms,
+            Some(Percentiles { p50: 51.0, p90: 90.0, p99: 99.0 })
+        );
+    }
+
+    #[test]
+    fn test_log_line() {
+        let metrics = Metrics::default();
+        metrics.request("textDocument/hover");
+
+        assert_eq!(
+            metrics.log_line(Duration::from_secs(60)).as_deref(),
+            Some("metrics: 1 requests, cache hit rate -, 0 fetches (-)")
+        );
+        assert_eq!(metrics.log_line(Duration::from_secs(60)), None);
+    }
+}
@@ -0,0 +1,135 @@
+//! Keeping the server alive through panics in its handlers, so a bug in one
+//! feature doesn't take the others down until the editor restarts the
+//! server.
+
+use std::{
+    any::Any,
+    panic::AssertUnwindSafe,
+    task::{Context, Poll},
+};
+
+use futures::{FutureExt, future::BoxFuture};
+use tower::Service;
+use tower_lsp::jsonrpc::{Error, Request, Response};
+
+/// Wraps a service (i.e. [`crate::ls::service`]), so a request whose
+/// handler panics gets an internal error, and a notification whose handler
+/// panics is dropped, with the panic logged along with the method.
+///
+/// Panics unwind on native builds only; on WASM, they still abort.
+#[derive(Debug)]
+pub struct CatchPanic<S> {
+    inner: S,
+}
+
+impl<S> CatchPanic<S> {
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+}
+
+impl<S> Service<Request> for CatchPanic<S>
+where
+    S: Service<Request, Response = Option<Response>>,
+    S::Future: Send + 'static,
+    S::Error: Send + 'static,
+{
+    type Response = Option<Response>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let method = req.method().to_owned();
+        let id = req.id().cloned();
+        // the handlers are async, so they run (and panic) when the future is
+        // polled.
+        AssertUnwindSafe(self.inner.call(req))
+            .catch_unwind()
+            .map(move |result| {
+                result.unwrap_or_else(|panic| {
+                    let message = panic_message(&*panic);
+                    eprintln!("panicked while handling `{method}`: {message}");
+                    let error = Error {
+                        message: format!(
+                            "the server panicked while handling `{method}`"
+                        )
+                        .into(),
+                        ..Error::internal_error()
+                    };
+                    Ok(id.map(|id| Response::from_error(id, error)))
+                })
+            })
+            .boxed()
+    }
+}
+
+/// The message a panic was raised with, if it's a string (as the panics of
+/// `panic!`, `unwrap` and `expect` are).
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown payload"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use tower_lsp::jsonrpc::ErrorCode;
+
+    use super::*;
+
+    /// Panics on the requests of `panic`, and responds to the rest.
+    struct Panicky;
+
+    impl Service<Request> for Panicky {
+        type Response = Option<Response>;
+        type Error = Infallible;
+        type Future = BoxFuture<'static, Result<Option<Response>, Infallible>>;
+
+        fn poll_ready(
+            &mut self,
+            _: &mut Context<'_>,
+        ) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, req: Request) -> Self::Future {
+            let panics = req.method() == "panic";
+            let id = req.id().cloned();
+            async move {
+                assert!(!panics, "a bug");
+                Ok(id.map(|id| Response::from_ok(id, 1.into())))
+            }
+            .boxed()
+        }
+    }
+
+    async fn call(req: Request) -> Option<Response> {
+        CatchPanic::new(Panicky).call(req).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_catch_panic() {
+        let ok = call(Request::build("ok").id(1).finish()).await.unwrap();
+        assert!(ok.is_ok());
+
+        let res = call(Request::build("panic").id(2).finish()).await.unwrap();
+        let error = res.error().unwrap();
+        assert_eq!(error.code, ErrorCode::InternalError);
+        assert_eq!(error.message, "the server panicked while handling `panic`");
+
+        assert!(call(Request::build("panic").finish()).await.is_none());
+    }
+}
@@ -1,21 +1,192 @@
-use std::path::{Path, PathBuf};
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+};
 
 use taplo::{
     dom::{
         self, Node,
         node::{DomNode, Key},
     },
-    rowan::TextRange,
+    rowan::{TextRange, TextSize},
+    syntax::SyntaxKind,
+};
+use tower_lsp::lsp_types::{self, Position, Range, Url};
+
+use crate::{
+    index::{Field, PositionIndex},
+    lints,
+    messages::Catalog,
+    metadata, path, profile, publish, resolver, target, workspace,
 };
-use tower_lsp::lsp_types::{self, Position, Range};
 
 pub const DEPENDENCIES_KEYS: &[&str] =
     &["dependencies", "dev-dependencies", "build-dependencies"];
 
-#[derive(Debug, thiserror::Error)]
+/// The key of the platform specific tables, e.g.
+/// `[target.'cfg(windows)'.dependencies]`.
+pub const TARGET_KEY: &str = "target";
+
+pub const MANIFEST_FILE_NAME: &str = "Cargo.toml";
+
+/// The key of the crate's own features table.
+pub const FEATURES_TABLE: &str = "features";
+
+/// The name cargo reserves for crates.io in the `registry` key.
+pub const CRATES_IO_REGISTRY: &str = "crates-io";
+
+const PACKAGE_KEY: &str = "package";
+const LINKS_KEY: &str = "links";
+
+/// Top-level tables that (besides the dependencies tables) only appear in
+/// Cargo manifests.
+const MANIFEST_KEYS: &[&str] = &[PACKAGE_KEY, "workspace", TARGET_KEY];
+
+/// An error in a dependency entry, or a redefinition that cargo fails to
+/// parse, located at `range`.
+#[derive(Debug)]
 pub enum Error {
-    #[error("failed to parse toml document")]
-    Parse,
+    InvalidDependency { range: Range },
+    InvalidType {
+        key: String,
+        expected: &'static str,
+        range: Range,
+    },
+    DuplicateTable {
+        table: String,
+        line: u32,
+        range: Range,
+    },
+    DuplicateKey {
+        key: String,
+        line: u32,
+        range: Range,
+    },
+    NotExtensible {
+        key: String,
+        expected: &'static str,
+        line: u32,
+        range: Range,
+    },
+}
+
+impl Error {
+    /// The message of the error, in the locale of `messages`.
+    pub fn message(&self, messages: Catalog) -> String {
+        match self {
+            Self::InvalidDependency { .. } => {
+                messages.get("invalid-dependency", &[])
+            }
+            Self::InvalidType { key, expected, .. } => messages
+                .get("invalid-type", &[("key", key), ("expected", expected)]),
+            Self::DuplicateTable { table, line, .. } => messages
+                .get("duplicate-table", &[("table", table), ("line", line)]),
+            Self::DuplicateKey { key, line, .. } => {
+                messages.get("duplicate-key", &[("key", key), ("line", line)])
+            }
+            Self::NotExtensible { key, expected, line, .. } => messages.get(
+                "not-extensible",
+                &[("key", key), ("expected", expected), ("line", line)],
+            ),
+        }
+    }
+
+    pub fn range(&self) -> Range {
+        match self {
+            Self::InvalidDependency { range }
+            | Self::InvalidType { range, .. }
+            | Self::DuplicateTable { range, .. }
+            | Self::DuplicateKey { range, .. }
+            | Self::NotExtensible { range, .. } => *range,
+        }
+    }
+}
+
+/// The type of a value in a manifest.
+#[derive(Debug, Clone, Copy)]
+pub enum ValueType {
+    String,
+    Bool,
+    /// The items are checked separately, to point at the invalid ones.
+    StringArray,
+}
+
+impl ValueType {
+    pub fn matches(self, node: &Node) -> bool {
+        match self {
+            Self::String => node.as_str().is_some(),
+            Self::Bool => node.as_bool().is_some(),
+            Self::StringArray => node.as_array().is_some(),
+        }
+    }
+
+    pub fn expected(self) -> &'static str {
+        match self {
+            Self::String => "a string",
+            Self::Bool => "a boolean",
+            Self::StringArray => "an array of strings",
+        }
+    }
+}
+
+/// The parsed contents of a manifest.
+#[derive(Debug, Default)]
+pub struct Manifest {
+    pub dependencies: Vec<Dependency>,
+    pub errors: Vec<Error>,
+    /// The features declared in the `[features]` table.
+    pub features: Vec<Feature>,
+    /// Mistakes in the well-known `[package.metadata.*]` tables.
+    pub metadata_warnings: Vec<metadata::Warning>,
+    /// The (opt-in) publish-readiness lints, see [`crate::publish`].
+    pub publish_lints: Vec<publish::Lint>,
+    /// The targets declared with tables, e.g. `[[bin]]`.
+    pub targets: Vec<target::Target>,
+    pub target_errors: Vec<target::Error>,
+    pub profiles: profile::Profiles,
+    /// Mistakes in the `[lints]` tables.
+    pub lint_warnings: Vec<lints::Warning>,
+    pub resolver_warnings: Vec<resolver::Warning>,
+    /// The native library the package links (`package.links`).
+    pub links: Option<Span<String>>,
+    /// The keys of `[package]` that are inherited from the workspace (e.g.
+    /// `version.workspace = true`).
+    pub inherited: Vec<Span<String>>,
+    /// The names of the dependency entries that are still being typed, i.e.
+    /// that have no value yet (e.g. `tok` or `tok =`).
+    pub incomplete: Vec<Span<String>>,
+    pub index: PositionIndex,
+}
+
+/// A feature declared in the `[features]` table.
+#[derive(Debug)]
+pub struct Feature {
+    pub name: Span<String>,
+    /// The features (and `dep:` dependencies) it enables.
+    pub enables: Vec<Span<String>>,
+}
+
+impl Feature {
+    fn parse(s: &str, key: &Key, node: &Node) -> Option<Self> {
+        let range = key_range(key, s)?;
+        let name = Span::new(key.value().to_owned(), range);
+
+        let enables = node
+            .as_array()
+            .map(|items| {
+                items
+                    .items()
+                    .read()
+                    .iter()
+                    .filter_map(|item| {
+                        Span::parse(item.as_str()?, |v| Some(v.to_owned()), s)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Some(Self { name, enables })
+    }
 }
 
 #[derive(Debug)]
@@ -28,13 +199,13 @@ pub enum Kind {
 
 #[derive(Debug)]
 pub struct LocalKind {
-    path: Span<PathBuf>,
+    pub path: Span<PathBuf>,
 }
 
 #[derive(Debug)]
 pub struct GitKind {
-    url: Span<String>,
-    specifier: Option<GitSpecifier>,
+    pub url: Span<String>,
+    pub specifier: Option<GitSpecifier>,
 }
 
 #[derive(Debug)]
@@ -44,26 +215,241 @@ pub enum GitSpecifier {
     Rev(Span<String>),
 }
 
+impl GitSpecifier {
+    pub fn span(&self) -> &Span<String> {
+        match self {
+            Self::Branch(span) | Self::Tag(span) | Self::Rev(span) => span,
+        }
+    }
+}
+
+/// The kind of a dependencies table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DependencyKind {
+    /// `[dependencies]`
+    Normal,
+    /// `[dev-dependencies]`
+    Dev,
+    /// `[build-dependencies]`
+    Build,
+}
+
+impl DependencyKind {
+    pub fn from_key(key: &str) -> Option<Self> {
+        match key {
+            "dependencies" => Some(Self::Normal),
+            "dev-dependencies" => Some(Self::Dev),
+            "build-dependencies" => Some(Self::Build),
+            _ => None,
+        }
+    }
+}
+
+/// The dependencies table a dependency is declared in.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Section {
+    pub kind: DependencyKind,
+    /// The platform of a platform specific table, e.g. `cfg(windows)` for
+    /// `[target.'cfg(windows)'.dependencies]`.
+    pub target: Option<String>,
+}
+
+impl Section {
+    /// The header of the table, e.g. `[target.'cfg(unix)'.dependencies]`.
+    pub fn header(&self) -> String {
+        let kind = match self.kind {
+            DependencyKind::Normal => "dependencies",
+            DependencyKind::Dev => "dev-dependencies",
+            DependencyKind::Build => "build-dependencies",
+        };
+        match &self.target {
+            Some(target) => format!("[target.'{target}'.{kind}]"),
+            None => format!("[{kind}]"),
+        }
+    }
+}
+
+/// Identifies the text of a dependency entry, so the analysis of the entries
+/// that didn't change between edits can be reused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fingerprint {
+    /// A hash of the entry's section and text.
+    pub hash: u64,
+    /// Where the entry starts, as it might have moved even if it didn't
+    /// change.
+    pub start: Position,
+}
+
+impl Fingerprint {
+    fn new(s: &str, section: &Section, range: std::ops::Range<usize>) -> Self {
+        let mut hasher = DefaultHasher::new();
+        section.hash(&mut hasher);
+        s.get(range.clone()).hash(&mut hasher);
+
+        Self { hash: hasher.finish(), start: idx_to_position(s, range.start) }
+    }
+
+    /// Make a range (inside of the entry) relative to the entry's start.
+    pub fn to_relative(self, range: Range) -> Range {
+        let relative = |pos: Position| Position {
+            line: pos.line - self.start.line,
+            character: if pos.line == self.start.line {
+                pos.character - self.start.character
+            } else {
+                pos.character
+            },
+        };
+        Range::new(relative(range.start), relative(range.end))
+    }
+
+    /// The inverse of [`Self::to_relative`].
+    pub fn to_absolute(self, range: Range) -> Range {
+        let absolute = |pos: Position| Position {
+            line: pos.line + self.start.line,
+            character: if pos.line == 0 {
+                pos.character + self.start.character
+            } else {
+                pos.character
+            },
+        };
+        Range::new(absolute(range.start), absolute(range.end))
+    }
+}
+
 #[derive(Debug)]
 pub struct Dependency {
     pub kind: Kind,
+    pub section: Section,
+    pub fingerprint: Fingerprint,
     pub name: Span<String>,
-    pub version: Option<Span<Option<semver::VersionReq>>>,
+    pub version: Option<Span<Requirement>>,
     pub features: Option<Vec<Span<String>>>,
+    pub optional: Option<Span<bool>>,
+    pub default_features: Option<Span<bool>>,
+    /// The name of an alternative registry (from `.cargo/config.toml`).
+    pub registry: Option<Span<String>>,
+    /// The name of the crate, if the dependency is renamed (i.e., its key
+    /// isn't the crate's name).
+    pub package: Option<Span<String>>,
+    /// Whether the dependency is inherited from the workspace (i.e.,
+    /// `workspace = true`).
+    pub workspace: Option<Span<bool>>,
+    /// The ranges of the keys of the dependency's table (e.g. `version` on
+    /// its own line under `[dependencies.foo]`), if it's a table.
+    pub keys: Vec<Range>,
+    /// The range of the whole entry (e.g. `foo = { version = "1" }`), if
+    /// it's a single `key = value` entry, rather than a table of its own
+    /// (`[dependencies.foo]`) or dotted keys (`foo.version = "1"`).
+    pub entry: Option<Range>,
+}
+
+/// A version requirement, as written in the manifest.
+#[derive(Debug)]
+pub struct Requirement {
+    pub raw: String,
+    /// `None` if `raw` is not a valid version requirement.
+    pub req: Option<semver::VersionReq>,
 }
 
 impl Dependency {
-    pub fn parse(s: &str, key: &Key, node: &Node) -> Result<Self, Error> {
-        let name = Self::parse_name(key, s).ok_or(Error::Parse)?;
+    pub fn parse(
+        s: &str,
+        key: &Key,
+        node: &Node,
+        section: Section,
+    ) -> Result<Self, Error> {
+        let name = Self::parse_name(key, s).ok_or_else(|| {
+            Error::InvalidDependency {
+                range: node_range(node, s).unwrap_or_default(),
+            }
+        })?;
+        if node.as_str().is_none() && node.as_table().is_none() {
+            return Err(Error::InvalidDependency { range: name.range });
+        }
+
+        let start = key.text_ranges().next().map(TextRange::start);
+        let end = node_end(node);
+        let entry = match (start, end) {
+            (Some(start), Some(end)) => usize::from(start)..usize::from(end),
+            _ => text_range_to_range(
+                key.text_ranges().next().unwrap_or_default(),
+            ),
+        };
+        let fingerprint = Fingerprint::new(s, &section, entry.clone());
+        let key_end = key.text_ranges().last().map(|r| usize::from(r.end()));
+        let is_single = key_end.is_some_and(|end| {
+            s.get(end..)
+                .is_some_and(|s| s.trim_start().starts_with('='))
+        });
+        let entry = is_single.then(|| range_to_positions(s, entry));
+
         let version = Self::parse_version(node, s);
         let features = Self::parse_features(node, s);
 
+        let optional = Self::parse_bool(node, &[Self::OPTIONAL_KEY], s);
+        let default_features =
+            Self::parse_bool(node, Self::DEFAULT_FEATURES_KEYS, s);
+        let registry = Self::parse_registry(node, s);
+        let package = Self::parse_package(node, s);
+        let workspace = Self::parse_bool(node, &[Self::WORKSPACE_KEY], s);
+        let keys = Self::parse_keys(node, s);
+
         let kind = Self::parse_local(node, s)
             .map(Kind::Local)
             .or_else(|| Self::parse_git(node, s).map(Kind::Git))
             .unwrap_or(Kind::Registry);
 
-        Ok(Self { name, kind, version, features })
+        Ok(Self {
+            name,
+            kind,
+            section,
+            fingerprint,
+            version,
+            features,
+            optional,
+            default_features,
+            registry,
+            package,
+            workspace,
+            keys,
+            entry,
+        })
+    }
+
+    fn parse_keys(node: &Node, s: &str) -> Vec<Range> {
+        let Some(table) = node.as_table() else { return Vec::new() };
+        table
+            .entries()
+            .read()
+            .iter()
+            .filter_map(|(key, _)| {
+                let range = text_range_to_range(key.text_ranges().next()?);
+                Some(range_to_positions(s, range))
+            })
+            .collect()
+    }
+
+    /// Whether the dependency is pulled from crates.io.
+    pub fn is_crates_io(&self) -> bool {
+        matches!(self.kind, Kind::Registry)
+            && self
+                .registry
+                .as_ref()
+                .is_none_or(|r| r.value == CRATES_IO_REGISTRY)
+    }
+
+    /// The name of the dependency's crate: its `package`, if it's renamed,
+    /// or its key.
+    pub fn crate_name(&self) -> &str {
+        &self.package.as_ref().unwrap_or(&self.name).value
+    }
+
+    /// The name of the alternative registry the dependency is pulled from.
+    pub fn alternative_registry(&self) -> Option<&str> {
+        let registry = self.registry.as_ref()?;
+        (matches!(self.kind, Kind::Registry)
+            && registry.value != CRATES_IO_REGISTRY)
+            .then_some(registry.value.as_str())
     }
 }
 
@@ -75,6 +461,89 @@ impl Dependency {
     const TAG_KEY: &str = "tag";
     const BRANCH_KEY: &str = "branch";
     const GIT_KEY: &str = "git";
+    const OPTIONAL_KEY: &str = "optional";
+    /// `default_features` is deprecated, but still supported by cargo.
+    const DEFAULT_FEATURES_KEYS: &[&str] =
+        &["default-features", "default_features"];
+    const REGISTRY_KEY: &str = "registry";
+    const WORKSPACE_KEY: &str = "workspace";
+    const PACKAGE_KEY: &str = "package";
+
+    /// The expected types of the keys of a dependency table.
+    const KEY_TYPES: &[(&str, ValueType)] = &[
+        (Self::VERSION_KEY, ValueType::String),
+        (Self::FEATURES_KEY, ValueType::StringArray),
+        (Self::PATH_KEY, ValueType::String),
+        (Self::GIT_KEY, ValueType::String),
+        (Self::BRANCH_KEY, ValueType::String),
+        (Self::TAG_KEY, ValueType::String),
+        (Self::REV_KEY, ValueType::String),
+        (Self::REGISTRY_KEY, ValueType::String),
+        (Self::PACKAGE_KEY, ValueType::String),
+        (Self::OPTIONAL_KEY, ValueType::Bool),
+        (Self::DEFAULT_FEATURES_KEYS[0], ValueType::Bool),
+        (Self::DEFAULT_FEATURES_KEYS[1], ValueType::Bool),
+        (Self::WORKSPACE_KEY, ValueType::Bool),
+    ];
+
+    /// Check the types of the known keys of a dependency table (e.g. that
+    /// `features` is an array of strings).
+    ///
+    /// Keys with invalid types are simply ignored when parsing, so this
+    /// reports them.
+    pub fn validate(node: &Node, s: &str) -> Vec<Error> {
+        let Some(table) = node.as_table() else { return Vec::new() };
+        let mut errors = Vec::new();
+
+        for &(key, ty) in Self::KEY_TYPES {
+            if let Some(value) = table.get(key)
+                && !ty.matches(&value)
+                && let Some(range) = node_range(&value, s)
+            {
+                errors.push(Error::InvalidType {
+                    key: key.to_owned(),
+                    expected: ty.expected(),
+                    range,
+                });
+            }
+        }
+
+        if let Some(features) = table.get(Self::FEATURES_KEY)
+            && let Some(features) = features.as_array()
+        {
+            let items = features.items().read();
+            let invalid = items.iter().filter(|f| f.as_str().is_none());
+            errors.extend(invalid.filter_map(|f| {
+                Some(Error::InvalidType {
+                    key: Self::FEATURES_KEY.to_owned(),
+                    expected: ValueType::StringArray.expected(),
+                    range: node_range(f, s)?,
+                })
+            }));
+        }
+
+        errors
+    }
+
+    /// Parse the first of the `keys` that exists in the table as a boolean.
+    fn parse_bool(node: &Node, keys: &[&str], s: &str) -> Option<Span<bool>> {
+        let table = node.as_table()?;
+        let value = keys.iter().find_map(|&key| table.get(key))?;
+        let bool = value.as_bool()?;
+        let range = text_range_to_range(bool.syntax()?.text_range());
+        let range = range_to_positions(s, range);
+        Some(Span::new(bool.value(), range))
+    }
+
+    fn parse_registry(node: &Node, s: &str) -> Option<Span<String>> {
+        let registry = node.as_table()?.get(Self::REGISTRY_KEY)?;
+        Span::parse(registry.as_str()?, |s| Some(s.to_owned()), s)
+    }
+
+    fn parse_package(node: &Node, s: &str) -> Option<Span<String>> {
+        let package = node.as_table()?.get(Self::PACKAGE_KEY)?;
+        Span::parse(package.as_str()?, |s| Some(s.to_owned()), s)
+    }
 
     fn parse_git(node: &Node, s: &str) -> Option<GitKind> {
         let table = node.as_table()?;
@@ -111,17 +580,15 @@ impl Dependency {
         Some(LocalKind { path })
     }
 
-    fn parse_version(
-        node: &Node,
-        s: &str,
-    ) -> Option<Span<Option<semver::VersionReq>>> {
+    fn parse_version(node: &Node, s: &str) -> Option<Span<Requirement>> {
         let value = node.as_str().cloned().or_else(|| {
             node.as_table()?.get(Self::VERSION_KEY)?.try_into_str().ok()
         })?;
         let range = text_range_to_range(value.syntax()?.text_range());
         let range = range_to_positions(s, range);
-        let value = semver::VersionReq::parse(value.value()).ok();
-        Some(Span::new(value, range))
+        let raw = value.value().to_owned();
+        let req = semver::VersionReq::parse(&raw).ok();
+        Some(Span::new(Requirement { raw, req }, range))
     }
 
     fn parse_features(node: &Node, s: &str) -> Option<Vec<Span<String>>> {
@@ -143,6 +610,26 @@ impl Dependency {
         Some(features)
     }
 
+    /// The name of an entry that has no value yet, as it's still being
+    /// typed (e.g. `tok`, or `tok =`), which the DOM only has as invalid.
+    fn parse_incomplete(
+        s: &str,
+        key: &Key,
+        node: &Node,
+    ) -> Option<Span<String>> {
+        if !matches!(node, Node::Invalid(_)) {
+            return None;
+        }
+        let entry = key.syntax()?.parent()?;
+        let has_value = entry.children().any(|child| {
+            child.kind() == SyntaxKind::VALUE && !child.text_range().is_empty()
+        });
+        if has_value {
+            return None;
+        }
+        Self::parse_name(key, s)
+    }
+
     fn parse_name(key: &Key, s: &str) -> Option<Span<String>> {
         let value = key.to_string();
         let range = text_range_to_range(key.text_ranges().nth(0)?);
@@ -151,6 +638,251 @@ impl Dependency {
     }
 }
 
+impl Manifest {
+    /// Parse all of the dependencies declared in the document.
+    pub fn parse(s: &str, dom: &Node) -> Self {
+        let mut manifest = Self::default();
+        let Some(root) = dom.as_table() else { return manifest };
+
+        // the top-level tables, and the platform specific tables
+        let mut tables = vec![(None, root.clone())];
+        if let Some(targets) = root.get(TARGET_KEY)
+            && let Some(targets) = targets.as_table()
+        {
+            tables.extend(targets.entries().read().iter().filter_map(
+                |(target, node)| {
+                    let table = node.as_table()?.clone();
+                    Some((Some(target.value().to_owned()), table))
+                },
+            ));
+        }
+
+        for (target, table) in tables {
+            for (key, node) in table.entries().read().iter() {
+                let Some(kind) = DependencyKind::from_key(key.value()) else {
+                    continue;
+                };
+                let Some(node) = node.as_table() else { continue };
+
+                let section = Section { kind, target: target.clone() };
+                for (key, node) in node.entries().read().iter() {
+                    if let Some(name) =
+                        Dependency::parse_incomplete(s, key, node)
+                    {
+                        manifest.incomplete.push(name);
+                        continue;
+                    }
+                    manifest.errors.extend(Dependency::validate(node, s));
+                    match Dependency::parse(s, key, node, section.clone()) {
+                        Ok(dep) => manifest.dependencies.push(dep),
+                        Err(err) => manifest.errors.push(err),
+                    }
+                }
+            }
+        }
+
+        manifest.errors.extend(redefinitions(dom, s));
+
+        if let Some(features) = root.get(FEATURES_TABLE)
+            && let Some(features) = features.as_table()
+        {
+            manifest.features = features
+                .entries()
+                .read()
+                .iter()
+                .filter_map(|(key, node)| Feature::parse(s, key, node))
+                .collect();
+        }
+
+        manifest.metadata_warnings =
+            metadata::validate(dom, s, |f| manifest.has_feature(f));
+        manifest.profiles = profile::parse(dom, s);
+        manifest.lint_warnings = lints::validate(dom, s);
+        manifest.resolver_warnings =
+            resolver::validate(dom, s, &manifest.dependencies);
+        manifest.links = dom
+            .get(PACKAGE_KEY)
+            .get(LINKS_KEY)
+            .as_str()
+            .and_then(|links| Span::parse(links, |v| Some(v.to_owned()), s));
+        manifest.inherited = workspace::inherited_keys(dom, s);
+        manifest.targets = target::parse(dom, s);
+        manifest.target_errors =
+            target::validate(&manifest.targets, |f| manifest.has_feature(f));
+        manifest.index = PositionIndex::new(&manifest.dependencies);
+        manifest
+    }
+
+    /// Checks whether `feature` can be enabled on this crate, i.e., it's
+    /// declared in `[features]`, it's the implicit feature of an optional
+    /// dependency, or it's a feature of a dependency (`dep/feature`).
+    pub fn has_feature(&self, feature: &str) -> bool {
+        if let Some((dependency, _)) = feature.split_once('/') {
+            let dependency = dependency.strip_suffix('?').unwrap_or(dependency);
+            return self
+                .dependencies
+                .iter()
+                .any(|d| d.name.value == dependency);
+        }
+
+        // the implicit feature of an optional dependency is removed if any
+        // feature refers to the dependency with `dep:`.
+        let dep = format!("dep:{feature}");
+        let is_implicit = || {
+            self.features
+                .iter()
+                .all(|f| f.enables.iter().all(|e| e.value != dep))
+        };
+
+        self.features.iter().any(|f| f.name.value == feature)
+            || self.dependencies.iter().any(|d| {
+                d.name.value == feature
+                    && d.optional.as_ref().is_some_and(|o| o.value)
+                    && is_implicit()
+            })
+    }
+
+    /// The names of all of the features of this crate, including the
+    /// implicit features of optional dependencies.
+    pub fn feature_names(&self) -> impl Iterator<Item = &str> {
+        let implicit = self
+            .dependencies
+            .iter()
+            .filter(|d| d.optional.as_ref().is_some_and(|o| o.value))
+            .map(|d| d.name.value.as_str())
+            .filter(|name| self.has_feature(name));
+        self.features
+            .iter()
+            .map(|f| f.name.value.as_str())
+            .chain(implicit)
+    }
+
+    /// Find the dependency and its field at `pos`.
+    pub fn find(&self, pos: Position) -> Option<(&Dependency, Field)> {
+        let (idx, field) = self.index.find(pos)?;
+        Some((&self.dependencies[idx], field))
+    }
+}
+
+/// Checks whether a document is a Cargo manifest, either by its file name, or
+/// by the presence of tables that are specific to Cargo manifests.
+pub fn is_manifest(uri: &Url, dom: &Node) -> bool {
+    if path::file_name(uri).is_some_and(|name| name == MANIFEST_FILE_NAME) {
+        return true;
+    }
+
+    dom.as_table().is_some_and(|table| {
+        MANIFEST_KEYS
+            .iter()
+            .chain(DEPENDENCIES_KEYS)
+            .any(|&key| table.get(key).is_some())
+    })
+}
+
+/// Where a node ends, including all of its descendants (which, e.g. for tables
+/// declared with a header, are not a part of the table's own syntax).
+fn node_end(node: &Node) -> Option<TextSize> {
+    let own = node.syntax().map(|s| s.text_range().end());
+    let children = match node {
+        Node::Table(table) => table
+            .entries()
+            .read()
+            .iter()
+            .filter_map(|(key, node)| {
+                let key = key.text_ranges().last().map(TextRange::end);
+                node_end(node).max(key)
+            })
+            .max(),
+        Node::Array(array) => {
+            array.items().read().iter().filter_map(node_end).max()
+        }
+        _ => None,
+    };
+    own.max(children)
+}
+
+/// The tables and keys that are defined more than once (e.g. two
+/// `[dependencies]` tables), which TOML (and so cargo) rejects, but taplo
+/// merges.
+fn redefinitions(dom: &Node, s: &str) -> Vec<Error> {
+    let Err(errors) = dom.validate() else { return Vec::new() };
+    // the range of a key, and the line (from 1) of its first definition.
+    let ranges = |key: &Key, first: &Key| {
+        let range = text_range_to_range(key.text_ranges().next()?);
+        let first = text_range_to_range(first.text_ranges().next()?);
+        let line = idx_to_position(s, first.start).line + 1;
+        Some((range, line))
+    };
+
+    errors
+        .filter_map(|err| match err {
+            taplo::dom::Error::ConflictingKeys { key, other } => {
+                let (range, line) = ranges(&key, &other)?;
+                let (_, line_start) = line_of_idx(s, range.start);
+                let header = table_header(&s[line_start..]);
+                let range = range_to_positions(s, range);
+                match header {
+                    Some(table) => Some(Error::DuplicateTable {
+                        table: table.to_owned(),
+                        line,
+                        range,
+                    }),
+                    None => Some(Error::DuplicateKey {
+                        key: key.value().to_owned(),
+                        line,
+                        range,
+                    }),
+                }
+            }
+            taplo::dom::Error::ExpectedTable { not_table, required_by } => {
+                let (range, line) = ranges(&not_table, &required_by)?;
+                Some(Error::NotExtensible {
+                    key: not_table.value().to_owned(),
+                    expected: "a table",
+                    line,
+                    range: range_to_positions(s, range),
+                })
+            }
+            taplo::dom::Error::ExpectedArrayOfTables {
+                not_array_of_tables,
+                required_by,
+            } => {
+                let (range, line) = ranges(&not_array_of_tables, &required_by)?;
+                Some(Error::NotExtensible {
+                    key: not_array_of_tables.value().to_owned(),
+                    expected: "an array of tables",
+                    line,
+                    range: range_to_positions(s, range),
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// The key of the table header at the start of `s` (e.g. `dependencies`
+/// for `[dependencies] # comment`), if there's one.
+pub fn table_header(s: &str) -> Option<&str> {
+    let line = s
+        .lines()
+        .next()?
+        .trim_start()
+        .strip_prefix('[')?
+        .trim_start_matches('[');
+    let (key, _) = line.split_once(']')?;
+    Some(key.trim())
+}
+
+pub fn node_range(node: &Node, s: &str) -> Option<Range> {
+    let range = text_range_to_range(node.syntax()?.text_range());
+    Some(range_to_positions(s, range))
+}
+
+pub fn key_range(key: &Key, s: &str) -> Option<Range> {
+    let range = text_range_to_range(key.text_ranges().next()?);
+    Some(range_to_positions(s, range))
+}
+
 pub fn text_range_to_range(text_range: TextRange) -> std::ops::Range<usize> {
     usize::from(text_range.start())..usize::from(text_range.end())
 }
@@ -166,7 +898,7 @@ impl<T> Span<T> {
         Self { value, range }
     }
 
-    fn parse<F>(string: &dom::node::Str, f: F, s: &str) -> Option<Span<T>>
+    pub fn parse<F>(string: &dom::node::Str, f: F, s: &str) -> Option<Span<T>>
     where
         F: Fn(&str) -> Option<T>,
     {
@@ -175,33 +907,87 @@ impl<T> Span<T> {
         let range = range_to_positions(s, range);
         Some(Span::new(value, range))
     }
-
-    pub fn contains_pos(&self, pos: Position) -> bool {
-        let (start, end) = (self.range.start, self.range.end);
-        !(!(start.line..=end.line).contains(&pos.line)
-            || (start.line == pos.line && pos.character < start.character)
-            || (end.line == pos.line && pos.character > end.character))
-    }
 }
 
+/// Returns the (zero-indexed) line containing the byte index `idx`, along
+/// with the byte index at which that line starts.
+///
+/// Following the [LSP spec], a line is terminated by either `\n`, `\r\n` or
+/// a lone `\r`.
+///
+/// [LSP spec]: https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#textDocuments
 fn line_of_idx(s: &str, idx: usize) -> (usize, usize) {
-    s.chars()
-        .enumerate()
-        .fold((0, 0), |(line, line_pos), (i, c)| {
-            // FIXME: stuff with '\r'
-            if c == '\n' && i < idx { (line + 1, i) } else { (line, line_pos) }
-        })
+    let bytes = s.as_bytes();
+    let (mut line, mut line_start) = (0, 0);
+
+    for (i, &b) in bytes.iter().enumerate().take(idx) {
+        let is_line_break = match b {
+            b'\n' => true,
+            // a `\r` that is followed by a `\n` is counted with the `\n`.
+            b'\r' => bytes.get(i + 1) != Some(&b'\n'),
+            _ => false,
+        };
+        if is_line_break {
+            (line, line_start) = (line + 1, i + 1);
+        }
+    }
+
+    (line, line_start)
 }
 
 pub fn idx_to_position(s: &str, idx: usize) -> lsp_types::Position {
-    let (line, line_idx) = line_of_idx(s, idx);
+    let (line, line_start) = line_of_idx(s, idx);
+    let idx = s.floor_char_boundary(idx).max(line_start);
     lsp_types::Position {
         line: line as u32,
-        character: if line == 0 { idx - line_idx } else { idx - (line_idx + 1) }
-            as u32,
+        character: s[line_start..idx].encode_utf16().count() as u32,
+    }
+}
+
+/// The inverse of [`idx_to_position`]. Positions past the end of a line are
+/// clamped to it (as the LSP spec requires).
+pub fn position_to_idx(s: &str, pos: lsp_types::Position) -> usize {
+    let bytes = s.as_bytes();
+    let (mut line, mut line_start) = (0, 0);
+
+    let mut i = 0;
+    while i < bytes.len() {
+        let break_len = match bytes[i] {
+            b'\n' => 1,
+            b'\r' if bytes.get(i + 1) == Some(&b'\n') => 2,
+            b'\r' => 1,
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        if line == pos.line as usize {
+            return line_start + utf16_to_idx(&s[line_start..i], pos.character);
+        }
+        i += break_len;
+        (line, line_start) = (line + 1, i);
+    }
+
+    if line == pos.line as usize {
+        line_start + utf16_to_idx(&s[line_start..], pos.character)
+    } else {
+        s.len()
     }
 }
 
+/// The byte index of the UTF-16 offset `character` into `line` (as LSP
+/// positions count them), clamped to its end.
+fn utf16_to_idx(line: &str, character: u32) -> usize {
+    let mut units = 0;
+    for (i, c) in line.char_indices() {
+        if units >= character as usize {
+            return i;
+        }
+        units += c.len_utf16();
+    }
+    line.len()
+}
+
 pub fn range_to_positions(
     s: &str,
     r: std::ops::Range<usize>,
@@ -217,6 +1003,7 @@ mod tests {
     use indoc::indoc;
 
     use super::*;
+    use crate::{config::Settings, ls};
 
     #[test]
     fn test_range_to_positions() {
@@ -253,4 +1040,464 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn test_range_to_positions_crlf() {
+        let s = "12345678\r\n480\r\n3\r\n";
+
+        // basic
+        assert_eq!(
+            range_to_positions(s, 0..2),
+            lsp_types::Range {
+                start: lsp_types::Position::new(0, 0),
+                end: lsp_types::Position::new(0, 2),
+            }
+        );
+        // multiline
+        assert_eq!(
+            range_to_positions(s, 6..11),
+            lsp_types::Range {
+                start: lsp_types::Position::new(0, 6),
+                end: lsp_types::Position::new(1, 1),
+            }
+        );
+        // to line end
+        assert_eq!(
+            range_to_positions(s, 15..16),
+            lsp_types::Range {
+                start: lsp_types::Position::new(2, 0),
+                end: lsp_types::Position::new(2, 1),
+            }
+        );
+        // between the `\r` and the `\n`
+        assert_eq!(idx_to_position(s, 9), lsp_types::Position::new(0, 9));
+    }
+
+    #[test]
+    fn test_range_to_positions_mixed_line_endings() {
+        let s = "ab\rcd\r\nef\ngh";
+
+        assert_eq!(idx_to_position(s, 3), lsp_types::Position::new(1, 0));
+        assert_eq!(idx_to_position(s, 7), lsp_types::Position::new(2, 0));
+        assert_eq!(idx_to_position(s, 11), lsp_types::Position::new(3, 1));
+    }
+
+    #[test]
+    fn test_position_to_idx() {
+        for s in ["ab\ncd\r\nef\rgh", "", "\n\n", "a\r\n"] {
+            // the index inside of a `\r\n` isn't a valid position.
+            let valid = |&idx: &usize| {
+                !(s[..idx].ends_with('\r') && s[idx..].starts_with('\n'))
+            };
+            for idx in (0..=s.len()).filter(valid) {
+                let pos = idx_to_position(s, idx);
+                assert_eq!(position_to_idx(s, pos), idx, "{s:?} {pos:?}");
+            }
+        }
+
+        let s = "ab\r\ncd";
+        let idx =
+            |line, character| position_to_idx(s, Position { line, character });
+        assert_eq!(idx(0, 10), 2);
+        assert_eq!(idx(1, 10), s.len());
+        assert_eq!(idx(5, 0), s.len());
+
+        // the characters are counted in UTF-16 code units.
+        let s = "a = \"é🦀\"\nb";
+        for idx in [0, 5, 7, 11, 12, 13, 14] {
+            let pos = idx_to_position(s, idx);
+            assert_eq!(position_to_idx(s, pos), idx, "{pos:?}");
+        }
+        assert_eq!(idx_to_position(s, 11), Position::new(0, 8));
+        assert_eq!(position_to_idx(s, Position::new(0, 8)), 11);
+    }
+
+    #[test]
+    fn test_is_manifest() {
+        let is_manifest = |uri: &str, s: &str| {
+            let dom = taplo::parser::parse(s).into_dom();
+            is_manifest(&Url::parse(uri).unwrap(), &dom)
+        };
+
+        assert!(is_manifest("file:///foo/Cargo.toml", ""));
+        assert!(is_manifest("file:///foo/a.toml", "[dependencies]\n"));
+        assert!(is_manifest("file:///foo/a.toml", "[workspace]\n"));
+        assert!(is_manifest("file:///foo/a.toml", "package.name = \"a\"\n"));
+        assert!(!is_manifest("file:///foo/a.toml", "[tool.ruff]\n"));
+        assert!(!is_manifest("file:///foo/pyproject.toml", "[project]\n"));
+        assert!(is_manifest("file:///my%20ws/%E2%9C%A8/Cargo.toml", ""));
+    }
+
+    #[test]
+    fn test_dependencies_sections() {
+        let s = indoc! {r#"
+            [dependencies]
+            a = "1"
+
+            [dev-dependencies]
+            b = "1"
+
+            [target.'cfg(windows)'.build-dependencies]
+            c = "1"
+
+            [target.x86_64-unknown-linux-gnu.dependencies]
+            d = "1"
+        "#};
+        let dom = taplo::parser::parse(s).into_dom();
+
+        let sections = Manifest::parse(s, &dom)
+            .dependencies
+            .into_iter()
+            .map(|d| (d.name.value, d.section))
+            .collect::<Vec<_>>();
+        let section = |kind, target: Option<&str>| Section {
+            kind,
+            target: target.map(str::to_owned),
+        };
+
+        assert_eq!(
+            sections,
+            [
+                ("a".to_owned(), section(DependencyKind::Normal, None)),
+                ("b".to_owned(), section(DependencyKind::Dev, None)),
+                (
+                    "c".to_owned(),
+                    section(DependencyKind::Build, Some("cfg(windows)"))
+                ),
+                (
+                    "d".to_owned(),
+                    section(
+                        DependencyKind::Normal,
+                        Some("x86_64-unknown-linux-gnu")
+                    )
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_keys() {
+        let s = indoc! {r#"
+            [dependencies]
+            a = { version = "1", optional = true, default_features = false }
+            b = { version = "1", registry = "internal" }
+            c = { workspace = true, default-features = true }
+            d = "1"
+        "#};
+        let dom = taplo::parser::parse(s).into_dom();
+        let deps = Manifest::parse(s, &dom).dependencies;
+        let value = |span: &Option<Span<bool>>| span.as_ref().map(|s| s.value);
+
+        assert_eq!(value(&deps[0].optional), Some(true));
+        assert_eq!(value(&deps[0].default_features), Some(false));
+        assert_eq!(
+            deps[0].optional.as_ref().unwrap().range,
+            lsp_types::Range {
+                start: lsp_types::Position::new(1, 32),
+                end: lsp_types::Position::new(1, 36),
+            }
+        );
+        assert_eq!(deps[1].registry.as_ref().unwrap().value, "internal");
+        assert!(!deps[1].is_crates_io());
+        assert_eq!(deps[1].alternative_registry(), Some("internal"));
+        assert_eq!(value(&deps[2].workspace), Some(true));
+        assert_eq!(value(&deps[2].default_features), Some(true));
+        assert!(deps[3].optional.is_none() && deps[3].registry.is_none());
+        assert!(deps[3].is_crates_io());
+        assert_eq!(deps[3].alternative_registry(), None);
+    }
+
+    #[test]
+    fn test_redefinitions() {
+        let s = indoc! {r#"
+            [dependencies]
+            a = "1"
+            a = "2"
+
+            [dependencies]
+            b = "1"
+
+            [package]
+            name = "foo"
+            name = "bar"
+
+            [lib]
+            test = true
+
+            [lib.test.foo]
+        "#};
+        let dom = taplo::parser::parse(s).into_dom();
+        let errors = Manifest::parse(s, &dom)
+            .errors
+            .iter()
+            .map(|e| {
+                (e.message(Catalog::for_locale(None)), e.range().start.line)
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            errors,
+            [
+                ("`a` is already defined at line 2".to_owned(), 2),
+                (
+                    "the table `[dependencies]` is already defined at line 1, \
+                     merge them into one"
+                        .to_owned(),
+                    4
+                ),
+                ("`name` is already defined at line 9".to_owned(), 9),
+                (
+                    "`test` must be a table, as it's extended at line 15"
+                        .to_owned(),
+                    12
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_incomplete() {
+        let s = indoc! {r#"
+            [dependencies]
+            serde = "1"
+            tok
+            rand = "0.8"
+
+            [dev-dependencies]
+            insta =
+            invalid = 1
+        "#};
+        let dom = taplo::parser::parse(s).into_dom();
+        let manifest = Manifest::parse(s, &dom);
+
+        let incomplete = manifest
+            .incomplete
+            .iter()
+            .map(|name| (name.value.as_str(), name.range.start.line))
+            .collect::<Vec<_>>();
+        assert_eq!(incomplete, [("tok", 2), ("insta", 6)]);
+        assert_eq!(manifest.dependencies.len(), 2);
+        // values of the wrong type are still errors.
+        assert!(matches!(
+            manifest.errors.as_slice(),
+            [Error::InvalidDependency { .. }]
+        ));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let s = indoc! {r#"
+            [dependencies]
+            a = ["1"]
+            b = { version = 1, features = "full", optional = "yes" }
+            c = { version = "1", features = ["ok", 2] }
+            d = "1"
+        "#};
+        let dom = taplo::parser::parse(s).into_dom();
+        let manifest = Manifest::parse(s, &dom);
+
+        let names = manifest
+            .dependencies
+            .iter()
+            .map(|d| d.name.value.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["b", "c", "d"]);
+
+        let errors = manifest
+            .errors
+            .iter()
+            .map(|e| (e.message(Catalog::for_locale(None)), e.range().start))
+            .collect::<Vec<_>>();
+        let pos = lsp_types::Position::new;
+        let features = "`features` must be an array of strings".to_owned();
+        assert_eq!(
+            errors,
+            [
+                (
+                    "a dependency must be a version requirement string or a \
+                     table"
+                        .to_owned(),
+                    pos(1, 0)
+                ),
+                ("`version` must be a string".to_owned(), pos(2, 16)),
+                (features.clone(), pos(2, 30)),
+                ("`optional` must be a boolean".to_owned(), pos(2, 49)),
+                (features, pos(3, 39)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let parse = |s: &str| {
+            let dom = taplo::parser::parse(s).into_dom();
+            Manifest::parse(s, &dom).dependencies
+        };
+        let before = parse(indoc! {r#"
+            [dependencies]
+            a = "1"
+            b = { version = "1", features = ["x"] }
+
+            [dependencies.c]
+            version = "1"
+        "#});
+        let after = parse(indoc! {r#"
+            [dependencies]
+            new = "1"
+            a = "2"
+              b = { version = "1", features = ["x"] }
+
+            [dependencies.c]
+            version = "2"
+        "#});
+
+        let (a, b, c) = (&before[0], &before[1], &before[2]);
+        let (a2, b2, c2) = (&after[1], &after[2], &after[3]);
+        assert_ne!(a.fingerprint.hash, a2.fingerprint.hash);
+        assert_eq!(b.fingerprint.hash, b2.fingerprint.hash);
+        assert_ne!(c.fingerprint.hash, c2.fingerprint.hash);
+
+        // the unchanged entry moved, so its relative ranges should move too
+        let features = &b.features.as_ref().unwrap()[0];
+        let relative = b.fingerprint.to_relative(features.range);
+        assert_eq!(
+            b2.fingerprint.to_absolute(relative),
+            b2.features.as_ref().unwrap()[0].range
+        );
+    }
+
+    #[test]
+    fn test_parse_crlf_manifest() {
+        let s = "[package]\r\nname = \"foo\"\r\n\r\n[dependencies]\r\n\
+                 serde = \"1\"\r\n";
+        let dom = taplo::parser::parse(s).into_dom();
+        let deps = dom.as_table().unwrap().get("dependencies").unwrap();
+        let entries = deps.as_table().unwrap().entries().read();
+        let (key, node) = entries.iter().next().unwrap();
+        let section = Section { kind: DependencyKind::Normal, target: None };
+        let dependency = Dependency::parse(s, key, node, section).unwrap();
+
+        assert_eq!(
+            dependency.name.range,
+            lsp_types::Range {
+                start: lsp_types::Position::new(4, 0),
+                end: lsp_types::Position::new(4, 5),
+            }
+        );
+        assert_eq!(
+            dependency.version.unwrap().range,
+            lsp_types::Range {
+                start: lsp_types::Position::new(4, 8),
+                end: lsp_types::Position::new(4, 11),
+            }
+        );
+    }
+
+    fn format_range(range: Range) -> String {
+        let Range { start, end } = range;
+        format!(
+            "{}:{}-{}:{}",
+            start.line, start.character, end.line, end.character
+        )
+    }
+
+    /// A readable summary of the dependencies parsed from a manifest and of
+    /// the diagnostics published for it, to snapshot (the fingerprints' hashes
+    /// aren't stable, so the `Debug` output isn't snapshotted as it is).
+    fn summarize(path: &Path, s: &str) -> String {
+        use std::fmt::Write;
+
+        let uri = Url::from_file_path(path).unwrap();
+        let settings = Settings {
+            force_enable: true,
+            publish_lints: true,
+            ..Settings::default()
+        };
+        let manifest =
+            ls::parse_manifest(&uri, s, &settings, None, None).unwrap();
+        let mut out = String::new();
+
+        for dependency in &manifest.dependencies {
+            let Dependency { name, section, kind, version, .. } = dependency;
+            writeln!(
+                out,
+                "{} {} ({})",
+                section.header(),
+                name.value,
+                format_range(name.range)
+            )
+            .unwrap();
+            match kind {
+                Kind::Registry => {}
+                Kind::Git(git) => {
+                    write!(out, "  git: {}", git.url.value).unwrap();
+                    if let Some(specifier) = &git.specifier {
+                        let key = match specifier {
+                            GitSpecifier::Branch(_) => "branch",
+                            GitSpecifier::Tag(_) => "tag",
+                            GitSpecifier::Rev(_) => "rev",
+                        };
+                        write!(out, " ({key} {})", specifier.span().value)
+                            .unwrap();
+                    }
+                    writeln!(out).unwrap();
+                }
+                Kind::Local(local) => {
+                    writeln!(out, "  path: {}", local.path.value.display())
+                        .unwrap();
+                }
+            }
+            if let Some(version) = version {
+                writeln!(out, "  version: {}", version.value.raw).unwrap();
+            }
+            if let Some(features) = &dependency.features {
+                let features = features
+                    .iter()
+                    .map(|f| f.value.as_str())
+                    .collect::<Vec<_>>();
+                writeln!(out, "  features: {}", features.join(", ")).unwrap();
+            }
+            let flags = [
+                ("optional", &dependency.optional),
+                ("default-features", &dependency.default_features),
+                ("workspace", &dependency.workspace),
+            ];
+            for (key, flag) in flags {
+                if let Some(flag) = flag {
+                    writeln!(out, "  {key}: {}", flag.value).unwrap();
+                }
+            }
+            if let Some(registry) = &dependency.registry {
+                writeln!(out, "  registry: {}", registry.value).unwrap();
+            }
+            if let Some(package) = &dependency.package {
+                writeln!(out, "  package: {}", package.value).unwrap();
+            }
+        }
+
+        // the diagnostics that the server publishes, besides the ones of the
+        // registry.
+        for diagnostic in
+            ls::manifest_diagnostics(&manifest, Catalog::for_locale(None))
+                .concat()
+        {
+            let severity = diagnostic.severity.unwrap();
+            writeln!(
+                out,
+                "! {}: {severity:?}: {}",
+                format_range(diagnostic.range),
+                diagnostic.message
+            )
+            .unwrap();
+        }
+        out
+    }
+
+    #[test]
+    fn test_fixtures() {
+        insta::glob!("../tests/fixtures", "*.toml", |path| {
+            let s = std::fs::read_to_string(path).unwrap();
+            insta::assert_snapshot!(summarize(path, &s));
+        });
+    }
 }
@@ -0,0 +1,214 @@
+//! Conversions between URIs and paths, and resolving the `path`s of local
+//! dependencies against the URI of the manifest that declares them.
+//!
+//! The resolution is done on URIs rather than on `Path`s, so that manifests
+//! written on Windows (e.g. `path = "..\\foo"`, or `path = "C:\\foo"`) are
+//! resolved the same way on every platform.
+//!
+//! All of the conversions between URIs and paths should go through here, as
+//! the URIs are percent-encoded (e.g. spaces and non-ASCII directory names),
+//! and the paths might not even be valid UTF-8.
+
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+};
+
+use url::Url;
+
+use crate::parse::MANIFEST_FILE_NAME;
+
+/// Resolve the URI of the manifest of a local dependency (at `path`), that is
+/// declared in the manifest at `manifest`.
+pub fn resolve_manifest(manifest: &Url, path: &str) -> Option<Url> {
+    let mut dir = resolve_dir(manifest, path)?;
+    if !dir.path().ends_with('/') {
+        dir.set_path(&format!("{}/", dir.path()));
+    }
+    dir.join(MANIFEST_FILE_NAME).ok()
+}
+
+/// Resolve the URI of a file (e.g. the `path` of a `[[bin]]`), relative to
+/// the manifest at `manifest`.
+pub fn resolve_file(manifest: &Url, path: &str) -> Option<Url> {
+    resolve_dir(manifest, path)
+}
+
+/// Checks whether there's definitely no file at `uri`, i.e., it's a local
+/// file URI, and the file doesn't exist.
+pub fn is_missing(uri: &Url) -> bool {
+    to_path(uri).is_some_and(|path| !path.exists())
+}
+
+/// The (percent-decoded) file name of a URI, e.g. `Cargo.toml`.
+pub fn file_name(uri: &Url) -> Option<Cow<'_, str>> {
+    let name = uri.path_segments()?.next_back()?;
+    Some(percent_encoding::percent_decode_str(name).decode_utf8_lossy())
+}
+
+/// The local path of a `file:` URI.
+///
+/// Returns `None` for other schemes (e.g. `untitled:`), and on platforms
+/// without a file system.
+pub fn to_path(uri: &Url) -> Option<PathBuf> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        if uri.scheme() != "file" {
+            return None;
+        }
+        uri.to_file_path().ok()
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        let _ = uri;
+        None
+    }
+}
+
+/// The `file:` URI of a local (absolute) path.
+pub fn to_uri(path: &Path) -> Option<Url> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        Url::from_file_path(path).ok()
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        let _ = path;
+        None
+    }
+}
+
+fn resolve_dir(manifest: &Url, path: &str) -> Option<Url> {
+    // both separators are valid on Windows, and `\` is rarely a part of a
+    // file name on other platforms.
+    let path = path.replace('\\', "/");
+
+    if let Some(unc) = path.strip_prefix("//") {
+        // `\\server\share\foo`
+        let (host, path) = unc.split_once('/').unwrap_or((unc, ""));
+        let mut url = Url::parse(&format!("file://{host}/")).ok()?;
+        url.set_path(path);
+        return Some(url);
+    }
+
+    // the path is joined as a URI reference, so the characters that are
+    // special in references (but not in paths) must be escaped.
+    let escaped = path
+        .replace('%', "%25")
+        .replace('?', "%3F")
+        .replace('#', "%23");
+
+    if has_drive_letter(&path) {
+        // `C:/foo` would be parsed as a URI with a `c` scheme.
+        Url::parse(&format!("file:///{escaped}")).ok()
+    } else {
+        // relative paths, and paths that are absolute but have no drive
+        // letter (which on Windows are resolved on the manifest's drive).
+        manifest.join(&escaped).ok()
+    }
+}
+
+fn has_drive_letter(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    bytes.len() >= 2
+        && bytes[0].is_ascii_alphabetic()
+        && bytes[1] == b':'
+        && bytes.get(2).is_none_or(|&b| b == b'/')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(manifest: &str, path: &str) -> String {
+        let manifest = Url::parse(manifest).unwrap();
+        resolve_manifest(&manifest, path).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_resolve_unix() {
+        let manifest = "file:///ws/app/Cargo.toml";
+
+        assert_eq!(resolve(manifest, "../foo"), "file:///ws/foo/Cargo.toml");
+        assert_eq!(resolve(manifest, "foo/"), "file:///ws/app/foo/Cargo.toml");
+        assert_eq!(resolve(manifest, "/abs"), "file:///abs/Cargo.toml");
+        assert_eq!(
+            resolve(manifest, "my crate#1"),
+            "file:///ws/app/my%20crate%231/Cargo.toml"
+        );
+    }
+
+    #[test]
+    fn test_resolve_windows() {
+        let manifest = "file:///C:/ws/app/Cargo.toml";
+
+        assert_eq!(
+            resolve(manifest, "..\\foo"),
+            "file:///C:/ws/foo/Cargo.toml"
+        );
+        assert_eq!(
+            resolve(manifest, "D:\\libs\\foo"),
+            "file:///D:/libs/foo/Cargo.toml"
+        );
+        assert_eq!(resolve(manifest, "\\abs"), "file:///C:/abs/Cargo.toml");
+        // `..` never climbs above the drive.
+        assert_eq!(
+            resolve(manifest, "..\\..\\..\\foo"),
+            "file:///C:/foo/Cargo.toml"
+        );
+        // percent-encoded drive letter colons, as sent by VS Code.
+        assert_eq!(
+            resolve("file:///c%3A/ws/app/Cargo.toml", "..\\foo"),
+            "file:///c%3A/ws/foo/Cargo.toml"
+        );
+    }
+
+    #[test]
+    fn test_file_name() {
+        let name =
+            |uri| file_name(&Url::parse(uri).unwrap()).unwrap().into_owned();
+
+        assert_eq!(name("file:///ws/Cargo.toml"), "Cargo.toml");
+        assert_eq!(name("file:///ws/my%20app/Cargo%2Etoml"), "Cargo.toml");
+        assert_eq!(
+            name("file:///ws/%D7%A9%D7%9C%D7%95%D7%9D.toml"),
+            "שלום.toml"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_path_roundtrip() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let paths = [
+            Path::new("/ws/my app/Cargo.toml"),
+            Path::new("/ws/שלום/日本/Cargo.toml"),
+            Path::new("/ws/100%#?/Cargo.toml"),
+            // not valid UTF-8
+            Path::new(OsStr::from_bytes(b"/ws/\xff\xfe/Cargo.toml")),
+        ];
+        for path in paths {
+            let uri = to_uri(path).unwrap();
+            assert_eq!(to_path(&uri).unwrap(), path, "{uri}");
+        }
+
+        let uri = Url::parse("file:///ws/my%20app/Cargo.toml").unwrap();
+        assert_eq!(to_path(&uri).unwrap(), Path::new("/ws/my app/Cargo.toml"));
+        let uri = Url::parse("file://localhost/ws/Cargo.toml").unwrap();
+        assert_eq!(to_path(&uri).unwrap(), Path::new("/ws/Cargo.toml"));
+        assert_eq!(to_path(&Url::parse("untitled:Untitled-1").unwrap()), None);
+    }
+
+    #[test]
+    fn test_resolve_unc() {
+        assert_eq!(
+            resolve("file:///C:/ws/Cargo.toml", "\\\\server\\share\\foo"),
+            "file://server/share/foo/Cargo.toml"
+        );
+        assert_eq!(
+            resolve("file://server/share/ws/Cargo.toml", "..\\foo"),
+            "file://server/share/foo/Cargo.toml"
+        );
+    }
+}
@@ -0,0 +1,215 @@
+//! The few things that differ between native builds and WASM builds (for
+//! browser-based editors).
+//!
+//! Everything else only relies on runtime agnostic APIs (e.g. `tokio::sync`).
+
+use std::{future::Future, io, path::Path, process::Output, time::Duration};
+
+use chrono::{DateTime, Utc};
+pub use web_time::Instant;
+
+/// The current time, as `chrono`'s clock isn't available on WASM.
+pub fn now() -> DateTime<Utc> {
+    let since_epoch = web_time::SystemTime::now()
+        .duration_since(web_time::UNIX_EPOCH)
+        .unwrap_or_default();
+    DateTime::from_timestamp(
+        since_epoch.as_secs() as i64,
+        since_epoch.subsec_nanos(),
+    )
+    .unwrap_or_default()
+}
+
+/// Read the body of a response, or `None` if it's longer than `limit` bytes.
+///
+/// The body is read in chunks, so a huge body is dropped as soon as it
+/// exceeds the limit, rather than after it's buffered.
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn read_body(
+    mut res: reqwest::Response,
+    limit: usize,
+) -> reqwest::Result<Option<Vec<u8>>> {
+    if res.content_length().is_some_and(|len| len > limit as u64) {
+        return Ok(None);
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = res.chunk().await? {
+        if body.len() + chunk.len() > limit {
+            return Ok(None);
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(Some(body))
+}
+
+/// Read the body of a response, or `None` if it's longer than `limit` bytes.
+///
+/// The browser buffers the body anyway, so only the declared length is
+/// checked before reading it.
+#[cfg(target_arch = "wasm32")]
+pub async fn read_body(
+    res: reqwest::Response,
+    limit: usize,
+) -> reqwest::Result<Option<Vec<u8>>> {
+    if res.content_length().is_some_and(|len| len > limit as u64) {
+        return Ok(None);
+    }
+    let body = res.bytes().await?;
+    Ok((body.len() <= limit).then(|| body.to_vec()))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn sleep(duration: Duration) {
+    tokio::time::sleep(duration).await
+}
+
+#[cfg(target_arch = "wasm32")]
+pub async fn sleep(duration: Duration) {
+    futures_timer::Delay::new(duration).await
+}
+
+/// Make a future `Send`, as the handlers' futures must be.
+///
+/// The futures of `fetch` requests on WASM are not `Send`, but there is only
+/// a single thread there anyway.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn send<F: Future>(future: F) -> F {
+    future
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn send<F: Future>(future: F) -> send_wrapper::SendWrapper<F> {
+    send_wrapper::SendWrapper::new(future)
+}
+
+/// Run a background task (e.g. draining the queue of API requests), which
+/// isn't awaited.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn spawn<F>(future: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    tokio::spawn(future);
+}
+
+/// Run a background task on the browser's event loop.
+#[cfg(target_arch = "wasm32")]
+pub fn spawn<F>(future: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    wasm_bindgen_futures::spawn_local(future);
+}
+
+/// Run blocking work (e.g. walking a directory) off of the async threads.
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn blocking<T, F>(f: F) -> T
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    match tokio::task::spawn_blocking(f).await {
+        Ok(value) => value,
+        Err(err) => std::panic::resume_unwind(err.into_panic()),
+    }
+}
+
+/// Run blocking work, on the only thread there is.
+#[cfg(target_arch = "wasm32")]
+pub async fn blocking<T, F>(f: F) -> T
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    f()
+}
+
+/// Run `cargo` with `args` in `dir`. It's killed if the request is dropped
+/// (e.g. the server exits), so it doesn't outlive the server.
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn cargo(dir: &Path, args: &[&str]) -> io::Result<Output> {
+    tokio::process::Command::new("cargo")
+        .args(args)
+        .current_dir(dir)
+        .kill_on_drop(true)
+        .output()
+        .await
+}
+
+/// Run `cargo` with `args` in `dir`, which the browser can't.
+#[cfg(target_arch = "wasm32")]
+pub async fn cargo(_dir: &Path, _args: &[&str]) -> io::Result<Output> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Run `git` with `args` in `dir` (e.g. to fetch a git index), killing it if
+/// the request is dropped, like [`cargo`].
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn git(dir: &Path, args: &[&str]) -> io::Result<Output> {
+    tokio::process::Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .kill_on_drop(true)
+        .output()
+        .await
+}
+
+/// Run `git` with `args` in `dir`, which the browser can't.
+#[cfg(target_arch = "wasm32")]
+pub async fn git(_dir: &Path, _args: &[&str]) -> io::Result<Output> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// An HTTP client that keeps at most `max_idle` idle connections per host,
+/// going through `proxy` (if any), and trusting the CA certificates of the
+/// PEM bundle `ca` besides the system's.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn http_client(
+    user_agent: &str,
+    max_idle: usize,
+    proxy: Option<&str>,
+    ca: Option<&[u8]>,
+) -> reqwest::Result<reqwest::Client> {
+    let mut builder = reqwest::ClientBuilder::new()
+        .user_agent(user_agent)
+        .pool_max_idle_per_host(max_idle);
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+    }
+    if let Some(ca) = ca {
+        for certificate in reqwest::Certificate::from_pem_bundle(ca)? {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+    builder.build()
+}
+
+/// An HTTP client, whose connections (and proxies and certificates) the
+/// browser manages.
+#[cfg(target_arch = "wasm32")]
+pub fn http_client(
+    user_agent: &str,
+    _max_idle: usize,
+    _proxy: Option<&str>,
+    _ca: Option<&[u8]>,
+) -> reqwest::Result<reqwest::Client> {
+    reqwest::ClientBuilder::new().user_agent(user_agent).build()
+}
+
+/// Fail a request that takes longer than `timeout`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn timeout(
+    request: reqwest::RequestBuilder,
+    timeout: Duration,
+) -> reqwest::RequestBuilder {
+    request.timeout(timeout)
+}
+
+/// The browser's own limits apply to `fetch` requests.
+#[cfg(target_arch = "wasm32")]
+pub fn timeout(
+    request: reqwest::RequestBuilder,
+    _timeout: Duration,
+) -> reqwest::RequestBuilder {
+    request
+}
@@ -0,0 +1,495 @@
+//! The keys of the `[profile.*]` tables, with the values cargo accepts for
+//! them.
+//!
+//! Unlike dependencies, the values of most profile keys are one of a fixed
+//! set, which is mixed of booleans, integers and strings (e.g. `opt-level = 3`
+//! and `opt-level = "s"`), so they are described here rather than with
+//! [`crate::parse::ValueType`].
+
+use taplo::dom::{Node, node::IntegerValue};
+use tower_lsp::lsp_types::{DiagnosticSeverity, Position, Range};
+
+use crate::{
+    messages::Catalog,
+    parse::{self, Span},
+};
+
+#[derive(Debug)]
+pub enum Warning {
+    UnknownKey { key: String, range: Range },
+    InvalidValue {
+        key: &'static str,
+        expected: String,
+        range: Range,
+    },
+    NotOverridable { key: &'static str, range: Range },
+    Ignored {
+        key: &'static str,
+        profile: String,
+        range: Range,
+    },
+    BuiltinInherits { profile: String, range: Range },
+    MissingInherits { profile: String, range: Range },
+}
+
+impl Warning {
+    /// The message of the warning, in the locale of `messages`.
+    pub fn message(&self, messages: Catalog) -> String {
+        match self {
+            Self::UnknownKey { key, .. } => {
+                messages.get("unknown-profile-key", &[("key", key)])
+            }
+            Self::InvalidValue { key, expected, .. } => messages
+                .get("invalid-type", &[("key", key), ("expected", expected)]),
+            Self::NotOverridable { key, .. } => {
+                messages.get("not-overridable", &[("key", key)])
+            }
+            Self::Ignored { key, profile, .. } => messages.get(
+                "ignored-in-profile",
+                &[("key", key), ("profile", profile)],
+            ),
+            Self::BuiltinInherits { profile, .. } => {
+                messages.get("builtin-inherits", &[("profile", profile)])
+            }
+            Self::MissingInherits { profile, .. } => {
+                messages.get("missing-inherits", &[("profile", profile)])
+            }
+        }
+    }
+
+    pub fn range(&self) -> Range {
+        match self {
+            Self::UnknownKey { range, .. }
+            | Self::InvalidValue { range, .. }
+            | Self::NotOverridable { range, .. }
+            | Self::Ignored { range, .. }
+            | Self::BuiltinInherits { range, .. }
+            | Self::MissingInherits { range, .. } => *range,
+        }
+    }
+
+    /// Whether cargo fails on it, rather than ignoring it.
+    pub fn severity(&self) -> DiagnosticSeverity {
+        match self {
+            Self::UnknownKey { .. } | Self::Ignored { .. } => {
+                DiagnosticSeverity::WARNING
+            }
+            _ => DiagnosticSeverity::ERROR,
+        }
+    }
+}
+
+/// A kind of value a profile key accepts.
+#[derive(Debug, Clone, Copy)]
+pub enum Value {
+    Bool,
+    /// An integer in `min..=max`.
+    Int {
+        min: i64,
+        max: i64,
+    },
+    /// One of the strings.
+    Str(&'static [&'static str]),
+    AnyStr,
+    Table,
+}
+
+impl Value {
+    fn matches(self, node: &Node) -> bool {
+        match (self, node) {
+            (Self::Bool, Node::Bool(_)) | (Self::Table, Node::Table(_)) => true,
+            (Self::Int { min, max }, Node::Integer(int)) => {
+                let int = match int.value() {
+                    IntegerValue::Negative(i) => Some(i),
+                    IntegerValue::Positive(i) => i64::try_from(i).ok(),
+                };
+                int.is_some_and(|i| (min..=max).contains(&i))
+            }
+            (Self::Str(values), Node::Str(s)) => values.contains(&s.value()),
+            (Self::AnyStr, Node::Str(_)) => true,
+            _ => false,
+        }
+    }
+
+    fn describe(self) -> Vec<String> {
+        match self {
+            Self::Bool => vec!["`true`".to_owned(), "`false`".to_owned()],
+            Self::Int { min, max } if max - min <= 3 => {
+                (min..=max).map(|i| format!("`{i}`")).collect()
+            }
+            Self::Int { min, .. } => vec![format!("an integer from `{min}`")],
+            Self::Str(values) => {
+                values.iter().map(|v| format!("`\"{v}\"`")).collect()
+            }
+            Self::AnyStr => vec!["a string".to_owned()],
+            Self::Table => vec!["a table".to_owned()],
+        }
+    }
+}
+
+/// A key of a profile table.
+#[derive(Debug)]
+pub struct Key {
+    pub name: &'static str,
+    pub values: &'static [Value],
+    /// Whether it can be set in `package` and `build-override` tables.
+    pub overridable: bool,
+    pub description: &'static str,
+}
+
+impl Key {
+    /// The accepted values, e.g. "`true`, `false` or `\"thin\"`".
+    pub fn expected(&self) -> String {
+        let values = self
+            .values
+            .iter()
+            .flat_map(|v| v.describe())
+            .collect::<Vec<_>>();
+        match values.as_slice() {
+            [] => String::new(),
+            [value] => value.clone(),
+            [init @ .., last] => format!("{} or {last}", init.join(", ")),
+        }
+    }
+}
+
+const fn key(
+    name: &'static str,
+    values: &'static [Value],
+    description: &'static str,
+) -> Key {
+    Key { name, values, overridable: true, description }
+}
+
+pub const KEYS: &[Key] = &[
+    key(
+        "opt-level",
+        &[Value::Int { min: 0, max: 3 }, Value::Str(&["s", "z"])],
+        "The optimization level (`-C opt-level`), `\"s\"` and `\"z\"` \
+         optimize for size.",
+    ),
+    key(
+        "debug",
+        &[
+            Value::Bool,
+            Value::Int { min: 0, max: 2 },
+            Value::Str(&[
+                "none",
+                "line-directives-only",
+                "line-tables-only",
+                "limited",
+                "full",
+            ]),
+        ],
+        "The amount of debug info (`-C debuginfo`).",
+    ),
+    key(
+        "split-debuginfo",
+        &[Value::Str(&["off", "packed", "unpacked"])],
+        "Whether the debug info is placed in separate files \
+         (`-C split-debuginfo`).",
+    ),
+    key(
+        "strip",
+        &[Value::Bool, Value::Str(&["none", "debuginfo", "symbols"])],
+        "What to strip from the binaries (`-C strip`).",
+    ),
+    key(
+        "debug-assertions",
+        &[Value::Bool],
+        "Whether `debug_assert!` and `cfg(debug_assertions)` are enabled.",
+    ),
+    key(
+        "overflow-checks",
+        &[Value::Bool],
+        "Whether integer overflows panic (`-C overflow-checks`).",
+    ),
+    Key {
+        name: "lto",
+        values: &[Value::Bool, Value::Str(&["fat", "thin", "off"])],
+        overridable: false,
+        description: "The link time optimizations (`-C lto`), `true` is \
+                      `\"fat\"`, and `false` is thin local LTO.",
+    },
+    Key {
+        name: "panic",
+        values: &[Value::Str(&["unwind", "abort"])],
+        overridable: false,
+        description: "The panic strategy (`-C panic`).",
+    },
+    key(
+        "incremental",
+        &[Value::Bool],
+        "Whether incremental compilation is enabled.",
+    ),
+    key(
+        "codegen-units",
+        &[Value::Int { min: 1, max: i64::MAX }],
+        "How many units a crate is split into (`-C codegen-units`), fewer \
+         units may produce faster code, but compile slower.",
+    ),
+    Key {
+        name: "rpath",
+        values: &[Value::Bool],
+        overridable: false,
+        description: "Whether the `rpath` is set (`-C rpath`).",
+    },
+    Key {
+        name: "inherits",
+        values: &[Value::AnyStr],
+        overridable: false,
+        description: "The profile a custom profile is based on.",
+    },
+    Key {
+        name: PACKAGE_KEY,
+        values: &[Value::Table],
+        overridable: false,
+        description: "Overrides of the profile for specific packages (e.g. \
+                      `[profile.dev.package.\"*\"]`).",
+    },
+    Key {
+        name: BUILD_OVERRIDE_KEY,
+        values: &[Value::Table],
+        overridable: false,
+        description: "Overrides of the profile for build scripts, proc \
+                      macros, and their dependencies.",
+    },
+];
+
+const PROFILE_KEY: &str = "profile";
+const PACKAGE_KEY: &str = "package";
+const BUILD_OVERRIDE_KEY: &str = "build-override";
+const INHERITS_KEY: &str = "inherits";
+const PANIC_KEY: &str = "panic";
+
+/// The profiles that exist without being declared, and so don't inherit.
+const BUILTIN_PROFILES: &[&str] = &["dev", "release", "test", "bench"];
+
+/// The profiles that are always built with `panic = "unwind"`.
+const UNWINDING_PROFILES: &[&str] = &["test", "bench"];
+
+pub fn find(name: &str) -> Option<&'static Key> {
+    KEYS.iter().find(|k| k.name == name)
+}
+
+/// The keys that can be set in the table at `path` (which is relative to the
+/// root), if it's a profile table.
+pub fn table_keys(path: &[String]) -> Option<Vec<&'static Key>> {
+    let [profile, name, rest @ ..] = path else { return None };
+    if profile != PROFILE_KEY {
+        return None;
+    }
+
+    let keys = match rest {
+        [] => KEYS
+            .iter()
+            .filter(|k| {
+                k.name != INHERITS_KEY
+                    || !BUILTIN_PROFILES.contains(&name.as_str())
+            })
+            .collect(),
+        [package, _] if package == PACKAGE_KEY => {
+            KEYS.iter().filter(|k| k.overridable).collect()
+        }
+        [build_override] if build_override == BUILD_OVERRIDE_KEY => {
+            KEYS.iter().filter(|k| k.overridable).collect()
+        }
+        _ => return None,
+    };
+    Some(keys)
+}
+
+/// The spans of the known keys, and the mistakes in the profile tables.
+#[derive(Debug, Default)]
+pub struct Profiles {
+    pub keys: Vec<Span<&'static Key>>,
+    pub warnings: Vec<Warning>,
+}
+
+pub fn parse(root: &Node, s: &str) -> Profiles {
+    let mut profiles = Profiles::default();
+    let profile = root.get(PROFILE_KEY);
+    let Some(entries) = profile.as_table().map(|t| t.entries().read()) else {
+        return profiles;
+    };
+
+    for (name, table) in entries.iter() {
+        let Some(table) = table.as_table() else { continue };
+        let range = parse::key_range(name, s);
+        let name = name.value();
+        let is_builtin = BUILTIN_PROFILES.contains(&name);
+
+        if !is_builtin
+            && table.get(INHERITS_KEY).is_none()
+            && let Some(range) = range
+        {
+            profiles.warnings.push(Warning::MissingInherits {
+                profile: name.to_owned(),
+                range,
+            });
+        }
+
+        for (key, value) in table.entries().read().iter() {
+            let Some(known) = profiles.check(key, value, false, s) else {
+                continue;
+            };
+            let Some(range) = parse::key_range(key, s) else { continue };
+
+            if known.name == INHERITS_KEY && is_builtin {
+                profiles.warnings.push(Warning::BuiltinInherits {
+                    profile: name.to_owned(),
+                    range,
+                });
+            }
+            if known.name == PANIC_KEY && UNWINDING_PROFILES.contains(&name) {
+                profiles.warnings.push(Warning::Ignored {
+                    key: known.name,
+                    profile: name.to_owned(),
+                    range,
+                });
+            }
+        }
+
+        let overrides = table
+            .get(PACKAGE_KEY)
+            .and_then(|p| p.as_table().cloned())
+            .map(|p| {
+                let entries = p.entries().read();
+                entries.iter().map(|(_, n)| n.clone()).collect::<Vec<_>>()
+            })
+            .unwrap_or_default()
+            .into_iter()
+            .chain(table.get(BUILD_OVERRIDE_KEY));
+        for table in overrides {
+            let Some(table) = table.as_table() else { continue };
+            for (key, value) in table.entries().read().iter() {
+                profiles.check(key, value, true, s);
+            }
+        }
+    }
+
+    profiles
+}
+
+impl Profiles {
+    /// Check a key (and its value) of a profile table, and return it if it's
+    /// known.
+    fn check(
+        &mut self,
+        key: &taplo::dom::node::Key,
+        value: &Node,
+        is_override: bool,
+        s: &str,
+    ) -> Option<&'static Key> {
+        let range = parse::key_range(key, s)?;
+        let Some(known) = find(key.value()) else {
+            self.warnings.push(Warning::UnknownKey {
+                key: key.value().to_owned(),
+                range,
+            });
+            return None;
+        };
+        self.keys.push(Span::new(known, range));
+
+        if is_override && !known.overridable {
+            self.warnings
+                .push(Warning::NotOverridable { key: known.name, range });
+        } else if !known.values.iter().any(|v| v.matches(value))
+            && let Some(range) = parse::node_range(value, s)
+        {
+            self.warnings.push(Warning::InvalidValue {
+                key: known.name,
+                expected: known.expected(),
+                range,
+            });
+        }
+
+        Some(known)
+    }
+
+    /// The known key at `pos`.
+    pub fn find(&self, pos: Position) -> Option<&Span<&'static Key>> {
+        self.keys
+            .iter()
+            .find(|k| k.range.start <= pos && pos <= k.range.end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    fn warnings(s: &str) -> Vec<String> {
+        let dom = taplo::parser::parse(s).into_dom();
+        parse(&dom, s)
+            .warnings
+            .iter()
+            .map(|warning| warning.message(Catalog::for_locale(None)))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let s = indoc! {r#"
+            [profile.release]
+            opt-level = 4
+            lto = "thin"
+            debug = "some"
+            codegen-units = 0
+            inherits = "dev"
+            opt_level = 3
+
+            [profile.test]
+            panic = "abort"
+
+            [profile.dist]
+            strip = true
+
+            [profile.dev.package."*"]
+            opt-level = "z"
+            lto = true
+
+            [profile.dev.build-override]
+            opt-level = 3
+        "#};
+
+        assert_eq!(
+            warnings(s),
+            [
+                "`opt-level` must be `0`, `1`, `2`, `3`, `\"s\"` or `\"z\"`",
+                "`debug` must be `true`, `false`, `0`, `1`, `2`, `\"none\"`, \
+                 `\"line-directives-only\"`, `\"line-tables-only\"`, \
+                 `\"limited\"` or `\"full\"`",
+                "`codegen-units` must be an integer from `1`",
+                "the built-in `release` profile can't inherit another profile",
+                "unknown profile key `opt_level`, it will be ignored by cargo",
+                "`panic` is ignored in the `test` profile",
+                "the custom `dist` profile must set `inherits`",
+                "`lto` can't be overridden for a single package",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_table_keys() {
+        let path =
+            |p: &[&str]| p.iter().map(|&s| s.to_owned()).collect::<Vec<_>>();
+        let names = |p: &[&str]| {
+            table_keys(&path(p))
+                .map(|keys| keys.iter().map(|k| k.name).collect::<Vec<_>>())
+        };
+
+        assert!(names(&["profile", "dev"]).unwrap().contains(&"opt-level"));
+        assert!(!names(&["profile", "dev"]).unwrap().contains(&"inherits"));
+        assert!(names(&["profile", "dist"]).unwrap().contains(&"inherits"));
+        assert!(
+            !names(&["profile", "dev", "package", "foo"])
+                .unwrap()
+                .contains(&"lto")
+        );
+        assert_eq!(names(&["profile"]), None);
+        assert_eq!(names(&["package"]), None);
+    }
+}
@@ -0,0 +1,110 @@
+//! The configuration of the server that is checked in with a project, in
+//! `.crates-ls.toml` files, unlike the [settings](crate::config::Settings)
+//! of each user's editor. E.g.:
+//!
+//! ```toml
+//! # the docs of the crates that aren't on docs.rs (e.g. internal forks of
+//! # crates.io crates), by their names.
+//! [docs]
+//! serde = "https://docs.example.com/serde"
+//! ```
+//!
+//! The files of the manifest's directory and its ancestors are read, and the
+//! closer ones take precedence.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use url::Url;
+
+use crate::path;
+
+const CONFIG_FILE: &str = ".crates-ls.toml";
+
+const DOCS_KEY: &str = "docs";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProjectConfig {
+    /// The docs of crates, by their names, instead of their own.
+    docs: HashMap<String, String>,
+}
+
+impl ProjectConfig {
+    /// Load the configuration that applies to the manifest at `manifest`.
+    pub fn load(manifest: &Url) -> Self {
+        let mut config = Self::default();
+        let manifest = path::to_path(manifest);
+        let dir = manifest.as_deref().and_then(Path::parent);
+        for file in config_files(dir) {
+            if let Ok(s) = std::fs::read_to_string(&file) {
+                config.merge(&s);
+            }
+        }
+        config
+    }
+
+    /// Add the settings of a configuration file, unless they're already set
+    /// (by a file that takes precedence).
+    fn merge(&mut self, s: &str) {
+        let dom = taplo::parser::parse(s).into_dom();
+
+        let docs = dom.get(DOCS_KEY);
+        let docs = docs.as_table().map(|d| d.entries().read());
+        for (name, url) in docs.iter().flat_map(|d| d.iter()) {
+            if let Some(url) = url.as_str() {
+                self.docs
+                    .entry(name.value().to_owned())
+                    .or_insert_with(|| url.value().to_owned());
+            }
+        }
+    }
+
+    /// The docs of the crate `name`, if they're overridden.
+    pub fn docs_url(&self, name: &str) -> Option<&str> {
+        self.docs.get(name).map(String::as_str)
+    }
+}
+
+/// The configuration files that apply in `dir`, from the one that takes
+/// precedence.
+fn config_files(dir: Option<&Path>) -> Vec<PathBuf> {
+    dir.into_iter()
+        .flat_map(Path::ancestors)
+        .map(|dir| dir.join(CONFIG_FILE))
+        .filter(|file| file.is_file())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    #[test]
+    fn test_merge() {
+        let mut config = ProjectConfig::default();
+        config.merge(indoc! {r#"
+            [docs]
+            serde = "https://docs.example.com/serde"
+        "#});
+        config.merge(indoc! {r#"
+            docs.serde = "https://ignored.example.com/serde"
+            docs.internal = "https://docs.example.com/internal"
+            docs.invalid = 1
+        "#});
+
+        assert_eq!(
+            config.docs_url("serde"),
+            Some("https://docs.example.com/serde")
+        );
+        assert_eq!(
+            config.docs_url("internal"),
+            Some("https://docs.example.com/internal")
+        );
+        assert_eq!(config.docs_url("invalid"), None);
+        assert_eq!(config.docs_url("tokio"), None);
+    }
+}
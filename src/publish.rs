@@ -0,0 +1,448 @@
+//! Opt-in lints for the `[package]` table, which catch what would fail (or be
+//! missing from) `cargo publish` before it runs.
+
+use std::path::{Path, PathBuf};
+
+use ignore::gitignore::GitignoreBuilder;
+use taplo::dom::Node;
+use tower_lsp::lsp_types::{DiagnosticSeverity, Range, Url};
+
+use crate::{messages::Catalog, parse, path, workspace};
+
+const PACKAGE_KEY: &str = "package";
+const PUBLISH_KEY: &str = "publish";
+/// The name of crates.io in `publish` (and `registry`) keys.
+const CRATES_IO: &str = "crates-io";
+
+/// The most files to walk when matching the `include`/`exclude` globs, so huge
+/// directories don't stall the diagnostics.
+const MAX_WALKED_FILES: usize = 10_000;
+
+#[derive(Debug)]
+pub enum Lint {
+    MissingRequired { key: &'static str, range: Range },
+    MissingRecommended { key: &'static str, range: Range },
+    NotInherited { key: String, range: Range },
+    MissingLicenseFile { path: String, range: Range },
+    UnmatchedGlob {
+        key: &'static str,
+        pattern: String,
+        range: Range,
+    },
+    InvalidGlob {
+        key: &'static str,
+        pattern: String,
+        reason: String,
+        range: Range,
+    },
+}
+
+impl Lint {
+    /// The message of the lint, in the locale of `messages`.
+    pub fn message(&self, messages: Catalog) -> String {
+        match self {
+            Self::MissingRequired { key, .. } => {
+                messages.get("publish-required", &[("key", key)])
+            }
+            Self::MissingRecommended { key, .. } => {
+                messages.get("publish-recommended", &[("key", key)])
+            }
+            Self::NotInherited { key, .. } => {
+                messages.get("not-inherited", &[("key", key)])
+            }
+            Self::MissingLicenseFile { path, .. } => {
+                messages.get("missing-license-file", &[("path", path)])
+            }
+            Self::UnmatchedGlob { key, pattern, .. } => messages
+                .get("unmatched-glob", &[("key", key), ("pattern", pattern)]),
+            Self::InvalidGlob { key, pattern, reason, .. } => messages.get(
+                "invalid-glob",
+                &[("key", key), ("pattern", pattern), ("reason", reason)],
+            ),
+        }
+    }
+
+    pub fn range(&self) -> Range {
+        match self {
+            Self::MissingRequired { range, .. }
+            | Self::MissingRecommended { range, .. }
+            | Self::NotInherited { range, .. }
+            | Self::MissingLicenseFile { range, .. }
+            | Self::UnmatchedGlob { range, .. }
+            | Self::InvalidGlob { range, .. } => *range,
+        }
+    }
+
+    /// Whether cargo fails on it, rather than publishing a crate that might
+    /// be missing something.
+    pub fn severity(&self) -> DiagnosticSeverity {
+        match self {
+            Self::NotInherited { .. } | Self::InvalidGlob { .. } => {
+                DiagnosticSeverity::ERROR
+            }
+            _ => DiagnosticSeverity::WARNING,
+        }
+    }
+}
+
+/// Where a package can be published to, by its `publish` key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Publish {
+    /// `publish = false` (or `[]`).
+    Nowhere,
+    /// Only to alternative registries (e.g. `publish = ["my-registry"]`).
+    Private,
+    /// To crates.io (which is the default).
+    CratesIo,
+}
+
+impl Publish {
+    fn of(publish: &Node) -> Self {
+        if let Some(publish) = publish.as_bool() {
+            return if publish.value() {
+                Self::CratesIo
+            } else {
+                Self::Nowhere
+            };
+        }
+        let Some(registries) = publish.as_array() else {
+            // unset, or inherited from the workspace.
+            return Self::CratesIo;
+        };
+        let registries = registries.items().read();
+        if registries.is_empty() {
+            Self::Nowhere
+        } else if registries
+            .iter()
+            .any(|r| r.as_str().is_some_and(|r| r.value() == CRATES_IO))
+        {
+            Self::CratesIo
+        } else {
+            Self::Private
+        }
+    }
+}
+
+/// The files of a package, which the `include`/`exclude` globs are matched
+/// against. They're walked once (e.g. when the manifest is opened or saved),
+/// as walking them on every change would stall the diagnostics.
+#[derive(Debug, Clone, Default)]
+pub struct Files {
+    /// The paths of the files and directories, and whether they're
+    /// directories.
+    entries: Vec<(PathBuf, bool)>,
+}
+
+impl Files {
+    /// Walk the package of the (local) manifest at `uri`, skipping its
+    /// `target` and `.git` directories.
+    pub fn walk(uri: &Url) -> Option<Self> {
+        let path = path::to_path(uri)?;
+        let dir = path.parent()?;
+        let entries = ignore::WalkBuilder::new(dir)
+            .standard_filters(false)
+            .filter_entry(|e| {
+                !matches!(e.file_name().to_str(), Some("target" | ".git"))
+            })
+            .build()
+            .skip(1) // the directory itself
+            .take(MAX_WALKED_FILES)
+            .filter_map(Result::ok)
+            .map(|e| {
+                let is_dir = e.file_type().is_some_and(|t| t.is_dir());
+                (e.into_path(), is_dir)
+            })
+            .collect();
+        Some(Self { entries })
+    }
+
+    /// Checks whether the (gitignore-style, like cargo's) `pattern` matches
+    /// any of the files, relative to `dir`.
+    fn matches_any(&self, dir: &Path, pattern: &str) -> bool {
+        let mut builder = GitignoreBuilder::new(dir);
+        // invalid patterns are reported by `check_glob`.
+        if builder.add_line(None, pattern).is_err() {
+            return true;
+        }
+        let Ok(matcher) = builder.build() else { return true };
+
+        self.entries
+            .iter()
+            .any(|(path, is_dir)| matcher.matched(path, *is_dir).is_ignore())
+    }
+}
+
+/// Lint the `[package]` table of the manifest at `uri`.
+///
+/// Packages that can't be published (`publish = false`) are never linted, and
+/// the requirements of crates.io are only checked if it's one of the
+/// registries the package can be published to. The lints that check the file
+/// system are only applied to local manifests, and the globs are only
+/// matched once the `files` of the package are walked.
+///
+/// The keys inherited from the workspace (e.g. `description.workspace =
+/// true`) are looked up in its `[workspace.package]` table (`workspace`),
+/// and are assumed to be set when the workspace isn't known.
+pub fn lint(
+    root: &Node,
+    s: &str,
+    uri: &Url,
+    workspace: Option<&Node>,
+    files: Option<&Files>,
+) -> Vec<Lint> {
+    let mut lints = Vec::new();
+    let package = root.get(PACKAGE_KEY);
+    let Some(table) = package.as_table() else { return lints };
+    let inherits = |key: &str| {
+        workspace.is_none_or(|workspace| {
+            workspace.as_table().is_some_and(|t| t.get(key).is_some())
+        })
+    };
+
+    let mut publish = package.get(PUBLISH_KEY);
+    if let Some(workspace) = workspace
+        && workspace::is_inherited(&publish)
+    {
+        publish = workspace.get(PUBLISH_KEY);
+    }
+    let publish = Publish::of(&publish);
+    if publish == Publish::Nowhere {
+        return lints;
+    }
+
+    // point at the `package` key (e.g. the `[package]` header).
+    let Some(package_range) = root.as_table().and_then(|root| {
+        let entries = root.entries().read();
+        let (key, _) =
+            entries.iter().find(|(k, _)| k.value() == PACKAGE_KEY)?;
+        parse::key_range(key, s)
+    }) else {
+        return lints;
+    };
+
+    for key in workspace::inherited_keys(root, s) {
+        if !inherits(&key.value) {
+            lints.push(Lint::NotInherited { key: key.value, range: key.range });
+        }
+    }
+
+    // the keys that aren't set in the workspace are already linted above.
+    let has = |key| table.get(key).is_some();
+    let crates_io = publish == Publish::CratesIo;
+    if crates_io && !has("description") {
+        lints.push(Lint::MissingRequired {
+            key: "description",
+            range: package_range,
+        });
+    }
+    if crates_io && !has("license") && !has("license-file") {
+        lints.push(Lint::MissingRequired {
+            key: "license",
+            range: package_range,
+        });
+    }
+    if crates_io && !has("repository") {
+        lints.push(Lint::MissingRecommended {
+            key: "repository",
+            range: package_range,
+        });
+    }
+
+    // the globs that cargo can parse, to be matched against the files.
+    let mut globs = Vec::new();
+    for key in ["include", "exclude"] {
+        let Some(patterns) = table.get(key) else { continue };
+        let Some(patterns) = patterns.as_array() else { continue };
+        for pattern in patterns.items().read().iter() {
+            let Some(pattern_str) = pattern.as_str() else { continue };
+            let Some(range) = parse::node_range(pattern, s) else { continue };
+            let pattern = pattern_str.value().to_owned();
+            match check_glob(&pattern) {
+                Ok(()) => globs.push((key, pattern, range)),
+                Err(reason) => lints.push(Lint::InvalidGlob {
+                    key,
+                    pattern,
+                    reason,
+                    range,
+                }),
+            }
+        }
+    }
+
+    let Some(dir) = path::to_path(uri)
+        .as_deref()
+        .and_then(Path::parent)
+        .map(Path::to_path_buf)
+    else {
+        return lints;
+    };
+
+    if let Some(node) = table.get("license-file")
+        && let Some(license_file) = node.as_str()
+        && !dir.join(license_file.value()).exists()
+        && let Some(range) = parse::node_range(&node, s)
+    {
+        lints.push(Lint::MissingLicenseFile {
+            path: license_file.value().to_owned(),
+            range,
+        });
+    }
+
+    let Some(files) = files else { return lints };
+    for (key, pattern, range) in globs {
+        if !files.matches_any(&dir, &pattern) {
+            lints.push(Lint::UnmatchedGlob { key, pattern, range });
+        }
+    }
+
+    lints
+}
+
+/// Checks that cargo can parse the (gitignore-style) `pattern`, or why not.
+fn check_glob(pattern: &str) -> Result<(), String> {
+    match GitignoreBuilder::new("").add_line(None, pattern) {
+        Ok(_) => Ok(()),
+        Err(ignore::Error::Glob { err, .. }) => Err(err),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use indoc::indoc;
+
+    use super::*;
+
+    fn lint(dir: &Path, s: &str) -> Vec<String> {
+        lint_in_workspace(dir, s, None)
+    }
+
+    fn lint_in_workspace(
+        dir: &Path,
+        s: &str,
+        workspace: Option<&str>,
+    ) -> Vec<String> {
+        let dom = taplo::parser::parse(s).into_dom();
+        let uri = path::to_uri(&dir.join("Cargo.toml")).unwrap();
+        let workspace = workspace.map(|w| {
+            let dom = taplo::parser::parse(w).into_dom();
+            dom.get("workspace").get(PACKAGE_KEY)
+        });
+        let files = Files::walk(&uri);
+        super::lint(&dom, s, &uri, workspace.as_ref(), files.as_ref())
+            .iter()
+            .map(|lint| lint.message(Catalog::for_locale(None)))
+            .collect()
+    }
+
+    #[test]
+    fn test_lint() {
+        let dir = std::env::temp_dir()
+            .join(format!("crates-ls-publish-{}", std::process::id()));
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("src/lib.rs"), "").unwrap();
+        fs::write(dir.join("LICENSE"), "").unwrap();
+
+        let s = indoc! {r#"
+            [package]
+            name = "foo"
+            license-file = "LICENSE-MIT"
+            include = ["src/**/*.rs", "/README.md", "*.rs"]
+            exclude = ["benches/"]
+        "#};
+        assert_eq!(
+            lint(&dir, s),
+            [
+                "`description` is required to publish to crates.io",
+                "`repository` is missing, it's recommended for crates.io",
+                "no such license file `LICENSE-MIT`",
+                "the `include` pattern `/README.md` doesn't match any file",
+                "the `exclude` pattern `benches/` doesn't match any file",
+            ]
+        );
+
+        let s = indoc! {r#"
+            [package]
+            name = "foo"
+            description = "Foo"
+            license-file = "LICENSE"
+            repository = "https://github.com/foo/foo"
+        "#};
+        assert!(lint(&dir, s).is_empty());
+
+        let s = "[package]\nname = \"foo\"\npublish = false\n";
+        assert!(lint(&dir, s).is_empty());
+        let s = "[package]\nname = \"foo\"\npublish = []\n";
+        assert!(lint(&dir, s).is_empty());
+
+        let s = indoc! {r#"
+            [package]
+            name = "foo"
+            publish = ["my-registry"]
+            include = ["src/[ab.rs", "src/*.rs"]
+        "#};
+        assert_eq!(
+            lint(&dir, s),
+            ["invalid `include` pattern `src/[ab.rs`: unclosed character \
+              class; missing ']'"]
+        );
+
+        // private registries don't require crates.io's metadata, but the
+        // packaged files are still checked.
+        let s = indoc! {r#"
+            [package]
+            name = "foo"
+            publish = ["my-registry"]
+            license-file = "LICENSE-MIT"
+        "#};
+        assert_eq!(lint(&dir, s), ["no such license file `LICENSE-MIT`"]);
+        let s = indoc! {r#"
+            [package]
+            name = "foo"
+            publish = ["my-registry", "crates-io"]
+            description = "Foo"
+            license = "MIT"
+        "#};
+        assert_eq!(
+            lint(&dir, s),
+            ["`repository` is missing, it's recommended for crates.io"]
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_lint_inherited() {
+        let dir = std::env::temp_dir();
+        let s = indoc! {r#"
+            [package]
+            name = "foo"
+            description.workspace = true
+            license = { workspace = true }
+            repository.workspace = true
+            publish.workspace = true
+        "#};
+        // the inherited keys are assumed to be set in an unknown workspace.
+        assert!(lint(&dir, s).is_empty());
+
+        let workspace = indoc! {r#"
+            [workspace.package]
+            description = "Foo"
+            license = "MIT"
+        "#};
+        assert_eq!(
+            lint_in_workspace(&dir, s, Some(workspace)),
+            [
+                "`repository` is inherited, but the workspace doesn't set \
+                 `workspace.package.repository`",
+                "`publish` is inherited, but the workspace doesn't set \
+                 `workspace.package.publish`",
+            ]
+        );
+
+        let workspace = "[workspace.package]\npublish = false\n";
+        assert!(lint_in_workspace(&dir, s, Some(workspace)).is_empty());
+    }
+}
@@ -0,0 +1,344 @@
+//! The summaries of the READMEs of crates, for the hovers.
+//!
+//! crates.io serves the READMEs rendered as HTML, so they're turned back
+//! into (a subset of) Markdown: headings, paragraphs, lists, code, emphasis
+//! and links are kept, while images (e.g. badges) and anything else are
+//! dropped. Only the beginning of a README is kept, as a hover isn't the
+//! place to read all of it.
+
+/// The maximal length of a summary (in bytes), after which the rest of the
+/// README is cut, at the end of a block.
+const MAX_LENGTH: usize = 1500;
+
+/// The elements whose contents are never shown.
+const HIDDEN: &[&str] = &["script", "style", "svg", "template", "head"];
+
+/// The Markdown summary of a README that was rendered to `html`, or `None`
+/// if there's nothing to show.
+pub fn summary(html: &str) -> Option<String> {
+    let markdown = to_markdown(html);
+    let mut summary = String::new();
+    for block in markdown.split("\n\n") {
+        let separator = if summary.is_empty() { 0 } else { 2 };
+        if summary.len() + separator + block.len() > MAX_LENGTH {
+            if summary.is_empty() {
+                // a first block that's too long on its own is cut at the last
+                // word that fits.
+                let end = block.floor_char_boundary(MAX_LENGTH);
+                let end =
+                    block[..end].rfind(char::is_whitespace).unwrap_or(end);
+                summary.push_str(block[..end].trim_end());
+            }
+            // a cut code block is closed, so the rest isn't code.
+            if summary.matches("```").count() % 2 == 1 {
+                summary.push_str("\n```");
+            }
+            summary.push_str("\n\n…");
+            break;
+        }
+        if !summary.is_empty() {
+            summary.push_str("\n\n");
+        }
+        summary.push_str(block);
+    }
+    (!summary.is_empty()).then_some(summary)
+}
+
+/// Convert the (rendered) HTML of a README to Markdown.
+fn to_markdown(html: &str) -> String {
+    let mut out = String::new();
+    // the start of the text of each open link in `out`, and its target.
+    let mut links = Vec::<(usize, Option<String>)>::new();
+    let mut lists = 0usize;
+    let mut in_pre = false;
+    let mut hidden = None::<String>;
+
+    let mut rest = html;
+    while !rest.is_empty() {
+        let Some(start) = rest.find('<') else {
+            text(&mut out, rest, in_pre);
+            break;
+        };
+        if hidden.is_none() {
+            text(&mut out, &rest[..start], in_pre);
+        }
+        rest = &rest[start..];
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.split_once("-->").map_or("", |(_, rest)| rest);
+            continue;
+        }
+        let Some(end) = rest.find('>') else { break };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        if let Some(hidden_name) = &hidden {
+            if closing && &name == hidden_name {
+                hidden = None;
+            }
+            continue;
+        }
+        // a self-closing element (e.g. `<svg/>`) has no contents to hide.
+        if !closing && !tag.ends_with('/') && HIDDEN.contains(&name.as_str()) {
+            hidden = Some(name);
+            continue;
+        }
+
+        match (name.as_str(), closing) {
+            ("h1" | "h2" | "h3" | "h4" | "h5" | "h6", false) => {
+                let level = name[1..].parse().unwrap_or(1);
+                block(&mut out);
+                out.push_str(&"#".repeat(level));
+                out.push(' ');
+            }
+            ("p" | "div" | "blockquote" | "table" | "tr" | "hr", _)
+            | ("h1" | "h2" | "h3" | "h4" | "h5" | "h6", true) => {
+                block(&mut out);
+            }
+            ("br", _) => out.push('\n'),
+            ("pre", false) => {
+                block(&mut out);
+                out.push_str("```\n");
+                in_pre = true;
+            }
+            ("pre", true) => {
+                if !out.ends_with('\n') {
+                    out.push('\n');
+                }
+                out.push_str("```");
+                block(&mut out);
+                in_pre = false;
+            }
+            ("code", _) if !in_pre => out.push('`'),
+            ("strong" | "b", _) => out.push_str("**"),
+            ("em" | "i", _) => out.push('*'),
+            ("ul" | "ol", false) => {
+                lists += 1;
+                line(&mut out);
+            }
+            ("ul" | "ol", true) => {
+                lists = lists.saturating_sub(1);
+                if lists == 0 {
+                    block(&mut out);
+                }
+            }
+            ("li", false) => {
+                line(&mut out);
+                out.push_str(&"  ".repeat(lists.saturating_sub(1)));
+                out.push_str("- ");
+            }
+            ("a", false) => links.push((out.len(), attribute(tag, "href"))),
+            ("a", true) => {
+                let Some((start, href)) = links.pop() else { continue };
+                let text = out[start..].trim().to_owned();
+                out.truncate(start);
+                // links around images (e.g. badges) are dropped with them,
+                // and relative ones lead nowhere outside of the repository.
+                match href.filter(|h| h.starts_with("http")) {
+                    _ if text.is_empty() => {}
+                    Some(href) => out.push_str(&format!("[{text}]({href})")),
+                    None => out.push_str(&text),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // trailing spaces, and the blank lines of dropped elements.
+    let mut markdown = String::new();
+    let mut blank = true;
+    for line in out.lines().map(str::trim_end) {
+        if line.trim().is_empty() {
+            blank = true;
+            continue;
+        }
+        if !markdown.is_empty() {
+            markdown.push_str(if blank { "\n\n" } else { "\n" });
+        }
+        markdown.push_str(line);
+        blank = false;
+    }
+    markdown
+}
+
+/// Add the `text` between tags, whose whitespace is collapsed (unless it's
+/// preformatted).
+fn text(out: &mut String, text: &str, preformatted: bool) {
+    let text = decode_entities(text);
+    if preformatted {
+        out.push_str(&text);
+        return;
+    }
+    for (i, word) in text.split_whitespace().enumerate() {
+        let starts_word = i > 0 || text.starts_with(char::is_whitespace);
+        if starts_word && !out.ends_with([' ', '\n']) {
+            out.push(' ');
+        }
+        out.push_str(word);
+    }
+    if text.ends_with(char::is_whitespace) && !out.ends_with([' ', '\n']) {
+        out.push(' ');
+    }
+}
+
+/// Start a new block (e.g. a paragraph).
+fn block(out: &mut String) {
+    out.push_str("\n\n");
+}
+
+/// Start a new line, unless the output is at the start of one.
+fn line(out: &mut String) {
+    if !out.is_empty() && !out.ends_with('\n') {
+        out.push('\n');
+    }
+}
+
+/// The value of the attribute `name` of a tag, e.g. the `href` of
+/// `a href="https://example.com"`.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let (_, value) = tag.split_once(&format!("{name}="))?;
+    let value = match value.chars().next()? {
+        quote @ ('"' | '\'') => value[1..].split(quote).next()?,
+        _ => value.split_whitespace().next()?,
+    };
+    Some(decode_entities(value))
+}
+
+/// Decode the character references of HTML text, e.g. `&amp;` as `&`.
+fn decode_entities(s: &str) -> String {
+    let mut decoded = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let reference = rest[1..]
+            .find(';')
+            .filter(|&end| end <= 10)
+            .map(|end| &rest[1..=end]);
+        let c = reference.and_then(|r| match r {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => {
+                let code = r.strip_prefix('#')?;
+                let code = match code.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => code.parse().ok()?,
+                };
+                char::from_u32(code)
+            }
+        });
+        match (c, reference) {
+            (Some(c), Some(reference)) => {
+                decoded.push(c);
+                rest = &rest[reference.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    #[test]
+    fn test_to_markdown() {
+        let html = indoc! {r#"
+            <h1>Serde</h1>
+            <p><a href="https://crates.io/crates/serde"><img src="badge.svg"></a>
+            <a href="./LICENSE">License</a></p>
+            <p><strong>Serde is a framework for <em>ser</em>ializing and
+            deserializing Rust data structures.</strong> See
+            <a href="https://serde.rs">the book</a> &amp; <code>serde_json</code>.</p>
+            <ul>
+            <li>fast</li>
+            <li>generic<ul><li>nested</li></ul></li>
+            </ul>
+            <!-- a <comment> -->
+            <pre><code class="language-rust">let x = 1 &lt; 2;
+
+            println!("{x}");
+            </code></pre>
+            <script>alert("hidden")</script>
+            <p><svg class="icon"/>Shown</p>
+        "#};
+
+        assert_eq!(
+            to_markdown(html),
+            indoc! {r#"
+                # Serde
+
+                License
+
+                **Serde is a framework for *ser*ializing and deserializing Rust data structures.** See [the book](https://serde.rs) & `serde_json`.
+
+                - fast
+                - generic
+                  - nested
+
+                ```
+                let x = 1 < 2;
+
+                println!("{x}");
+                ```
+
+                Shown"#}
+        );
+    }
+
+    #[test]
+    fn test_summary() {
+        assert_eq!(summary("<p>  </p><img src=\"logo.png\">"), None);
+
+        let paragraph = format!("<p>{}</p>", "word ".repeat(100));
+        let summary = summary(&paragraph.repeat(10)).unwrap();
+        assert!(summary.len() <= MAX_LENGTH + "\n\n…".len());
+        assert!(summary.ends_with("\n\n…"));
+
+        let html = format!("<p>{}</p><pre>{}</pre>", "a ".repeat(200), {
+            "code\n\n".repeat(400)
+        });
+        let summary = super::summary(&html).unwrap();
+        assert_eq!(summary.matches("```").count(), 2);
+
+        // a single block, longer than a summary.
+        let summary =
+            super::summary(&paragraph.repeat(10).replace("</p><p>", ""));
+        let summary = summary.unwrap();
+        assert!(summary.len() <= MAX_LENGTH + "\n\n…".len());
+        assert!(summary.ends_with("word\n\n…"));
+        let code =
+            super::summary(&format!("<pre>{}</pre>", "code\n".repeat(400)));
+        let code = code.unwrap();
+        assert!(code.len() <= MAX_LENGTH + "\n```\n\n…".len());
+        assert!(code.starts_with("```") && code.ends_with("code\n```\n\n…"));
+    }
+
+    #[test]
+    fn test_decode_entities() {
+        assert_eq!(
+            decode_entities("a &amp;&lt;b&gt; &#39;&#x41;"),
+            "a &<b> 'A"
+        );
+        assert_eq!(decode_entities("R&D & co &unknown;"), "R&D & co &unknown;");
+    }
+}
@@ -0,0 +1,224 @@
+//! Hints about the feature resolver (`resolver = "1"` or `"2"`), which cargo
+//! only warns about (or silently applies) when building.
+
+use taplo::dom::Node;
+use tower_lsp::lsp_types::Range;
+
+use crate::{
+    messages::Catalog,
+    parse::{self, Dependency, DependencyKind},
+};
+
+/// The docs of the differences between the resolvers.
+pub const DOCS_URL: &str = "https://doc.rust-lang.org/cargo/reference/resolver.html#feature-resolver-version-2";
+
+#[derive(Debug)]
+pub enum Warning {
+    VirtualDefault { edition: String, range: Range },
+    UnifiedFeatures {
+        name: String,
+        section: String,
+        range: Range,
+    },
+}
+
+impl Warning {
+    /// The message of the warning, in the locale of `messages`.
+    pub fn message(&self, messages: Catalog) -> String {
+        match self {
+            Self::VirtualDefault { edition, .. } => {
+                messages.get("virtual-resolver", &[("edition", edition)])
+            }
+            Self::UnifiedFeatures { name, section, .. } => messages.get(
+                "resolver-unified-features",
+                &[("name", name), ("section", section)],
+            ),
+        }
+    }
+
+    pub fn range(&self) -> Range {
+        match self {
+            Self::VirtualDefault { range, .. }
+            | Self::UnifiedFeatures { range, .. } => *range,
+        }
+    }
+}
+
+const PACKAGE_KEY: &str = "package";
+const WORKSPACE_KEY: &str = "workspace";
+const RESOLVER_KEY: &str = "resolver";
+const EDITION_KEY: &str = "edition";
+
+/// The first edition that implies `resolver = "2"`.
+const RESOLVER_2_EDITION: &str = "2021";
+
+pub fn validate(
+    root: &Node,
+    s: &str,
+    dependencies: &[Dependency],
+) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    let package = root.get(PACKAGE_KEY);
+    let workspace = root.get(WORKSPACE_KEY);
+
+    let resolver = [&workspace, &package].into_iter().find_map(|t| {
+        t.get(RESOLVER_KEY).as_str().map(|r| r.value().to_owned())
+    });
+    // the workspace's edition is only used by inheriting packages (and by
+    // cargo's warning for virtual workspaces).
+    let workspace_edition = workspace.get(PACKAGE_KEY).get(EDITION_KEY);
+    let workspace_edition = workspace_edition.as_str().map(|e| e.value());
+    let edition = match package.get(EDITION_KEY) {
+        Node::Str(edition) => Some(edition.value().to_owned()),
+        Node::Table(_) => workspace_edition.map(str::to_owned),
+        _ => None,
+    };
+
+    let is_virtual = package.as_table().is_none();
+    if is_virtual {
+        if resolver.is_none()
+            && let Some(edition) = workspace_edition
+            && is_resolver_2_edition(edition)
+            && let Some(range) = key_range(root, WORKSPACE_KEY, s)
+        {
+            warnings.push(Warning::VirtualDefault {
+                edition: edition.to_owned(),
+                range,
+            });
+        }
+        return warnings;
+    }
+
+    let is_resolver_1 = match resolver.as_deref() {
+        Some(resolver) => resolver == "1",
+        None => edition.as_deref().is_none_or(|e| !is_resolver_2_edition(e)),
+    };
+    if is_resolver_1 {
+        warnings.extend(unified_features(dependencies));
+    }
+
+    warnings
+}
+
+/// The dependencies that are declared more than once with different
+/// features, but in tables that only the second resolver keeps apart (e.g.
+/// `[dev-dependencies]` and `[dependencies]`).
+fn unified_features(dependencies: &[Dependency]) -> Vec<Warning> {
+    let is_separate = |d: &Dependency| {
+        d.section.kind != DependencyKind::Normal || d.section.target.is_some()
+    };
+
+    dependencies
+        .iter()
+        .filter(|&d| is_separate(d))
+        .filter(|&d| {
+            dependencies.iter().any(|other| {
+                other.name.value == d.name.value
+                    && other.section != d.section
+                    && features(other) != features(d)
+            })
+        })
+        .map(|d| Warning::UnifiedFeatures {
+            name: d.name.value.clone(),
+            section: d.section.header(),
+            range: d.name.range,
+        })
+        .collect()
+}
+
+/// The enabled features of a dependency (sorted), and whether its default
+/// features are enabled.
+fn features(dependency: &Dependency) -> (Vec<&str>, bool) {
+    let mut features = dependency
+        .features
+        .iter()
+        .flatten()
+        .map(|f| f.value.as_str())
+        .collect::<Vec<_>>();
+    features.sort_unstable();
+    let default = dependency.default_features.as_ref().is_none_or(|d| d.value);
+    (features, default)
+}
+
+fn is_resolver_2_edition(edition: &str) -> bool {
+    // the editions are years, so they're ordered as strings.
+    edition >= RESOLVER_2_EDITION
+}
+
+fn key_range(root: &Node, key: &str, s: &str) -> Option<Range> {
+    let entries = root.as_table()?.entries().read();
+    let (key, _) = entries.iter().find(|(k, _)| k.value() == key)?;
+    parse::key_range(key, s)
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    fn warnings(s: &str) -> Vec<String> {
+        let dom = taplo::parser::parse(s).into_dom();
+        let manifest = parse::Manifest::parse(s, &dom);
+        manifest
+            .resolver_warnings
+            .iter()
+            .map(|warning| warning.message(Catalog::for_locale(None)))
+            .collect()
+    }
+
+    #[test]
+    fn test_virtual_workspace() {
+        let s = indoc! {r#"
+            [workspace]
+            members = ["a", "b"]
+
+            [workspace.package]
+            edition = "2021"
+        "#};
+        assert_eq!(
+            warnings(s),
+            ["this virtual workspace defaults to `resolver = \"1\"`, \
+                 despite edition 2021 implying `resolver = \"2\"`, set \
+                 `resolver` explicitly"]
+        );
+
+        let s = s.replace("[workspace]\n", "[workspace]\nresolver = \"2\"\n");
+        assert!(warnings(&s).is_empty());
+    }
+
+    #[test]
+    fn test_unified_features() {
+        let s = indoc! {r#"
+            [package]
+            name = "foo"
+            edition = "2018"
+
+            [dependencies]
+            serde = "1"
+            tokio = { version = "1", features = ["rt"] }
+
+            [dev-dependencies]
+            serde = { version = "1", features = ["derive"] }
+            tokio = { version = "1", features = ["rt"] }
+
+            [target.'cfg(unix)'.dependencies]
+            serde = { version = "1", default-features = false }
+        "#};
+        assert_eq!(
+            warnings(s),
+            [
+                "with `resolver = \"1\"`, the features of `serde` in \
+                 `[dev-dependencies]` are unified with the ones in the other \
+                 tables",
+                "with `resolver = \"1\"`, the features of `serde` in \
+                 `[target.'cfg(unix)'.dependencies]` are unified with the ones \
+                 in the other tables",
+            ]
+        );
+
+        // the second resolver is implied by the edition.
+        let s = s.replace("2018", "2021");
+        assert!(warnings(&s).is_empty());
+    }
+}
@@ -0,0 +1,551 @@
+//! The JSON Schema of the [settings](crate::config::Settings), returned by
+//! the `crates/settingsSchema` request so editor plugins can offer validated
+//! configuration UIs, and used to find the settings the server doesn't know
+//! (e.g. typos), which are ignored otherwise.
+
+use serde_json::{Value, json};
+
+/// The name of the custom request, which takes no params and returns
+/// [`schema`].
+pub const METHOD: &str = "crates/settingsSchema";
+
+/// The JSON Schema (draft 7) of the settings.
+///
+/// It's part of the public interface of the server: settings are only ever
+/// added to it, so the configuration UIs built on it keep working.
+pub fn schema() -> Value {
+    let seconds = |description: &str| {
+        json!({
+            "type": ["integer", "null"],
+            "minimum": 0,
+            "default": null,
+            "description": description,
+        })
+    };
+    let severities = ["hint", "information", "warning", "error"];
+    let sections = [
+        "version",
+        "lock",
+        "declaration",
+        "native",
+        "registry",
+        "links",
+        "downloads",
+        "size",
+        "publisher",
+        "description",
+        "features",
+        "readme",
+    ];
+
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "crates-language-server settings",
+        "type": "object",
+        "additionalProperties": false,
+        "properties": {
+            "forceEnable": {
+                "type": "boolean",
+                "default": false,
+                "description": "Treat every TOML document as a Cargo manifest.",
+            },
+            "outdatedSeverity": {
+                "type": "object",
+                "additionalProperties": false,
+                "properties": {
+                    "patch": {
+                        "enum": severities,
+                        "default": "hint",
+                        "description": "The severity of the outdated version \
+                            hint when only a patch release is newer.",
+                    },
+                    "minor": {
+                        "enum": severities,
+                        "default": "information",
+                        "description": "The severity of the outdated version \
+                            hint when a compatible minor release is newer.",
+                    },
+                    "major": {
+                        "enum": severities,
+                        "default": "warning",
+                        "description": "The severity of the outdated version \
+                            hint when a breaking release is newer (including \
+                            the minor releases of 0.x versions).",
+                    },
+                },
+            },
+            "outdatedWarning": {
+                "type": "object",
+                "additionalProperties": false,
+                "properties": {
+                    "majorsBehind": {
+                        "type": ["integer", "null"],
+                        "minimum": 0,
+                        "default": null,
+                        "description": "Show the outdated version hint as a \
+                            warning when the declared version is more than \
+                            this many major versions behind the latest.",
+                    },
+                    "yearsBehind": {
+                        "type": ["number", "null"],
+                        "minimum": 0,
+                        "default": null,
+                        "description": "Show the outdated version hint as a \
+                            warning when the declared version was released \
+                            more than this many years before the latest.",
+                    },
+                },
+            },
+            "gotoDefinition": {
+                "enum": [
+                    "auto",
+                    "location",
+                    "showDocument",
+                    "message",
+                    "disabled",
+                ],
+                "default": "auto",
+                "description": "What goto definition does with the docs of \
+                    a dependency.",
+            },
+            "publishLints": {
+                "type": "boolean",
+                "default": false,
+                "description": "Warn about what `cargo publish` would reject \
+                    or leave out.",
+            },
+            "prereleases": {
+                "type": "boolean",
+                "default": false,
+                "description": "Let pre-releases be the latest versions of \
+                    crates.",
+            },
+            "cache": {
+                "type": "object",
+                "additionalProperties": false,
+                "properties": {
+                    "maxCrates": {
+                        "type": ["integer", "null"],
+                        "minimum": 0,
+                        "default": null,
+                        "description": "The maximal number of crates kept in \
+                            the (in-memory) cache.",
+                    },
+                    "ttl": seconds(
+                        "Fetch a cached crate again after this many seconds.",
+                    ),
+                    "prefetchLockfile": {
+                        "type": "boolean",
+                        "default": true,
+                        "description": "On startup, fetch the crates.io \
+                            packages of the `Cargo.lock` of each workspace \
+                            folder.",
+                    },
+                    "disk": {
+                        "type": "boolean",
+                        "default": true,
+                        "description": "Keep the fetched index files in the \
+                            user's cache directory.",
+                    },
+                    "diskTtl": {
+                        "type": "integer",
+                        "minimum": 0,
+                        "default": 3600,
+                        "description": "Use a file of the disk cache without \
+                            asking the registry whether it changed for this \
+                            many seconds.",
+                    },
+                    "diskMaxSize": {
+                        "type": ["integer", "null"],
+                        "minimum": 0,
+                        "default": null,
+                        "description": "The megabytes the files of the disk \
+                            cache may take, past which the least recently \
+                            used ones are removed.",
+                    },
+                    "gitIndex": {
+                        "type": "boolean",
+                        "default": false,
+                        "description": "Fetch the git indexes of the \
+                            alternative registries that don't have a sparse \
+                            one into the disk cache, with the `git` CLI.",
+                    },
+                },
+            },
+            "http": {
+                "type": "object",
+                "additionalProperties": false,
+                "properties": {
+                    "indexTimeout": seconds(
+                        "The seconds a request to the sparse index may take.",
+                    ),
+                    "apiTimeout": seconds(
+                        "The seconds a request to the crates.io web API may \
+                            take.",
+                    ),
+                    "concurrency": {
+                        "type": ["integer", "null"],
+                        "minimum": 1,
+                        "default": null,
+                        "description": "The maximal number of crates fetched \
+                            at once.",
+                    },
+                },
+            },
+            "api": {
+                "type": "object",
+                "additionalProperties": false,
+                "properties": {
+                    "enabled": {
+                        "type": "boolean",
+                        "default": true,
+                        "description": "Use the crates.io web API for \
+                            descriptions, downloads and search, rather than \
+                            only the index.",
+                    },
+                },
+            },
+            "hover": {
+                "type": "object",
+                "additionalProperties": false,
+                "properties": {
+                    "sections": {
+                        "type": "array",
+                        "items": { "enum": sections },
+                        "default": sections,
+                        "description": "The sections of the hover of a \
+                            dependency name, in the order they are shown.",
+                    },
+                },
+            },
+            "registries": {
+                "type": "object",
+                "default": {},
+                "description": "The alternative registries whose crate names \
+                    are completed, by their names.",
+                "additionalProperties": {
+                    "type": "object",
+                    "required": ["api"],
+                    "additionalProperties": false,
+                    "properties": {
+                        "api": {
+                            "type": "string",
+                            "format": "uri",
+                            "description": "The base URL of the web API of \
+                                the registry.",
+                        },
+                    },
+                },
+            },
+            "defaultRegistry": {
+                "type": ["string", "null"],
+                "default": null,
+                "description": "The registry of the dependencies without a \
+                    `registry` key.",
+            },
+            "locale": {
+                "type": ["string", "null"],
+                "default": null,
+                "description": "The locale of the messages (e.g. `en`), \
+                    instead of the client's.",
+            },
+            "debounce": {
+                "type": ["integer", "null"],
+                "minimum": 0,
+                "default": null,
+                "description": "The milliseconds the diagnostics of a \
+                    changed document wait for the next change (300 if \
+                    unset).",
+            },
+            "metricsLogInterval": seconds(
+                "Log a summary of the metrics to the client at most once per \
+                    this many seconds.",
+            ),
+        },
+    })
+}
+
+/// The (dotted) keys of the settings in `value` that [`schema`] doesn't
+/// have, e.g. `cache.maxCrate`.
+pub fn unknown_keys(value: &Value) -> Vec<String> {
+    let mut keys = Vec::new();
+    collect_unknown(value, &schema(), "", &mut keys);
+    keys
+}
+
+fn collect_unknown(
+    value: &Value,
+    schema: &Value,
+    path: &str,
+    keys: &mut Vec<String>,
+) {
+    let Some(object) = value.as_object() else { return };
+    // objects that aren't settings tables (e.g. a wrong type) are left to the
+    // deserialization of the settings.
+    if schema.get("type").and_then(Value::as_str) != Some("object") {
+        return;
+    }
+    for (key, value) in object {
+        let path =
+            if path.is_empty() { key.clone() } else { format!("{path}.{key}") };
+        let known = schema.get("properties").and_then(|p| p.get(key));
+        match known.or_else(|| {
+            schema.get("additionalProperties").filter(|a| a.is_object())
+        }) {
+            Some(schema) => collect_unknown(value, schema, &path, keys),
+            None => keys.push(path),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use serde::{
+        Deserialize,
+        de::{self, DeserializeSeed, IntoDeserializer, Visitor, value::Error},
+    };
+
+    use super::*;
+    use crate::config::Settings;
+
+    /// The fields of the settings, by their paths (e.g. `cache.maxCrates`,
+    /// with `*` for the keys of maps), and the variants of the enums at them.
+    #[derive(Debug, Default)]
+    struct Traced {
+        fields: Vec<String>,
+        variants: Vec<(String, &'static str)>,
+    }
+
+    /// A deserializer that walks the `Deserialize` impls of the settings
+    /// rather than a value, recording their fields and variants.
+    #[derive(Clone)]
+    struct Trace<'a> {
+        path: String,
+        traced: &'a RefCell<Traced>,
+    }
+
+    impl Trace<'_> {
+        fn at(&self, key: &str) -> Self {
+            let path = match self.path.as_str() {
+                "" => key.to_owned(),
+                path => format!("{path}.{key}"),
+            };
+            Self { path, traced: self.traced }
+        }
+    }
+
+    impl<'de> de::Deserializer<'de> for Trace<'_> {
+        type Error = Error;
+
+        fn deserialize_any<V: Visitor<'de>>(
+            self,
+            _: V,
+        ) -> Result<V::Value, Error> {
+            let msg = format!("an unsupported type at `{}`", self.path);
+            Err(de::Error::custom(msg))
+        }
+
+        fn deserialize_bool<V: Visitor<'de>>(
+            self,
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            visitor.visit_bool(false)
+        }
+
+        fn deserialize_u64<V: Visitor<'de>>(
+            self,
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            visitor.visit_u64(0)
+        }
+
+        fn deserialize_f64<V: Visitor<'de>>(
+            self,
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            visitor.visit_f64(0.0)
+        }
+
+        fn deserialize_str<V: Visitor<'de>>(
+            self,
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            visitor.visit_str("")
+        }
+
+        fn deserialize_string<V: Visitor<'de>>(
+            self,
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            visitor.visit_str("")
+        }
+
+        fn deserialize_option<V: Visitor<'de>>(
+            self,
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            visitor.visit_some(self)
+        }
+
+        fn deserialize_seq<V: Visitor<'de>>(
+            self,
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            visitor.visit_seq(Element(Some(self)))
+        }
+
+        fn deserialize_map<V: Visitor<'de>>(
+            self,
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            visitor.visit_map(Entries::new(self, &["*"]))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _: &'static str,
+            fields: &'static [&'static str],
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            let paths = fields.iter().map(|field| self.at(field).path);
+            self.traced.borrow_mut().fields.extend(paths);
+            visitor.visit_map(Entries::new(self, fields))
+        }
+
+        fn deserialize_enum<V: Visitor<'de>>(
+            self,
+            _: &'static str,
+            variants: &'static [&'static str],
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            let paths = variants.iter().map(|&v| (self.path.clone(), v));
+            self.traced.borrow_mut().variants.extend(paths);
+            visitor.visit_enum(variants[0].into_deserializer())
+        }
+
+        serde::forward_to_deserialize_any! {
+            i8 i16 i32 i64 i128 u8 u16 u32 u128 f32 char bytes byte_buf unit
+            unit_struct newtype_struct tuple tuple_struct identifier
+            ignored_any
+        }
+    }
+
+    /// The single element of a traced sequence.
+    struct Element<'a>(Option<Trace<'a>>);
+
+    impl<'de> de::SeqAccess<'de> for Element<'_> {
+        type Error = Error;
+
+        fn next_element_seed<T: DeserializeSeed<'de>>(
+            &mut self,
+            seed: T,
+        ) -> Result<Option<T::Value>, Error> {
+            self.0
+                .take()
+                .map(|trace| seed.deserialize(trace))
+                .transpose()
+        }
+    }
+
+    /// The entries of a traced struct (or map), by their keys.
+    struct Entries<'a> {
+        keys: std::slice::Iter<'static, &'static str>,
+        trace: Trace<'a>,
+        value: Option<Trace<'a>>,
+    }
+
+    impl<'a> Entries<'a> {
+        fn new(trace: Trace<'a>, keys: &'static [&'static str]) -> Self {
+            Self { keys: keys.iter(), trace, value: None }
+        }
+    }
+
+    impl<'de> de::MapAccess<'de> for Entries<'_> {
+        type Error = Error;
+
+        fn next_key_seed<K: DeserializeSeed<'de>>(
+            &mut self,
+            seed: K,
+        ) -> Result<Option<K::Value>, Error> {
+            let Some(&key) = self.keys.next() else { return Ok(None) };
+            self.value = Some(self.trace.at(key));
+            seed.deserialize(key.into_deserializer()).map(Some)
+        }
+
+        fn next_value_seed<V: DeserializeSeed<'de>>(
+            &mut self,
+            seed: V,
+        ) -> Result<V::Value, Error> {
+            let value = self.value.take();
+            seed.deserialize(value.expect("a value follows its key"))
+        }
+    }
+
+    #[test]
+    fn test_defaults_are_valid_settings() {
+        fn defaults(schema: &Value) -> Value {
+            match schema.get("properties").and_then(Value::as_object) {
+                Some(properties) => properties
+                    .iter()
+                    .map(|(key, schema)| (key.clone(), defaults(schema)))
+                    .collect(),
+                None => schema["default"].clone(),
+            }
+        }
+
+        let value = defaults(&schema());
+        assert_eq!(unknown_keys(&value), Vec::<String>::new());
+        let settings = Settings::from_value(Some(value)).unwrap();
+        assert_eq!(settings.cache.disk_ttl, 3600);
+    }
+
+    #[test]
+    fn test_unknown_keys() {
+        let value = json!({
+            "forceEnabled": true,
+            "cache": { "maxCrates": 10, "maxCrate": 10 },
+            "hover": { "sections": ["version"] },
+            "registries": { "my-registry": { "api": "", "token": "" } },
+            "locale": { "lang": "en" },
+        });
+        assert_eq!(
+            unknown_keys(&value),
+            ["cache.maxCrate", "forceEnabled", "registries.my-registry.token"]
+        );
+        assert_eq!(unknown_keys(&Value::Null), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_schema_has_every_setting() {
+        let traced = RefCell::default();
+        Settings::deserialize(Trace { path: String::new(), traced: &traced })
+            .unwrap();
+        let Traced { fields, variants } = traced.into_inner();
+        assert!(fields.iter().any(|f| f == "cache.maxCrates"));
+        assert!(fields.iter().any(|f| f == "registries.*.api"));
+
+        let schema = schema();
+        let at = |path: &str| {
+            path.split('.').try_fold(&schema, |schema, key| match key {
+                "*" => schema.get("additionalProperties"),
+                _ => schema.get("properties")?.get(key),
+            })
+        };
+        for field in &fields {
+            assert!(at(field).is_some(), "`{field}` isn't in the schema");
+        }
+        for (path, variant) in &variants {
+            let schema = at(path).unwrap();
+            let values = schema
+                .get("enum")
+                .or_else(|| schema.get("items")?.get("enum"))
+                .and_then(Value::as_array);
+            assert!(
+                values.is_some_and(|values| values.contains(&json!(variant))),
+                "`{variant}` of `{path}` isn't in the schema"
+            );
+        }
+    }
+}
@@ -0,0 +1,91 @@
+//! The `crates/search` request, so editor frontends can implement an "add
+//! dependency" palette through the running server (and its rate limited
+//! access to the crates.io API).
+
+use serde::{Deserialize, Serialize};
+
+use crate::crates::SearchPage;
+
+/// The name of the custom request, whose params are [`Params`] and result a
+/// [`Response`].
+pub const METHOD: &str = "crates/search";
+
+const DEFAULT_PER_PAGE: usize = 20;
+/// The most results crates.io returns in a page.
+const MAX_PER_PAGE: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Params {
+    pub query: String,
+    /// The page of the results, from 1 (the default).
+    pub page: Option<usize>,
+    /// The results per page (20 by default, at most 100).
+    pub per_page: Option<usize>,
+}
+
+impl Params {
+    pub fn page(&self) -> usize {
+        self.page.unwrap_or(1).max(1)
+    }
+
+    pub fn per_page(&self) -> usize {
+        self.per_page
+            .unwrap_or(DEFAULT_PER_PAGE)
+            .clamp(1, MAX_PER_PAGE)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Response {
+    pub crates: Vec<Crate>,
+    pub page: usize,
+    /// The number of the matching crates, on all of the pages.
+    pub total: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Crate {
+    pub name: String,
+    pub description: Option<String>,
+    pub downloads: u64,
+    pub latest_version: String,
+}
+
+impl Response {
+    pub fn new(found: SearchPage, page: usize) -> Self {
+        let crates = found
+            .crates
+            .into_iter()
+            .map(|krate| Crate {
+                name: krate.name,
+                description: krate.description,
+                downloads: krate.downloads,
+                latest_version: krate.max_version,
+            })
+            .collect();
+        Self { crates, page, total: found.total }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_params() {
+        let params: Params =
+            serde_json::from_value(json!({ "query": "serde" })).unwrap();
+        assert_eq!((params.page(), params.per_page()), (1, DEFAULT_PER_PAGE));
+
+        let params: Params = serde_json::from_value(
+            json!({ "query": "serde", "page": 0, "perPage": 500 }),
+        )
+        .unwrap();
+        assert_eq!((params.page(), params.per_page()), (1, MAX_PER_PAGE));
+    }
+}
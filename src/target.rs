@@ -0,0 +1,262 @@
+//! The build targets of the crate, i.e., the `[lib]` table and the arrays of
+//! tables of the other kinds (e.g. `[[bin]]`).
+
+use taplo::dom::Node;
+use tower_lsp::lsp_types::Range;
+
+use crate::{messages::Catalog, parse::Span};
+
+#[derive(Debug)]
+pub enum Error {
+    DuplicateName {
+        kind: &'static str,
+        name: String,
+        range: Range,
+    },
+    UnknownFeature { feature: String, range: Range },
+}
+
+impl Error {
+    /// The message of the error, in the locale of `messages`.
+    pub fn message(&self, messages: Catalog) -> String {
+        match self {
+            Self::DuplicateName { kind, name, .. } => messages
+                .get("duplicate-target", &[("kind", kind), ("name", name)]),
+            Self::UnknownFeature { feature, .. } => messages
+                .get("unknown-required-feature", &[("feature", feature)]),
+        }
+    }
+
+    pub fn range(&self) -> Range {
+        match self {
+            Self::DuplicateName { range, .. }
+            | Self::UnknownFeature { range, .. } => *range,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetKind {
+    /// `[lib]`
+    Lib,
+    /// `[[bin]]`
+    Bin,
+    /// `[[example]]`
+    Example,
+    /// `[[test]]`
+    Test,
+    /// `[[bench]]`
+    Bench,
+}
+
+impl TargetKind {
+    const ALL: &[Self] =
+        &[Self::Lib, Self::Bin, Self::Example, Self::Test, Self::Bench];
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|kind| kind.key() == key)
+    }
+
+    pub fn key(self) -> &'static str {
+        match self {
+            Self::Lib => "lib",
+            Self::Bin => "bin",
+            Self::Example => "example",
+            Self::Test => "test",
+            Self::Bench => "bench",
+        }
+    }
+}
+
+/// A build target declared in the manifest.
+///
+/// Targets that are inferred from the layout of the crate (e.g.
+/// `src/main.rs`) are not included.
+#[derive(Debug)]
+pub struct Target {
+    pub kind: TargetKind,
+    pub name: Option<Span<String>>,
+    pub path: Option<Span<String>>,
+    pub required_features: Vec<Span<String>>,
+}
+
+impl Target {
+    const NAME_KEY: &str = "name";
+    const PATH_KEY: &str = "path";
+    pub const REQUIRED_FEATURES_KEY: &str = "required-features";
+
+    fn parse(kind: TargetKind, node: &Node, s: &str) -> Option<Self> {
+        let table = node.as_table()?;
+        let string = |key| {
+            let node = table.get(key)?;
+            Span::parse(node.as_str()?, |v| Some(v.to_owned()), s)
+        };
+
+        let required_features = table
+            .get(Self::REQUIRED_FEATURES_KEY)
+            .and_then(|features| {
+                let items = features.as_array()?.items().read();
+                let features = items.iter().filter_map(|item| {
+                    Span::parse(item.as_str()?, |v| Some(v.to_owned()), s)
+                });
+                Some(features.collect())
+            })
+            .unwrap_or_default();
+
+        Some(Self {
+            kind,
+            name: string(Self::NAME_KEY),
+            path: string(Self::PATH_KEY),
+            required_features,
+        })
+    }
+}
+
+/// Parse the targets declared in the manifest.
+pub fn parse(root: &Node, s: &str) -> Vec<Target> {
+    let mut targets = Vec::new();
+    for &kind in TargetKind::ALL {
+        let node = root.get(kind.key());
+        match &node {
+            Node::Array(array) => targets.extend(
+                array
+                    .items()
+                    .read()
+                    .iter()
+                    .filter_map(|item| Target::parse(kind, item, s)),
+            ),
+            _ => targets.extend(Target::parse(kind, &node, s)),
+        }
+    }
+    targets
+}
+
+/// Validate the targets of the manifest.
+///
+/// `has_feature` checks whether a feature can be enabled on the crate (see
+/// [`crate::parse::Manifest::has_feature`]).
+pub fn validate(
+    targets: &[Target],
+    has_feature: impl Fn(&str) -> bool,
+) -> Vec<Error> {
+    let mut errors = Vec::new();
+
+    for (i, target) in targets.iter().enumerate() {
+        // cargo rejects targets of the same kind with the same name, so all
+        // but the first one are reported.
+        if let Some(name) = &target.name
+            && targets[..i].iter().any(|t| {
+                t.kind == target.kind
+                    && t.name.as_ref().is_some_and(|n| n.value == name.value)
+            })
+        {
+            errors.push(Error::DuplicateName {
+                kind: target.kind.key(),
+                name: name.value.clone(),
+                range: name.range,
+            });
+        }
+
+        errors.extend(
+            target
+                .required_features
+                .iter()
+                .filter(|f| !has_feature(&f.value))
+                .map(|f| Error::UnknownFeature {
+                    feature: f.value.clone(),
+                    range: f.range,
+                }),
+        );
+    }
+
+    errors
+}
+
+/// The most similar of the `candidates` to an unknown `name`, if any of them
+/// is similar enough to be a likely typo.
+pub fn suggest<'a>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    let max = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .map(|c| (edit_distance(name, c), c))
+        .filter(|&(distance, _)| distance <= max)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, c)| c)
+}
+
+/// The Levenshtein distance between `a` and `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn test_validate() {
+        let s = indoc! {r#"
+            [features]
+            cli = []
+
+            [lib]
+            path = "src/lib.rs"
+
+            [[bin]]
+            name = "foo"
+            required-features = ["cli", "clu"]
+
+            [[bin]]
+            name = "foo"
+
+            [[example]]
+            name = "foo"
+        "#};
+        let dom = taplo::parser::parse(s).into_dom();
+        let manifest = parse::Manifest::parse(s, &dom);
+
+        assert_eq!(manifest.targets.len(), 4);
+        assert_eq!(
+            manifest.targets[0].path.as_ref().map(|p| p.value.as_str()),
+            Some("src/lib.rs")
+        );
+        let errors = manifest
+            .target_errors
+            .iter()
+            .map(|err| err.message(Catalog::for_locale(None)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            [
+                "unknown feature `clu` in `required-features`",
+                "duplicate bin target name `foo`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_suggest() {
+        let features = ["cli", "serde", "full"];
+
+        assert_eq!(suggest("clu", features), Some("cli"));
+        assert_eq!(suggest("serd", features), Some("serde"));
+        assert_eq!(suggest("tokio", features), None);
+    }
+}
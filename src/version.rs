@@ -0,0 +1,621 @@
+use std::ops::Range;
+
+use chrono::{DateTime, Utc};
+use semver::{Comparator, Op, Version, VersionReq};
+use serde::{Deserialize, Serialize};
+
+/// The maximal number of older series [`notable_versions`] lists.
+const OLDER_SERIES: usize = 5;
+
+/// How an update from a version requirement to a newer version affects the
+/// dependents, according to [Cargo's SemVer compatibility rules].
+///
+/// [Cargo's SemVer compatibility rules]: https://doc.rust-lang.org/cargo/reference/semver.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpdateKind {
+    /// The new version is SemVer compatible with the requirement (e.g.
+    /// `1.0.100` to `1.0.219`).
+    Compatible,
+    /// The new version is not SemVer compatible with the requirement (e.g.
+    /// `1.0.100` to `2.0.0`).
+    Breaking,
+}
+
+/// The lowest version a requirement can be satisfied with, if it has a lower
+/// bound.
+///
+/// Missing minor and patch versions are filled with zeros, e.g. the
+/// requirement `1` has the lower bound `1.0.0`.
+pub fn lower_bound(req: &VersionReq) -> Option<Version> {
+    req.comparators
+        .iter()
+        .find(|c| !matches!(c.op, Op::Less | Op::LessEq))
+        .map(|c| Version {
+            major: c.major,
+            minor: c.minor.unwrap_or(0),
+            patch: c.patch.unwrap_or(0),
+            pre: c.pre.clone(),
+            build: semver::BuildMetadata::EMPTY,
+        })
+}
+
+/// Whether `req` only allows versions newer than `version`, e.g. the
+/// pre-release `2.0.0-rc.1` when `version` is the latest stable `1.5.0`, so
+/// `version` isn't an update of it.
+pub fn is_ahead(req: &VersionReq, version: &Version) -> bool {
+    lower_bound(req).is_some_and(|lower| *version < lower)
+}
+
+/// Classify the update from `req` to `version`.
+pub fn update_kind(req: &VersionReq, version: &Version) -> UpdateKind {
+    let compatible = lower_bound(req).is_some_and(|lower| {
+        let caret = Comparator {
+            op: Op::Caret,
+            major: lower.major,
+            minor: Some(lower.minor),
+            patch: Some(lower.patch),
+            pre: lower.pre,
+        };
+        caret.matches(version)
+    });
+
+    if compatible { UpdateKind::Compatible } else { UpdateKind::Breaking }
+}
+
+/// How far a version requirement is behind a newer version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Behind {
+    /// Only the patch version is newer (e.g. `1.0.100` to `1.0.219`).
+    Patch,
+    /// The minor version is newer, and the update is still compatible (e.g.
+    /// `1.2` to `1.5.0`).
+    Minor,
+    /// The update is [breaking](UpdateKind::Breaking), which includes the
+    /// minor (and patch) updates of `0.x` (and `0.0.x`) versions.
+    Major,
+}
+
+/// Classify how far `req` is behind `version`.
+pub fn behind(req: &VersionReq, version: &Version) -> Behind {
+    if update_kind(req, version) == UpdateKind::Breaking {
+        return Behind::Major;
+    }
+    match lower_bound(req) {
+        Some(lower) if lower.minor == version.minor => Behind::Patch,
+        _ => Behind::Minor,
+    }
+}
+
+/// The updates of a version requirement that are worth offering.
+#[derive(Debug, Default, PartialEq)]
+pub struct Updates<'a> {
+    /// The latest version that is SemVer compatible with the requirement,
+    /// if it's newer than the requirement's lower bound.
+    pub compatible: Option<&'a Version>,
+    /// The `latest` version, if it isn't SemVer compatible with the
+    /// requirement.
+    pub breaking: Option<&'a Version>,
+}
+
+/// The updates of `req`, out of the (unyanked) `versions` and the `latest`
+/// one.
+///
+/// Pre-releases are only offered as compatible updates of requirements on
+/// pre-releases.
+pub fn updates<'a>(
+    req: &VersionReq,
+    versions: impl IntoIterator<Item = &'a Version>,
+    latest: &'a Version,
+) -> Updates<'a> {
+    let lower = lower_bound(req);
+    let compatible = versions
+        .into_iter()
+        .filter(|v| {
+            v.pre.is_empty()
+                || lower.as_ref().is_some_and(|l| !l.pre.is_empty())
+        })
+        .filter(|&v| lower.as_ref().is_some_and(|l| v > l))
+        .filter(|&v| update_kind(req, v) == UpdateKind::Compatible)
+        .max();
+    let breaking =
+        (update_kind(req, latest) == UpdateKind::Breaking).then_some(latest);
+
+    Updates { compatible, breaking }
+}
+
+/// The range of the comparator (e.g. `>=1.2` in `>=1.2, <1.5`) of a
+/// requirement at `offset`, without the surrounding whitespace.
+///
+/// Returns `None` if `offset` is at a `,` (or out of the requirement).
+pub fn comparator_at(raw: &str, offset: usize) -> Option<Range<usize>> {
+    let rest = raw.get(offset..).filter(|r| !r.starts_with(','))?;
+    let start = raw[..offset].rfind(',').map_or(0, |i| i + 1);
+    let end = rest.find(',').map_or(raw.len(), |i| offset + i);
+
+    let part = &raw[start..end];
+    let start = start + (part.len() - part.trim_start().len());
+    let end = end - (part.len() - part.trim_end().len());
+    (start < end).then_some(start..end)
+}
+
+/// What a comparator (e.g. `~1.2`) allows, as the id of its message in the
+/// catalogs, and the versions it matches as plain comparisons (e.g.
+/// `>=1.2.0, <1.3.0`).
+///
+/// Returns `None` if the comparator can't be explained, e.g. if its bound
+/// is past the largest version.
+pub fn explain_comparator(comparator: &str) -> Option<(&'static str, String)> {
+    if comparator == "*" {
+        return Some(("comparator-any", "*".to_owned()));
+    }
+    let c = Comparator::parse(comparator).ok()?;
+    let lower = lower(&c);
+    let range = |upper: Version| format!(">={lower}, <{upper}");
+
+    let explained = match c.op {
+        Op::Exact if c.patch.is_some() => {
+            ("comparator-exact", format!("={lower}"))
+        }
+        Op::Exact => ("comparator-exact-parts", range(next(&c)?)),
+        Op::Greater if c.patch.is_some() => {
+            ("comparator-comparison", format!(">{lower}"))
+        }
+        Op::Greater => ("comparator-comparison", format!(">={}", next(&c)?)),
+        Op::GreaterEq => ("comparator-comparison", format!(">={lower}")),
+        Op::Less => ("comparator-comparison", format!("<{lower}")),
+        Op::LessEq if c.patch.is_some() => {
+            ("comparator-comparison", format!("<={lower}"))
+        }
+        Op::LessEq => ("comparator-comparison", format!("<{}", next(&c)?)),
+        Op::Tilde => ("comparator-tilde", range(tilde_upper(&c)?)),
+        Op::Caret => {
+            let what = if comparator.starts_with('^') {
+                "comparator-caret"
+            } else {
+                "comparator-default"
+            };
+            (what, range(caret_upper(&c)?))
+        }
+        Op::Wildcard => ("comparator-wildcard", range(next(&c)?)),
+        _ => return None,
+    };
+    Some(explained)
+}
+
+/// A simpler requirement that matches the same versions, e.g. `1.2` for
+/// `>=1.2, <2`, and `1` for `1.*`.
+///
+/// Returns `None` if there's none, or if the requirement is already a plain
+/// caret (or tilde) requirement.
+pub fn normalize(raw: &str) -> Option<String> {
+    let req = VersionReq::parse(raw).ok()?;
+    // the pre-releases are only matched by the comparators with the same
+    // version, so they're never equivalent to other comparators.
+    if req.comparators.iter().any(|c| !c.pre.is_empty()) {
+        return None;
+    }
+    if let [c] = req.comparators.as_slice()
+        && (c.op == Op::Tilde || c.op == Op::Caret && !raw.starts_with('^'))
+    {
+        return None;
+    }
+
+    let mut lower = Version::new(0, 0, 0);
+    let mut upper = None::<Version>;
+    for c in &req.comparators {
+        let (l, u) = bounds(c)?;
+        lower = lower.max(l);
+        upper = match (upper, u) {
+            (Some(upper), Some(u)) => Some(upper.min(u)),
+            (upper, u) => upper.or(u),
+        };
+    }
+    let matched = (lower, Some(upper?));
+
+    let Version { major, minor, patch, .. } = matched.0;
+    [
+        (minor == 0 && patch == 0).then(|| format!("{major}")),
+        (patch == 0).then(|| format!("{major}.{minor}")),
+        Some(format!("{major}.{minor}.{patch}")),
+        (patch == 0).then(|| format!("~{major}.{minor}")),
+        Some(format!("~{major}.{minor}.{patch}")),
+    ]
+    .into_iter()
+    .flatten()
+    .find(|candidate| {
+        Comparator::parse(candidate)
+            .ok()
+            .and_then(|c| bounds(&c))
+            .is_some_and(|b| b == matched)
+    })
+    .filter(|candidate| candidate != raw.trim())
+}
+
+/// The plain comparisons a single caret, tilde, or wildcard requirement
+/// matches the same versions as, e.g. `>=1.2.0, <2.0.0` for `1.2`.
+pub fn expand(raw: &str) -> Option<String> {
+    let req = VersionReq::parse(raw).ok()?;
+    let [c] = req.comparators.as_slice() else { return None };
+    if !matches!(c.op, Op::Caret | Op::Tilde | Op::Wildcard) {
+        return None;
+    }
+    explain_comparator(raw.trim()).map(|(_, bounds)| bounds)
+}
+
+/// The versions a comparator matches, as the (inclusive) lower bound and
+/// the (exclusive) upper bound.
+///
+/// The pre-releases of the bounds are ignored, so it's only accurate for
+/// comparators without them. Returns `None` if a bound is past the largest
+/// version.
+fn bounds(c: &Comparator) -> Option<(Version, Option<Version>)> {
+    let zero = Version::new(0, 0, 0);
+    let bounds = match c.op {
+        Op::Exact | Op::Wildcard => (lower(c), Some(next(c)?)),
+        Op::Greater => (next(c)?, None),
+        Op::GreaterEq => (lower(c), None),
+        Op::Less => (zero, Some(lower(c))),
+        Op::LessEq => (zero, Some(next(c)?)),
+        Op::Tilde => (lower(c), Some(tilde_upper(c)?)),
+        Op::Caret => (lower(c), Some(caret_upper(c)?)),
+        _ => return None,
+    };
+    Some(bounds)
+}
+
+/// The version of a comparator, with the missing parts filled with zeros.
+fn lower(c: &Comparator) -> Version {
+    Version {
+        major: c.major,
+        minor: c.minor.unwrap_or(0),
+        patch: c.patch.unwrap_or(0),
+        pre: c.pre.clone(),
+        build: semver::BuildMetadata::EMPTY,
+    }
+}
+
+/// The first version after the given parts, e.g. `1.3.0` for `1.2`, or
+/// `None` if the last part is already the largest one.
+fn next(c: &Comparator) -> Option<Version> {
+    let next = match (c.minor, c.patch) {
+        (None, _) => Version::new(c.major.checked_add(1)?, 0, 0),
+        (Some(minor), None) => Version::new(c.major, minor.checked_add(1)?, 0),
+        (Some(minor), Some(patch)) => {
+            Version::new(c.major, minor, patch.checked_add(1)?)
+        }
+    };
+    Some(next)
+}
+
+fn tilde_upper(c: &Comparator) -> Option<Version> {
+    let upper = match c.minor {
+        Some(minor) => Version::new(c.major, minor.checked_add(1)?, 0),
+        None => Version::new(c.major.checked_add(1)?, 0, 0),
+    };
+    Some(upper)
+}
+
+fn caret_upper(c: &Comparator) -> Option<Version> {
+    let upper = match (c.major, c.minor, c.patch) {
+        (0, None, _) => Version::new(1, 0, 0),
+        (0, Some(0), None) => Version::new(0, 1, 0),
+        (0, Some(0), Some(patch)) => Version::new(0, 0, patch.checked_add(1)?),
+        (0, Some(minor), _) => Version::new(0, minor.checked_add(1)?, 0),
+        (major, ..) => Version::new(major.checked_add(1)?, 0, 0),
+    };
+    Some(upper)
+}
+
+/// The series of SemVer compatible versions `version` belongs to, e.g. `1.x`
+/// for `1.2.3`, and `0.2.x` for `0.2.3` (see [`update_kind`]).
+pub fn series(version: &Version) -> String {
+    match (version.major, version.minor) {
+        (0, 0) => format!("0.0.{}", version.patch),
+        (0, minor) => format!("0.{minor}.x"),
+        (major, _) => format!("{major}.x"),
+    }
+}
+
+/// The versions worth offering besides the `latest` one, out of the
+/// (unyanked) `versions`, with the group each one is offered as:
+///
+/// * The latest stable version, if `latest` is a pre-release.
+/// * The latest version of each older series (up to [`OLDER_SERIES`] of
+///   them), for downgrades.
+/// * The latest pre-release, if it's newer than `latest`.
+pub fn notable_versions<'a>(
+    versions: impl IntoIterator<Item = &'a Version>,
+    latest: &Version,
+) -> Vec<(&'a Version, String)> {
+    let mut stable = Vec::new();
+    let mut pre = None::<&Version>;
+    for version in versions {
+        if version.pre.is_empty() {
+            stable.push(version);
+        } else if pre.is_none_or(|p| version > p) {
+            pre = Some(version);
+        }
+    }
+    // from the newest.
+    stable.sort_unstable_by(|a, b| b.cmp(a));
+
+    let mut notable = Vec::new();
+    let mut seen = vec![series(latest)];
+    if !latest.pre.is_empty()
+        && let Some(&stable) = stable.first()
+    {
+        notable.push((stable, "latest stable".to_owned()));
+        seen.push(series(stable));
+    }
+
+    let older = seen.len();
+    for version in stable {
+        if seen.len() - older == OLDER_SERIES {
+            break;
+        }
+        let series = series(version);
+        if !seen.contains(&series) {
+            notable.push((version, series.clone()));
+            seen.push(series);
+        }
+    }
+
+    if let Some(pre) = pre.filter(|&p| p > latest) {
+        notable.push((pre, "pre-release".to_owned()));
+    }
+
+    notable
+}
+
+/// How long ago a version was `released`, e.g. `2 years old`.
+pub fn format_age(released: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    match elapsed(released, now) {
+        Some(elapsed) => format!("{elapsed} old"),
+        None => "released today".to_owned(),
+    }
+}
+
+/// When a version was `released`, relative to `now`, e.g. `2 years ago`.
+pub fn format_ago(released: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    match elapsed(released, now) {
+        Some(elapsed) => format!("{elapsed} ago"),
+        None => "today".to_owned(),
+    }
+}
+
+/// The time between `released` and `now` in its largest unit, e.g.
+/// `2 years`, or `None` if it's less than a day.
+fn elapsed(released: DateTime<Utc>, now: DateTime<Utc>) -> Option<String> {
+    let days = (now - released).num_days();
+    let (count, unit) = match days {
+        ..1 => return None,
+        1..14 => (days, "day"),
+        14..61 => (days / 7, "week"),
+        61..365 => (days / 30, "month"),
+        _ => (days / 365, "year"),
+    };
+    let plural = if count == 1 { "" } else { "s" };
+    Some(format!("{count} {unit}{plural}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kind(req: &str, version: &str) -> UpdateKind {
+        update_kind(
+            &VersionReq::parse(req).unwrap(),
+            &Version::parse(version).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_lower_bound() {
+        let lower = |req| lower_bound(&VersionReq::parse(req).unwrap());
+
+        assert_eq!(lower("1"), Some(Version::new(1, 0, 0)));
+        assert_eq!(lower("~1.2"), Some(Version::new(1, 2, 0)));
+        assert_eq!(lower("<2, >=1.4.3"), Some(Version::new(1, 4, 3)));
+        assert_eq!(lower("<2"), None);
+        assert_eq!(lower("*"), None);
+    }
+
+    #[test]
+    fn test_is_ahead() {
+        let ahead = |req, version| {
+            is_ahead(
+                &VersionReq::parse(req).unwrap(),
+                &Version::parse(version).unwrap(),
+            )
+        };
+
+        assert!(ahead("2.0.0-rc.1", "1.5.0"));
+        assert!(!ahead("2.0.0-rc.1", "2.0.0"));
+        assert!(!ahead("1", "1.5.0"));
+        assert!(!ahead("<2", "1.5.0"));
+    }
+
+    #[test]
+    fn test_update_kind() {
+        use UpdateKind::*;
+
+        assert_eq!(kind("=1.0.100", "1.0.219"), Compatible);
+        assert_eq!(kind("~1.2", "1.5.0"), Compatible);
+        assert_eq!(kind("1", "2.0.0"), Breaking);
+        assert_eq!(kind("0.1.3", "0.1.9"), Compatible);
+        assert_eq!(kind("0.1.3", "0.2.0"), Breaking);
+        assert_eq!(kind("0.0.3", "0.0.4"), Breaking);
+        assert_eq!(kind("<2", "2.0.0"), Breaking);
+    }
+
+    #[test]
+    fn test_behind() {
+        let behind = |req, version| {
+            behind(
+                &VersionReq::parse(req).unwrap(),
+                &Version::parse(version).unwrap(),
+            )
+        };
+
+        assert_eq!(behind("1.0.100", "1.0.219"), Behind::Patch);
+        assert_eq!(behind("~1.2", "1.5.0"), Behind::Minor);
+        assert_eq!(behind("1", "2.0.0"), Behind::Major);
+        assert_eq!(behind("0.1.3", "0.1.9"), Behind::Patch);
+        assert_eq!(behind("0.1.3", "0.2.0"), Behind::Major);
+        assert_eq!(behind("<2", "2.0.0"), Behind::Major);
+    }
+
+    #[test]
+    fn test_updates() {
+        let versions = ["1.0.100", "1.0.219", "1.1.0-rc.1", "2.0.0", "2.3.0"]
+            .map(|v| Version::parse(v).unwrap());
+        let latest = &versions[4];
+        let updates = |req| {
+            let updates =
+                updates(&VersionReq::parse(req).unwrap(), &versions, latest);
+            (
+                updates.compatible.map(Version::to_string),
+                updates.breaking.map(Version::to_string),
+            )
+        };
+
+        assert_eq!(
+            updates("1.0.100"),
+            (Some("1.0.219".to_owned()), Some("2.3.0".to_owned()))
+        );
+        assert_eq!(updates("2.0"), (Some("2.3.0".to_owned()), None));
+        assert_eq!(updates("1.0.219"), (None, Some("2.3.0".to_owned())));
+        assert_eq!(updates("<2"), (None, Some("2.3.0".to_owned())));
+    }
+
+    #[test]
+    fn test_comparator_at() {
+        let raw = ">=1.2,  <1.5";
+        let at = |offset| comparator_at(raw, offset).map(|r| &raw[r]);
+
+        assert_eq!(at(0), Some(">=1.2"));
+        assert_eq!(at(4), Some(">=1.2"));
+        assert_eq!(at(5), None);
+        assert_eq!(at(7), Some("<1.5"));
+        assert_eq!(at(12), Some("<1.5"));
+        assert_eq!(at(13), None);
+    }
+
+    #[test]
+    fn test_explain_comparator() {
+        let bounds = |c| explain_comparator(c).map(|(_, bounds)| bounds);
+
+        assert_eq!(bounds("1.2.3").as_deref(), Some(">=1.2.3, <2.0.0"));
+        assert_eq!(bounds("^0.2.3").as_deref(), Some(">=0.2.3, <0.3.0"));
+        assert_eq!(bounds("^0.0.3").as_deref(), Some(">=0.0.3, <0.0.4"));
+        assert_eq!(bounds("^0.0").as_deref(), Some(">=0.0.0, <0.1.0"));
+        assert_eq!(bounds("~1").as_deref(), Some(">=1.0.0, <2.0.0"));
+        assert_eq!(bounds("~1.2.3").as_deref(), Some(">=1.2.3, <1.3.0"));
+        assert_eq!(bounds(">1.2").as_deref(), Some(">=1.3.0"));
+        assert_eq!(bounds("<=1.2").as_deref(), Some("<1.3.0"));
+        assert_eq!(bounds("=1.2").as_deref(), Some(">=1.2.0, <1.3.0"));
+        assert_eq!(bounds("=1.2.3").as_deref(), Some("=1.2.3"));
+        assert_eq!(bounds("1.*").as_deref(), Some(">=1.0.0, <2.0.0"));
+        assert_eq!(bounds("*").as_deref(), Some("*"));
+        assert_eq!(bounds("foo"), None);
+        // the bound would be past the largest version.
+        assert_eq!(bounds("18446744073709551615"), None);
+        assert_eq!(bounds("~1.18446744073709551615"), None);
+        assert_eq!(
+            bounds("<=1.2.18446744073709551615").as_deref(),
+            Some("<=1.2.18446744073709551615")
+        );
+        assert_eq!(bounds(">1.18446744073709551615"), None);
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize(">=1.2, <2").as_deref(), Some("1.2"));
+        assert_eq!(normalize(">=1.2.3, <1.3").as_deref(), Some("~1.2.3"));
+        assert_eq!(normalize("1.*").as_deref(), Some("1"));
+        assert_eq!(normalize("1.2.*").as_deref(), Some("~1.2"));
+        assert_eq!(normalize("0.2.*").as_deref(), Some("0.2"));
+        assert_eq!(normalize("^1.2").as_deref(), Some("1.2"));
+        assert_eq!(normalize("=1").as_deref(), Some("1"));
+        assert_eq!(normalize("1.2.0"), None);
+        assert_eq!(normalize("~1.2"), None);
+        assert_eq!(normalize("=1.2.3"), None);
+        assert_eq!(normalize(">=1.2"), None);
+        assert_eq!(normalize(">=1.0.0-rc.1, <2"), None);
+        assert_eq!(normalize("=18446744073709551615"), None);
+    }
+
+    #[test]
+    fn test_expand() {
+        assert_eq!(expand("1.2").as_deref(), Some(">=1.2.0, <2.0.0"));
+        assert_eq!(expand("~0.3").as_deref(), Some(">=0.3.0, <0.4.0"));
+        assert_eq!(expand(">=1.2, <2"), None);
+        assert_eq!(expand("=1.2.3"), None);
+    }
+
+    #[test]
+    fn test_notable_versions() {
+        let versions = [
+            "0.9.0",
+            "1.0.0",
+            "1.4.2",
+            "2.0.0",
+            "2.1.0",
+            "3.0.0-rc.1",
+            "0.1.5",
+        ]
+        .map(|v| Version::parse(v).unwrap());
+        let notable = |latest| {
+            notable_versions(&versions, &Version::parse(latest).unwrap())
+                .into_iter()
+                .map(|(v, group)| (v.to_string(), group))
+                .collect::<Vec<_>>()
+        };
+        let owned = |v: &[(&str, &str)]| {
+            v.iter()
+                .map(|(v, g)| (v.to_string(), g.to_string()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            notable("2.1.0"),
+            owned(&[
+                ("1.4.2", "1.x"),
+                ("0.9.0", "0.9.x"),
+                ("0.1.5", "0.1.x"),
+                ("3.0.0-rc.1", "pre-release"),
+            ])
+        );
+        assert_eq!(
+            notable("3.0.0-rc.1"),
+            owned(&[
+                ("2.1.0", "latest stable"),
+                ("1.4.2", "1.x"),
+                ("0.9.0", "0.9.x"),
+                ("0.1.5", "0.1.x"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_format_age() {
+        let now = "2024-06-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let age = |date: &str| format_age(date.parse().unwrap(), now);
+
+        assert_eq!(age("2024-06-01T00:00:00Z"), "released today");
+        assert_eq!(age("2024-05-31T00:00:00Z"), "1 day old");
+        assert_eq!(age("2024-03-01T00:00:00Z"), "3 months old");
+        assert_eq!(age("2022-05-01T00:00:00Z"), "2 years old");
+    }
+
+    #[test]
+    fn test_format_ago() {
+        let now = "2024-06-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let ago = |date: &str| format_ago(date.parse().unwrap(), now);
+
+        assert_eq!(ago("2024-06-01T00:00:00Z"), "today");
+        assert_eq!(ago("2024-05-18T00:00:00Z"), "2 weeks ago");
+        assert_eq!(ago("2021-05-01T00:00:00Z"), "3 years ago");
+    }
+}
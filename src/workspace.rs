@@ -0,0 +1,692 @@
+//! The members of a manifest's workspace, and the features that cargo
+//! unifies across them: a dependency is built once for the whole workspace,
+//! with all of the features that any of the members enables.
+//!
+//! The keys of `[package]` (and the dependencies) that the members inherit
+//! from the workspace's root (e.g. `version.workspace = true`) are resolved
+//! here too.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use std::collections::HashMap;
+use taplo::dom::{Node, node::DomNode};
+
+use tower_lsp::lsp_types::{Position, Range, TextEdit};
+use url::Url;
+
+use crate::{
+    edit,
+    parse::{
+        self, Dependency, DependencyKind, MANIFEST_FILE_NAME, Manifest, Span,
+    },
+    path,
+};
+
+/// The number of features (and optional dependencies) that a feature must
+/// enable to be worth warning about when it's unified into other members
+/// (e.g. `tokio/full`).
+pub const HEAVY_FEATURE_SIZE: usize = 5;
+
+const WORKSPACE_KEY: &str = "workspace";
+const MEMBERS_KEY: &str = "members";
+const EXCLUDE_KEY: &str = "exclude";
+const PACKAGE_KEY: &str = "package";
+const DEPENDENCIES_KEY: &str = "dependencies";
+
+/// The root manifest of a workspace, i.e. the one with the `[workspace]`
+/// table.
+#[derive(Debug, Clone)]
+pub struct Root {
+    pub uri: Url,
+    doc: String,
+}
+
+/// An entry of the root manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// The range of its key.
+    pub range: Range,
+    /// Its value, as it's written.
+    pub value: String,
+}
+
+impl Root {
+    /// The root of the workspace of the manifest at `uri`: the closest
+    /// manifest with a `[workspace]` table, starting at the manifest itself,
+    /// as cargo finds it. `read` reads the manifests, so the unsaved changes
+    /// of open ones can be used.
+    pub fn find(
+        uri: &Url,
+        read: impl Fn(&Url) -> Option<String>,
+    ) -> Option<Self> {
+        ancestors(uri).find_map(|manifest| {
+            let doc = read(&manifest)?;
+            let dom = taplo::parser::parse(&doc).into_dom();
+            dom.get(WORKSPACE_KEY)
+                .as_table()
+                .is_some()
+                .then_some(Self { uri: manifest, doc })
+        })
+    }
+
+    pub fn dom(&self) -> Node {
+        taplo::parser::parse(&self.doc).into_dom()
+    }
+
+    /// The manifests of the members of the workspace (see [`members`]).
+    pub fn members(&self) -> Vec<Url> {
+        members(&self.uri, &self.dom())
+    }
+
+    /// The manifests of the members of the workspace other than the one at
+    /// `uri`, if it's a member.
+    pub fn other_members(&self, uri: &Url) -> Vec<Url> {
+        let members = self.members();
+        // cargo rejects manifests that their workspace doesn't include.
+        if !members.contains(uri) {
+            return Vec::new();
+        }
+        members.into_iter().filter(|member| member != uri).collect()
+    }
+
+    /// The `[workspace.package]` table.
+    pub fn package(&self) -> Node {
+        self.dom().get(WORKSPACE_KEY).get(PACKAGE_KEY)
+    }
+
+    /// The `[workspace.package]` entry of `key`, which the members inherit
+    /// with `key.workspace = true`.
+    pub fn package_entry(&self, key: &str) -> Option<Entry> {
+        self.entry(PACKAGE_KEY, key)
+    }
+
+    /// The `[workspace.dependencies]` entry of `name`, which the members
+    /// inherit with `name.workspace = true`.
+    pub fn dependency_entry(&self, name: &str) -> Option<Entry> {
+        self.entry(DEPENDENCIES_KEY, name)
+    }
+
+    fn entry(&self, table: &str, key: &str) -> Option<Entry> {
+        let dom = self.dom();
+        let table = dom.get(WORKSPACE_KEY).get(table);
+        let entries = table.as_table()?.entries().read();
+        let (key, node) = entries.iter().find(|(k, _)| k.value() == key)?;
+        let range = parse::key_range(key, &self.doc)?;
+        let value = node.syntax()?.text_range();
+        let value = self.doc.get(parse::text_range_to_range(value))?;
+        Some(Entry { range, value: value.trim().to_owned() })
+    }
+}
+
+/// The edits that move the dependencies that at least two members declare
+/// identically (with the same source, package, version requirement and
+/// features) into `[workspace.dependencies]`, and make the members inherit
+/// them.
+#[derive(Debug, Default)]
+pub struct Centralization {
+    /// The names of the moved dependencies.
+    pub names: Vec<String>,
+    pub changes: HashMap<Url, Vec<TextEdit>>,
+}
+
+impl Root {
+    /// Centralize the duplicated dependencies of the `members` (which may
+    /// include the root itself).
+    ///
+    /// Only the single-line entries are moved (see
+    /// [`Dependency::entry`]), and not the `path` dependencies, whose paths
+    /// are relative to their members. The dependencies the workspace already
+    /// declares are left alone.
+    pub fn centralize(
+        &self,
+        members: &[(Url, Arc<Manifest>)],
+    ) -> Centralization {
+        let dom = self.dom();
+        let declared = dom.get(WORKSPACE_KEY).get(DEPENDENCIES_KEY);
+        let is_declared = |name: &str| {
+            declared.as_table().is_some_and(|t| t.get(name).is_some())
+        };
+
+        // the declarations of each spec, by the spec.
+        let mut specs: Vec<(String, Vec<(&Url, &Dependency)>)> = Vec::new();
+        for (uri, manifest) in members {
+            for dependency in &manifest.dependencies {
+                if dependency.entry.is_none()
+                    || matches!(dependency.kind, parse::Kind::Local(_))
+                    || dependency.workspace.is_some()
+                    || is_declared(&dependency.name.value)
+                {
+                    continue;
+                }
+                let spec = edit::dependency_spec(dependency);
+                match specs.iter_mut().find(|(s, _)| *s == spec) {
+                    Some((_, declarations)) => {
+                        declarations.push((uri, dependency))
+                    }
+                    None => specs.push((spec, vec![(uri, dependency)])),
+                }
+            }
+        }
+
+        let mut centralization = Centralization::default();
+        let mut entries = Vec::new();
+        for (spec, declarations) in specs {
+            let name = &declarations[0].1.name.value;
+            let crate_name = declarations[0].1.crate_name();
+            let mut uris = declarations.iter().map(|(uri, _)| uri);
+            let first = uris.next();
+            // a crate declared differently elsewhere (even under another
+            // name) would change its members' builds.
+            let is_conflicting = members.iter().any(|(_, manifest)| {
+                manifest.dependencies.iter().any(|d| {
+                    d.crate_name() == crate_name
+                        && d.workspace.is_none()
+                        && edit::dependency_spec(d) != spec
+                })
+            });
+            if uris.all(|uri| Some(uri) == first) || is_conflicting {
+                continue;
+            }
+
+            for (uri, dependency) in declarations {
+                let Some(range) = dependency.entry else { continue };
+                let optional = dependency.optional.as_ref();
+                let inherit = if optional.is_some_and(|o| o.value) {
+                    "{ workspace = true, optional = true }"
+                } else {
+                    "{ workspace = true }"
+                };
+                let edit = TextEdit::new(range, format!("{name} = {inherit}"));
+                centralization
+                    .changes
+                    .entry(uri.clone())
+                    .or_default()
+                    .push(edit);
+            }
+            centralization.names.push(name.clone());
+            entries.push(spec);
+        }
+        if entries.is_empty() {
+            return centralization;
+        }
+
+        let line_break = if self.doc.contains("\r\n") { "\r\n" } else { "\n" };
+        entries.sort();
+        let entries = entries.join(line_break);
+        let header = format!("{WORKSPACE_KEY}.{DEPENDENCIES_KEY}");
+        let mut offset = 0;
+        let mut insert = None;
+        for line in self.doc.split_inclusive('\n') {
+            offset += line.len();
+            if parse::table_header(line) == Some(header.as_str()) {
+                insert = Some((offset, format!("{entries}{line_break}")));
+                break;
+            }
+        }
+        let (offset, text) = insert.unwrap_or_else(|| {
+            let separator = match self.doc.ends_with('\n') {
+                true => line_break.to_owned(),
+                false => line_break.repeat(2),
+            };
+            let text = format!("{separator}[{header}]{line_break}{entries}");
+            (self.doc.len(), format!("{text}{line_break}"))
+        });
+        let position = parse::idx_to_position(&self.doc, offset);
+        centralization
+            .changes
+            .entry(self.uri.clone())
+            .or_default()
+            .push(TextEdit::new(Range::new(position, position), text));
+        centralization
+    }
+}
+
+/// Whether a value is inherited from the workspace, i.e. it's
+/// `{ workspace = true }` (or `key.workspace = true`).
+pub fn is_inherited(node: &Node) -> bool {
+    node.get(WORKSPACE_KEY).as_bool().is_some_and(|b| b.value())
+}
+
+/// The keys of the `[package]` table that are inherited from the
+/// workspace, with their ranges.
+pub fn inherited_keys(dom: &Node, s: &str) -> Vec<Span<String>> {
+    let package = dom.get(PACKAGE_KEY);
+    let Some(package) = package.as_table() else { return Vec::new() };
+    let entries = package.entries().read();
+    entries
+        .iter()
+        .filter(|(_, node)| is_inherited(node))
+        .filter_map(|(key, _)| {
+            let range = parse::key_range(key, s)?;
+            Some(Span::new(key.value().to_owned(), range))
+        })
+        .collect()
+}
+
+/// The inherited key at `pos`, if any.
+pub fn find_inherited(
+    keys: &[Span<String>],
+    pos: Position,
+) -> Option<&Span<String>> {
+    keys.iter()
+        .find(|k| k.range.start <= pos && pos <= k.range.end)
+}
+
+/// The manifest at `uri`, and the manifests of the directories above it.
+fn ancestors(uri: &Url) -> impl Iterator<Item = Url> {
+    std::iter::successors(Some(uri.clone()), |manifest| {
+        let parent = manifest.join(&format!("../{MANIFEST_FILE_NAME}")).ok()?;
+        (&parent != manifest).then_some(parent)
+    })
+}
+
+/// The manifests of the members of the workspace whose root manifest is at
+/// `root`: the root's own package (if it has one), and the packages in the
+/// directories that the `members` globs match, except the `exclude`d ones.
+fn members(root: &Url, dom: &Node) -> Vec<Url> {
+    let Some(dir) = path::to_path(root)
+        .and_then(|root| root.parent().map(Path::to_path_buf))
+    else {
+        return Vec::new();
+    };
+    let workspace = dom.get(WORKSPACE_KEY);
+    let strings = |key| {
+        let array = workspace.get(key);
+        let Some(array) = array.as_array() else { return Vec::new() };
+        let items = array.items().read();
+        items
+            .iter()
+            .filter_map(|item| item.as_str().map(|s| s.value().to_owned()))
+            .collect::<Vec<_>>()
+    };
+    let excluded = strings(EXCLUDE_KEY)
+        .iter()
+        .map(|exclude| dir.join(exclude))
+        .collect::<Vec<_>>();
+
+    let mut members = Vec::new();
+    if dom.get(PACKAGE_KEY).as_table().is_some() {
+        members.push(root.clone());
+    }
+    for pattern in strings(MEMBERS_KEY) {
+        for member in expand(&dir, &pattern) {
+            let manifest = member.join(MANIFEST_FILE_NAME);
+            if !excluded.iter().any(|excluded| member.starts_with(excluded))
+                && manifest.is_file()
+                && let Some(manifest) = path::to_uri(&manifest)
+                && !members.contains(&manifest)
+            {
+                members.push(manifest);
+            }
+        }
+    }
+    members
+}
+
+/// The directories under `dir` that a `members` glob matches (`*` and `?`
+/// in any of its segments).
+fn expand(dir: &Path, pattern: &str) -> Vec<PathBuf> {
+    let mut dirs = vec![dir.to_path_buf()];
+    for segment in pattern.split(['/', '\\']).filter(|s| !s.is_empty()) {
+        if !segment.contains(['*', '?']) {
+            dirs.iter_mut().for_each(|dir| dir.push(segment));
+            continue;
+        }
+        dirs = dirs
+            .iter()
+            .filter_map(|dir| std::fs::read_dir(dir).ok())
+            .flatten()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.path().is_dir())
+            .filter(|entry| {
+                entry.file_name().to_str().is_some_and(|name| {
+                    matches(segment.as_bytes(), name.as_bytes())
+                })
+            })
+            .map(|entry| entry.path())
+            .collect();
+        dirs.sort();
+    }
+    dirs
+}
+
+/// Whether a file name matches a glob segment.
+fn matches(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.split_first(), name.split_first()) {
+        (None, None) => true,
+        (Some((b'*', rest)), _) => {
+            matches(rest, name)
+                || !name.is_empty() && matches(pattern, &name[1..])
+        }
+        (Some((b'?', rest)), Some((_, name))) => matches(rest, name),
+        (Some((p, rest)), Some((n, name))) => p == n && matches(rest, name),
+        _ => false,
+    }
+}
+
+/// A feature that a member enables, which cargo unifies into the builds of
+/// the other members that depend on the same crate without it.
+#[derive(Debug)]
+pub struct UnifiedFeature<'a> {
+    pub dependency: &'a Dependency,
+    pub feature: &'a Span<String>,
+    /// The other members' declarations of the crate.
+    pub affected: Vec<(Url, Range)>,
+}
+
+/// The (`heavy`) features of `dependencies` that are unified into the other
+/// `members`. Only the `[dependencies]` tables are compared, as those are
+/// unified whenever the members are built together.
+pub fn unified_features<'a>(
+    dependencies: &'a [Dependency],
+    members: &[(Url, Arc<Manifest>)],
+    is_heavy: impl Fn(&Dependency, &str) -> bool,
+) -> Vec<UnifiedFeature<'a>> {
+    let is_normal = |d: &&Dependency| d.section.kind == DependencyKind::Normal;
+    let enables = |d: &Dependency, feature: &str| {
+        d.features.iter().flatten().any(|f| f.value == feature)
+    };
+
+    let mut unified = Vec::new();
+    for dependency in dependencies.iter().filter(is_normal) {
+        for feature in dependency.features.iter().flatten() {
+            if !is_heavy(dependency, &feature.value) {
+                continue;
+            }
+            let affected = members
+                .iter()
+                .flat_map(|(uri, manifest)| {
+                    manifest
+                        .dependencies
+                        .iter()
+                        .filter(is_normal)
+                        .filter(|d| d.crate_name() == dependency.crate_name())
+                        .filter(|d| !enables(d, &feature.value))
+                        .map(|d| (uri.clone(), d.name.range))
+                })
+                .collect::<Vec<_>>();
+            if !affected.is_empty() {
+                unified.push(UnifiedFeature { dependency, feature, affected });
+            }
+        }
+    }
+    unified
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    fn parse(s: &str) -> Arc<Manifest> {
+        Arc::new(Manifest::parse(s, &taplo::parser::parse(s).into_dom()))
+    }
+
+    #[test]
+    fn test_matches() {
+        assert!(matches(b"*", b"foo"));
+        assert!(matches(b"foo-*", b"foo-bar"));
+        assert!(matches(b"f?o", b"foo"));
+        assert!(matches(b"*-cli", b"my-cli"));
+        assert!(!matches(b"foo-*", b"bar-foo"));
+        assert!(!matches(b"f?o", b"fo"));
+    }
+
+    #[test]
+    fn test_ancestors() {
+        let uri = Url::parse("file:///ws/crates/app/Cargo.toml").unwrap();
+        assert_eq!(
+            ancestors(&uri).map(String::from).collect::<Vec<_>>(),
+            [
+                "file:///ws/crates/app/Cargo.toml",
+                "file:///ws/crates/Cargo.toml",
+                "file:///ws/Cargo.toml",
+                "file:///Cargo.toml",
+            ]
+        );
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    fn test_other_members() {
+        let root = std::env::temp_dir()
+            .join(format!("crates-ls-workspace-{}", std::process::id()));
+        let write = |path: &str, s: &str| {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, s).unwrap();
+        };
+        write(
+            "Cargo.toml",
+            "[workspace]\nmembers = [\"crates/*\", \"tool\"]\n\
+             exclude = [\"crates/old\"]\n\n[package]\nname = \"root\"\n",
+        );
+        for member in ["crates/a", "crates/b", "crates/old", "tool"] {
+            write(&format!("{member}/Cargo.toml"), "[package]\n");
+        }
+        // not a package.
+        std::fs::create_dir_all(root.join("crates/docs")).unwrap();
+
+        let uri = |path: &str| path::to_uri(&root.join(path)).unwrap();
+        let read =
+            |uri: &Url| std::fs::read_to_string(path::to_path(uri)?).ok();
+        let others = Root::find(&uri("crates/a/Cargo.toml"), read)
+            .unwrap()
+            .other_members(&uri("crates/a/Cargo.toml"));
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(
+            others,
+            [
+                uri("Cargo.toml"),
+                uri("crates/b/Cargo.toml"),
+                uri("tool/Cargo.toml"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_inherited() {
+        let member = indoc! {r#"
+            [package]
+            name = "app"
+            version.workspace = true
+            edition = { workspace = true }
+            license = "MIT"
+            publish.workspace = false
+
+            [dependencies]
+            serde.workspace = true
+        "#};
+        let dom = taplo::parser::parse(member).into_dom();
+        let keys = inherited_keys(&dom, member);
+        assert_eq!(
+            keys.iter().map(|k| k.value.as_str()).collect::<Vec<_>>(),
+            ["version", "edition"]
+        );
+        let pos = Position::new(2, 3);
+        assert_eq!(find_inherited(&keys, pos).unwrap().value, "version");
+        assert!(find_inherited(&keys, Position::new(4, 3)).is_none());
+
+        let root = indoc! {r#"
+            [workspace]
+            members = ["app"]
+
+            [workspace.package]
+            version = "1.2.0"
+            edition = "2024"
+
+            [workspace.dependencies]
+            serde = { version = "1", features = ["derive"] }
+        "#};
+        let uri = |s| Url::parse(s).unwrap();
+        let read = |u: &Url| match u.as_str() {
+            "file:///ws/Cargo.toml" => Some(root.to_owned()),
+            "file:///ws/app/Cargo.toml" => Some(member.to_owned()),
+            _ => None,
+        };
+        let root = Root::find(&uri("file:///ws/app/Cargo.toml"), read).unwrap();
+        assert_eq!(root.uri, uri("file:///ws/Cargo.toml"));
+        assert_eq!(
+            root.package_entry("version"),
+            Some(Entry {
+                range: Range::new(Position::new(4, 0), Position::new(4, 7)),
+                value: "\"1.2.0\"".to_owned(),
+            })
+        );
+        assert_eq!(root.package_entry("license"), None);
+        assert_eq!(
+            root.dependency_entry("serde").unwrap().value,
+            r#"{ version = "1", features = ["derive"] }"#
+        );
+    }
+
+    #[test]
+    fn test_centralize() {
+        let workspace = "[workspace]\nmembers = [\"a\", \"b\"]\n";
+        let a = indoc! {r#"
+            [dependencies]
+            serde = { version = "1", features = ["derive"] }
+            tokio = "1"
+            anyhow = "1"
+            local = { path = "../local" }
+
+            [dev-dependencies]
+            insta = "1"
+        "#};
+        let b = indoc! {r#"
+            [dependencies]
+            serde = { version = "1", features = ["derive"], optional = true }
+            tokio = "1.40"
+            local = { path = "../local" }
+
+            [dev-dependencies.insta]
+            version = "1"
+            [target.'cfg(unix)'.dependencies]
+            anyhow = "1"
+        "#};
+        let uri = |s| Url::parse(s).unwrap();
+        let root = Root::find(&uri("file:///ws/Cargo.toml"), |_| {
+            Some(workspace.to_owned())
+        })
+        .unwrap();
+        let members = [
+            (uri("file:///ws/a/Cargo.toml"), parse(a)),
+            (uri("file:///ws/b/Cargo.toml"), parse(b)),
+        ];
+
+        let centralization = root.centralize(&members);
+        assert_eq!(centralization.names, ["serde", "anyhow"]);
+        let new_text = |uri: &str| {
+            centralization.changes[&Url::parse(uri).unwrap()]
+                .iter()
+                .map(|e| e.new_text.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            new_text("file:///ws/Cargo.toml"),
+            ["\n[workspace.dependencies]\nanyhow = \"1\"\nserde = { version \
+              = \"1\", features = [\"derive\"] }\n"]
+        );
+        assert_eq!(
+            new_text("file:///ws/a/Cargo.toml"),
+            ["serde = { workspace = true }", "anyhow = { workspace = true }"]
+        );
+        assert_eq!(
+            new_text("file:///ws/b/Cargo.toml"),
+            [
+                "serde = { workspace = true, optional = true }",
+                "anyhow = { workspace = true }"
+            ]
+        );
+
+        // the declared ones are left alone, and the rest are added under the
+        // existing table.
+        let root =
+            format!("{workspace}\n[workspace.dependencies]\nserde = \"1\"\n");
+        let root =
+            Root::find(&uri("file:///ws/Cargo.toml"), |_| Some(root.clone()))
+                .unwrap();
+        let centralization = root.centralize(&members);
+        assert_eq!(centralization.names, ["anyhow"]);
+        let edit = &centralization.changes[&root.uri][0];
+        assert_eq!(edit.range.start, Position::new(4, 0));
+        assert_eq!(edit.new_text, "anyhow = \"1\"\n");
+
+        // the renamed dependencies keep their packages, and are compared with
+        // the other declarations of their crates.
+        let renamed = indoc! {r#"
+            [dependencies]
+            json = { package = "serde_json", version = "1" }
+            rand = "0.8"
+        "#};
+        let members = [
+            (uri("file:///ws/a/Cargo.toml"), parse(renamed)),
+            (uri("file:///ws/b/Cargo.toml"), parse(renamed)),
+            (
+                uri("file:///ws/c/Cargo.toml"),
+                parse(
+                    "[dependencies]\nrand07 = { package = \"rand\", version = \
+                     \"0.7\" }\n",
+                ),
+            ),
+        ];
+        let root = Root::find(&uri("file:///ws/Cargo.toml"), |_| {
+            Some(workspace.to_owned())
+        })
+        .unwrap();
+        let centralization = root.centralize(&members);
+        assert_eq!(centralization.names, ["json"]);
+        assert_eq!(
+            centralization.changes[&root.uri][0].new_text,
+            "\n[workspace.dependencies]\njson = { version = \"1\", package = \
+             \"serde_json\" }\n"
+        );
+    }
+
+    #[test]
+    fn test_unified_features() {
+        let app = parse(indoc! {r#"
+            [dependencies]
+            tokio = { version = "1", features = ["full", "rt"] }
+
+            [dev-dependencies]
+            serde = { version = "1", features = ["derive"] }
+        "#});
+        let uri = |name| Url::parse(name).unwrap();
+        let members = [
+            (
+                uri("file:///ws/lib/Cargo.toml"),
+                parse("[dependencies]\ntokio = { version = \"1\" }\n"),
+            ),
+            (
+                uri("file:///ws/cli/Cargo.toml"),
+                parse(
+                    "[dependencies]\ntokio = { version = \"1\", features = [\"full\"] }\n",
+                ),
+            ),
+            (
+                uri("file:///ws/tests/Cargo.toml"),
+                parse("[dev-dependencies]\ntokio = \"1\"\nserde = \"1\"\n"),
+            ),
+        ];
+
+        let unified = unified_features(&app.dependencies, &members, |_, f| {
+            f == "full" || f == "derive"
+        });
+        let [unified] = unified.as_slice() else { panic!("{unified:?}") };
+        assert_eq!(unified.feature.value, "full");
+        assert_eq!(
+            unified
+                .affected
+                .iter()
+                .map(|(u, _)| u.as_str())
+                .collect::<Vec<_>>(),
+            ["file:///ws/lib/Cargo.toml"]
+        );
+    }
+}